@@ -0,0 +1,343 @@
+/*
+    Copyright (C) 2025 bugo07
+    Released under EUPL 1.2 License
+*/
+
+// ahci sata, the disk controller q35 has instead of the legacy ide ports. one command
+// slot per port and polled completion like the ata driver, transfers go through a
+// bounce buffer per port so callers don't have to care about alignment or where their
+// buffer is in physical memory
+
+use alloc::{format, string::String, vec::Vec};
+
+use crate::{
+    arch::{
+        mem::{PAGE_SIZE, dma_alloc, map_mmio, virt_to_phys},
+        pci::{self, PciDevice},
+    },
+    info,
+    utils::{
+        asm::{mmio_read, mmio_write},
+        block::{BlockDevice, BlockError, check_access},
+    },
+    warn,
+};
+
+// mass storage / sata
+const PCI_CLASS: (u8, u8) = (0x01, 0x06);
+const ABAR: u8 = 5;
+
+// hba registers
+const HBA_CAP: u64 = 0x00;
+const HBA_GHC: u64 = 0x04;
+const HBA_PI: u64 = 0x0C;
+const HBA_PORTS: u64 = 0x100;
+const HBA_PORT_SIZE: u64 = 0x80;
+const HBA_SIZE: u64 = HBA_PORTS + 32 * HBA_PORT_SIZE;
+
+const CAP_S64A: u32 = 1 << 31;
+const GHC_AE: u32 = 1 << 31;
+const GHC_IE: u32 = 1 << 1;
+
+// port registers
+const PORT_CLB: u64 = 0x00;
+const PORT_CLBU: u64 = 0x04;
+const PORT_FB: u64 = 0x08;
+const PORT_FBU: u64 = 0x0C;
+const PORT_IS: u64 = 0x10;
+const PORT_IE: u64 = 0x14;
+const PORT_CMD: u64 = 0x18;
+const PORT_TFD: u64 = 0x20;
+const PORT_SIG: u64 = 0x24;
+const PORT_SSTS: u64 = 0x28;
+const PORT_SERR: u64 = 0x30;
+const PORT_CI: u64 = 0x38;
+
+const CMD_ST: u32 = 1 << 0;
+const CMD_FRE: u32 = 1 << 4;
+const CMD_FR: u32 = 1 << 14;
+const CMD_CR: u32 = 1 << 15;
+
+const IS_TFES: u32 = 1 << 30;
+const TFD_ERR: u32 = 1 << 0;
+const TFD_DRQ: u32 = 1 << 3;
+const TFD_BSY: u32 = 1 << 7;
+
+const SIG_ATA: u32 = 0x0000_0101;
+const SSTS_DET_PRESENT: u32 = 3;
+const SSTS_IPM_ACTIVE: u32 = 1;
+
+const FIS_REG_H2D: u8 = 0x27;
+
+const ATA_IDENTIFY: u8 = 0xEC;
+const ATA_READ_DMA_EXT: u8 = 0x25;
+const ATA_WRITE_DMA_EXT: u8 = 0x35;
+const ATA_FLUSH_CACHE_EXT: u8 = 0xEA;
+
+const SECTOR_SIZE: usize = 512;
+// one prdt entry per page of the bounce buffer
+const BOUNCE_PAGES: usize = 16;
+const BOUNCE_SIZE: usize = BOUNCE_PAGES * PAGE_SIZE as usize;
+const CMD_TABLE_SIZE: usize = 0x80 + BOUNCE_PAGES * 16;
+const POLL_LIMIT: u32 = 10_000_000;
+
+pub struct AhciDrive {
+    name: String,
+    pub model: String,
+    port: spin::Mutex<Port>,
+    sectors: u64,
+}
+
+// the dma structures of one port, addresses kept as numbers so the drive is Send
+struct Port {
+    regs: u64,
+    cmd_list: u64,
+    cmd_table: u64,
+    bounce: u64,
+    s64a: bool,
+}
+
+pub fn probe() -> Vec<AhciDrive> {
+    let mut drives = Vec::new();
+    for controller in pci::find_class(PCI_CLASS.0, PCI_CLASS.1) {
+        let Some(abar) = controller.bar(ABAR) else {
+            warn!("ahci {controller}: no memory bar");
+            continue;
+        };
+        info!("ahci controller {controller} at 0x{abar:X}");
+        probe_controller(controller, abar, &mut drives);
+    }
+    drives
+}
+
+fn probe_controller(controller: PciDevice, abar: u64, drives: &mut Vec<AhciDrive>) {
+    controller.enable_bus_master();
+    let hba = map_mmio(abar, HBA_SIZE);
+
+    // ahci mode on, interrupts off, we poll
+    let ghc = read32(hba, HBA_GHC);
+    write32(hba, HBA_GHC, (ghc | GHC_AE) & !GHC_IE);
+    let s64a = read32(hba, HBA_CAP) & CAP_S64A != 0;
+
+    let implemented = read32(hba, HBA_PI);
+    for index in 0..32 {
+        if implemented & (1 << index) == 0 {
+            continue;
+        }
+        let regs = hba + HBA_PORTS + index * HBA_PORT_SIZE;
+        let status = read32(regs, PORT_SSTS);
+        if status & 0xF != SSTS_DET_PRESENT || (status >> 8) & 0xF != SSTS_IPM_ACTIVE {
+            continue;
+        }
+        // atapi drives and port multipliers don't get a driver
+        if read32(regs, PORT_SIG) != SIG_ATA {
+            continue;
+        }
+
+        match Port::new(regs, s64a).and_then(|port| port.identify(drives.len())) {
+            Ok(Some(drive)) => drives.push(drive),
+            Ok(None) => {}
+            Err(err) => warn!("ahci port {index}: {err:?}"),
+        }
+    }
+}
+
+fn read32(base: u64, offset: u64) -> u32 {
+    mmio_read(base + offset, 4) as u32
+}
+
+fn write32(base: u64, offset: u64, value: u32) {
+    mmio_write(base + offset, value as u64, 4);
+}
+
+fn wait_clear(regs: u64, offset: u64, mask: u32) -> Result<(), BlockError> {
+    for _ in 0..POLL_LIMIT {
+        if read32(regs, offset) & mask == 0 {
+            return Ok(());
+        }
+        core::hint::spin_loop();
+    }
+    Err(BlockError::Timeout)
+}
+
+impl Port {
+    fn new(regs: u64, s64a: bool) -> Result<Self, BlockError> {
+        // stopped before the command list and fis area can be moved
+        write32(regs, PORT_CMD, read32(regs, PORT_CMD) & !CMD_ST);
+        wait_clear(regs, PORT_CMD, CMD_CR)?;
+        write32(regs, PORT_CMD, read32(regs, PORT_CMD) & !CMD_FRE);
+        wait_clear(regs, PORT_CMD, CMD_FR)?;
+
+        let port = Self {
+            regs,
+            cmd_list: dma_alloc(1024, 1024),
+            cmd_table: dma_alloc(CMD_TABLE_SIZE, 1024),
+            bounce: dma_alloc(BOUNCE_SIZE, PAGE_SIZE as usize),
+            s64a,
+        };
+        let fis = dma_alloc(256, 256);
+
+        let cmd_list = port.phys(port.cmd_list)?;
+        let fis = port.phys(fis)?;
+        write32(regs, PORT_CLB, cmd_list as u32);
+        write32(regs, PORT_CLBU, (cmd_list >> 32) as u32);
+        write32(regs, PORT_FB, fis as u32);
+        write32(regs, PORT_FBU, (fis >> 32) as u32);
+        write32(regs, PORT_IE, 0);
+        write32(regs, PORT_IS, !0);
+        write32(regs, PORT_SERR, !0);
+
+        write32(regs, PORT_CMD, read32(regs, PORT_CMD) | CMD_FRE);
+        write32(regs, PORT_CMD, read32(regs, PORT_CMD) | CMD_ST);
+        Ok(port)
+    }
+
+    fn phys(&self, virt: u64) -> Result<u64, BlockError> {
+        match virt_to_phys(virt) {
+            Some(phys) if self.s64a || phys >> 32 == 0 => Ok(phys),
+            Some(_) => Err(BlockError::Io("dma buffer above 4GiB")),
+            None => Err(BlockError::Io("dma buffer not mapped")),
+        }
+    }
+
+    fn identify(self, index: usize) -> Result<Option<AhciDrive>, BlockError> {
+        self.command(ATA_IDENTIFY, 0, 0, SECTOR_SIZE, false)?;
+        let mut words = [0u16; 256];
+        let identify =
+            unsafe { core::slice::from_raw_parts(self.bounce as *const u8, SECTOR_SIZE) };
+        for (word, bytes) in words.iter_mut().zip(identify.as_chunks::<2>().0) {
+            *word = u16::from_le_bytes(*bytes);
+        }
+
+        // dma ext commands need lba48 anyway
+        if words[83] & (1 << 10) == 0 {
+            warn!("ahci drive without lba48, skipping it");
+            return Ok(None);
+        }
+        let sectors = words[100..104]
+            .iter()
+            .rev()
+            .fold(0u64, |acc, &x| (acc << 16) | x as u64);
+        if sectors == 0 {
+            return Ok(None);
+        }
+
+        let model = words[27..47]
+            .iter()
+            .flat_map(|x| x.to_be_bytes())
+            .map(|x| x as char)
+            .collect::<String>();
+
+        Ok(Some(AhciDrive {
+            name: format!("ahci{index}"),
+            model: String::from(model.trim()),
+            port: spin::Mutex::new(self),
+            sectors,
+        }))
+    }
+
+    // runs one command in slot 0 with `len` bytes of the bounce buffer and waits for it
+    fn command(
+        &self,
+        command: u8,
+        lba: u64,
+        count: u16,
+        len: usize,
+        write: bool,
+    ) -> Result<(), BlockError> {
+        wait_clear(self.regs, PORT_TFD, TFD_BSY | TFD_DRQ)?;
+
+        let pages = len.div_ceil(PAGE_SIZE as usize);
+        unsafe {
+            let table = self.cmd_table as *mut u8;
+            core::ptr::write_bytes(table, 0, CMD_TABLE_SIZE);
+
+            let fis = core::slice::from_raw_parts_mut(table, 20);
+            fis[0] = FIS_REG_H2D;
+            fis[1] = 1 << 7; // command, not control
+            fis[2] = command;
+            fis[4] = lba as u8;
+            fis[5] = (lba >> 8) as u8;
+            fis[6] = (lba >> 16) as u8;
+            fis[7] = 1 << 6; // lba mode
+            fis[8] = (lba >> 24) as u8;
+            fis[9] = (lba >> 32) as u8;
+            fis[10] = (lba >> 40) as u8;
+            fis[12] = count as u8;
+            fis[13] = (count >> 8) as u8;
+
+            for page in 0..pages {
+                let offset = page * PAGE_SIZE as usize;
+                let phys = self.phys(self.bounce + offset as u64)?;
+                let bytes = (len - offset).min(PAGE_SIZE as usize);
+                let prd = table.add(0x80 + page * 16) as *mut u32;
+                prd.write_volatile(phys as u32);
+                prd.add(1).write_volatile((phys >> 32) as u32);
+                prd.add(3).write_volatile(bytes as u32 - 1);
+            }
+
+            let header = self.cmd_list as *mut u32;
+            let table_phys = self.phys(self.cmd_table)?;
+            // fis length in dwords, write direction, prdt entries
+            header.write_volatile(5 | (write as u32) << 6 | (pages as u32) << 16);
+            header.add(1).write_volatile(0);
+            header.add(2).write_volatile(table_phys as u32);
+            header.add(3).write_volatile((table_phys >> 32) as u32);
+        }
+
+        write32(self.regs, PORT_IS, !0);
+        write32(self.regs, PORT_CI, 1);
+        for _ in 0..POLL_LIMIT {
+            if read32(self.regs, PORT_IS) & IS_TFES != 0 {
+                return Err(BlockError::Io("task file error"));
+            }
+            if read32(self.regs, PORT_CI) & 1 == 0 {
+                if read32(self.regs, PORT_TFD) & TFD_ERR != 0 {
+                    return Err(BlockError::Io("drive error"));
+                }
+                return Ok(());
+            }
+            core::hint::spin_loop();
+        }
+        Err(BlockError::Timeout)
+    }
+
+    fn bounce(&mut self) -> &mut [u8] {
+        unsafe { core::slice::from_raw_parts_mut(self.bounce as *mut u8, BOUNCE_SIZE) }
+    }
+}
+
+impl BlockDevice for AhciDrive {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn sector_count(&self) -> u64 {
+        self.sectors
+    }
+
+    fn read(&self, lba: u64, buf: &mut [u8]) -> Result<(), BlockError> {
+        check_access(self, lba, buf.len())?;
+        let mut port = self.port.lock();
+        for (i, chunk) in buf.chunks_mut(BOUNCE_SIZE).enumerate() {
+            let start = lba + (i * BOUNCE_SIZE / SECTOR_SIZE) as u64;
+            let count = (chunk.len() / SECTOR_SIZE) as u16;
+            port.command(ATA_READ_DMA_EXT, start, count, chunk.len(), false)?;
+            chunk.copy_from_slice(&port.bounce()[..chunk.len()]);
+        }
+        Ok(())
+    }
+
+    fn write(&self, lba: u64, buf: &[u8]) -> Result<(), BlockError> {
+        check_access(self, lba, buf.len())?;
+        let mut port = self.port.lock();
+        for (i, chunk) in buf.chunks(BOUNCE_SIZE).enumerate() {
+            let start = lba + (i * BOUNCE_SIZE / SECTOR_SIZE) as u64;
+            let count = (chunk.len() / SECTOR_SIZE) as u16;
+            port.bounce()[..chunk.len()].copy_from_slice(chunk);
+            port.command(ATA_WRITE_DMA_EXT, start, count, chunk.len(), true)?;
+        }
+        // writes sit in the drive's cache until this
+        port.command(ATA_FLUSH_CACHE_EXT, 0, 0, 0, false)
+    }
+}
@@ -0,0 +1,282 @@
+/*
+    Copyright (C) 2025 bugo07
+    Released under EUPL 1.2 License
+*/
+
+// polled ata pio for the legacy ide channels at 0x1F0 and 0x170. the drive irqs stay
+// off (nIEN) and every command just spins on the status register. slow but the only
+// thing that goes through here is the odd save
+
+use alloc::{format, string::String, vec::Vec};
+
+use crate::{
+    utils::{
+        asm::{inb, inw, outb, outw},
+        block::{BlockDevice, BlockError, check_access},
+    },
+    warn,
+};
+
+const CHANNELS: [(u16, u16); 2] = [(0x1F0, 0x3F6), (0x170, 0x376)];
+
+// offsets from the io base
+const REG_DATA: u16 = 0;
+const REG_SECTOR_COUNT: u16 = 2;
+const REG_LBA_LOW: u16 = 3;
+const REG_LBA_MID: u16 = 4;
+const REG_LBA_HIGH: u16 = 5;
+const REG_DRIVE: u16 = 6;
+const REG_STATUS: u16 = 7;
+const REG_COMMAND: u16 = 7;
+
+const STATUS_ERR: u8 = 1 << 0;
+const STATUS_DRQ: u8 = 1 << 3;
+const STATUS_DF: u8 = 1 << 5;
+const STATUS_BSY: u8 = 1 << 7;
+
+const CTRL_NIEN: u8 = 1 << 1;
+
+const CMD_READ_SECTORS: u8 = 0x20;
+const CMD_READ_SECTORS_EXT: u8 = 0x24;
+const CMD_WRITE_SECTORS: u8 = 0x30;
+const CMD_WRITE_SECTORS_EXT: u8 = 0x34;
+const CMD_FLUSH_CACHE: u8 = 0xE7;
+const CMD_FLUSH_CACHE_EXT: u8 = 0xEA;
+const CMD_IDENTIFY: u8 = 0xEC;
+
+const SECTOR_SIZE: usize = 512;
+const POLL_LIMIT: u32 = 10_000_000;
+
+// master and slave share the registers
+static CHANNEL_LOCKS: [spin::Mutex<()>; 2] = [spin::Mutex::new(()), spin::Mutex::new(())];
+
+pub struct AtaDrive {
+    name: String,
+    pub model: String,
+    channel: usize,
+    slave: bool,
+    lba48: bool,
+    sectors: u64,
+}
+
+pub fn probe() -> Vec<AtaDrive> {
+    let mut drives = Vec::new();
+    for channel in 0..CHANNELS.len() {
+        let (io, ctrl) = CHANNELS[channel];
+        // nothing on the bus reads back as all ones
+        if inb(io + REG_STATUS) == 0xFF {
+            continue;
+        }
+        outb(ctrl, CTRL_NIEN);
+
+        for slave in [false, true] {
+            let _lock = CHANNEL_LOCKS[channel].lock();
+            match identify(channel, slave) {
+                Ok(Some(drive)) => drives.push(drive),
+                Ok(None) => {}
+                Err(err) => warn!("ata {channel}:{} identify failed: {err:?}", slave as u8),
+            }
+        }
+    }
+    drives
+}
+
+fn identify(channel: usize, slave: bool) -> Result<Option<AtaDrive>, BlockError> {
+    let (io, ctrl) = CHANNELS[channel];
+    outb(io + REG_DRIVE, 0xA0 | ((slave as u8) << 4));
+    delay(ctrl);
+    for reg in [REG_SECTOR_COUNT, REG_LBA_LOW, REG_LBA_MID, REG_LBA_HIGH] {
+        outb(io + reg, 0);
+    }
+    outb(io + REG_COMMAND, CMD_IDENTIFY);
+    if inb(io + REG_STATUS) == 0 {
+        return Ok(None);
+    }
+
+    wait_not_busy(io)?;
+    // atapi and sata drives answer with a signature instead
+    if inb(io + REG_LBA_MID) != 0 || inb(io + REG_LBA_HIGH) != 0 {
+        return Ok(None);
+    }
+    wait_drq(io)?;
+
+    let mut words = [0u16; 256];
+    for word in &mut words {
+        *word = inw(io + REG_DATA);
+    }
+
+    let lba48 = words[83] & (1 << 10) != 0;
+    let sectors = if lba48 {
+        words[100..104]
+            .iter()
+            .rev()
+            .fold(0u64, |acc, &x| (acc << 16) | x as u64)
+    } else {
+        (words[61] as u64) << 16 | words[60] as u64
+    };
+    if sectors == 0 {
+        return Ok(None);
+    }
+
+    // the model string has its bytes swapped in every word
+    let model = words[27..47]
+        .iter()
+        .flat_map(|x| x.to_be_bytes())
+        .map(|x| x as char)
+        .collect::<String>();
+
+    Ok(Some(AtaDrive {
+        name: format!("ata{}", channel * 2 + slave as usize),
+        model: String::from(model.trim()),
+        channel,
+        slave,
+        lba48,
+        sectors,
+    }))
+}
+
+// 400ns for the drive select to settle, every alternate status read takes ~100
+fn delay(ctrl: u16) {
+    for _ in 0..4 {
+        inb(ctrl);
+    }
+}
+
+fn wait_not_busy(io: u16) -> Result<u8, BlockError> {
+    for _ in 0..POLL_LIMIT {
+        let status = inb(io + REG_STATUS);
+        if status & STATUS_BSY == 0 {
+            return Ok(status);
+        }
+        core::hint::spin_loop();
+    }
+    Err(BlockError::Timeout)
+}
+
+fn wait_drq(io: u16) -> Result<(), BlockError> {
+    for _ in 0..POLL_LIMIT {
+        let status = inb(io + REG_STATUS);
+        if status & STATUS_BSY != 0 {
+            continue;
+        }
+        if status & (STATUS_ERR | STATUS_DF) != 0 {
+            return Err(BlockError::Io("drive error"));
+        }
+        if status & STATUS_DRQ != 0 {
+            return Ok(());
+        }
+        core::hint::spin_loop();
+    }
+    Err(BlockError::Timeout)
+}
+
+impl AtaDrive {
+    // sets up a transfer of `count` sectors (at most 256, 0 meaning 256 in lba28)
+    fn command(&self, lba: u64, count: u16, lba28: u8, lba48: u8) -> Result<(), BlockError> {
+        let (io, ctrl) = CHANNELS[self.channel];
+        let slave = (self.slave as u8) << 4;
+        wait_not_busy(io)?;
+
+        if self.lba48 {
+            outb(io + REG_DRIVE, 0x40 | slave);
+            delay(ctrl);
+            outb(io + REG_SECTOR_COUNT, (count >> 8) as u8);
+            outb(io + REG_LBA_LOW, (lba >> 24) as u8);
+            outb(io + REG_LBA_MID, (lba >> 32) as u8);
+            outb(io + REG_LBA_HIGH, (lba >> 40) as u8);
+            outb(io + REG_SECTOR_COUNT, count as u8);
+            outb(io + REG_LBA_LOW, lba as u8);
+            outb(io + REG_LBA_MID, (lba >> 8) as u8);
+            outb(io + REG_LBA_HIGH, (lba >> 16) as u8);
+            outb(io + REG_COMMAND, lba48);
+        } else {
+            outb(io + REG_DRIVE, 0xE0 | slave | ((lba >> 24) as u8 & 0x0F));
+            delay(ctrl);
+            outb(io + REG_SECTOR_COUNT, count as u8);
+            outb(io + REG_LBA_LOW, lba as u8);
+            outb(io + REG_LBA_MID, (lba >> 8) as u8);
+            outb(io + REG_LBA_HIGH, (lba >> 16) as u8);
+            outb(io + REG_COMMAND, lba28);
+        }
+        Ok(())
+    }
+
+    fn max_chunk(&self) -> usize {
+        if self.lba48 { 65536 } else { 256 }
+    }
+}
+
+impl BlockDevice for AtaDrive {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn sector_count(&self) -> u64 {
+        self.sectors
+    }
+
+    fn read(&self, lba: u64, buf: &mut [u8]) -> Result<(), BlockError> {
+        check_access(self, lba, buf.len())?;
+        let (io, _) = CHANNELS[self.channel];
+        let _lock = CHANNEL_LOCKS[self.channel].lock();
+
+        let chunk_size = self.max_chunk() * SECTOR_SIZE;
+        for (i, chunk) in buf.chunks_mut(chunk_size).enumerate() {
+            let count = chunk.len() / SECTOR_SIZE;
+            let start = lba + (i * self.max_chunk()) as u64;
+            self.command(start, count as u16, CMD_READ_SECTORS, CMD_READ_SECTORS_EXT)?;
+
+            for sector in chunk.as_chunks_mut::<SECTOR_SIZE>().0 {
+                wait_drq(io)?;
+                for word in sector.as_chunks_mut::<2>().0 {
+                    word.copy_from_slice(&inw(io + REG_DATA).to_le_bytes());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn write(&self, lba: u64, buf: &[u8]) -> Result<(), BlockError> {
+        check_access(self, lba, buf.len())?;
+        let (io, _) = CHANNELS[self.channel];
+        let _lock = CHANNEL_LOCKS[self.channel].lock();
+
+        let chunk_size = self.max_chunk() * SECTOR_SIZE;
+        for (i, chunk) in buf.chunks(chunk_size).enumerate() {
+            let count = chunk.len() / SECTOR_SIZE;
+            let start = lba + (i * self.max_chunk()) as u64;
+            self.command(
+                start,
+                count as u16,
+                CMD_WRITE_SECTORS,
+                CMD_WRITE_SECTORS_EXT,
+            )?;
+
+            for sector in chunk.as_chunks::<SECTOR_SIZE>().0 {
+                wait_drq(io)?;
+                for word in sector.as_chunks::<2>().0 {
+                    outw(io + REG_DATA, u16::from_le_bytes(*word));
+                }
+            }
+
+            // the last sector still has to make it out before the next command
+            let status = wait_not_busy(io)?;
+            if status & (STATUS_ERR | STATUS_DF) != 0 {
+                return Err(BlockError::Io("write failed"));
+            }
+        }
+
+        // writes sit in the drive's cache until this
+        let flush = if self.lba48 {
+            CMD_FLUSH_CACHE_EXT
+        } else {
+            CMD_FLUSH_CACHE
+        };
+        outb(io + REG_COMMAND, flush);
+        let status = wait_not_busy(io)?;
+        if status & (STATUS_ERR | STATUS_DF) != 0 {
+            return Err(BlockError::Io("flush failed"));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,174 @@
+/*
+    Copyright (C) 2025 bugo07
+    Released under EUPL 1.2 License
+*/
+
+// intel ac'97, what qemu's -device AC97 is. the codec's mixer sits behind one io bar and
+// the bus master dma engine behind the other. pcm out plays a ring of 32 buffers and
+// we keep a few of them queued ahead of the one playing, the audio task tops them up
+// from the mixer. no interrupts, it's polled like the disks
+
+use crate::{
+    arch::{
+        audio::{
+            AudioError,
+            mixer::{CHANNELS, Mixer},
+        },
+        mem::{dma_alloc, virt_to_phys},
+        pci::{self, PciDevice},
+    },
+    utils::asm::{inb, inl, inw, outb, outl, outw},
+};
+
+// multimedia / audio
+const PCI_CLASS: (u8, u8) = (0x04, 0x01);
+const NAM_BAR: u8 = 0;
+const NABM_BAR: u8 = 1;
+
+// codec mixer registers
+const NAM_RESET: u16 = 0x00;
+const NAM_MASTER_VOLUME: u16 = 0x02;
+const NAM_PCM_OUT_VOLUME: u16 = 0x18;
+
+// bus master registers, the pcm out box and the global ones
+const PO_BDBAR: u16 = 0x10;
+const PO_CIV: u16 = 0x14;
+const PO_LVI: u16 = 0x15;
+const PO_SR: u16 = 0x16;
+const PO_CR: u16 = 0x1B;
+const GLOB_CNT: u16 = 0x2C;
+const GLOB_STA: u16 = 0x30;
+
+const CR_RPBM: u8 = 1 << 0;
+const CR_RR: u8 = 1 << 1;
+const SR_DCH: u16 = 1 << 0;
+// last valid buffer, buffer completion and fifo error, write 1 to clear
+const SR_CLEAR: u16 = 0b1_1100;
+const GLOB_CNT_COLD_RESET: u32 = 1 << 1;
+const GLOB_STA_CODEC_READY: u32 = 1 << 8;
+
+// 0 is no attenuation, the pcm gain's 0db sits at 8 on both sides
+const MASTER_VOLUME: u16 = 0x0000;
+const PCM_OUT_VOLUME: u16 = 0x0808;
+
+// the fixed rate, without the variable rate extension it's all a codec can do
+pub const RATE: u32 = 48_000;
+
+const BUFFERS: u8 = 32;
+// 10ms each
+const FRAMES: usize = 480;
+const BUFFER_SAMPLES: usize = FRAMES * CHANNELS;
+const BUFFER_SIZE: usize = BUFFER_SAMPLES * 2;
+// queued past the one playing. a sound starts this many buffers late at worst, and
+// the audio task has as long to come back before the card runs dry
+const AHEAD: u8 = 4;
+const POLL_LIMIT: u32 = 10_000_000;
+
+#[repr(C)]
+struct Descriptor {
+    addr: u32,
+    // 16 bit samples, not frames
+    samples: u16,
+    flags: u16,
+}
+
+pub struct Ac97 {
+    pub device: PciDevice,
+    nabm: u16,
+    buffers: u64,
+    // the last buffer handed to the card
+    lvi: u8,
+}
+
+pub fn probe() -> Option<Result<Ac97, AudioError>> {
+    pci::find_class(PCI_CLASS.0, PCI_CLASS.1)
+        .next()
+        .map(Ac97::new)
+}
+
+impl Ac97 {
+    fn new(device: PciDevice) -> Result<Self, AudioError> {
+        let (Some(nam), Some(nabm)) = (device.io_bar(NAM_BAR), device.io_bar(NABM_BAR)) else {
+            return Err(AudioError::NoBar);
+        };
+        device.enable_bus_master();
+
+        // out of cold reset and wait for the codec to show up
+        outl(nabm + GLOB_CNT, GLOB_CNT_COLD_RESET);
+        wait(|| inl(nabm + GLOB_STA) & GLOB_STA_CODEC_READY != 0)?;
+        // any write puts the mixer back to its defaults, everything muted
+        outw(nam + NAM_RESET, 0);
+        outw(nam + NAM_MASTER_VOLUME, MASTER_VOLUME);
+        outw(nam + NAM_PCM_OUT_VOLUME, PCM_OUT_VOLUME);
+
+        outb(nabm + PO_CR, CR_RR);
+        wait(|| inb(nabm + PO_CR) & CR_RR == 0)?;
+
+        let card = Self {
+            device,
+            nabm,
+            buffers: dma_alloc(BUFFERS as usize * BUFFER_SIZE, 8),
+            lvi: 0,
+        };
+        let list = dma_alloc(BUFFERS as usize * size_of::<Descriptor>(), 8);
+        let buffers = phys(card.buffers)?;
+        for i in 0..BUFFERS as usize {
+            let descriptor = Descriptor {
+                addr: (buffers + (i * BUFFER_SIZE) as u64) as u32,
+                samples: BUFFER_SAMPLES as u16,
+                flags: 0,
+            };
+            unsafe { (list as *mut Descriptor).add(i).write_volatile(descriptor) };
+        }
+
+        // starts on a silent buffer and stops there until the first pump
+        outl(nabm + PO_BDBAR, phys(list)? as u32);
+        outb(nabm + PO_LVI, 0);
+        outb(nabm + PO_CR, CR_RPBM);
+        Ok(card)
+    }
+
+    pub fn rate(&self) -> u32 {
+        RATE
+    }
+
+    // mixes into every buffer the card is done with, up to AHEAD past the playing one
+    pub fn pump(&mut self, mixer: &mut Mixer) {
+        let civ = inb(self.nabm + PO_CIV);
+        while self.lvi.wrapping_sub(civ) % BUFFERS < AHEAD {
+            self.lvi = (self.lvi + 1) % BUFFERS;
+            mixer.mix(self.buffer(self.lvi));
+        }
+
+        outw(self.nabm + PO_SR, SR_CLEAR);
+        outb(self.nabm + PO_LVI, self.lvi);
+        // it halts when it runs dry, the new lvi usually restarts it but not everywhere
+        if inw(self.nabm + PO_SR) & SR_DCH != 0 {
+            outb(self.nabm + PO_CR, CR_RPBM);
+        }
+    }
+
+    fn buffer(&mut self, index: u8) -> &mut [i16] {
+        let addr = self.buffers + index as u64 * BUFFER_SIZE as u64;
+        unsafe { core::slice::from_raw_parts_mut(addr as *mut i16, BUFFER_SAMPLES) }
+    }
+}
+
+// the descriptors only have room for 32 bit addresses
+fn phys(virt: u64) -> Result<u64, AudioError> {
+    match virt_to_phys(virt) {
+        Some(phys) if phys >> 32 == 0 => Ok(phys),
+        Some(_) => Err(AudioError::Dma("dma buffer above 4GiB")),
+        None => Err(AudioError::Dma("dma buffer not mapped")),
+    }
+}
+
+fn wait(done: impl Fn() -> bool) -> Result<(), AudioError> {
+    for _ in 0..POLL_LIMIT {
+        if done() {
+            return Ok(());
+        }
+        core::hint::spin_loop();
+    }
+    Err(AudioError::Timeout)
+}
@@ -0,0 +1,165 @@
+/*
+    Copyright (C) 2025 bugo07
+    Released under EUPL 1.2 License
+*/
+
+// mixes whatever's playing into the card's buffers. sounds are mono at any rate and get
+// resampled (linearly) to the card's, every voice has its own volume and the sum goes
+// through the master volume before getting clipped to 16 bits
+
+use alloc::{sync::Arc, vec::Vec};
+
+// interleaved stereo, both sides get the same
+pub const CHANNELS: usize = 2;
+pub const MAX_VOICES: usize = 16;
+
+// volumes are kept as multiples of 1/256
+const UNITY: i32 = 256;
+
+pub type VoiceId = u64;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sound {
+    pub rate: u32,
+    pub samples: Vec<i16>,
+}
+
+impl Sound {
+    pub fn new(rate: u32, samples: Vec<i16>) -> Self {
+        Self { rate, samples }
+    }
+
+    pub fn duration_ms(&self) -> u64 {
+        self.samples.len() as u64 * 1000 / self.rate as u64
+    }
+}
+
+struct Voice {
+    id: VoiceId,
+    sound: Arc<Sound>,
+    // in samples of the sound, 32.32 fixed point
+    position: u64,
+    step: u64,
+    volume: i32,
+    looping: bool,
+    done: bool,
+}
+
+impl Voice {
+    fn next(&mut self) -> i32 {
+        let samples = &self.sound.samples;
+        let mut index = (self.position >> 32) as usize;
+        if index >= samples.len() && self.looping && !samples.is_empty() {
+            self.position -= (samples.len() as u64) << 32;
+            index = (self.position >> 32) as usize;
+        }
+        if index >= samples.len() {
+            self.done = true;
+            return 0;
+        }
+
+        let a = samples[index] as i64;
+        let next = match samples.get(index + 1) {
+            Some(x) => *x,
+            None if self.looping => samples[0],
+            None => 0,
+        };
+        let b = next as i64;
+        let frac = ((self.position >> 16) & 0xFFFF) as i64;
+        self.position += self.step;
+        (a + (((b - a) * frac) >> 16)) as i32 * self.volume / UNITY
+    }
+}
+
+pub struct Mixer {
+    rate: u32,
+    voices: Vec<Voice>,
+    next_id: VoiceId,
+    volume: i32,
+}
+
+impl Mixer {
+    pub const fn new(rate: u32) -> Self {
+        Self {
+            rate,
+            voices: Vec::new(),
+            next_id: 0,
+            volume: UNITY,
+        }
+    }
+
+    pub fn rate(&self) -> u32 {
+        self.rate
+    }
+
+    // when the card ends up running at something else
+    pub fn set_rate(&mut self, rate: u32) {
+        self.rate = rate;
+        for voice in &mut self.voices {
+            voice.step = step(voice.sound.rate, rate);
+        }
+    }
+
+    // 0 is silent, 1 as loud as the sound itself. with every voice taken the oldest one
+    // makes room
+    pub fn play(&mut self, sound: Arc<Sound>, volume: f32, looping: bool) -> VoiceId {
+        if self.voices.len() >= MAX_VOICES {
+            self.voices.remove(0);
+        }
+        let id = self.next_id;
+        self.next_id += 1;
+        self.voices.push(Voice {
+            id,
+            step: step(sound.rate, self.rate),
+            sound,
+            position: 0,
+            volume: to_fixed(volume),
+            looping,
+            done: false,
+        });
+        id
+    }
+
+    pub fn stop(&mut self, id: VoiceId) {
+        self.voices.retain(|x| x.id != id);
+    }
+
+    pub fn stop_all(&mut self) {
+        self.voices.clear();
+    }
+
+    pub fn is_playing(&self, id: VoiceId) -> bool {
+        self.voices.iter().any(|x| x.id == id)
+    }
+
+    pub fn voices(&self) -> usize {
+        self.voices.len()
+    }
+
+    pub fn volume(&self) -> f32 {
+        self.volume as f32 / UNITY as f32
+    }
+
+    pub fn set_volume(&mut self, volume: f32) {
+        self.volume = to_fixed(volume);
+    }
+
+    // fills `out` (interleaved, CHANNELS per frame), silence when nothing's playing
+    pub fn mix(&mut self, out: &mut [i16]) {
+        for frame in out.as_chunks_mut::<CHANNELS>().0 {
+            let sum = self.voices.iter_mut().map(|x| x.next()).sum::<i32>();
+            let sample = (sum * self.volume / UNITY).clamp(i16::MIN as i32, i16::MAX as i32);
+            frame.fill(sample as i16);
+        }
+        self.voices.retain(|x| !x.done);
+    }
+}
+
+fn step(from: u32, to: u32) -> u64 {
+    ((from as u64) << 32) / to.max(1) as u64
+}
+
+// anything past twice as loud just clips
+fn to_fixed(volume: f32) -> i32 {
+    (volume.clamp(0.0, 2.0) * UNITY as f32) as i32
+}
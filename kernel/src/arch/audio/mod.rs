@@ -0,0 +1,179 @@
+/*
+    Copyright (C) 2025 bugo07
+    Released under EUPL 1.2 License
+*/
+
+// sound. the wavs under sounds/ in the asset registry get decoded once and played by
+// name through the mixer onto the ac'97 card, and the pc speaker beeps on any machine.
+// playing only touches the mixer and queues beeps, the audio task does the port io,
+// feeding the card and turning the speaker off again
+
+pub mod ac97;
+pub mod mixer;
+pub mod speaker;
+
+use core::{future::poll_fn, task::Poll, time::Duration};
+
+use alloc::{collections::btree_map::BTreeMap, string::String, sync::Arc, vec::Vec};
+
+use crate::{
+    arch::time::{preferred_timer_ns, sleep},
+    assets::{self, SOUND_DIR},
+    info,
+    utils::{cmdline, executor::AtomicWaker, wav},
+    warn,
+};
+
+use self::{
+    ac97::Ac97,
+    mixer::{Mixer, Sound, VoiceId},
+};
+
+// well under how far ahead the card is fed
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AudioError {
+    // the card's registers aren't where they should be
+    NoBar,
+    Timeout,
+    Dma(&'static str),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tone {
+    pub frequency: u32,
+    pub ms: u32,
+}
+
+impl Tone {
+    pub const fn new(frequency: u32, ms: u32) -> Self {
+        Self { frequency, ms }
+    }
+}
+
+static MIXER: spin::Mutex<Mixer> = spin::Mutex::new(Mixer::new(ac97::RATE));
+static CARD: spin::Mutex<Option<Ac97>> = spin::Mutex::new(None);
+static SOUNDS: spin::RwLock<BTreeMap<String, Arc<Sound>>> = spin::RwLock::new(BTreeMap::new());
+static BEEP: spin::Mutex<Option<Tone>> = spin::Mutex::new(None);
+static WAKER: AtomicWaker = AtomicWaker::new();
+
+// after assets::init, needs pci
+pub fn init() {
+    let mut sounds = SOUNDS.write();
+    for (name, _, _) in assets::list() {
+        let Some(file) = name.strip_prefix(SOUND_DIR) else {
+            continue;
+        };
+        let stem = file.rsplit_once('.').map_or(file, |(stem, _)| stem);
+        match wav::decode(assets::get(&name).unwrap_or(&[])) {
+            Ok(wav) => {
+                sounds.insert(
+                    String::from(stem),
+                    Arc::new(Sound::new(wav.rate, wav.samples)),
+                );
+            }
+            Err(err) => warn!("skipping sound {name}: {err:?}"),
+        }
+    }
+    info!("{} sounds", sounds.len());
+
+    let mut mixer = MIXER.lock();
+    mixer.set_volume(cmdline::config().volume as f32 / 100.0);
+    match ac97::probe() {
+        Some(Ok(card)) => {
+            info!("ac97 {} at {} hz", card.device, card.rate());
+            mixer.set_rate(card.rate());
+            *CARD.lock() = Some(card);
+        }
+        Some(Err(err)) => warn!("ac97 didn't come up ({err:?}), only the pc speaker works"),
+        None => info!("no sound card, only the pc speaker works"),
+    }
+}
+
+pub fn has_card() -> bool {
+    CARD.lock().is_some()
+}
+
+// starts a sound by name (its file name under sounds/ without the .wav), None with no
+// card or no such sound. `volume` is 0 to 1, looping ones play until stopped
+pub fn play(name: &str, volume: f32, looping: bool) -> Option<VoiceId> {
+    if !has_card() {
+        return None;
+    }
+    let sound = SOUNDS.read().get(name).cloned()?;
+    Some(MIXER.lock().play(sound, volume, looping))
+}
+
+pub fn stop(voice: VoiceId) {
+    MIXER.lock().stop(voice);
+}
+
+pub fn is_playing(voice: VoiceId) -> bool {
+    MIXER.lock().is_playing(voice)
+}
+
+pub fn voices() -> usize {
+    MIXER.lock().voices()
+}
+
+pub fn volume() -> f32 {
+    MIXER.lock().volume()
+}
+
+// for everything the mixer plays, the speaker has no volume
+pub fn set_volume(volume: f32) {
+    MIXER.lock().set_volume(volume);
+}
+
+// replaces whatever the speaker's playing
+pub fn beep(tone: Tone) {
+    *BEEP.lock() = Some(tone);
+    WAKER.wake();
+}
+
+// names and lengths in ms
+pub fn sounds() -> Vec<(String, u64)> {
+    SOUNDS
+        .read()
+        .iter()
+        .map(|(name, sound)| (name.clone(), sound.duration_ms()))
+        .collect()
+}
+
+pub async fn audio_task() {
+    let mut beep_until = None;
+    loop {
+        if let Some(tone) = BEEP.lock().take() {
+            speaker::tone(tone.frequency);
+            beep_until = Some(preferred_timer_ns() + tone.ms as u64 * 1_000_000);
+        }
+        if beep_until.is_some_and(|x| preferred_timer_ns() >= x) {
+            speaker::off();
+            beep_until = None;
+        }
+
+        let fed = match CARD.lock().as_mut() {
+            Some(card) => {
+                card.pump(&mut MIXER.lock());
+                true
+            }
+            None => false,
+        };
+
+        // the card needs feeding and the speaker turning off, otherwise wait for a beep
+        if fed || beep_until.is_some() {
+            sleep(POLL_INTERVAL).await;
+        } else {
+            poll_fn(|cx| {
+                WAKER.register(cx.waker());
+                if BEEP.lock().is_some() {
+                    Poll::Ready(())
+                } else {
+                    Poll::Pending
+                }
+            })
+            .await;
+        }
+    }
+}
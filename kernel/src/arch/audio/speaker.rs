@@ -0,0 +1,36 @@
+/*
+    Copyright (C) 2025 bugo07
+    Released under EUPL 1.2 License
+*/
+
+// the pc speaker, a square wave from pit channel 2 gated through port 0x61. one tone at
+// a time and no volume, but every machine (and qemu with pcspk-audiodev) has one
+
+use crate::{
+    arch::time::pit::PIT_FREQUENCY,
+    utils::asm::{inb, outb},
+};
+
+const PIT_CHANNEL_2: u16 = 0x42;
+const PIT_COMMAND: u16 = 0x43;
+const SPEAKER_CONTROL: u16 = 0x61;
+
+// channel 2, lobyte/hibyte, square wave
+const PIT_SQUARE_WAVE: u8 = 0b1011_0110;
+// gate for channel 2 and the speaker data line
+const SPEAKER_ON: u8 = 0b11;
+
+pub const MIN_FREQUENCY: u32 = 20;
+pub const MAX_FREQUENCY: u32 = 20_000;
+
+pub fn tone(frequency: u32) {
+    let divisor = PIT_FREQUENCY / frequency.clamp(MIN_FREQUENCY, MAX_FREQUENCY);
+    outb(PIT_COMMAND, PIT_SQUARE_WAVE);
+    outb(PIT_CHANNEL_2, divisor as u8);
+    outb(PIT_CHANNEL_2, (divisor >> 8) as u8);
+    outb(SPEAKER_CONTROL, inb(SPEAKER_CONTROL) | SPEAKER_ON);
+}
+
+pub fn off() {
+    outb(SPEAKER_CONTROL, inb(SPEAKER_CONTROL) & !SPEAKER_ON);
+}
@@ -0,0 +1,73 @@
+/*
+    Copyright (C) 2025 bugo07
+    Released under EUPL 1.2 License
+*/
+
+// the bochs display adapter, what qemu's -vga std (and bochs, and virtualbox) shows up
+// as. its dispi registers can change the mode any time, unlike the firmware limine got
+// its modes from. the linear framebuffer stays where it was
+
+use crate::{
+    arch::pci,
+    utils::{
+        asm::{inw, outw},
+        video::VideoError,
+    },
+};
+
+const DISPI_INDEX: u16 = 0x01CE;
+const DISPI_DATA: u16 = 0x01CF;
+
+const REG_ID: u16 = 0;
+const REG_XRES: u16 = 1;
+const REG_YRES: u16 = 2;
+const REG_BPP: u16 = 3;
+const REG_ENABLE: u16 = 4;
+const REG_VIRT_WIDTH: u16 = 6;
+const REG_VIRT_HEIGHT: u16 = 7;
+const REG_X_OFFSET: u16 = 8;
+const REG_Y_OFFSET: u16 = 9;
+
+const ENABLED: u16 = 0x01;
+const LFB_ENABLED: u16 = 0x40;
+
+// every version since the one that added the linear framebuffer
+const IDS: core::ops::RangeInclusive<u16> = 0xB0C2..=0xB0CF;
+
+// qemu/bochs and virtualbox
+const DEVICES: [(u16, u16); 2] = [(0x1234, 0x1111), (0x80EE, 0xBEEF)];
+
+fn read(register: u16) -> u16 {
+    outw(DISPI_INDEX, register);
+    inw(DISPI_DATA)
+}
+
+fn write(register: u16, value: u16) {
+    outw(DISPI_INDEX, register);
+    outw(DISPI_DATA, value);
+}
+
+pub fn available() -> bool {
+    pci::enumerate()
+        .iter()
+        .any(|x| DEVICES.contains(&(x.vendor_id, x.device_id)))
+        && IDS.contains(&read(REG_ID))
+}
+
+pub fn set_mode(width: u16, height: u16, bpp: u16) -> Result<(), VideoError> {
+    write(REG_ENABLE, 0);
+    write(REG_XRES, width);
+    write(REG_YRES, height);
+    write(REG_BPP, bpp);
+    write(REG_VIRT_WIDTH, width);
+    write(REG_VIRT_HEIGHT, height);
+    write(REG_X_OFFSET, 0);
+    write(REG_Y_OFFSET, 0);
+    write(REG_ENABLE, ENABLED | LFB_ENABLED);
+
+    // it clamps to what its video memory holds
+    if read(REG_XRES) != width || read(REG_YRES) != height || read(REG_BPP) != bpp {
+        return Err(VideoError::Rejected);
+    }
+    Ok(())
+}
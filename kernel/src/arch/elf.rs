@@ -0,0 +1,490 @@
+/*
+    Copyright (C) 2025 bugo07
+    Released under EUPL 1.2 License
+*/
+
+// loads static elf64 games from limine modules and runs them in ring 3. a program
+// gets a sysv-style stack (argc, argv, envp) and a pointer to `ProgramApi` in rdi. the
+// api and the syscall stubs it points to sit in a read-only page of the program's own,
+// it can't see anything of the kernel's
+
+use core::{
+    alloc::Layout,
+    future::poll_fn,
+    sync::atomic::{AtomicU64, Ordering},
+    task::Poll,
+};
+
+use alloc::{collections::btree_map::BTreeMap, string::String, vec::Vec};
+
+use crate::{
+    arch::{
+        mem::{PAGE_SIZE, USER_SPACE_END, map_user_page, unmap_page, virt_to_phys},
+        syscall::{SYS_EXIT, SYS_FB_BLIT, SYS_INPUT_POLL, SYS_TIME, SYS_WRITE, SYS_YIELD},
+        thread::{self, ThreadId},
+    },
+    error, info,
+    utils::{bootloader::get_modules, executor::AtomicWaker, video},
+    warn,
+};
+
+const ELF_MAGIC: [u8; 4] = *b"\x7fELF";
+const ELFCLASS64: u8 = 2;
+const ELFDATA2LSB: u8 = 1;
+const ET_EXEC: u16 = 2;
+const EM_X86_64: u16 = 0x3E;
+const PT_LOAD: u32 = 1;
+const PF_X: u32 = 1;
+const PF_W: u32 = 2;
+
+const USER_STACK_TOP: u64 = 0x0000_7FFF_FFFF_0000;
+const USER_STACK_SIZE: u64 = 64 * 1024;
+// right above the stack
+const API_PAGE: u64 = USER_STACK_TOP;
+const STUBS_OFFSET: u64 = 0x100;
+const PROGRAM_PRIORITY: u8 = 1;
+const NONE: u64 = u64::MAX;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct ElfHeader {
+    ident: [u8; 16],
+    kind: u16,
+    machine: u16,
+    version: u32,
+    entry: u64,
+    phoff: u64,
+    shoff: u64,
+    flags: u32,
+    ehsize: u16,
+    phentsize: u16,
+    phnum: u16,
+    shentsize: u16,
+    shnum: u16,
+    shstrndx: u16,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct ProgramHeader {
+    kind: u32,
+    flags: u32,
+    offset: u64,
+    vaddr: u64,
+    paddr: u64,
+    filesz: u64,
+    memsz: u64,
+    align: u64,
+}
+
+#[derive(Debug)]
+pub enum ElfError {
+    TooSmall,
+    BadMagic,
+    Unsupported(&'static str),
+    BadSegment(usize),
+}
+
+pub struct Program {
+    pub name: String,
+    pub data: &'static [u8],
+}
+
+// handed to programs in rdi. the functions are syscall stubs in the same page, called
+// like the extern "C" fns in the comments
+#[repr(C)]
+pub struct ProgramApi {
+    pub version: u32,
+    pub fb_width: u32,
+    pub fb_height: u32,
+    pub fb_blit: u64, // fn(*const u32, x: u32, y: u32, width: u32, height: u32) -> u64
+    pub input_poll: u64, // fn() -> u64
+    pub time_ns: u64, // fn() -> u64
+    pub yield_now: u64, // fn()
+    pub write: u64,   // fn(*const u8, len: u64) -> u64
+    pub exit: u64,    // fn(i64) -> !
+}
+
+// number in eax, the sysv argument registers already line up with the syscall ones
+// except the 4th (rcx, which syscall clobbers) going to r10
+const fn stub(number: u8) -> [u8; 8] {
+    [0xB8, number, 0, 0, 0, 0x0F, 0x05, 0xC3] // mov eax, number; syscall; ret
+}
+
+// the u32 arguments get zero extended first, sysv leaves the upper halves undefined
+// and the screen index rides in the top of x
+const FB_BLIT_STUB: [u8; 18] = [
+    0x89,
+    0xF6, // mov esi, esi
+    0x89,
+    0xD2, // mov edx, edx
+    0x41,
+    0x89,
+    0xCA, // mov r10d, ecx
+    0x45,
+    0x89,
+    0xC0, // mov r8d, r8d
+    0xB8,
+    SYS_FB_BLIT as u8,
+    0,
+    0,
+    0, // mov eax, SYS_FB_BLIT
+    0x0F,
+    0x05, // syscall
+    0xC3, // ret
+];
+
+// writes the api and its stubs into the program's api page
+fn write_api(loaded: &mut LoadedProgram) -> u64 {
+    let mut stubs = Vec::new();
+    let mut add = |code: &[u8]| {
+        let addr = API_PAGE + STUBS_OFFSET + stubs.len() as u64;
+        stubs.extend_from_slice(code);
+        addr
+    };
+
+    let screen = video::screen().map(|x| x.size).unwrap_or_default();
+    let api = ProgramApi {
+        version: 1,
+        fb_width: screen.x,
+        fb_height: screen.y,
+        fb_blit: add(&FB_BLIT_STUB),
+        input_poll: add(&stub(SYS_INPUT_POLL as u8)),
+        time_ns: add(&stub(SYS_TIME as u8)),
+        yield_now: add(&stub(SYS_YIELD as u8)),
+        write: add(&stub(SYS_WRITE as u8)),
+        // ud2 after it, exit doesn't come back
+        exit: add(&[0xB8, SYS_EXIT as u8, 0, 0, 0, 0x0F, 0x05, 0x0F, 0x0B]),
+    };
+
+    let page = loaded.page(API_PAGE);
+    page.executable = true;
+    let bytes = unsafe {
+        core::slice::from_raw_parts(&raw const api as *const u8, size_of::<ProgramApi>())
+    };
+    loaded.write(API_PAGE, bytes);
+    loaded.write(API_PAGE + STUBS_OFFSET, &stubs);
+    API_PAGE
+}
+
+static PROGRAMS: spin::Once<Vec<Program>> = spin::Once::new();
+static EXEC_REQUEST: AtomicU64 = AtomicU64::new(NONE);
+static EXEC_PARENT: AtomicU64 = AtomicU64::new(NONE);
+static RUNNING_CHILD: AtomicU64 = AtomicU64::new(NONE);
+static CHILD_EXITED: AtomicU64 = AtomicU64::new(NONE);
+static LOADER_WAKER: AtomicWaker = AtomicWaker::new();
+static EXECS_DONE: AtomicU64 = AtomicU64::new(0);
+static EXEC_WAKER: AtomicWaker = AtomicWaker::new();
+
+pub fn init() {
+    info!("scanning boot modules...");
+    PROGRAMS.call_once(|| {
+        let mut programs = Vec::new();
+        // assets go to the asset registry
+        for module in get_modules().iter().filter(|x| !crate::assets::is_asset(x)) {
+            let data =
+                unsafe { core::slice::from_raw_parts(module.addr(), module.size() as usize) };
+            let path = module.path().to_str().unwrap_or("?");
+            let name = match module.string().to_str() {
+                Ok(cmdline) if !cmdline.is_empty() => String::from(cmdline),
+                _ => String::from(path.rsplit('/').next().unwrap_or(path)),
+            };
+
+            match validate(data) {
+                Ok(_) => {
+                    info!("found game {name} ({path}, {} bytes)", data.len());
+                    programs.push(Program { name, data });
+                }
+                Err(err) => warn!("skipping module {path}: {err:?}"),
+            }
+        }
+        programs
+    });
+}
+
+pub fn programs() -> &'static [Program] {
+    PROGRAMS.get().map(|x| x.as_slice()).unwrap_or(&[])
+}
+
+fn read<T: Copy>(data: &[u8], offset: u64) -> Option<T> {
+    let end = offset.checked_add(size_of::<T>() as u64)?;
+    if end > data.len() as u64 {
+        return None;
+    }
+    Some(unsafe { core::ptr::read_unaligned(data.as_ptr().add(offset as usize) as *const T) })
+}
+
+fn validate(data: &[u8]) -> Result<(ElfHeader, Vec<ProgramHeader>), ElfError> {
+    let header: ElfHeader = read(data, 0).ok_or(ElfError::TooSmall)?;
+
+    if header.ident[0..4] != ELF_MAGIC {
+        return Err(ElfError::BadMagic);
+    }
+    if header.ident[4] != ELFCLASS64 || header.ident[5] != ELFDATA2LSB {
+        return Err(ElfError::Unsupported("not a little endian elf64"));
+    }
+    if header.kind != ET_EXEC {
+        return Err(ElfError::Unsupported("not a static executable"));
+    }
+    if header.machine != EM_X86_64 {
+        return Err(ElfError::Unsupported("not x86_64"));
+    }
+    if header.phentsize as usize != size_of::<ProgramHeader>() {
+        return Err(ElfError::Unsupported("weird program header size"));
+    }
+    if header.entry >= USER_SPACE_END {
+        return Err(ElfError::Unsupported("entry point outside of user space"));
+    }
+
+    let mut segments = Vec::new();
+    for i in 0..header.phnum as u64 {
+        let ph: ProgramHeader = read(data, header.phoff + i * header.phentsize as u64)
+            .ok_or(ElfError::BadSegment(i as usize))?;
+        if ph.kind != PT_LOAD {
+            continue;
+        }
+
+        let in_file = ph
+            .offset
+            .checked_add(ph.filesz)
+            .is_some_and(|end| end <= data.len() as u64);
+        let in_user = ph
+            .vaddr
+            .checked_add(ph.memsz)
+            .is_some_and(|end| end <= USER_STACK_TOP - USER_STACK_SIZE);
+        if ph.filesz > ph.memsz || !in_file || !in_user {
+            return Err(ElfError::BadSegment(i as usize));
+        }
+        segments.push(ph);
+    }
+
+    if segments.is_empty() {
+        return Err(ElfError::Unsupported("nothing to load"));
+    }
+    Ok((header, segments))
+}
+
+struct Page {
+    frame: *mut u8, // kernel address of the backing memory
+    writable: bool,
+    executable: bool,
+}
+
+fn frame_layout() -> Layout {
+    Layout::from_size_align(PAGE_SIZE as usize, PAGE_SIZE as usize).unwrap()
+}
+
+// everything a running program has mapped, unmapped and freed once it exits
+struct LoadedProgram {
+    pages: BTreeMap<u64, Page>,
+}
+
+// the frames are only touched by whoever owns this
+unsafe impl Send for LoadedProgram {}
+
+impl LoadedProgram {
+    fn page(&mut self, vaddr: u64) -> &mut Page {
+        self.pages.entry(vaddr).or_insert_with(|| Page {
+            frame: unsafe { alloc::alloc::alloc_zeroed(frame_layout()) },
+            writable: false,
+            executable: false,
+        })
+    }
+
+    fn write(&mut self, mut vaddr: u64, mut bytes: &[u8]) {
+        while !bytes.is_empty() {
+            let offset = vaddr % PAGE_SIZE;
+            let len = bytes.len().min((PAGE_SIZE - offset) as usize);
+            let page = self.page(vaddr - offset);
+            unsafe {
+                core::ptr::copy_nonoverlapping(bytes.as_ptr(), page.frame.add(offset as usize), len)
+            };
+            vaddr += len as u64;
+            bytes = &bytes[len..];
+        }
+    }
+
+    fn map(&self) {
+        for (vaddr, page) in &self.pages {
+            let phys = virt_to_phys(page.frame as u64).unwrap();
+            map_user_page(*vaddr, phys, page.writable, page.executable);
+        }
+    }
+}
+
+impl Drop for LoadedProgram {
+    fn drop(&mut self) {
+        for (vaddr, page) in &self.pages {
+            unmap_page(*vaddr);
+            unsafe { alloc::alloc::dealloc(page.frame, frame_layout()) };
+        }
+    }
+}
+
+fn load(program: &'static Program) -> Result<(LoadedProgram, ThreadId), ElfError> {
+    let (header, segments) = validate(program.data)?;
+    let mut loaded = LoadedProgram {
+        pages: BTreeMap::new(),
+    };
+
+    for ph in &segments {
+        let start = ph.vaddr & !(PAGE_SIZE - 1);
+        for vaddr in (start..ph.vaddr + ph.memsz).step_by(PAGE_SIZE as usize) {
+            // segments sharing a page get the union of their permissions
+            let page = loaded.page(vaddr);
+            page.writable |= ph.flags & PF_W != 0;
+            page.executable |= ph.flags & PF_X != 0;
+        }
+        let file = &program.data[ph.offset as usize..(ph.offset + ph.filesz) as usize];
+        loaded.write(ph.vaddr, file); // the rest up to memsz is already zeroed
+    }
+
+    for vaddr in (USER_STACK_TOP - USER_STACK_SIZE..USER_STACK_TOP).step_by(PAGE_SIZE as usize) {
+        loaded.page(vaddr).writable = true;
+    }
+    let rsp = build_stack(&mut loaded, &[program.name.as_str()], &["OS=flappyos"]);
+    let api = write_api(&mut loaded);
+
+    loaded.map();
+    let id = thread::spawn_user_at(
+        program.name.as_str(),
+        PROGRAM_PRIORITY,
+        header.entry,
+        rsp,
+        api,
+    );
+    Ok((loaded, id))
+}
+
+// lays out strings, then envp, argv and argc downwards from the stack top, returns the new rsp
+fn build_stack(loaded: &mut LoadedProgram, argv: &[&str], envp: &[&str]) -> u64 {
+    let mut sp = USER_STACK_TOP;
+    let mut push_str = |loaded: &mut LoadedProgram, s: &str| {
+        sp -= s.len() as u64 + 1;
+        loaded.write(sp, s.as_bytes());
+        loaded.write(sp + s.len() as u64, &[0]);
+        sp
+    };
+
+    let argv_ptrs = argv.iter().map(|s| push_str(loaded, s)).collect::<Vec<_>>();
+    let envp_ptrs = envp.iter().map(|s| push_str(loaded, s)).collect::<Vec<_>>();
+
+    let mut words = Vec::new();
+    words.push(argv.len() as u64);
+    words.extend(&argv_ptrs);
+    words.push(0);
+    words.extend(&envp_ptrs);
+    words.push(0);
+
+    // rsp has to be 16 byte aligned at the entry point
+    let mut sp = (sp - words.len() as u64 * 8) & !0xF;
+    let base = sp;
+    for word in words {
+        loaded.write(sp, &word.to_le_bytes());
+        sp += 8;
+    }
+    base
+}
+
+// called from the exec syscall, the caller stays blocked until the program exits
+pub fn request_exec(index: u64) -> bool {
+    if !queue_exec(index, thread::current_id()) {
+        return false;
+    }
+    thread::block_current();
+    true
+}
+
+// for tasks, resolves once the program exited
+pub async fn exec(index: usize) -> bool {
+    let done = EXECS_DONE.load(Ordering::Acquire);
+    if !queue_exec(index as u64, NONE) {
+        return false;
+    }
+
+    poll_fn(|cx| {
+        EXEC_WAKER.register(cx.waker());
+        if EXECS_DONE.load(Ordering::Acquire) != done {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    })
+    .await;
+    true
+}
+
+fn queue_exec(index: u64, parent: ThreadId) -> bool {
+    if index as usize >= programs().len() || RUNNING_CHILD.load(Ordering::Acquire) != NONE {
+        return false;
+    }
+    if EXEC_REQUEST
+        .compare_exchange(NONE, index, Ordering::AcqRel, Ordering::Acquire)
+        .is_err()
+    {
+        return false;
+    }
+
+    EXEC_PARENT.store(parent, Ordering::Release);
+    LOADER_WAKER.wake();
+    true
+}
+
+// lets whoever asked for the program carry on
+fn finish_exec() {
+    thread::wake(EXEC_PARENT.swap(NONE, Ordering::AcqRel));
+    EXECS_DONE.fetch_add(1, Ordering::Release);
+    EXEC_WAKER.wake();
+}
+
+// called from the exit syscall
+pub fn on_exit(id: ThreadId) {
+    if RUNNING_CHILD.load(Ordering::Acquire) == id {
+        CHILD_EXITED.store(id, Ordering::Release);
+        LOADER_WAKER.wake();
+    }
+}
+
+// does the actual loading, outside of the syscall since that can't touch the allocator
+pub async fn loader_task() {
+    let mut running: Option<LoadedProgram> = None;
+
+    loop {
+        poll_fn(|cx| {
+            LOADER_WAKER.register(cx.waker());
+            if EXEC_REQUEST.load(Ordering::Acquire) != NONE
+                || CHILD_EXITED.load(Ordering::Acquire) != NONE
+            {
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        })
+        .await;
+
+        if CHILD_EXITED.swap(NONE, Ordering::AcqRel) != NONE {
+            drop(running.take());
+            RUNNING_CHILD.store(NONE, Ordering::Release);
+            finish_exec();
+        }
+
+        let index = EXEC_REQUEST.load(Ordering::Acquire);
+        if index == NONE {
+            continue;
+        }
+
+        let program = &programs()[index as usize];
+        info!("loading {}", program.name);
+        match load(program) {
+            Ok((loaded, id)) => {
+                running = Some(loaded);
+                RUNNING_CHILD.store(id, Ordering::Release);
+            }
+            Err(err) => {
+                error!("failed to load {}: {err:?}", program.name);
+                finish_exec();
+            }
+        }
+        EXEC_REQUEST.store(NONE, Ordering::Release);
+    }
+}
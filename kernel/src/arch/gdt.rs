@@ -137,8 +137,8 @@ impl TssEntry {
             reserved: 0,
         }
     }
-    fn tss_segment(tss: &'static TaskStateSegment) -> Self {
-        let base = &raw const *tss as u64;
+    fn tss_segment(tss: *const TaskStateSegment) -> Self {
+        let base = tss as u64;
         let limit = size_of::<TaskStateSegment>() as u32 - 1;
 
         TssEntry {
@@ -161,8 +161,8 @@ pub struct GlobalDescriptorTable {
     pub kernel_code: GdtEntry,
     kernel_data: GdtEntry,
     pub user_code_32bit: GdtEntry,
+    user_data: GdtEntry, // sysret wants user data right before 64 bit user code
     user_code: GdtEntry,
-    user_data: GdtEntry,
     tss: TssEntry,
 }
 
@@ -179,14 +179,14 @@ impl GlobalDescriptorTable {
             kernel_code: GdtEntry::kernel_code(),
             kernel_data: GdtEntry::kernel_data(),
             user_code_32bit: GdtEntry::user_code_32bit(),
-            user_code: GdtEntry::user_code(),
             user_data: GdtEntry::user_data(),
+            user_code: GdtEntry::user_code(),
             tss: TssEntry::null(),
         }
     }
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug)]
 #[repr(C, packed)]
 pub struct TaskStateSegment {
     reserved0: u32,
@@ -206,18 +206,35 @@ pub struct TaskStateSegment {
     io_map_base_address: u16,
 }
 
-lazy_static::lazy_static! {
-    static ref TSS: TaskStateSegment = TaskStateSegment {
-        rsp0: unsafe {
-            alloc::alloc::alloc(Layout::from_size_align(KERNEL_STACK_SIZE, 0x8).unwrap()) as u64
-                + KERNEL_STACK_SIZE as u64
-        },
-        ..Default::default()
-    };
+impl TaskStateSegment {
+    const fn new() -> Self {
+        TaskStateSegment {
+            reserved0: 0,
+            rsp0: 0,
+            rsp1: 0,
+            rsp2: 0,
+            reserved1: 0,
+            ist1: 0,
+            ist2: 0,
+            ist3: 0,
+            ist4: 0,
+            ist5: 0,
+            ist6: 0,
+            ist7: 0,
+            reserved2: 0,
+            reserved3: 0,
+            io_map_base_address: 0,
+        }
+    }
+}
+
+// mutable so the scheduler can point rsp0 at the running thread's kernel stack
+static mut TSS: TaskStateSegment = TaskStateSegment::new();
 
+lazy_static::lazy_static! {
     pub static ref GDT: GlobalDescriptorTable = {
         let mut gdt = GlobalDescriptorTable::new();
-        gdt.tss = TssEntry::tss_segment(&TSS);
+        gdt.tss = TssEntry::tss_segment(&raw const TSS);
         gdt
     };
 
@@ -226,28 +243,37 @@ lazy_static::lazy_static! {
         base: &raw const *GDT as u64,
     };
 
-    static ref SELECTORS: Selectors = {
+    pub static ref SELECTORS: Selectors = {
         Selectors {
             kernel_code: SegmentSelector::new(1, 0, 0),
             kernel_data: SegmentSelector::new(2, 0, 0),
-            _user_code_32bit: SegmentSelector::new(3, 0, 3),
-            _user_code: SegmentSelector::new(4, 0, 3),
-            _user_data: SegmentSelector::new(5, 0, 3),
+            user_code_32bit: SegmentSelector::new(3, 0, 3),
+            user_data: SegmentSelector::new(4, 0, 3),
+            user_code: SegmentSelector::new(5, 0, 3),
             tss: SegmentSelector::new(6, 0, 0),
         }
     };
 }
 
-struct Selectors {
-    kernel_code: SegmentSelector,
-    kernel_data: SegmentSelector,
-    _user_code_32bit: SegmentSelector,
-    _user_code: SegmentSelector,
-    _user_data: SegmentSelector,
-    tss: SegmentSelector,
+pub struct Selectors {
+    pub kernel_code: SegmentSelector,
+    pub kernel_data: SegmentSelector,
+    pub user_code_32bit: SegmentSelector,
+    pub user_data: SegmentSelector,
+    pub user_code: SegmentSelector,
+    pub tss: SegmentSelector,
+}
+
+pub fn set_kernel_stack(rsp0: u64) {
+    unsafe { TSS.rsp0 = rsp0 };
 }
 
 pub fn init() {
+    set_kernel_stack(unsafe {
+        alloc::alloc::alloc(Layout::from_size_align(KERNEL_STACK_SIZE, 0x8).unwrap()) as u64
+            + KERNEL_STACK_SIZE as u64
+    });
+
     unsafe {
         info!("loading gdt");
         asm!(
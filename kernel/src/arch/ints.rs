@@ -5,6 +5,8 @@
 
 use core::arch::asm;
 
+use crate::error;
+
 #[repr(C, packed)]
 #[derive(Default, Debug, Clone, Copy, PartialEq, PartialOrd)]
 pub struct StackFrame {
@@ -81,6 +83,7 @@ impl IdtEntry {
 core::arch::global_asm! {
     r#"
 .extern isr_handler
+.global isr_common_stub
 isr_common_stub:
     cld
 
@@ -102,6 +105,7 @@ isr_common_stub:
 
     mov rdi, rsp
     call isr_handler
+    mov rsp, rax
 
     pop r15
     pop r14
@@ -197,28 +201,52 @@ const EXCEPTION_NAMES: [&str; 32] = [
     "reserved",
 ];
 
+// returns the frame to resume, which is a different thread's after a context switch
 #[unsafe(no_mangle)]
-extern "C" fn isr_handler(regs: *mut StackFrame) {
+extern "C" fn isr_handler(regs: *mut StackFrame) -> *mut StackFrame {
     unsafe {
         let registers = &*regs;
 
-        if registers.vector == 14 {
-            let cr2: u64;
-            asm!("mov {}, cr2", out(reg) cr2, options(nomem, nostack, preserves_flags));
-            panic!("page fault at {:#x}, registers:\n\n{:?}", cr2, registers);
-        }
-
-        if registers.vector < 32 {
+        if registers.vector < 32 && registers.cs & 3 == 3 {
+            kill_faulting_program(registers);
+        } else if registers.vector == 14 {
+            panic!("page fault at {:#x}, registers:\n\n{:?}", cr2(), registers);
+        } else if registers.vector < 32 {
             panic!(
                 "exception: {}, registers:\n\n{:?}",
                 EXCEPTION_NAMES[registers.vector as usize], registers
             );
-        }
-
-        if let Some(handler) = HANDLERS[registers.vector as usize] {
+        } else if let Some(handler) = HANDLERS[registers.vector as usize] {
             handler(regs);
         }
     };
+
+    crate::arch::thread::preempt(regs)
+}
+
+fn cr2() -> u64 {
+    let cr2: u64;
+    unsafe { asm!("mov {}, cr2", out(reg) cr2, options(nomem, nostack, preserves_flags)) };
+    cr2
+}
+
+// a crashing program only takes itself down, the scheduler switches away on the way out
+fn kill_faulting_program(registers: &StackFrame) {
+    let id = crate::arch::thread::current_id();
+    let (vector, rip, ec) = (registers.vector, registers.rip, registers.ec);
+    if vector == 14 {
+        error!(
+            "thread {id} killed: page fault at {:#x} (rip {rip:#x}, error {ec:#x})",
+            cr2()
+        );
+    } else {
+        error!(
+            "thread {id} killed: {} (rip {rip:#x}, error {ec:#x})",
+            EXCEPTION_NAMES[vector as usize]
+        );
+    }
+    crate::arch::elf::on_exit(id);
+    crate::arch::thread::kill_current();
 }
 
 unsafe extern "C" {
@@ -237,9 +265,8 @@ pub fn init() {
         }
         IDTR.base = IDT.as_ptr() as u64;
 
-        asm!("cli; lidt [{}]", in(reg) &IDTR, options(readonly, nostack, preserves_flags));
-
-        // HANDLERS[0x80] = Some(crate::arch::system::syscall::syscall_dispatch); // skibidi syscall handler
+        asm!("cli");
+        load_idt();
 
         install_interrupt(0x20, crate::arch::time::pit::timer_interrupt_handler);
         install_interrupt(0x21, crate::arch::keyboard::keyboard_interrupt_handler);
@@ -259,6 +286,12 @@ pub fn init() {
     }
 }
 
+pub fn load_idt() {
+    unsafe {
+        asm!("lidt [{}]", in(reg) &IDTR, options(readonly, nostack, preserves_flags));
+    }
+}
+
 pub fn install_interrupt(vector: u8, func: HandlerFn) {
     unsafe {
         HANDLERS[vector as usize] = Some(func);
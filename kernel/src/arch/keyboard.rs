@@ -3,13 +3,31 @@
     Released under EUPL 1.2 License
 */
 
+use core::{
+    future::poll_fn,
+    sync::atomic::{AtomicU8, AtomicU64, AtomicUsize, Ordering},
+    task::Poll,
+};
+
 use alloc::{collections::vec_deque::VecDeque, vec::Vec};
 use bevy_ecs::prelude::*;
-use pc_keyboard::{KeyCode, Keyboard, ScancodeSet1, layouts::Us104Key};
+use pc_keyboard::{KeyCode, Keyboard, ScancodeSet1, layouts::AnyLayout};
+
+use crate::utils::executor::AtomicWaker;
+
+pub const SCANCODE_NONE: u64 = u64::MAX;
+
+const SCANCODE_BUF_SIZE: usize = 64;
+static SCANCODE_BUF: [AtomicU8; SCANCODE_BUF_SIZE] =
+    [const { AtomicU8::new(0) }; SCANCODE_BUF_SIZE];
+static SCANCODE_HEAD: AtomicUsize = AtomicUsize::new(0);
+static SCANCODE_TAIL: AtomicUsize = AtomicUsize::new(0);
+static SCANCODES_RECEIVED: AtomicU64 = AtomicU64::new(0);
+static KEYBOARD_WAKER: AtomicWaker = AtomicWaker::new();
 
 #[derive(Resource)]
 pub struct KeyboardState {
-    pub keyboard: Keyboard<Us104Key, ScancodeSet1>,
+    pub keyboard: Keyboard<AnyLayout, ScancodeSet1>,
     pub scancodes: VecDeque<u8>,
     pub keys_down: Vec<KeyCode>,
     pub last_keys_down: Vec<KeyCode>,
@@ -40,22 +58,49 @@ impl KeyboardState {
 }
 
 pub fn keyboard_interrupt_handler(_stack_frame: *mut crate::arch::ints::StackFrame) {
-    unsafe {
-        let mut keyboard_state = crate::game::WORLD
-            .get_mut()
-            .unwrap()
-            .get_resource_mut::<KeyboardState>()
-            .unwrap();
-        keyboard_state
-            .scancodes
-            .push_back(crate::utils::asm::inb(0x60))
-    };
+    let scancode = crate::utils::asm::inb(0x60);
+    let head = SCANCODE_HEAD.load(Ordering::Relaxed);
+    let next = (head + 1) % SCANCODE_BUF_SIZE;
+    if next != SCANCODE_TAIL.load(Ordering::Acquire) {
+        SCANCODE_BUF[head].store(scancode, Ordering::Relaxed);
+        SCANCODE_HEAD.store(next, Ordering::Release);
+    }
+    SCANCODES_RECEIVED.fetch_add(1, Ordering::Release);
+    KEYBOARD_WAKER.wake();
     crate::arch::ints::pic::send_eoi(1);
 }
 
+pub fn pop_scancode() -> Option<u8> {
+    let tail = SCANCODE_TAIL.load(Ordering::Relaxed);
+    if tail == SCANCODE_HEAD.load(Ordering::Acquire) {
+        return None;
+    }
+
+    let scancode = SCANCODE_BUF[tail].load(Ordering::Relaxed);
+    SCANCODE_TAIL.store((tail + 1) % SCANCODE_BUF_SIZE, Ordering::Release);
+    Some(scancode)
+}
+
+// resolves on the next keyboard irq
+pub async fn wait_for_scancode() {
+    let start = SCANCODES_RECEIVED.load(Ordering::Acquire);
+    poll_fn(|cx| {
+        KEYBOARD_WAKER.register(cx.waker());
+        if SCANCODES_RECEIVED.load(Ordering::Acquire) != start {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    })
+    .await
+}
+
 // * i can probably do this inside keyboard_interrupt_handler with &mut World
 pub fn keyboard_system(mut keyboard_state: ResMut<KeyboardState>) {
     keyboard_state.last_keys_down = keyboard_state.keys_down.clone();
+    while let Some(scancode) = pop_scancode() {
+        keyboard_state.scancodes.push_back(scancode);
+    }
     if !keyboard_state.scancodes.is_empty() {
         let scancode = keyboard_state.scancodes.pop_front().unwrap();
         if let Ok(Some(key_event)) = keyboard_state.keyboard.add_byte(scancode) {
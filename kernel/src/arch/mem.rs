@@ -3,6 +3,8 @@
     Released under EUPL 1.2 License
 */
 
+use core::sync::atomic::{AtomicBool, Ordering};
+
 use limine::memory_map::EntryType;
 use talc::*;
 
@@ -49,3 +51,253 @@ pub fn init() {
 
     info!("memory setup done");
 }
+
+pub fn heap_stats() -> Counters {
+    *ALLOCATOR.lock().get_counters()
+}
+
+pub const PAGE_SIZE: u64 = 4096;
+
+const PAGE_PRESENT: u64 = 1 << 0;
+const PAGE_WRITABLE: u64 = 1 << 1;
+const PAGE_USER: u64 = 1 << 2;
+const PAGE_HUGE: u64 = 1 << 7;
+const PAGE_NO_EXECUTE: u64 = 1 << 63;
+// one of the bits left to the os, marks tables we allocated and can free again
+const PAGE_OWNED: u64 = 1 << 9;
+const PAGE_ADDR_MASK: u64 = 0x000F_FFFF_FFFF_F000;
+
+pub const USER_SPACE_END: u64 = 0x0000_8000_0000_0000;
+
+const CR4_SMEP: u64 = 1 << 20;
+const CR4_SMAP: u64 = 1 << 21;
+
+// stac/clac are #UD without smap
+static SMAP: AtomicBool = AtomicBool::new(false);
+
+// kernel pages never get the user bit, smep/smap also keep ring 0 from running or
+// touching user pages by accident. cr4 is per cpu, so the aps call this too
+pub fn protect_kernel() {
+    let features = crate::utils::asm::_cpuid_count(7, 0).ebx;
+    let mut enable = 0;
+    if features & (1 << 7) != 0 {
+        enable |= CR4_SMEP;
+    }
+    if features & (1 << 20) != 0 {
+        enable |= CR4_SMAP;
+        SMAP.store(true, Ordering::Relaxed);
+    }
+
+    unsafe {
+        let mut cr4: u64;
+        core::arch::asm!("mov {}, cr4", out(reg) cr4);
+        core::arch::asm!("mov cr4, {}", in(reg) cr4 | enable);
+    }
+}
+
+// lets `f` read and write user pages, only after checking them with `is_user_range`
+pub fn with_user_access<T>(f: impl FnOnce() -> T) -> T {
+    set_user_access(true);
+    let ret = f();
+    set_user_access(false);
+    ret
+}
+
+// ring 3 can set AC itself, so syscalls clear it on the way in too
+pub fn set_user_access(allowed: bool) {
+    if !SMAP.load(Ordering::Relaxed) {
+        return;
+    }
+    unsafe {
+        if allowed {
+            core::arch::asm!("stac", options(nomem, nostack));
+        } else {
+            core::arch::asm!("clac", options(nomem, nostack));
+        }
+    }
+}
+
+// whether ring 3 can touch every byte of [addr, addr + len), for syscall arguments
+pub fn is_user_range(addr: u64, len: u64, writable: bool) -> bool {
+    let Some(end) = addr.checked_add(len) else {
+        return false;
+    };
+    if addr == 0 || end > USER_SPACE_END {
+        return false;
+    }
+
+    let mut needed = PAGE_PRESENT | PAGE_USER;
+    if writable {
+        needed |= PAGE_WRITABLE;
+    }
+    (addr & !(PAGE_SIZE - 1)..end)
+        .step_by(PAGE_SIZE as usize)
+        .all(|page| user_page_flags(page) & needed == needed)
+}
+
+// the flags of every level anded together, user pages are all 4k
+fn user_page_flags(virt: u64) -> u64 {
+    let mut flags = !0;
+    let mut table = pml4_phys();
+    for level in (1..=4).rev() {
+        let entry =
+            unsafe { *((table + get_hhdm_offset()) as *const u64).add(table_index(virt, level)) };
+        flags &= entry;
+        if entry & PAGE_PRESENT == 0 || (level > 1 && entry & PAGE_HUGE != 0) {
+            return 0;
+        }
+        table = entry & PAGE_ADDR_MASK;
+    }
+    flags
+}
+
+fn pml4_phys() -> u64 {
+    let cr3: u64;
+    unsafe { core::arch::asm!("mov {}, cr3", out(reg) cr3) };
+    cr3 & PAGE_ADDR_MASK
+}
+
+fn table_index(virt: u64, level: u8) -> usize {
+    ((virt >> (12 + 9 * (level as u64 - 1))) & 0x1FF) as usize
+}
+
+pub fn virt_to_phys(virt: u64) -> Option<u64> {
+    let mut table = pml4_phys();
+    for level in (1..=4).rev() {
+        let entry =
+            unsafe { *((table + get_hhdm_offset()) as *const u64).add(table_index(virt, level)) };
+        if entry & PAGE_PRESENT == 0 {
+            return None;
+        }
+
+        if level == 1 || (level < 4 && entry & PAGE_HUGE != 0) {
+            let page_mask = (1u64 << (12 + 9 * (level as u64 - 1))) - 1;
+            return Some((entry & PAGE_ADDR_MASK & !page_mask) | (virt & page_mask));
+        }
+        table = entry & PAGE_ADDR_MASK;
+    }
+    None
+}
+
+// maps a 4k page for ring 3. missing tables get allocated and huge pages in the way
+// (limine's identity map of low memory) get split, the rest of them stays as it was
+pub fn map_user_page(virt: u64, phys: u64, writable: bool, executable: bool) {
+    let mut flags = PAGE_USER;
+    if writable {
+        flags |= PAGE_WRITABLE;
+    }
+    if !executable {
+        flags |= PAGE_NO_EXECUTE;
+    }
+    map_page(virt, phys, flags);
+}
+
+// clears the mapping, tables we allocated go back to the heap once nothing's left in them
+pub fn unmap_page(virt: u64) {
+    let mut entries = [core::ptr::null_mut::<u64>(); 4];
+    let mut table = pml4_phys();
+    for level in (1..=4).rev() {
+        unsafe {
+            let entry = ((table + get_hhdm_offset()) as *mut u64).add(table_index(virt, level));
+            if *entry & PAGE_PRESENT == 0 || (level > 1 && *entry & PAGE_HUGE != 0) {
+                return;
+            }
+            entries[level as usize - 1] = entry;
+            table = *entry & PAGE_ADDR_MASK;
+        }
+    }
+
+    unsafe {
+        *entries[0] = 0;
+        // the pml4 itself stays
+        for level in 2..=4 {
+            let parent = entries[level - 1];
+            let child = ((*parent & PAGE_ADDR_MASK) + get_hhdm_offset()) as *mut u64;
+            if *parent & PAGE_OWNED == 0 || (0..512).any(|i| *child.add(i) != 0) {
+                break;
+            }
+            *parent = 0;
+            alloc::alloc::dealloc(child as *mut u8, table_layout());
+        }
+        // also drops whatever the cpu cached about the freed tables
+        core::arch::asm!("invlpg [{}]", in(reg) virt, options(nostack));
+    }
+}
+
+// zeroed memory for a device to dma to and from. it's never freed, drivers keep it for
+// as long as they run. a single allocation stays within one hhdm range, so it's
+// contiguous in physical memory too
+pub fn dma_alloc(size: usize, align: usize) -> u64 {
+    let layout = core::alloc::Layout::from_size_align(size, align).unwrap();
+    unsafe { alloc::alloc::alloc_zeroed(layout) as u64 }
+}
+
+const PAGE_WRITE_THROUGH: u64 = 1 << 3;
+const PAGE_CACHE_DISABLE: u64 = 1 << 4;
+
+// makes device registers reachable through the hhdm, which only has to cover ram.
+// missing pages get mapped uncached, gives back the virtual address of `phys`
+pub fn map_mmio(phys: u64, size: u64) -> u64 {
+    let hhdm = get_hhdm_offset();
+    let start = phys & !(PAGE_SIZE - 1);
+    for page in (start..phys + size).step_by(PAGE_SIZE as usize) {
+        if virt_to_phys(page + hhdm) == Some(page) {
+            continue;
+        }
+        map_page(
+            page + hhdm,
+            page,
+            PAGE_WRITABLE | PAGE_WRITE_THROUGH | PAGE_CACHE_DISABLE | PAGE_NO_EXECUTE,
+        );
+    }
+    phys + hhdm
+}
+
+fn table_layout() -> core::alloc::Layout {
+    core::alloc::Layout::from_size_align(PAGE_SIZE as usize, PAGE_SIZE as usize).unwrap()
+}
+
+// fills in the 4k entry for `virt`. huge pages in the way get split into the same
+// mapping one level down, the hhdm uses them for ram we're still using. tables only
+// get the user bit on the way to user pages, so ring 3 never sees the kernel's
+fn map_page(virt: u64, phys: u64, mut flags: u64) {
+    if crate::utils::asm::rdmsr(0xC0000080) & (1 << 11) == 0 {
+        flags &= !PAGE_NO_EXECUTE; // only valid with EFER.NXE
+    }
+
+    let mut table = pml4_phys();
+    for level in (2..=4).rev() {
+        unsafe {
+            let entry = ((table + get_hhdm_offset()) as *mut u64).add(table_index(virt, level));
+            if *entry & PAGE_PRESENT == 0 || *entry & PAGE_HUGE != 0 {
+                let new_table = alloc::alloc::alloc_zeroed(table_layout()) as *mut u64;
+
+                if *entry & PAGE_PRESENT != 0 {
+                    // same mapping one level down, 4k entries keep pat in a different bit
+                    let child_size = 1u64 << (12 + 9 * (level as u64 - 2));
+                    let base = *entry & PAGE_ADDR_MASK & !((child_size << 9) - 1);
+                    let mut child_flags = *entry & !PAGE_ADDR_MASK & !(1 << 12);
+                    if level == 2 {
+                        child_flags &= !PAGE_HUGE;
+                    }
+                    for i in 0..512 {
+                        *new_table.add(i) = (base + i as u64 * child_size) | child_flags;
+                    }
+                }
+
+                let new_phys = virt_to_phys(new_table as u64).unwrap();
+                *entry =
+                    new_phys | PAGE_PRESENT | PAGE_WRITABLE | PAGE_OWNED | (*entry & PAGE_USER);
+            }
+            // the leaf decides what's actually allowed
+            *entry |= PAGE_WRITABLE | (flags & PAGE_USER);
+            table = *entry & PAGE_ADDR_MASK;
+        }
+    }
+
+    unsafe {
+        let entry = ((table + get_hhdm_offset()) as *mut u64).add(table_index(virt, 1));
+        *entry = (phys & PAGE_ADDR_MASK) | PAGE_PRESENT | flags;
+        core::arch::asm!("invlpg [{}]", in(reg) virt, options(nostack));
+    }
+}
@@ -3,8 +3,17 @@
     Released under EUPL 1.2 License
 */
 
+pub mod ahci;
+pub mod ata;
+pub mod audio;
+pub mod bga;
+pub mod elf;
 pub mod gdt;
 pub mod ints;
 pub mod keyboard;
 pub mod mem;
+pub mod pci;
+pub mod smp;
+pub mod syscall;
+pub mod thread;
 pub mod time;
@@ -0,0 +1,158 @@
+/*
+    Copyright (C) 2025 bugo07
+    Released under EUPL 1.2 License
+*/
+
+// pci config space through the legacy 0xCF8/0xCFC ports. enough to find controllers by
+// class, read their bars and let them do dma
+
+use alloc::vec::Vec;
+
+use crate::utils::asm::{inl, outl};
+
+const CONFIG_ADDRESS: u16 = 0xCF8;
+const CONFIG_DATA: u16 = 0xCFC;
+
+const REG_ID: u8 = 0x00;
+const REG_COMMAND: u8 = 0x04;
+const REG_CLASS: u8 = 0x08;
+const REG_HEADER_TYPE: u8 = 0x0C;
+const REG_BAR0: u8 = 0x10;
+
+const COMMAND_IO: u32 = 1 << 0;
+const COMMAND_MEMORY: u32 = 1 << 1;
+const COMMAND_BUS_MASTER: u32 = 1 << 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PciDevice {
+    pub bus: u8,
+    pub device: u8,
+    pub function: u8,
+    pub vendor_id: u16,
+    pub device_id: u16,
+    pub class: u8,
+    pub subclass: u8,
+    pub prog_if: u8,
+}
+
+pub fn read_config(bus: u8, device: u8, function: u8, offset: u8) -> u32 {
+    outl(
+        CONFIG_ADDRESS,
+        config_address(bus, device, function, offset),
+    );
+    inl(CONFIG_DATA)
+}
+
+pub fn write_config(bus: u8, device: u8, function: u8, offset: u8, value: u32) {
+    outl(
+        CONFIG_ADDRESS,
+        config_address(bus, device, function, offset),
+    );
+    outl(CONFIG_DATA, value);
+}
+
+fn config_address(bus: u8, device: u8, function: u8, offset: u8) -> u32 {
+    1 << 31
+        | (bus as u32) << 16
+        | (device as u32) << 11
+        | (function as u32) << 8
+        | (offset as u32 & 0xFC)
+}
+
+// brute force over every bus, there's only a handful of devices in a vm anyway
+pub fn enumerate() -> Vec<PciDevice> {
+    let mut devices = Vec::new();
+    for bus in 0..=255u8 {
+        for device in 0..32u8 {
+            let Some(first) = PciDevice::probe(bus, device, 0) else {
+                continue;
+            };
+            devices.push(first);
+
+            let multifunction = first.read(REG_HEADER_TYPE) & (1 << 23) != 0;
+            if multifunction {
+                devices
+                    .extend((1..8).filter_map(|function| PciDevice::probe(bus, device, function)));
+            }
+        }
+    }
+    devices
+}
+
+pub fn find_class(class: u8, subclass: u8) -> impl Iterator<Item = PciDevice> {
+    enumerate()
+        .into_iter()
+        .filter(move |x| x.class == class && x.subclass == subclass)
+}
+
+impl PciDevice {
+    fn probe(bus: u8, device: u8, function: u8) -> Option<Self> {
+        let id = read_config(bus, device, function, REG_ID);
+        if id & 0xFFFF == 0xFFFF {
+            return None;
+        }
+        let class = read_config(bus, device, function, REG_CLASS);
+        Some(Self {
+            bus,
+            device,
+            function,
+            vendor_id: id as u16,
+            device_id: (id >> 16) as u16,
+            class: (class >> 24) as u8,
+            subclass: (class >> 16) as u8,
+            prog_if: (class >> 8) as u8,
+        })
+    }
+
+    pub fn read(&self, offset: u8) -> u32 {
+        read_config(self.bus, self.device, self.function, offset)
+    }
+
+    pub fn write(&self, offset: u8, value: u32) {
+        write_config(self.bus, self.device, self.function, offset, value);
+    }
+
+    // physical address of a memory bar, None for io bars
+    pub fn bar(&self, index: u8) -> Option<u64> {
+        let offset = REG_BAR0 + index * 4;
+        let low = self.read(offset);
+        if low & 1 != 0 {
+            return None;
+        }
+
+        let mut address = (low & !0xF) as u64;
+        // type 2 is a 64 bit bar taking up the next slot too
+        if (low >> 1) & 0x3 == 2 {
+            address |= (self.read(offset + 4) as u64) << 32;
+        }
+        Some(address)
+    }
+
+    // first port of an io bar, None for memory bars
+    pub fn io_bar(&self, index: u8) -> Option<u16> {
+        let low = self.read(REG_BAR0 + index * 4);
+        if low & 1 == 0 {
+            return None;
+        }
+        Some((low & !0x3) as u16)
+    }
+
+    // lets the device answer mmio and io ports and do dma
+    pub fn enable_bus_master(&self) {
+        let command = self.read(REG_COMMAND);
+        self.write(
+            REG_COMMAND,
+            command | COMMAND_IO | COMMAND_MEMORY | COMMAND_BUS_MASTER,
+        );
+    }
+}
+
+impl core::fmt::Display for PciDevice {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "{:02x}:{:02x}.{} {:04x}:{:04x}",
+            self.bus, self.device, self.function, self.vendor_id, self.device_id
+        )
+    }
+}
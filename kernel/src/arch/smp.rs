@@ -0,0 +1,138 @@
+/*
+    Copyright (C) 2025 bugo07
+    Released under EUPL 1.2 License
+*/
+
+// bevy_ecs keeps its `SystemExecutor` trait crate-private and the multi-threaded
+// executor needs std, so schedules stay on the single-threaded executor. the
+// parallelism happens inside systems instead, through `scope`/`par_chunks_mut`.
+
+use core::{
+    marker::PhantomData,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use alloc::{boxed::Box, collections::vec_deque::VecDeque, sync::Arc};
+use limine::mp::Cpu;
+
+use crate::{
+    arch::time::preferred_timer_ns,
+    info,
+    utils::{asm::toggle_ints, bootloader::get_mp_response},
+    warn,
+};
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+static QUEUE: spin::Mutex<VecDeque<Job>> = spin::Mutex::new(VecDeque::new());
+static ONLINE: AtomicUsize = AtomicUsize::new(0);
+
+pub fn init() {
+    info!("starting application processors...");
+    let mp = get_mp_response();
+    let bsp = mp.bsp_lapic_id();
+    let aps = mp.cpus().iter().filter(|cpu| cpu.lapic_id != bsp).count();
+
+    for cpu in mp.cpus() {
+        if cpu.lapic_id != bsp {
+            cpu.goto_address.write(ap_entry);
+        }
+    }
+
+    let start = preferred_timer_ns();
+    while ONLINE.load(Ordering::Acquire) < aps {
+        if preferred_timer_ns() - start > 1_000_000_000 {
+            warn!(
+                "only {}/{} application processors came up",
+                ONLINE.load(Ordering::Acquire),
+                aps
+            );
+            break;
+        }
+        core::hint::spin_loop();
+    }
+
+    info!("{} cores online", threads());
+}
+
+unsafe extern "C" fn ap_entry(_cpu: &Cpu) -> ! {
+    toggle_ints(false);
+    crate::arch::ints::load_idt();
+    crate::arch::mem::protect_kernel();
+    ONLINE.fetch_add(1, Ordering::Release);
+
+    loop {
+        if !run_one() {
+            core::hint::spin_loop();
+        }
+    }
+}
+
+// number of cores that pick up jobs, including the bsp
+pub fn threads() -> usize {
+    ONLINE.load(Ordering::Acquire) + 1
+}
+
+fn run_one() -> bool {
+    let job = QUEUE.lock().pop_front();
+    match job {
+        Some(job) => {
+            job();
+            true
+        }
+        None => false,
+    }
+}
+
+pub struct Scope<'env> {
+    pending: Arc<AtomicUsize>,
+    _marker: PhantomData<&'env mut &'env ()>,
+}
+
+impl<'env> Scope<'env> {
+    pub fn spawn<F: FnOnce() + Send + 'env>(&self, f: F) {
+        let pending = self.pending.clone();
+        pending.fetch_add(1, Ordering::AcqRel);
+
+        let job: Box<dyn FnOnce() + Send + 'env> = Box::new(move || {
+            f();
+            pending.fetch_sub(1, Ordering::AcqRel);
+        });
+        // * fine because `scope` doesn't return before every job is done
+        let job: Job = unsafe { core::mem::transmute(job) };
+        QUEUE.lock().push_back(job);
+    }
+}
+
+// runs `f` and blocks until every job it spawned has finished. the calling
+// core works through the queue too, so this also works on a single core.
+pub fn scope<'env, R>(f: impl FnOnce(&Scope<'env>) -> R) -> R {
+    let scope = Scope {
+        pending: Arc::new(AtomicUsize::new(0)),
+        _marker: PhantomData,
+    };
+    let ret = f(&scope);
+
+    while scope.pending.load(Ordering::Acquire) != 0 {
+        if !run_one() {
+            core::hint::spin_loop();
+        }
+    }
+
+    ret
+}
+
+// one chunk per core, `f` gets its index too
+pub fn par_chunks_mut<T: Send>(slice: &mut [T], f: impl Fn(usize, &mut [T]) + Sync) {
+    if slice.is_empty() {
+        return;
+    }
+
+    let chunk_size = slice.len().div_ceil(threads());
+    let f = &f;
+    scope(|s| {
+        for (i, chunk) in slice.chunks_mut(chunk_size).enumerate() {
+            s.spawn(move || f(i, chunk));
+        }
+    });
+}
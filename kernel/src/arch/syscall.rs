@@ -0,0 +1,189 @@
+/*
+    Copyright (C) 2025 bugo07
+    Released under EUPL 1.2 License
+*/
+
+// syscall abi: number in rax, arguments in rdi, rsi, rdx, r10, r8, result in rax.
+// both `int 0x80` and `syscall` end up in `syscall_dispatch`, the `syscall` path
+// builds an interrupt frame and returns through iretq like every other interrupt
+// so the scheduler can switch threads on the way out. pointers from ring 3 get checked
+// against its own pages before the kernel touches them.
+
+use crate::{
+    arch::{
+        elf,
+        gdt::SELECTORS,
+        ints::StackFrame,
+        keyboard::{self, SCANCODE_NONE},
+        mem::{is_user_range, set_user_access, with_user_access},
+        thread,
+    },
+    info,
+    utils::{
+        asm::{rdmsr, wrmsr},
+        serial::serial_write,
+        video,
+    },
+};
+
+pub const SYS_EXIT: u64 = 0;
+pub const SYS_WRITE: u64 = 1;
+pub const SYS_FB_BLIT: u64 = 2;
+pub const SYS_INPUT_POLL: u64 = 3;
+pub const SYS_TIME: u64 = 4;
+pub const SYS_YIELD: u64 = 5;
+pub const SYS_EXEC: u64 = 6;
+
+pub const SYSCALL_VECTOR: u8 = 0x80;
+pub const EFAULT: u64 = -14i64 as u64;
+pub const EINVAL: u64 = -22i64 as u64;
+pub const ENOSYS: u64 = -38i64 as u64;
+
+const MSR_EFER: u32 = 0xC0000080;
+const MSR_STAR: u32 = 0xC0000081;
+const MSR_LSTAR: u32 = 0xC0000082;
+const MSR_FMASK: u32 = 0xC0000084;
+
+#[unsafe(no_mangle)]
+static mut SYSCALL_KERNEL_RSP: u64 = 0;
+#[unsafe(no_mangle)]
+static mut SYSCALL_USER_RSP: u64 = 0;
+
+core::arch::global_asm! {
+    r#"
+.global syscall_entry
+syscall_entry:
+    mov [rip + SYSCALL_USER_RSP], rsp
+    mov rsp, [rip + SYSCALL_KERNEL_RSP]
+
+    push {user_ss}
+    push qword ptr [rip + SYSCALL_USER_RSP]
+    push r11
+    push {user_cs}
+    push rcx
+    push 0
+    push {vector}
+    jmp isr_common_stub
+    "#,
+    user_ss = const 0x23,
+    user_cs = const 0x2B,
+    vector = const SYSCALL_VECTOR,
+}
+
+unsafe extern "C" {
+    fn syscall_entry();
+}
+
+pub fn init() {
+    info!("setting up...");
+    // syscall_entry hardcodes these
+    assert_eq!({ SELECTORS.user_data.0 }, 0x23);
+    assert_eq!({ SELECTORS.user_code.0 }, 0x2B);
+
+    crate::arch::ints::install_interrupt(SYSCALL_VECTOR, syscall_dispatch);
+
+    wrmsr(MSR_EFER, rdmsr(MSR_EFER) | 1); // SCE
+    // sysret bases its selectors off user_code_32bit, syscall off kernel_code
+    wrmsr(
+        MSR_STAR,
+        ((SELECTORS.user_code_32bit.0 as u64) << 48) | ((SELECTORS.kernel_code.0 as u64) << 32),
+    );
+    wrmsr(MSR_LSTAR, syscall_entry as *const () as u64);
+    wrmsr(MSR_FMASK, (1 << 9) | (1 << 10) | (1 << 18)); // IF, DF and AC
+    info!("done");
+}
+
+pub fn set_kernel_stack(rsp: u64) {
+    unsafe { SYSCALL_KERNEL_RSP = rsp };
+}
+
+pub fn syscall_dispatch(frame: *mut StackFrame) {
+    let frame = unsafe { &mut *frame };
+    set_user_access(false); // int 0x80 keeps whatever AC ring 3 had
+    let (number, a0, a1, a2, a3, a4) = (
+        frame.rax, frame.rdi, frame.rsi, frame.rdx, frame.r10, frame.r8,
+    );
+
+    frame.rax = match number {
+        SYS_EXIT => {
+            let id = thread::current_id();
+            info!("thread {id} exited with {}", a0 as i64);
+            elf::on_exit(id);
+            thread::kill_current();
+            0
+        }
+        SYS_WRITE => {
+            if a1 > 4096 {
+                EINVAL
+            } else if !is_user_range(a0, a1, false) {
+                EFAULT
+            } else {
+                with_user_access(|| {
+                    let bytes =
+                        unsafe { core::slice::from_raw_parts(a0 as *const u8, a1 as usize) };
+                    for byte in bytes {
+                        serial_write(*byte);
+                    }
+                });
+                a1
+            }
+        }
+        SYS_FB_BLIT => fb_blit(
+            a0 as *const u32,
+            (a1 >> 32) as usize,
+            a1 as u32,
+            a2 as u32,
+            a3 as u32,
+            a4 as u32,
+        ),
+        SYS_INPUT_POLL => keyboard::pop_scancode().map_or(SCANCODE_NONE, |x| x as u64),
+        SYS_TIME => crate::arch::time::preferred_timer_ns(),
+        SYS_YIELD => {
+            thread::request_resched();
+            0
+        }
+        // blocks until the program exits, the loading happens in `elf::loader_task`
+        SYS_EXEC => {
+            if elf::request_exec(a0) {
+                0
+            } else {
+                EINVAL
+            }
+        }
+        _ => ENOSYS,
+    };
+}
+
+// copies `width`x`height` pixels (tightly packed) to (x, y) on a screen. the screen
+// index rides in the top half of x, so 0 is the first one
+fn fb_blit(src: *const u32, index: usize, x: u32, y: u32, width: u32, height: u32) -> u64 {
+    let Some(screen) = video::screens().get(index).copied() else {
+        return EINVAL;
+    };
+
+    if x as u64 + width as u64 > screen.size.x as u64
+        || y as u64 + height as u64 > screen.size.y as u64
+    {
+        return EINVAL;
+    }
+    // fits in a u64 since both are u32s
+    if !is_user_range(src as u64, width as u64 * height as u64 * 4, false) {
+        return EFAULT;
+    }
+
+    with_user_access(|| {
+        for row in 0..height as usize {
+            unsafe {
+                let dst = screen
+                    .addr
+                    .add((y as usize + row) * screen.pitch as usize + x as usize * 4);
+                core::ptr::copy_nonoverlapping(
+                    src.add(row * width as usize),
+                    dst as *mut u32,
+                    width as usize,
+                );
+            }
+        }
+    });
+    0
+}
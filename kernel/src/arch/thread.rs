@@ -0,0 +1,522 @@
+/*
+    Copyright (C) 2025 bugo07
+    Released under EUPL 1.2 License
+*/
+
+// preemptive kernel threads, only ever scheduled on the bsp. the aps stay in the smp job loop.
+
+use core::{
+    arch::asm,
+    cell::UnsafeCell,
+    ops::{Deref, DerefMut},
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+    time::Duration,
+};
+
+use alloc::{boxed::Box, collections::vec_deque::VecDeque, vec, vec::Vec};
+
+use crate::{
+    arch::{ints::StackFrame, time::preferred_timer_ns},
+    info,
+    utils::asm::{halt, without_ints},
+};
+
+pub const THREAD_STACK_SIZE: usize = 64 * 1024;
+pub const TIME_SLICE_MS: u64 = 10;
+pub const YIELD_VECTOR: u8 = 0x81;
+
+pub type ThreadId = u64;
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+static NEED_RESCHED: AtomicBool = AtomicBool::new(false);
+static FORCE_RESCHED: AtomicBool = AtomicBool::new(false);
+static SCHEDULER: spin::Mutex<Scheduler> = spin::Mutex::new(Scheduler {
+    threads: Vec::new(),
+    current: 0,
+    last_switch: 0,
+});
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThreadState {
+    Ready,
+    Running,
+    Sleeping(u64), // deadline in ns
+    Blocked,
+    Dead,
+}
+
+#[repr(C, align(16))]
+struct FpuState([u8; 512]);
+
+pub struct Thread {
+    pub id: ThreadId,
+    pub name: &'static str,
+    pub priority: u8,
+    pub state: ThreadState,
+    pub cpu_ns: u64,
+    frame: *mut StackFrame,
+    kernel_stack_top: u64,
+    _stack: Option<Box<[u8]>>, // none for the boot thread, it keeps the limine stack
+    fpu: Box<FpuState>,
+    entry: Option<Box<dyn FnOnce() + Send>>,
+}
+
+unsafe impl Send for Thread {}
+
+#[derive(Debug, Clone)]
+pub struct ThreadInfo {
+    pub id: ThreadId,
+    pub name: &'static str,
+    pub priority: u8,
+    pub state: ThreadState,
+    pub cpu_ns: u64,
+}
+
+struct Scheduler {
+    threads: Vec<Thread>,
+    current: ThreadId,
+    last_switch: u64,
+}
+
+impl Scheduler {
+    fn index_of(&self, id: ThreadId) -> Option<usize> {
+        self.threads.iter().position(|x| x.id == id)
+    }
+
+    // highest priority runnable thread, round robin between equal priorities
+    fn pick_next(&self, current: usize) -> usize {
+        let len = self.threads.len();
+        let mut best: Option<usize> = None;
+
+        for offset in 1..=len {
+            let i = (current + offset) % len;
+            let thread = &self.threads[i];
+            if !matches!(thread.state, ThreadState::Ready | ThreadState::Running) {
+                continue;
+            }
+            if best.is_none_or(|b| thread.priority > self.threads[b].priority) {
+                best = Some(i);
+            }
+        }
+
+        best.unwrap_or(current)
+    }
+}
+
+pub fn init() {
+    info!("setting up...");
+    crate::arch::ints::install_interrupt(YIELD_VECTOR, |_| {});
+
+    without_ints(|| {
+        let mut sched = SCHEDULER.lock();
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        sched.threads.push(Thread {
+            id,
+            name: "kmain",
+            priority: 1,
+            state: ThreadState::Running,
+            cpu_ns: 0,
+            frame: core::ptr::null_mut(),
+            kernel_stack_top: 0,
+            _stack: None,
+            fpu: Box::new(FpuState([0; 512])),
+            entry: None,
+        });
+        sched.current = id;
+        sched.last_switch = preferred_timer_ns();
+    });
+
+    spawn("idle", 0, || {
+        loop {
+            halt();
+        }
+    });
+    info!("done");
+}
+
+// frees dead threads, not done in `preempt` since irq handlers shouldn't touch the allocator
+fn reap() {
+    let dead = without_ints(|| {
+        let mut sched = SCHEDULER.lock();
+        let current = sched.current;
+        let (dead, alive) = core::mem::take(&mut sched.threads)
+            .into_iter()
+            .partition(|x| x.state == ThreadState::Dead && x.id != current);
+        sched.threads = alive;
+        dead
+    });
+    drop::<Vec<Thread>>(dead);
+}
+
+pub fn spawn(name: &'static str, priority: u8, f: impl FnOnce() + Send + 'static) -> ThreadId {
+    let (cs, ss): (u64, u64);
+    unsafe {
+        asm!("mov {0:r}, cs", out(reg) cs, options(nomem, nostack, preserves_flags));
+        asm!("mov {0:r}, ss", out(reg) ss, options(nomem, nostack, preserves_flags));
+    }
+
+    create(
+        name,
+        priority,
+        thread_start as *const () as u64,
+        cs,
+        ss,
+        None,
+        0,
+        Some(Box::new(f)),
+    )
+}
+
+// for loaded programs, which come with their own stack already mapped. `arg` ends up in rdi
+pub fn spawn_user_at(name: &'static str, priority: u8, rip: u64, rsp: u64, arg: u64) -> ThreadId {
+    let selectors = &*crate::arch::gdt::SELECTORS;
+    create(
+        name,
+        priority,
+        rip,
+        selectors.user_code.0 as u64,
+        selectors.user_data.0 as u64,
+        Some(rsp),
+        arg,
+        None,
+    )
+}
+
+fn create(
+    name: &'static str,
+    priority: u8,
+    rip: u64,
+    cs: u64,
+    ss: u64,
+    rsp: Option<u64>,
+    arg: u64,
+    entry: Option<Box<dyn FnOnce() + Send>>,
+) -> ThreadId {
+    reap();
+
+    let mut stack = vec![0u8; THREAD_STACK_SIZE].into_boxed_slice();
+    let top = (stack.as_mut_ptr() as u64 + THREAD_STACK_SIZE as u64) & !0xF;
+
+    // the frame isr_common_stub pops when this thread gets picked for the first time
+    let frame = (top - size_of::<StackFrame>() as u64) as *mut StackFrame;
+    unsafe {
+        frame.write(StackFrame {
+            rip,
+            cs,
+            rdi: arg,
+            rflags: 0x202,               // IF
+            rsp: rsp.unwrap_or(top - 8), // like right after a call
+            ss,
+            ..Default::default()
+        });
+    }
+
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    let mut fpu = Box::new(FpuState([0; 512]));
+    unsafe { asm!("fxsave64 [{}]", in(reg) fpu.0.as_mut_ptr()) };
+
+    without_ints(|| {
+        SCHEDULER.lock().threads.push(Thread {
+            id,
+            name,
+            priority,
+            state: ThreadState::Ready,
+            cpu_ns: 0,
+            frame,
+            kernel_stack_top: top,
+            _stack: Some(stack),
+            fpu,
+            entry,
+        })
+    });
+    info!(
+        "spawned {} thread {id} ({name}) with priority {priority}",
+        if cs & 3 == 3 { "user" } else { "kernel" }
+    );
+    id
+}
+
+extern "C" fn thread_start() -> ! {
+    let entry = without_ints(|| {
+        let mut sched = SCHEDULER.lock();
+        let current = sched.current;
+        let index = sched.index_of(current).unwrap();
+        sched.threads[index].entry.take()
+    });
+
+    if let Some(entry) = entry {
+        entry();
+    }
+
+    exit();
+}
+
+pub fn exit() -> ! {
+    kill_current();
+    yield_now();
+    unreachable!("dead thread got scheduled");
+}
+
+// for the exit syscall, the thread is switched away from on the way out of the interrupt
+pub fn kill_current() {
+    set_current_state(ThreadState::Dead);
+    request_resched();
+}
+
+// for syscalls that wait on something, the thread stays off the cpu until `wake`
+pub fn block_current() {
+    set_current_state(ThreadState::Blocked);
+    request_resched();
+}
+
+pub fn request_resched() {
+    FORCE_RESCHED.store(true, Ordering::Relaxed);
+}
+
+pub fn current_id() -> ThreadId {
+    without_ints(|| SCHEDULER.lock().current)
+}
+
+pub fn yield_now() {
+    unsafe { asm!("int {}", const YIELD_VECTOR) };
+}
+
+pub fn sleep(duration: Duration) {
+    set_current_state(ThreadState::Sleeping(
+        preferred_timer_ns() + duration.as_nanos() as u64,
+    ));
+    yield_now();
+}
+
+fn set_current_state(state: ThreadState) {
+    without_ints(|| {
+        let mut sched = SCHEDULER.lock();
+        let current = sched.current;
+        if let Some(index) = sched.index_of(current) {
+            sched.threads[index].state = state;
+        }
+    });
+}
+
+pub fn wake(id: ThreadId) {
+    without_ints(|| {
+        let mut sched = SCHEDULER.lock();
+        if let Some(index) = sched.index_of(id) {
+            let thread = &mut sched.threads[index];
+            if matches!(
+                thread.state,
+                ThreadState::Blocked | ThreadState::Sleeping(_)
+            ) {
+                thread.state = ThreadState::Ready;
+            }
+        }
+    });
+}
+
+pub fn set_priority(id: ThreadId, priority: u8) {
+    without_ints(|| {
+        let mut sched = SCHEDULER.lock();
+        if let Some(index) = sched.index_of(id) {
+            sched.threads[index].priority = priority;
+        }
+    });
+}
+
+pub fn list() -> Vec<ThreadInfo> {
+    without_ints(|| {
+        SCHEDULER
+            .lock()
+            .threads
+            .iter()
+            .map(|x| ThreadInfo {
+                id: x.id,
+                name: x.name,
+                priority: x.priority,
+                state: x.state,
+                cpu_ns: x.cpu_ns,
+            })
+            .collect()
+    })
+}
+
+// called by the pit every tick
+pub fn timer_tick(ticks_ms: u64) {
+    if ticks_ms.is_multiple_of(TIME_SLICE_MS) {
+        NEED_RESCHED.store(true, Ordering::Relaxed);
+    }
+}
+
+// called at the end of every interrupt, returns the frame that iretq should resume
+pub fn preempt(frame: *mut StackFrame) -> *mut StackFrame {
+    let vector = unsafe { (*frame).vector };
+    let timer = vector == 0x20 && NEED_RESCHED.swap(false, Ordering::Relaxed);
+    let forced = FORCE_RESCHED.swap(false, Ordering::Relaxed);
+    if !timer && !forced && vector != YIELD_VECTOR as u64 {
+        return frame;
+    }
+
+    // a thread holding the lock got interrupted, try again next slice
+    let Some(mut sched) = SCHEDULER.try_lock() else {
+        return frame;
+    };
+    if sched.threads.is_empty() {
+        return frame;
+    }
+
+    let now = preferred_timer_ns();
+    let current_id = sched.current;
+    let current = sched.index_of(current_id).unwrap();
+
+    for thread in sched.threads.iter_mut() {
+        if let ThreadState::Sleeping(deadline) = thread.state
+            && deadline <= now
+        {
+            thread.state = ThreadState::Ready;
+        }
+    }
+
+    let last_switch = sched.last_switch;
+    sched.threads[current].cpu_ns += now - last_switch;
+    sched.last_switch = now;
+
+    let next = sched.pick_next(current);
+    if next == current {
+        return frame;
+    }
+
+    let (prev, next_thread) = if current < next {
+        let (a, b) = sched.threads.split_at_mut(next);
+        (&mut a[current], &mut b[0])
+    } else {
+        let (a, b) = sched.threads.split_at_mut(current);
+        (&mut b[0], &mut a[next])
+    };
+
+    prev.frame = frame;
+    if prev.state == ThreadState::Running {
+        prev.state = ThreadState::Ready;
+    }
+    next_thread.state = ThreadState::Running;
+
+    unsafe {
+        asm!("fxsave64 [{}]", in(reg) prev.fpu.0.as_mut_ptr());
+        asm!("fxrstor64 [{}]", in(reg) next_thread.fpu.0.as_ptr());
+    }
+
+    if next_thread.kernel_stack_top != 0 {
+        crate::arch::gdt::set_kernel_stack(next_thread.kernel_stack_top);
+        crate::arch::syscall::set_kernel_stack(next_thread.kernel_stack_top);
+    }
+
+    let next_frame = next_thread.frame;
+    sched.current = sched.threads[next].id;
+    next_frame
+}
+
+// threads that are blocked on something, woken one at a time or all at once
+pub struct WaitQueue {
+    waiters: spin::Mutex<VecDeque<ThreadId>>,
+}
+
+impl Default for WaitQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WaitQueue {
+    pub const fn new() -> Self {
+        Self {
+            waiters: spin::Mutex::new(VecDeque::new()),
+        }
+    }
+
+    // blocks until woken, unless `condition` already doesn't hold anymore
+    pub fn wait_while(&self, condition: impl Fn() -> bool) {
+        let blocked = without_ints(|| {
+            if !condition() {
+                return false;
+            }
+            self.waiters.lock().push_back(current_id());
+            set_current_state(ThreadState::Blocked);
+            true
+        });
+        if blocked {
+            yield_now();
+        }
+    }
+
+    pub fn wake_one(&self) {
+        if let Some(id) = without_ints(|| self.waiters.lock().pop_front()) {
+            wake(id);
+        }
+    }
+
+    // pops one by one so irq handlers can use it without freeing anything
+    pub fn wake_all(&self) {
+        while let Some(id) = without_ints(|| self.waiters.lock().pop_front()) {
+            wake(id);
+        }
+    }
+}
+
+pub struct Mutex<T> {
+    locked: AtomicBool,
+    queue: WaitQueue,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send> Send for Mutex<T> {}
+unsafe impl<T: Send> Sync for Mutex<T> {}
+
+impl<T> Mutex<T> {
+    pub const fn new(data: T) -> Self {
+        Self {
+            locked: AtomicBool::new(false),
+            queue: WaitQueue::new(),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    pub fn try_lock(&self) -> Option<MutexGuard<'_, T>> {
+        self.locked
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .ok()
+            .map(|_| MutexGuard { mutex: self })
+    }
+
+    pub fn lock(&self) -> MutexGuard<'_, T> {
+        loop {
+            if let Some(guard) = self.try_lock() {
+                return guard;
+            }
+            self.queue
+                .wait_while(|| self.locked.load(Ordering::Acquire));
+        }
+    }
+}
+
+pub struct MutexGuard<'a, T> {
+    mutex: &'a Mutex<T>,
+}
+
+impl<T> Deref for MutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.mutex.data.get() }
+    }
+}
+
+impl<T> DerefMut for MutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.mutex.data.get() }
+    }
+}
+
+impl<T> Drop for MutexGuard<'_, T> {
+    fn drop(&mut self) {
+        self.mutex.locked.store(false, Ordering::Release);
+        self.mutex.queue.wake_one();
+    }
+}
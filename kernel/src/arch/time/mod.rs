@@ -8,16 +8,47 @@ pub mod kvm;
 pub mod pit;
 pub mod tsc;
 
-use alloc::string::String;
+use core::{
+    future::Future,
+    pin::Pin,
+    sync::atomic::{AtomicU64, Ordering},
+    task::{Context, Poll, Waker},
+    time::Duration,
+};
 
-use crate::{info, utils::heapless::HeaplessVec};
+use alloc::{string::String, vec::Vec};
+
+use crate::{
+    info,
+    utils::{asm::without_ints, cmdline, heapless::HeaplessVec},
+    warn,
+};
 
 pub static mut TIMERS: HeaplessVec<Timer, 10> = HeaplessVec::new();
 
+static NEXT_SLEEPER: AtomicU64 = AtomicU64::new(0);
+static SLEEPERS: spin::Mutex<Vec<(u64, u64, Waker)>> = spin::Mutex::new(Vec::new()); // id, deadline, waker
+
 pub fn init() {
     pit::init();
     kvm::init();
     tsc::init();
+
+    if let Some(kind) = cmdline::config().timer {
+        prefer_timer(kind);
+    }
+}
+
+// moves `kind` in front of the others if it's there to use
+pub fn prefer_timer(kind: TimerKind) {
+    let name: &str = kind.into();
+    let timers = get_timers();
+    if !timers.iter().any(|x| x.kind == kind && x.is_supported()) {
+        warn!("timer {name} isn't available");
+        return;
+    }
+    timers.sort_by(|a, b| (a.kind != kind, a.priority).cmp(&(b.kind != kind, b.priority)));
+    info!("using {name} as the preferred timer");
 }
 
 pub struct Timer {
@@ -203,3 +234,59 @@ pub fn busywait_ns(ns: u64) {
 pub fn busywait_ms(ms: u64) {
     busywait_ns(ms * 1_000_000);
 }
+
+pub fn sleep(duration: Duration) -> Sleep {
+    Sleep {
+        id: NEXT_SLEEPER.fetch_add(1, Ordering::Relaxed),
+        deadline: preferred_timer_ns() + duration.as_nanos() as u64,
+    }
+}
+
+pub struct Sleep {
+    id: u64,
+    deadline: u64,
+}
+
+impl Sleep {
+    fn unregister(&self) {
+        without_ints(|| SLEEPERS.lock().retain(|(id, _, _)| *id != self.id));
+    }
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if preferred_timer_ns() >= self.deadline {
+            self.unregister();
+            return Poll::Ready(());
+        }
+
+        without_ints(|| {
+            let mut sleepers = SLEEPERS.lock();
+            match sleepers.iter_mut().find(|(id, _, _)| *id == self.id) {
+                Some((_, _, waker)) => waker.clone_from(cx.waker()),
+                None => sleepers.push((self.id, self.deadline, cx.waker().clone())),
+            }
+        });
+        Poll::Pending
+    }
+}
+
+impl Drop for Sleep {
+    fn drop(&mut self) {
+        self.unregister();
+    }
+}
+
+// called from the pit irq, only wakes by ref so nothing gets freed in there
+pub fn wake_sleepers() {
+    let now = preferred_timer_ns();
+    if let Some(sleepers) = SLEEPERS.try_lock() {
+        for (_, deadline, waker) in sleepers.iter() {
+            if *deadline <= now {
+                waker.wake_by_ref();
+            }
+        }
+    }
+}
@@ -37,6 +37,9 @@ pub fn init() {
 
 pub fn timer_interrupt_handler(_stack_frame: *mut crate::arch::ints::StackFrame) {
     pit_tick();
+    super::wake_sleepers();
+    crate::arch::thread::timer_tick(current_pit_ticks());
+    crate::utils::logger::kick_drain();
     crate::arch::ints::pic::send_eoi(0);
 }
 
@@ -3,25 +3,190 @@
     Released under EUPL 1.2 License
 */
 
+// sprites, fonts and sounds by name. the copies built into the kernel are always there,
+// and limine modules under /boot/assets/ (or with an `asset:<name>` cmdline) replace
+// them, so a skin is just files in assets/ next to the GNUmakefile. raw sprites have to
+// keep the size of the one they replace, fonts under fonts/ get registered by file name
+// and wavs under sounds/ get decoded by the audio layer
+
+use alloc::{collections::btree_map::BTreeMap, string::String, sync::Arc, vec::Vec};
 use bevy_math::Vec2;
+use limine::file::File;
 
-lazy_static::lazy_static! {
-    pub static ref FLAPPY_BIRD_DATA: &'static [u32] =
-        U32Aligned(include_bytes!("../res/flappy_bird.bin")).as_u32_slice();
-    pub static ref PIPE_DATA: &'static [u32] =
-        U32Aligned(include_bytes!("../res/pipe.bin")).as_u32_slice();
-    pub static ref PIPE_FLIPPED_DATA: &'static [u32] =
-        U32Aligned(include_bytes!("../res/pipe_flipped.bin")).as_u32_slice();
-}
+use crate::{
+    info,
+    utils::{
+        bootloader::get_modules,
+        fb::BUILTIN_FONT,
+        font::{self, Font},
+    },
+    warn,
+};
 
 pub static FLAPPY_BIRD_SIZE: Vec2 = Vec2::new(57.0, 36.0);
 pub static PIPE_SIZE: Vec2 = Vec2::new(22.0, 160.0);
 
+pub const FLAPPY_BIRD: &str = "flappy_bird.bin";
+pub const PIPE: &str = "pipe.bin";
+pub const PIPE_FLIPPED: &str = "pipe_flipped.bin";
+pub const CONSOLE_FONT: &str = "font.bin";
+pub const FLAP_SOUND: &str = "sounds/flap.wav";
+pub const SCORE_SOUND: &str = "sounds/score.wav";
+pub const HIT_SOUND: &str = "sounds/hit.wav";
+pub const DIE_SOUND: &str = "sounds/die.wav";
+
+const MODULE_DIR: &str = "/boot/assets/";
+const MODULE_CMDLINE: &str = "asset:";
+const FONT_DIR: &str = "fonts/";
+pub const SOUND_DIR: &str = "sounds/";
+
+lazy_static::lazy_static! {
+    pub static ref FLAPPY_BIRD_DATA: &'static [u32] = sprite(FLAPPY_BIRD);
+    pub static ref PIPE_DATA: &'static [u32] = sprite(PIPE);
+    pub static ref PIPE_FLIPPED_DATA: &'static [u32] = sprite(PIPE_FLIPPED);
+}
+
+// include_bytes! only lines things up to a byte, the sprites are read as u32s
 #[repr(C, align(4))]
-struct U32Aligned(&'static [u8]);
+struct Aligned<Bytes: ?Sized>(Bytes);
+
+static FLAPPY_BIRD_BIN: &Aligned<[u8]> = &Aligned(*include_bytes!("../res/flappy_bird.bin"));
+static PIPE_BIN: &Aligned<[u8]> = &Aligned(*include_bytes!("../res/pipe.bin"));
+static PIPE_FLIPPED_BIN: &Aligned<[u8]> = &Aligned(*include_bytes!("../res/pipe_flipped.bin"));
+static FLAP_WAV: &[u8] = include_bytes!("../res/sounds/flap.wav");
+static SCORE_WAV: &[u8] = include_bytes!("../res/sounds/score.wav");
+static HIT_WAV: &[u8] = include_bytes!("../res/sounds/hit.wav");
+static DIE_WAV: &[u8] = include_bytes!("../res/sounds/die.wav");
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AssetSource {
+    Embedded,
+    Module,
+}
+
+#[derive(Clone, Copy)]
+struct Asset {
+    data: &'static [u8],
+    source: AssetSource,
+}
+
+static ASSETS: spin::RwLock<BTreeMap<String, Asset>> = spin::RwLock::new(BTreeMap::new());
+
+// before the game starts, after font::init
+pub fn init() {
+    let embedded: [(&str, &'static [u8]); 8] = [
+        (FLAPPY_BIRD, &FLAPPY_BIRD_BIN.0),
+        (PIPE, &PIPE_BIN.0),
+        (PIPE_FLIPPED, &PIPE_FLIPPED_BIN.0),
+        (CONSOLE_FONT, BUILTIN_FONT),
+        (FLAP_SOUND, FLAP_WAV),
+        (SCORE_SOUND, SCORE_WAV),
+        (HIT_SOUND, HIT_WAV),
+        (DIE_SOUND, DIE_WAV),
+    ];
+    let mut assets = ASSETS.write();
+    for (name, data) in embedded {
+        assets.insert(
+            String::from(name),
+            Asset {
+                data,
+                source: AssetSource::Embedded,
+            },
+        );
+    }
+
+    for module in get_modules() {
+        let Some(name) = module_name(module) else {
+            continue;
+        };
+        let data = unsafe { core::slice::from_raw_parts(module.addr(), module.size() as usize) };
+
+        if let Some(file) = name.strip_prefix(FONT_DIR) {
+            let font_name = file.rsplit_once('.').map_or(file, |(stem, _)| stem);
+            match Font::load(data) {
+                Ok(font) => font::register(font_name, Arc::new(font)),
+                Err(err) => {
+                    warn!("skipping font {name}: {err:?}");
+                    continue;
+                }
+            }
+        } else if let Some(err) = check(&name, data, assets.get(&name)) {
+            warn!("skipping asset {name}: {err}");
+            continue;
+        }
+
+        info!("asset {name} from {} ({} bytes)", path(module), data.len());
+        assets.insert(
+            name,
+            Asset {
+                data,
+                source: AssetSource::Module,
+            },
+        );
+    }
+}
+
+fn path(module: &File) -> &str {
+    module.path().to_str().unwrap_or("?")
+}
+
+// the name a module goes by if it's an asset
+fn module_name(module: &File) -> Option<String> {
+    if let Some(name) = module
+        .string()
+        .to_str()
+        .ok()
+        .and_then(|x| x.strip_prefix(MODULE_CMDLINE))
+    {
+        return Some(String::from(name.trim()));
+    }
+    let path = path(module);
+    path.find(MODULE_DIR)
+        .map(|i| String::from(&path[i + MODULE_DIR.len()..]))
+}
 
-impl U32Aligned {
-    fn as_u32_slice(&self) -> &[u32] {
-        bytemuck::cast_slice(self.0)
+pub fn is_asset(module: &File) -> bool {
+    module_name(module).is_some()
+}
+
+// replacements have to be usable wherever the built in one was
+fn check(name: &str, data: &[u8], builtin: Option<&Asset>) -> Option<&'static str> {
+    let builtin = builtin?;
+    if name.starts_with(SOUND_DIR) {
+        // any length works, the audio layer says if it can't decode it
+        return None;
+    }
+    if data.len() != builtin.data.len() {
+        return Some("size differs from the built in one");
     }
+    if name.ends_with(".bin") && bytemuck::try_cast_slice::<u8, u32>(data).is_err() {
+        return Some("not 4 byte aligned");
+    }
+    None
+}
+
+pub fn get(name: &str) -> Option<&'static [u8]> {
+    ASSETS.read().get(name).map(|x| x.data)
+}
+
+// falls back to the embedded font when the registry is busy, so a panic can still
+// print with it
+pub fn console_font() -> &'static [u8] {
+    ASSETS
+        .try_read()
+        .and_then(|x| x.get(CONSOLE_FONT).map(|x| x.data))
+        .unwrap_or(BUILTIN_FONT)
+}
+
+fn sprite(name: &str) -> &'static [u32] {
+    bytemuck::cast_slice(get(name).expect("sprite missing from the asset registry"))
+}
+
+// name, size and where each asset came from
+pub fn list() -> Vec<(String, usize, AssetSource)> {
+    ASSETS
+        .read()
+        .iter()
+        .map(|(name, asset)| (name.clone(), asset.data.len(), asset.source))
+        .collect()
 }
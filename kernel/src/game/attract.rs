@@ -0,0 +1,95 @@
+/*
+    Copyright (C) 2025 bugo07
+    Released under EUPL 1.2 License
+*/
+
+// `mode=attract` on the cmdline. the game starts straight into a round the bird flies
+// by itself and starts over a bit after every game over, until a key gets pressed and
+// it drops back to the main menu like a normal boot
+
+use bevy_ecs::prelude::*;
+use bevy_math::{UVec2, Vec2};
+
+use crate::{
+    arch::keyboard::KeyboardState,
+    assets::{FLAPPY_BIRD_SIZE, PIPE_SIZE},
+    game::{
+        MenuState, StateScoped,
+        player::{Player, flap, pipe_gap},
+        state::NextState,
+        ui::{Anchor, UiNode, label},
+    },
+    utils::{
+        cmdline::{BootConfig, BootMode},
+        fb::Framebuffer,
+    },
+};
+
+use super::ecs::*;
+
+// how long the game over screen stays up before the next round
+const RESTART_NS: u64 = 3_000_000_000;
+// how far above the bottom pipe the bird tries to stay
+const MARGIN: f32 = 12.0;
+
+pub fn attracting(config: Res<BootConfig>) -> bool {
+    config.mode == BootMode::Attract
+}
+
+pub fn attract_setup(mut commands: Commands) {
+    commands.spawn((
+        label(
+            UiNode::new(Anchor::Bottom).with_offset(Vec2::new(0.0, -16.0)),
+            Text::new("DEMO - PRESS ANY KEY").with_shadow(UVec2::new(1, 1), 0xABABAB),
+            2.0,
+        ),
+        StateScoped(MenuState::Playing),
+    ));
+}
+
+// flaps whenever the bird sinks close to the next gap's bottom edge
+pub fn attract_pilot(
+    player: Single<(&mut Transform, &mut Velocity), With<Player>>,
+    pipes: Query<&Transform, (With<NineSlice>, Without<Player>)>,
+    fb: Res<Framebuffer>,
+) {
+    let (mut transform, mut velocity) = player.into_inner();
+    let bird = transform.position;
+
+    // bottom pipes are the ones not hanging from the top of the screen
+    let next_gap = pipes
+        .iter()
+        .filter(|pipe| pipe.position.y > 0.0)
+        .filter(|pipe| pipe.position.x + PIPE_SIZE.x * pipe.scale.x > bird.x)
+        .min_by(|a, b| a.position.x.total_cmp(&b.position.x))
+        .map(|pipe| pipe.position.y);
+    let floor = next_gap.unwrap_or((fb.size.y as f32 + pipe_gap(&fb)) / 2.0);
+
+    if velocity.linear.y >= 0.0 && bird.y + FLAPPY_BIRD_SIZE.y > floor - MARGIN {
+        flap(&mut transform, &mut velocity);
+    }
+}
+
+pub fn attract_restart(
+    time: Res<Time>,
+    mut state: ResMut<NextState<MenuState>>,
+    mut restart_at: Local<Option<u64>>,
+) {
+    let at = *restart_at.get_or_insert(time.elapsed_ns + RESTART_NS);
+    if time.elapsed_ns >= at {
+        *restart_at = None;
+        state.set(MenuState::Playing);
+    }
+}
+
+// any key hands the game back
+pub fn attract_exit(
+    keyboard: Res<KeyboardState>,
+    mut config: ResMut<BootConfig>,
+    mut state: ResMut<NextState<MenuState>>,
+) {
+    if keyboard.just_pressed_any() {
+        config.mode = BootMode::Normal;
+        state.set(MenuState::Main);
+    }
+}
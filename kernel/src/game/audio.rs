@@ -0,0 +1,122 @@
+/*
+    Copyright (C) 2025 bugo07
+    Released under EUPL 1.2 License
+*/
+
+// sound effects for the game. `PlaySound` events are fire and forget, an `AudioSource`
+// plays for as long as its entity has it. without a sound card each effect falls back to
+// a pc speaker beep, and the attract demo plays silently
+
+use alloc::{collections::btree_map::BTreeMap, vec::Vec};
+use bevy_ecs::prelude::*;
+
+use crate::arch::audio::{self, Tone, mixer::VoiceId};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SoundEffect {
+    // under sounds/ in the assets, without the .wav
+    pub name: &'static str,
+    pub tone: Tone,
+}
+
+pub const FLAP: SoundEffect = SoundEffect::new("flap", Tone::new(660, 30));
+pub const SCORE: SoundEffect = SoundEffect::new("score", Tone::new(1319, 80));
+pub const HIT: SoundEffect = SoundEffect::new("hit", Tone::new(196, 80));
+pub const DIE: SoundEffect = SoundEffect::new("die", Tone::new(110, 300));
+
+impl SoundEffect {
+    pub const fn new(name: &'static str, tone: Tone) -> Self {
+        Self { name, tone }
+    }
+
+    fn play(&self, volume: f32, looping: bool) -> Option<VoiceId> {
+        let voice = audio::play(self.name, volume, looping);
+        if !audio::has_card() && volume > 0.0 {
+            audio::beep(self.tone);
+        }
+        voice
+    }
+}
+
+#[derive(Event, Debug, Clone, Copy)]
+pub struct PlaySound {
+    pub effect: SoundEffect,
+    pub volume: f32,
+}
+
+impl PlaySound {
+    pub fn new(effect: SoundEffect) -> Self {
+        Self {
+            effect,
+            volume: 1.0,
+        }
+    }
+
+    pub fn with_volume(mut self, volume: f32) -> Self {
+        self.volume = volume;
+        self
+    }
+}
+
+// starts when added, stops when removed or the entity goes away
+#[derive(Component, Debug, Clone, Copy)]
+pub struct AudioSource {
+    pub effect: SoundEffect,
+    pub volume: f32,
+    pub looping: bool,
+}
+
+impl AudioSource {
+    pub fn new(effect: SoundEffect) -> Self {
+        Self {
+            effect,
+            volume: 1.0,
+            looping: false,
+        }
+    }
+
+    pub fn with_volume(mut self, volume: f32) -> Self {
+        self.volume = volume;
+        self
+    }
+
+    pub fn with_looping(mut self) -> Self {
+        self.looping = true;
+        self
+    }
+}
+
+// the same effect twice in a frame (a couple of pipes hit at once) only plays once
+pub fn play_sounds(mut events: EventReader<PlaySound>) {
+    let mut played = Vec::new();
+    for event in events.read() {
+        if !played.contains(&event.effect) {
+            played.push(event.effect);
+            event.effect.play(event.volume, false);
+        }
+    }
+}
+
+pub fn audio_sources(
+    sources: Query<(Entity, &AudioSource)>,
+    mut voices: Local<BTreeMap<Entity, Option<VoiceId>>>,
+) {
+    voices.retain(|entity, voice| {
+        let kept = sources.contains(*entity);
+        if let (false, Some(voice)) = (kept, voice) {
+            audio::stop(*voice);
+        }
+        kept
+    });
+
+    for (entity, source) in &sources {
+        voices
+            .entry(entity)
+            .or_insert_with(|| source.effect.play(source.volume, source.looping));
+    }
+}
+
+// events live for two updates, so every reader sees them once
+pub fn update_sound_events(mut events: ResMut<Events<PlaySound>>) {
+    events.update();
+}
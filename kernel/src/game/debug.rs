@@ -0,0 +1,99 @@
+/*
+    Copyright (C) 2025 bugo07
+    Released under EUPL 1.2 License
+*/
+
+// the world lives on the game thread, so the serial shell queues requests here and
+// the game loop runs them between frames
+
+use alloc::{collections::vec_deque::VecDeque, vec::Vec};
+use bevy_ecs::prelude::*;
+
+use crate::{
+    game::{
+        MenuState, PlayState,
+        ecs::VirtualTime,
+        pause,
+        scores::SaveData,
+        state::{NextState, State},
+    },
+    println,
+    utils::{fb::Framebuffer, image::ImageFormat, save::SPEED_PERCENT, screenshot},
+};
+
+pub enum DebugRequest {
+    ListEntities,
+    SetState(MenuState),
+    SetSpeed(f32),
+    // same as esc, so the clock and the menu go along with it
+    TogglePause,
+    Screenshot(ImageFormat),
+}
+
+static REQUESTS: spin::Mutex<VecDeque<DebugRequest>> = spin::Mutex::new(VecDeque::new());
+
+pub fn request(request: DebugRequest) {
+    REQUESTS.lock().push_back(request);
+}
+
+pub fn run_debug_requests(world: &mut World) {
+    // don't spin if the shell got preempted while pushing
+    let Some(mut requests) = REQUESTS.try_lock() else {
+        return;
+    };
+    let requests = core::mem::take(&mut *requests);
+
+    for request in requests {
+        match request {
+            DebugRequest::ListEntities => list_entities(world),
+            DebugRequest::SetState(state) => {
+                println!(
+                    "state: {:?} -> {state:?}",
+                    **world.resource::<State<MenuState>>()
+                );
+                world.resource_mut::<NextState<MenuState>>().set(state);
+            }
+            DebugRequest::SetSpeed(speed) => {
+                let mut time = world.resource_mut::<VirtualTime>();
+                time.set_speed(speed);
+                let speed_percent = (time.speed * 100.0) as u16;
+                // stopping the clock is for this boot only
+                if SPEED_PERCENT.contains(&speed_percent) {
+                    let mut save = world.resource_mut::<SaveData>();
+                    save.0.settings.speed_percent = speed_percent;
+                    save.store();
+                }
+            }
+            DebugRequest::TogglePause => {
+                match world.get_resource::<State<PlayState>>().map(|x| **x) {
+                    Some(state) => {
+                        let next = pause::toggled(state);
+                        println!("play state: {state:?} -> {next:?}");
+                        world.resource_mut::<NextState<PlayState>>().set(next);
+                    }
+                    None => println!("not playing, nothing to pause"),
+                }
+            }
+            DebugRequest::Screenshot(format) => {
+                screenshot::capture(world.resource::<Framebuffer>(), format);
+            }
+        }
+    }
+}
+
+fn list_entities(world: &World) {
+    let mut count = 0;
+    for entity in world.iter_entities() {
+        let components = world
+            .inspect_entity(entity.id())
+            .map(|infos| {
+                infos
+                    .map(|info| info.name().rsplit("::").next().unwrap_or(info.name()))
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        println!("{:>8} {}", entity.id(), components.join(", "));
+        count += 1;
+    }
+    println!("{count} entities");
+}
@@ -3,13 +3,24 @@
     Released under EUPL 1.2 License
 */
 
-use alloc::string::{String, ToString};
+use alloc::{
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
 use bevy_ecs::{prelude::*, schedule::ScheduleLabel};
 use bevy_math::{UVec2, Vec2};
 use pc_keyboard::KeyCode;
 use rand::rngs::SmallRng;
 
-use crate::{arch::keyboard::KeyboardState, game::MenuState, utils::fb::Framebuffer};
+use crate::{
+    arch::{keyboard::KeyboardState, smp},
+    game::FRAMETIME_60FPS,
+    utils::{
+        fb::{Framebuffer, Insets, Sampling, SliceFill},
+        font::{Font, TextStyle},
+    },
+};
 
 #[derive(ScheduleLabel, Hash, PartialEq, Eq, Debug, Clone)]
 pub struct Startup;
@@ -28,15 +39,136 @@ pub struct Time {
     pub fixed_delta_secs: f32, // idfk very sheice
 }
 
+// longest step the game clock takes at once, anything past it (a launched game, the
+// debug pause, a slow frame) is just lost instead of catching up
+pub const MAX_VIRTUAL_DELTA_NS: u64 = 250_000_000;
+// keeps the scaled delta well inside a u64
+pub const MAX_SPEED: f32 = 16.0;
+
+// the clock gameplay runs on. it stops while paused, runs at `speed` and is advanced
+// from the real Time by game_loop
+#[derive(Resource, Debug)]
+pub struct VirtualTime {
+    pub elapsed_ns: u64,
+    pub delta_secs: f32,
+    pub speed: f32,
+    pub paused: bool,
+    // real length of a FixedUpdate tick, from the fps option
+    pub tick_secs: f32,
+}
+
+impl Default for VirtualTime {
+    fn default() -> Self {
+        Self {
+            elapsed_ns: 0,
+            delta_secs: 0.0,
+            speed: 1.0,
+            paused: false,
+            tick_secs: FRAMETIME_60FPS,
+        }
+    }
+}
+
+impl VirtualTime {
+    pub fn advance(&mut self, real_delta_ns: u64) {
+        let delta_ns = if self.paused {
+            0
+        } else {
+            (real_delta_ns.min(MAX_VIRTUAL_DELTA_NS) as f32 * self.speed) as u64
+        };
+        self.elapsed_ns += delta_ns;
+        self.delta_secs = delta_ns as f32 / 1_000_000_000.0;
+    }
+
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn unpause(&mut self) {
+        self.paused = false;
+    }
+
+    pub fn set_speed(&mut self, speed: f32) {
+        if !speed.is_nan() {
+            self.speed = speed.clamp(0.0, MAX_SPEED);
+        }
+    }
+
+    // what one FixedUpdate tick is worth in game time
+    pub fn fixed_delta_secs(&self) -> f32 {
+        if self.paused {
+            0.0
+        } else {
+            self.tick_secs * self.speed
+        }
+    }
+}
+
 #[derive(Component)]
 pub struct Sprite {
     pub data: &'static [u32],
     pub size: Vec2,
+    pub sampling: Sampling,
 }
 
 impl Sprite {
     pub fn new(data: &'static [u32], size: Vec2) -> Self {
-        Self { data, size }
+        Self {
+            data,
+            size,
+            sampling: Sampling::Nearest,
+        }
+    }
+
+    pub fn with_sampling(mut self, sampling: Sampling) -> Self {
+        self.sampling = sampling;
+        self
+    }
+}
+
+// a sprite drawn at `size` (times the transform's scale) with its `insets` border kept
+// as is, for things like the pipes where only the body should grow
+#[derive(Component)]
+pub struct NineSlice {
+    pub data: &'static [u32],
+    pub texture_size: Vec2,
+    pub insets: Insets,
+    pub size: Vec2,
+    pub fill: SliceFill,
+}
+
+impl NineSlice {
+    pub fn new(data: &'static [u32], texture_size: Vec2, insets: Insets, size: Vec2) -> Self {
+        Self {
+            data,
+            texture_size,
+            insets,
+            size,
+            fill: SliceFill::Stretch,
+        }
+    }
+
+    pub fn with_fill(mut self, fill: SliceFill) -> Self {
+        self.fill = fill;
+        self
+    }
+}
+
+// a sprite repeated over `size` (times the transform's scale)
+#[derive(Component)]
+pub struct TiledSprite {
+    pub data: &'static [u32],
+    pub texture_size: Vec2,
+    pub size: Vec2,
+}
+
+impl TiledSprite {
+    pub fn new(data: &'static [u32], texture_size: Vec2, size: Vec2) -> Self {
+        Self {
+            data,
+            texture_size,
+            size,
+        }
     }
 }
 
@@ -58,6 +190,9 @@ pub struct Text {
     pub fg: u32,
     pub bg: Option<u32>,
     pub shadow: Option<(UVec2, u32)>,
+    // proportional text, otherwise it's the built-in font scaled by the transform.
+    // `bg` only works without one
+    pub font: Option<(Arc<Font>, TextStyle)>,
 }
 
 impl Text {
@@ -67,9 +202,15 @@ impl Text {
             fg: 0xFFFFFFFF,
             bg: None,
             shadow: None,
+            font: None,
         }
     }
 
+    pub fn with_font(mut self, font: Arc<Font>, style: TextStyle) -> Self {
+        self.font = Some((font, style));
+        self
+    }
+
     pub fn with_color(mut self, fg: u32) -> Self {
         self.fg = fg;
         self
@@ -186,19 +327,9 @@ impl Collider {
     }
 }
 
-#[derive(Component)]
-pub struct StateScoped(pub MenuState);
-
 #[derive(Component)]
 pub struct ScreenScoped;
 
-pub fn in_state<R: Resource + PartialEq>(state: R) -> impl FnMut(Option<Res<R>>) -> bool {
-    move |current_state: Option<Res<R>>| match current_state {
-        Some(current_state) => *current_state == state,
-        None => false,
-    }
-}
-
 pub fn input_just_pressed(key: KeyCode) -> impl FnMut(Option<Res<KeyboardState>>) -> bool {
     move |current_state: Option<Res<KeyboardState>>| match current_state {
         Some(current_state) => current_state.just_pressed(key),
@@ -213,38 +344,51 @@ pub fn input_pressed(key: KeyCode) -> impl FnMut(Option<Res<KeyboardState>>) ->
     }
 }
 
-pub fn state_scoped(
-    mut commands: Commands,
-    state: Res<MenuState>,
-    query: Query<(Entity, &StateScoped)>,
-) {
-    for (entity, scope) in &query {
-        if scope.0 != *state {
-            commands.entity(entity).try_despawn();
-        }
-    }
-}
-
 pub fn screen_scoped(
     mut commands: Commands,
     fb: Res<Framebuffer>,
     sprite_q: Query<(Entity, &Transform, &Sprite), With<ScreenScoped>>,
     rect_q: Query<(Entity, &Transform, &Rect), With<ScreenScoped>>,
+    slice_q: Query<(Entity, &Transform, &NineSlice), With<ScreenScoped>>,
+    tiled_q: Query<(Entity, &Transform, &TiledSprite), With<ScreenScoped>>,
 ) {
-    for (entity, transform, sprite) in &sprite_q {
-        if transform.position.x + sprite.size.x * transform.scale.x < 0.0
-            || transform.position.x - sprite.size.x * transform.scale.x > fb.size.x as f32
-        {
-            commands.entity(entity).try_despawn();
+    let mut bodies = sprite_q
+        .iter()
+        .map(|(entity, transform, sprite)| (entity, transform, sprite.size))
+        .chain(
+            rect_q
+                .iter()
+                .map(|(entity, transform, rect)| (entity, transform, rect.size)),
+        )
+        .chain(
+            slice_q
+                .iter()
+                .map(|(entity, transform, slice)| (entity, transform, slice.size)),
+        )
+        .chain(
+            tiled_q
+                .iter()
+                .map(|(entity, transform, tiled)| (entity, transform, tiled.size)),
+        )
+        .collect::<Vec<_>>();
+
+    let width = fb.size.x as f32;
+    let offscreen = spin::Mutex::new(Vec::new());
+
+    smp::par_chunks_mut(&mut bodies, |_, chunk| {
+        let mut found = Vec::new();
+        for (entity, transform, size) in chunk.iter() {
+            if transform.position.x + size.x * transform.scale.x < 0.0
+                || transform.position.x - size.x * transform.scale.x > width
+            {
+                found.push(*entity);
+            }
         }
-    }
+        offscreen.lock().append(&mut found);
+    });
 
-    for (entity, transform, rect) in &rect_q {
-        if transform.position.x + rect.size.x * transform.scale.x < 0.0
-            || transform.position.x - rect.size.x * transform.scale.x > fb.size.x as f32
-        {
-            commands.entity(entity).try_despawn();
-        }
+    for entity in offscreen.into_inner() {
+        commands.entity(entity).try_despawn();
     }
 }
 
@@ -3,40 +3,67 @@
     Released under EUPL 1.2 License
 */
 
-use core::{cell::OnceCell, ops::Range};
+use core::{cell::OnceCell, ops::Range, time::Duration};
 
 use bevy_ecs::prelude::*;
 
-use alloc::{collections::vec_deque::VecDeque, vec::Vec};
+use alloc::{collections::vec_deque::VecDeque, format, vec::Vec};
 use bevy_math::{UVec2, Vec2};
 
+pub mod attract;
+pub mod audio;
+pub mod debug;
 pub mod ecs;
+pub mod pause;
 pub mod physics;
 pub mod player;
 pub mod render;
+pub mod scores;
+pub mod state;
+pub mod ui;
 
-use pc_keyboard::{HandleControl, KeyCode, Keyboard, ScancodeSet1, layouts::Us104Key};
+use pc_keyboard::{HandleControl, KeyCode, Keyboard, ScancodeSet1};
 use rand::{Rng, SeedableRng, distr::uniform::SampleUniform};
 
 use crate::{
     arch::{
+        elf,
         keyboard::{KeyboardState, keyboard_system},
-        time::preferred_timer_ns,
+        time::{preferred_timer_ns, sleep},
     },
     game::{
+        attract::*,
+        audio::{PlaySound, audio_sources, play_sounds, update_sound_events},
+        debug::run_debug_requests,
         ecs::*,
+        pause::*,
         physics::{collision_check, physics_update},
-        player::{Score, game_over, player_setup, player_update, update_score},
+        player::{Score, game_over, player_setup, player_update},
         render::render_fixed_update,
+        scores::{SaveData, high_score_table, initials_input},
+        state::*,
+        ui::*,
     },
-    utils::{bootloader::get_framebuffers, fb::Framebuffer},
+    utils::{
+        cmdline::{self, BootMode},
+        console,
+        executor::yield_now,
+        fb::Framebuffer,
+        image::ImageFormat,
+        save, screenshot, video,
+    },
+    warn,
 };
 
 pub const FRAMETIME_60FPS: f32 = 1.0 / 60.0;
 
+// what the game draws at, whatever the screen. gameplay is tuned to it so it plays the
+// same everywhere
+pub const VIRTUAL_SIZE: UVec2 = UVec2::new(480, 640);
+
 pub static mut WORLD: OnceCell<World> = OnceCell::new();
 
-#[derive(Resource, Default, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq, Hash)]
 pub enum MenuState {
     #[default]
     Main,
@@ -44,49 +71,215 @@ pub enum MenuState {
     GameOver,
 }
 
-pub fn setup(mut commands: Commands, fb: Res<Framebuffer>) {
-    let s = "WELCOME TO FLAPPYOS";
+impl States for MenuState {}
+
+// only there while playing
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq, Hash)]
+pub enum PlayState {
+    #[default]
+    Running,
+    Paused,
+    Countdown,
+}
+
+impl States for PlayState {}
+
+impl SubStates for PlayState {
+    type Source = MenuState;
+
+    fn exists_in(source: &MenuState) -> bool {
+        *source == MenuState::Playing
+    }
+}
+
+// the menu buttons, activated through the ui focus
+#[derive(Component, Clone, Copy, Debug)]
+pub enum MenuAction {
+    Play,
+    Launch(usize),
+    // plays the pipes of a saved run again
+    Replay(u64),
+    Resume,
+    Restart,
+    Quit,
+}
 
+pub fn setup(mut commands: Commands, save: Res<SaveData>) {
     commands.spawn((
-        Text::new(s).with_shadow(UVec2::new(2, 2), 0xABABAB),
-        Transform::from_translation(
-            UVec2::new(fb.centered_str_x(s, 2.0), fb.font_height * 2).as_vec2(),
-        )
-        .with_scale(Vec2::splat(2.0)),
+        label(
+            UiNode::new(Anchor::Top).with_offset(Vec2::new(0.0, 32.0)),
+            Text::new("WELCOME TO FLAPPYOS").with_shadow(UVec2::new(2, 2), 0xABABAB),
+            2.0,
+        ),
         StateScoped(MenuState::Main),
     ));
 
-    let s = "PRESS SPACE TO BEGIN";
+    commands
+        .spawn((
+            UiNode::new(Anchor::Center).with_layout(Layout::Vertical(8.0)),
+            StateScoped(MenuState::Main),
+        ))
+        .with_children(|menu| {
+            menu.spawn((
+                button(
+                    UiNode::default().with_width(Val::Percent(100.0)),
+                    Text::new("PLAY FLAPPY BIRD"),
+                    2.0,
+                ),
+                MenuAction::Play,
+            ));
+
+            if let Some(best) = save.0.high_scores.first() {
+                menu.spawn((
+                    button(
+                        UiNode::default().with_width(Val::Percent(100.0)),
+                        Text::new("REPLAY BEST RUN"),
+                        1.0,
+                    ),
+                    MenuAction::Replay(best.seed),
+                ));
+            }
+
+            // games loaded from boot modules
+            for (i, program) in elf::programs().iter().take(GAME_KEYS.len()).enumerate() {
+                let s = &format!("{} - PLAY {}", i + 1, program.name.to_uppercase());
+                menu.spawn((
+                    button(
+                        UiNode::default().with_width(Val::Percent(100.0)),
+                        Text::new(s),
+                        1.0,
+                    ),
+                    MenuAction::Launch(i),
+                ));
+            }
+        });
 
     commands.spawn((
-        Text::new(s).with_shadow(UVec2::new(2, 2), 0xABABAB),
-        Transform::from_translation(
-            UVec2::new(
-                fb.centered_str_x(s, 2.0),
-                fb.centered_str_y(2.0) + fb.font_height * 2,
-            )
-            .as_vec2(),
-        )
-        .with_scale(Vec2::splat(2.0)),
+        label(
+            UiNode::new(Anchor::Bottom).with_offset(Vec2::new(0.0, -16.0)),
+            Text::new("UP/DOWN TO PICK, SPACE TO START").with_shadow(UVec2::new(1, 1), 0xABABAB),
+            1.0,
+        ),
         StateScoped(MenuState::Main),
     ));
+
+    if !save.0.high_scores.is_empty() {
+        commands.spawn((
+            label(
+                UiNode::new(Anchor::Bottom).with_offset(Vec2::new(0.0, -48.0)),
+                Text::new(&high_score_table(&save.0)).with_shadow(UVec2::new(1, 1), 0xABABAB),
+                1.0,
+            ),
+            StateScoped(MenuState::Main),
+        ));
+    }
+}
+
+const GAME_KEYS: [KeyCode; 9] = [
+    KeyCode::Key1,
+    KeyCode::Key2,
+    KeyCode::Key3,
+    KeyCode::Key4,
+    KeyCode::Key5,
+    KeyCode::Key6,
+    KeyCode::Key7,
+    KeyCode::Key8,
+    KeyCode::Key9,
+];
+
+// picked from the menu, the game loop runs it once the frame is done
+#[derive(Resource, Default)]
+pub struct LaunchRequest(pub Option<usize>);
+
+// the number keys skip the menu
+pub fn launch_program(keyboard: Res<KeyboardState>, mut launch: ResMut<LaunchRequest>) {
+    let Some(index) = GAME_KEYS
+        .iter()
+        .take(elf::programs().len())
+        .position(|&key| keyboard.just_pressed(key))
+    else {
+        return;
+    };
+
+    launch.0 = Some(index);
 }
 
-pub fn press_space_to_begin(mut state: ResMut<MenuState>) {
-    *state = MenuState::Playing;
+// runs the picked game, nothing of ours gets polled until it exits
+async fn run_program(world: &mut World, index: usize) {
+    if !elf::exec(index).await {
+        warn!("couldn't start {}", elf::programs()[index].name);
+    }
+    // the game had the keyboard and the screens in the meantime
+    world.resource::<Framebuffer>().clear_screens();
+    let mut keyboard = world.resource_mut::<KeyboardState>();
+    keyboard.keys_down.clear();
+    keyboard.scancodes.clear();
 }
 
-pub fn game_loop() -> ! {
+// ` shows the log console, page up/down scroll it
+pub fn console_input(keyboard: Res<KeyboardState>) {
+    if keyboard.just_pressed(KeyCode::Oem8) {
+        console::toggle();
+    }
+    if console::is_visible() {
+        if keyboard.just_pressed(KeyCode::PageUp) {
+            console::scroll(10);
+        }
+        if keyboard.just_pressed(KeyCode::PageDown) {
+            console::scroll(-10);
+        }
+    }
+}
+
+pub fn screenshot_hotkey(keyboard: Res<KeyboardState>, fb: Res<Framebuffer>) {
+    if keyboard.just_pressed(KeyCode::F12) {
+        screenshot::capture(&fb, ImageFormat::Png);
+    }
+}
+
+pub fn menu_actions(
+    focus: Res<UiFocus>,
+    actions: Query<&MenuAction>,
+    mut state: ResMut<NextState<MenuState>>,
+    mut play_state: ResMut<NextState<PlayState>>,
+    mut launch: ResMut<LaunchRequest>,
+    mut score: ResMut<Score>,
+) {
+    let Some(&action) = focus.activated.and_then(|entity| actions.get(entity).ok()) else {
+        return;
+    };
+
+    match action {
+        MenuAction::Play => state.set(MenuState::Playing),
+        MenuAction::Launch(index) => launch.0 = Some(index),
+        MenuAction::Replay(seed) => {
+            score.replay = Some(seed);
+            state.set(MenuState::Playing);
+        }
+        MenuAction::Resume => play_state.set(PlayState::Countdown),
+        MenuAction::Restart => state.reenter(MenuState::Playing),
+        MenuAction::Quit => state.set(MenuState::Main),
+    }
+}
+
+pub async fn game_loop() {
     unsafe { WORLD.set(World::new()).unwrap() };
 
     let world = unsafe { WORLD.get_mut().unwrap() };
 
-    world.insert_resource(Framebuffer::new_from_limine(
-        &get_framebuffers().next().unwrap(),
+    let config = cmdline::config().clone();
+
+    world.insert_resource(Framebuffer::new_virtual(
+        VIRTUAL_SIZE,
+        config.scale,
+        config.display,
+        &video::screens(),
     ));
 
+    let tick_secs = config.tick_secs();
+
     world.insert_resource(Random {
-        rng: rand::SeedableRng::seed_from_u64(preferred_timer_ns()),
+        rng: rand::SeedableRng::seed_from_u64(config.seed.unwrap_or_else(preferred_timer_ns)),
     });
 
     let time = preferred_timer_ns();
@@ -99,60 +292,140 @@ pub fn game_loop() -> ! {
     });
 
     world.insert_resource(KeyboardState {
-        keyboard: Keyboard::new(ScancodeSet1::new(), Us104Key, HandleControl::Ignore),
+        keyboard: Keyboard::new(
+            ScancodeSet1::new(),
+            config.keymap.layout(),
+            HandleControl::Ignore,
+        ),
         keys_down: Vec::new(),
         last_keys_down: Vec::new(),
         scancodes: VecDeque::new(),
     });
 
-    world.init_resource::<MenuState>();
-    world.init_resource::<Score>();
+    let save = save::loaded();
+    world.insert_resource(VirtualTime {
+        speed: save.settings.speed_percent as f32 / 100.0,
+        tick_secs,
+        ..Default::default()
+    });
+    world.insert_resource(Score {
+        high: save.high_scores.first().map_or(0, |x| x.score),
+        ..Default::default()
+    });
+    world.insert_resource(SaveData(save));
+    world.init_resource::<UiFocus>();
+    world.init_resource::<LaunchRequest>();
+    world.init_resource::<Events<PlaySound>>();
 
-    let mut startup_schedule = Schedule::new(Startup);
-    startup_schedule.add_systems(self::setup);
-    startup_schedule.run(world);
+    init_state(world, MenuState::Main);
+    init_sub_state::<PlayState>(world);
+    if config.mode == BootMode::Attract {
+        world
+            .resource_mut::<NextState<MenuState>>()
+            .set(MenuState::Playing);
+    }
+    let program = config.program.clone();
+    world.insert_resource(config);
+
+    let mut schedules = world.get_resource_or_init::<Schedules>();
+    schedules.add_systems(OnEnter(MenuState::Main), setup);
+    schedules.add_systems(OnEnter(MenuState::Playing), player_setup);
+    schedules.add_systems(
+        OnEnter(MenuState::Playing),
+        attract_setup.after(player_setup).run_if(attracting),
+    );
+    schedules.add_systems(OnEnter(MenuState::GameOver), game_over);
+    schedules.add_systems(OnEnter(PlayState::Running), resume_clock);
+    schedules.add_systems(OnExit(PlayState::Running), pause_clock);
+    schedules.add_systems(OnEnter(PlayState::Paused), pause_menu);
+    schedules.add_systems(OnEnter(PlayState::Countdown), countdown_setup);
 
     let mut update_schedule = Schedule::new(Update);
 
     // actual update schedule
     update_schedule.add_systems((
-        player_update,
+        player_update.run_if(in_state(PlayState::Running)),
         keyboard_system,
-        update_score,
+        bind_text::<Score>,
         screen_scoped,
-        press_space_to_begin.run_if(
-            not(resource_exists_and_equals(MenuState::Playing))
-                .and(input_just_pressed(KeyCode::Spacebar)),
-        ),
+        ui_navigation.after(keyboard_system),
+        ui_button_style.after(ui_navigation),
+        menu_actions.after(ui_navigation),
+        console_input.after(keyboard_system),
+        screenshot_hotkey.after(keyboard_system),
+        launch_program
+            .after(keyboard_system)
+            .run_if(in_state(MenuState::Main)),
+        countdown_update.run_if(in_state(PlayState::Countdown)),
+        attract_pilot
+            .before(player_update)
+            .run_if(attracting.and(in_state(PlayState::Running))),
+        attract_restart.run_if(attracting.and(in_state(MenuState::GameOver))),
+        attract_exit
+            .after(keyboard_system)
+            .after(menu_actions)
+            .run_if(attracting),
+        initials_input
+            .after(keyboard_system)
+            .run_if(in_state(MenuState::GameOver)),
+        toggle_pause
+            .after(keyboard_system)
+            .run_if(in_state(MenuState::Playing).and(input_just_pressed(KeyCode::Escape))),
+        (play_sounds, audio_sources)
+            .after(player_update)
+            .run_if(not(attracting)),
+        update_sound_events.after(play_sounds),
     ));
 
-    // onenter
-    update_schedule.add_systems(
-        (
-            state_scoped,
-            setup.run_if(in_state(MenuState::Main)),
-            player_setup.run_if(in_state(MenuState::Playing)),
-            game_over.run_if(in_state(MenuState::GameOver)),
-        )
-            .run_if(resource_changed::<MenuState>),
-    );
-
     let mut fixed_update_schedule = Schedule::new(FixedUpdate);
-    fixed_update_schedule.add_systems((physics_update, collision_check, render_fixed_update));
+    fixed_update_schedule.add_systems((
+        (physics_update, collision_check).run_if(in_state(PlayState::Running)),
+        ui_layout.before(render_fixed_update),
+        render_fixed_update,
+    ));
+
+    // a ring 3 program picked on the cmdline goes first, we're the fallback once it exits
+    if let Some(name) = program {
+        match elf::programs().iter().position(|x| x.name == name) {
+            Some(index) => run_program(world, index).await,
+            None => warn!("no program called {name}, starting the game"),
+        }
+    }
 
     loop {
+        run_debug_requests(world);
+        run_state_transitions(world);
+
         let mut time = world.get_resource_mut::<Time>().unwrap();
         time.last_time = time.elapsed_ns;
         time.elapsed_ns = preferred_timer_ns();
-
         let delta = time.elapsed_ns - time.last_time;
         time.delta_secs = delta as f32 / 1_000_000_000.0;
-        time.fixed_delta_secs += time.delta_secs;
-        if time.fixed_delta_secs >= FRAMETIME_60FPS {
-            time.fixed_delta_secs -= FRAMETIME_60FPS;
+        // after a stall run one tick instead of all the missed ones back to back
+        time.fixed_delta_secs = (time.fixed_delta_secs + time.delta_secs).min(tick_secs * 2.0);
+        let run_fixed = time.fixed_delta_secs >= tick_secs;
+        if run_fixed {
+            time.fixed_delta_secs -= tick_secs;
+        }
+        world.resource_mut::<VirtualTime>().advance(delta);
+
+        if run_fixed {
             fixed_update_schedule.run(world);
         }
         update_schedule.run(world);
+
+        if let Some(index) = world.resource_mut::<LaunchRequest>().0.take() {
+            run_program(world, index).await;
+        }
+
+        // the other tasks get the cpu until the next tick is due, or at least once when
+        // we're behind
+        let wait = tick_secs - world.resource::<Time>().fixed_delta_secs;
+        if wait > 0.0 {
+            sleep(Duration::from_nanos((wait * 1_000_000_000.0) as u64)).await;
+        } else {
+            yield_now().await;
+        }
     }
 }
 
@@ -0,0 +1,113 @@
+/*
+    Copyright (C) 2025 bugo07
+    Released under EUPL 1.2 License
+*/
+
+// esc pauses a round. the menu can resume (through a 3-2-1 countdown so you can find
+// the bird again), restart or quit, and the game clock only runs in PlayState::Running
+
+use alloc::string::ToString;
+use bevy_ecs::prelude::*;
+use bevy_math::{UVec2, Vec2};
+
+use crate::game::{
+    MenuAction, PlayState,
+    ecs::{Text, Time, VirtualTime},
+    state::{NextState, State, StateScoped},
+    ui::{Anchor, Layout, UiNode, Val, button, label, panel},
+};
+
+const COUNTDOWN_NS: u64 = 3_000_000_000;
+
+// counts down on real time, the game clock is stopped while it runs
+#[derive(Component)]
+pub struct Countdown {
+    pub ends_ns: u64,
+}
+
+// what esc (or `pause` in the shell) goes to from `state`
+pub fn toggled(state: PlayState) -> PlayState {
+    match state {
+        PlayState::Running | PlayState::Countdown => PlayState::Paused,
+        PlayState::Paused => PlayState::Countdown,
+    }
+}
+
+pub fn toggle_pause(state: Res<State<PlayState>>, mut next: ResMut<NextState<PlayState>>) {
+    next.set(toggled(**state));
+}
+
+pub fn pause_clock(mut time: ResMut<VirtualTime>) {
+    time.pause();
+}
+
+pub fn resume_clock(mut time: ResMut<VirtualTime>) {
+    time.unpause();
+}
+
+pub fn pause_menu(mut commands: Commands) {
+    commands
+        .spawn((
+            panel(
+                UiNode::new(Anchor::Center)
+                    .with_padding(Vec2::splat(16.0))
+                    .with_layout(Layout::Vertical(8.0)),
+                0x202030,
+            ),
+            StateScoped(PlayState::Paused),
+        ))
+        .with_children(|menu| {
+            menu.spawn(label(
+                UiNode::default(),
+                Text::new("PAUSED").with_shadow(UVec2::new(2, 2), 0xABABAB),
+                2.0,
+            ));
+
+            for (s, action) in [
+                ("RESUME", MenuAction::Resume),
+                ("RESTART", MenuAction::Restart),
+                ("QUIT", MenuAction::Quit),
+            ] {
+                menu.spawn((
+                    button(
+                        UiNode::default().with_width(Val::Percent(100.0)),
+                        Text::new(s),
+                        2.0,
+                    ),
+                    action,
+                ));
+            }
+        });
+}
+
+pub fn countdown_setup(mut commands: Commands, time: Res<Time>) {
+    commands.spawn((
+        label(
+            UiNode::new(Anchor::Center),
+            Text::new("3").with_shadow(UVec2::new(3, 3), 0xABABAB),
+            4.0,
+        ),
+        Countdown {
+            ends_ns: time.elapsed_ns + COUNTDOWN_NS,
+        },
+        StateScoped(PlayState::Countdown),
+    ));
+}
+
+pub fn countdown_update(
+    time: Res<Time>,
+    countdown: Single<(&Countdown, &mut Text)>,
+    mut next: ResMut<NextState<PlayState>>,
+) {
+    let (countdown, mut text) = countdown.into_inner();
+    let left = countdown.ends_ns.saturating_sub(time.elapsed_ns);
+    if left == 0 {
+        next.set(PlayState::Running);
+        return;
+    }
+
+    let seconds = left.div_ceil(1_000_000_000).to_string();
+    if text.text != seconds {
+        text.text = seconds;
+    }
+}
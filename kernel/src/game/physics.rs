@@ -3,10 +3,23 @@
     Released under EUPL 1.2 License
 */
 
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use alloc::vec::Vec;
 use bevy_ecs::prelude::*;
 use bevy_math::Vec2;
 
-use crate::{assets::FLAPPY_BIRD_SIZE, game::MenuState, info, utils::fb::Framebuffer};
+use crate::{
+    arch::smp,
+    assets::FLAPPY_BIRD_SIZE,
+    game::{
+        MenuState,
+        audio::{HIT, PlaySound},
+        state::NextState,
+    },
+    info,
+    utils::fb::Framebuffer,
+};
 
 use super::ecs::*;
 
@@ -19,7 +32,8 @@ pub fn aabb_collides(pos1: Vec2, size1: Vec2, pos2: Vec2, size2: Vec2) -> bool {
 pub fn collision_check(
     collider_query: Query<(Entity, &Collider, &Transform)>,
     rigidbody_query: Query<(Entity, &Collider, &RigidBody, &Transform)>,
-    mut state: ResMut<MenuState>,
+    mut state: ResMut<NextState<MenuState>>,
+    mut sounds: EventWriter<PlaySound>,
 ) {
     for (collider_entity, collider, collider_transform) in collider_query.iter() {
         for (rigidbody_entity, rigidbody_collider, rigidbody, rigidbody_transform) in
@@ -34,45 +48,63 @@ pub fn collision_check(
                     rigidbody_collider.size * rigidbody_transform.scale,
                 )
             {
-                *state = MenuState::GameOver;
+                state.set(MenuState::GameOver);
+                sounds.write(PlaySound::new(HIT));
                 info!("Game Over");
             }
         }
     }
 }
 
+// the old 4.9 per tick at 60 fps
+const GRAVITY: f32 = 4.9 * 60.0;
+
 pub fn physics_update(
-    mut state: ResMut<MenuState>,
-    query: Query<(&mut Transform, &mut Velocity, &RigidBody)>,
+    mut state: ResMut<NextState<MenuState>>,
+    mut sounds: EventWriter<PlaySound>,
+    mut query: Query<(&mut Transform, &mut Velocity, &RigidBody)>,
     fb: Res<Framebuffer>,
+    time: Res<VirtualTime>,
 ) {
-    for (mut transform, mut velocity, rigidbody) in query {
-        if rigidbody == &RigidBody::Dynamic {
-            velocity.linear += Vec2::Y * 4.9; // 9.8/2
-            velocity.angular = (-velocity.linear.y).min(0.0) * -0.02; // idek
-        }
+    let dt = time.fixed_delta_secs();
+    let hit_ground = AtomicBool::new(false);
+    let ground = fb.size.y as f32 - FLAPPY_BIRD_SIZE.y;
+    let mut bodies = query.iter_mut().collect::<Vec<_>>();
 
-        // * don't going thru the box
-        // let test_x = Vec2::new(next_pos.x, transform.position.y);
-        // if aabb_collides(test_x, SPRITE_SIZE, box_q.position, box_q.scale) {
-        //     next_pos.x = transform.position.x;
-        // }
+    smp::par_chunks_mut(&mut bodies, |_, chunk| {
+        for (transform, velocity, rigidbody) in chunk {
+            if *rigidbody == &RigidBody::Dynamic {
+                velocity.linear += Vec2::Y * GRAVITY * dt;
+                velocity.angular = (-velocity.linear.y).min(0.0) * -0.02; // idek
+            }
 
-        // let test_y = Vec2::new(next_pos.x, next_pos.y);
-        // if aabb_collides(test_y, SPRITE_SIZE, box_q.position, box_q.scale) {
-        //     next_pos.y = transform.position.y;
-        // }
+            // * don't going thru the box
+            // let test_x = Vec2::new(next_pos.x, transform.position.y);
+            // if aabb_collides(test_x, SPRITE_SIZE, box_q.position, box_q.scale) {
+            //     next_pos.x = transform.position.x;
+            // }
 
-        transform.position += velocity.linear * super::FRAMETIME_60FPS;
+            // let test_y = Vec2::new(next_pos.x, next_pos.y);
+            // if aabb_collides(test_y, SPRITE_SIZE, box_q.position, box_q.scale) {
+            //     next_pos.y = transform.position.y;
+            // }
 
-        transform.position.y = transform.position.y.max(0.0);
+            transform.position += velocity.linear * dt;
 
-        transform.rotation = (transform.rotation + velocity.angular * super::FRAMETIME_60FPS)
-            .clamp(-100.0_f32.to_radians(), 100.0_f32.to_radians());
+            transform.position.y = transform.position.y.max(0.0);
 
-        if transform.position.y > fb.size.y as f32 - FLAPPY_BIRD_SIZE.y {
-            *state = MenuState::GameOver;
-            info!("Game Over");
+            transform.rotation = (transform.rotation + velocity.angular * dt)
+                .clamp(-100.0_f32.to_radians(), 100.0_f32.to_radians());
+
+            if transform.position.y > ground {
+                hit_ground.store(true, Ordering::Relaxed);
+            }
         }
+    });
+
+    if hit_ground.load(Ordering::Relaxed) {
+        state.set(MenuState::GameOver);
+        sounds.write(PlaySound::new(HIT));
+        info!("Game Over");
     }
 }
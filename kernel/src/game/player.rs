@@ -3,19 +3,32 @@
     Released under EUPL 1.2 License
 */
 
+use alloc::format;
 use bevy_ecs::prelude::*;
 use bevy_math::{UVec2, Vec2};
 use pc_keyboard::KeyCode;
+use rand::{Rng, SeedableRng, rngs::SmallRng};
 
 use crate::{
-    arch::keyboard::KeyboardState,
+    arch::{keyboard::KeyboardState, time::preferred_timer_ns},
     assets::{FLAPPY_BIRD_DATA, FLAPPY_BIRD_SIZE, PIPE_DATA, PIPE_FLIPPED_DATA, PIPE_SIZE},
-    game::{MenuState, StateScoped, get_random},
-    utils::fb::Framebuffer,
+    game::{
+        MenuAction, MenuState, StateScoped,
+        audio::{AudioSource, DIE, FLAP, PlaySound, SCORE},
+        scores::{InitialsEntry, SaveData},
+        ui::{Anchor, BindText, Layout, UiNode, button, label, panel},
+    },
+    utils::{
+        cmdline::{BootConfig, BootMode},
+        fb::{Framebuffer, Insets, Sampling, SliceFill},
+    },
 };
 
 use super::ecs::*;
 
+const PIPE_SCALE: f32 = 1.5;
+const PIPE_CAP_HEIGHT: u32 = 13; // rows of pipe.bin before the body starts
+
 #[derive(Component)]
 pub struct Player;
 
@@ -23,24 +36,45 @@ pub struct Player;
 pub struct Score {
     pub current: u32,
     pub high: u32,
+    // the pipes of this round come from it
+    pub seed: u64,
+    // the seed the next round should use instead of a fresh one
+    pub replay: Option<u64>,
 }
 
-#[derive(Component)]
-pub struct ScoreText;
+pub fn player_setup(
+    mut commands: Commands,
+    fb: Res<Framebuffer>,
+    mut score: ResMut<Score>,
+    mut random: ResMut<Random>,
+    config: Res<BootConfig>,
+) {
+    // restarting from the pause menu skips game_over
+    score.current = 0;
+    score.seed = score
+        .replay
+        .take()
+        .or(config.seed)
+        .unwrap_or_else(preferred_timer_ns);
+    random.rng = SmallRng::seed_from_u64(score.seed);
 
-pub fn player_setup(mut commands: Commands, fb: Res<Framebuffer>) {
     commands.spawn((
-        Text::new("SCORE - 0\nHIGH SCORE - 0").with_shadow(UVec2::new(1, 1), 0xABABAB),
-        Transform::from_translation(UVec2::new(5, 5).as_vec2()),
-        ScoreText,
-        StateScoped(MenuState::Main),
+        label(
+            UiNode::new(Anchor::TopLeft).with_offset(Vec2::new(5.0, 5.0)),
+            Text::new("").with_shadow(UVec2::new(1, 1), 0xABABAB),
+            1.0,
+        ),
+        BindText::<Score>(|score| {
+            format!("SCORE - {}\nHIGH SCORE - {}", score.current, score.high)
+        }),
+        StateScoped(MenuState::Playing),
     ));
 
     commands.spawn((
         Transform::from_translation(Vec2::new(fb.size.x as f32 / 3.0, fb.size.y as f32 / 2.0)),
         Velocity::linear(Vec2::ZERO),
         Collider::new(FLAPPY_BIRD_SIZE),
-        Sprite::new(*FLAPPY_BIRD_DATA, FLAPPY_BIRD_SIZE),
+        Sprite::new(*FLAPPY_BIRD_DATA, FLAPPY_BIRD_SIZE).with_sampling(Sampling::Supersampled),
         RigidBody::Dynamic,
         Player,
         StateScoped(MenuState::Playing),
@@ -51,45 +85,59 @@ pub fn player_update(
     mut commands: Commands,
     player: Single<(&mut Transform, &mut Velocity), With<Player>>,
     keyboard_state: Res<KeyboardState>,
-    time: Res<Time>,
+    time: Res<VirtualTime>,
     fb: Res<Framebuffer>,
     mut last_time: Local<u64>,
     mut score: ResMut<Score>,
+    mut random: ResMut<Random>,
+    mut sounds: EventWriter<PlaySound>,
 ) {
     let (mut transform, mut velocity) = player.into_inner();
     if keyboard_state.just_pressed(KeyCode::Spacebar) {
-        velocity.linear.y = -200.0;
-        transform.rotation = -35.0_f32.to_radians();
+        flap(&mut transform, &mut velocity);
+        sounds.write(PlaySound::new(FLAP));
     }
 
     if *last_time + 2_000_000_000 < time.elapsed_ns {
         score.current += 1;
+        sounds.write(PlaySound::new(SCORE));
         let quarter = fb.size.y / 4;
-        let y_pos = get_random(quarter..(quarter * 3)) as f32;
+        let y_pos = random.rng.random_range(quarter..(quarter * 3)) as f32;
+
+        // the caps keep their size and only the bodies grow to reach the gap
+        let scale = Vec2::splat(PIPE_SCALE);
+        let width = PIPE_SIZE.x;
 
         // bottom
         commands.spawn((
-            Transform::from_translation(Vec2::new(fb.size.x as f32, y_pos)).with_scale(Vec2 {
-                x: 1.5,
-                y: (((fb.size.y as f32 - y_pos) / fb.size.y as f32) * 5.0) + 1.0,
-            }),
+            Transform::from_translation(Vec2::new(fb.size.x as f32, y_pos)).with_scale(scale),
             Velocity::linear(Vec2::NEG_X * 200.0),
-            Collider::new(PIPE_SIZE),
-            Sprite::new(*PIPE_DATA, PIPE_SIZE),
+            Collider::new(Vec2::new(width, (fb.size.y as f32 - y_pos) / PIPE_SCALE)),
+            NineSlice::new(
+                *PIPE_DATA,
+                PIPE_SIZE,
+                Insets::new(0, PIPE_CAP_HEIGHT, 0, 0),
+                Vec2::new(width, (fb.size.y as f32 - y_pos) / PIPE_SCALE),
+            )
+            .with_fill(SliceFill::Tile),
             RigidBody::Static,
             ScreenScoped,
             StateScoped(MenuState::Playing),
         ));
 
         // top
+        let top_height = (y_pos - pipe_gap(&fb)).max(0.0);
         commands.spawn((
-            Transform::from_translation(Vec2::new(fb.size.x as f32, 0.0)).with_scale(Vec2 {
-                x: 1.5,
-                y: ((y_pos / fb.size.y as f32) * 5.0) - 1.0,
-            }),
+            Transform::from_translation(Vec2::new(fb.size.x as f32, 0.0)).with_scale(scale),
             Velocity::linear(Vec2::NEG_X * 200.0),
-            Collider::new(PIPE_SIZE),
-            Sprite::new(*PIPE_FLIPPED_DATA, PIPE_SIZE),
+            Collider::new(Vec2::new(width, top_height / PIPE_SCALE)),
+            NineSlice::new(
+                *PIPE_FLIPPED_DATA,
+                PIPE_SIZE,
+                Insets::new(0, 0, 0, PIPE_CAP_HEIGHT),
+                Vec2::new(width, top_height / PIPE_SCALE),
+            )
+            .with_fill(SliceFill::Tile),
             RigidBody::Static,
             ScreenScoped,
             StateScoped(MenuState::Playing),
@@ -100,57 +148,77 @@ pub fn player_update(
     score.high = score.high.max(score.current);
 }
 
-pub fn update_score(mut text: Single<&mut Text, With<ScoreText>>, score: Res<Score>) {
-    text.text = alloc::format!("SCORE - {}\nHIGH SCORE - {}", score.current, score.high);
+pub fn flap(transform: &mut Transform, velocity: &mut Velocity) {
+    velocity.linear.y = -200.0;
+    transform.rotation = -35.0_f32.to_radians();
 }
 
-pub fn game_over(mut commands: Commands, fb: Res<Framebuffer>, mut score: ResMut<Score>) {
-    score.high = score.high.max(score.current);
-
-    let s = "GAME OVER";
+// room between the pipes for the bird to get through
+pub fn pipe_gap(fb: &Framebuffer) -> f32 {
+    (fb.size.y / 4) as f32
+}
 
-    commands.spawn((
-        Text::new(s).with_shadow(UVec2::new(2, 2), 0xABABAB),
-        Transform::from_translation(
-            UVec2::new(
-                fb.centered_str_x(s, 2.0),
-                fb.centered_str_y(2.0) - fb.font_height * 3,
-            )
-            .as_vec2(),
-        )
-        .with_scale(Vec2::splat(2.0)),
-        StateScoped(MenuState::GameOver),
-    ));
+pub fn game_over(
+    mut commands: Commands,
+    mut score: ResMut<Score>,
+    save: Res<SaveData>,
+    config: Res<BootConfig>,
+) {
+    score.high = score.high.max(score.current);
 
-    let s = &alloc::format!(
-        "CURRENT SCORE - {}, HIGH SCORE - {}",
-        score.current,
-        score.high
+    let s = &format!(
+        "CURRENT SCORE - {}\nHIGH SCORE - {}",
+        score.current, score.high
     );
-
-    commands.spawn((
-        Text::new(s).with_shadow(UVec2::new(2, 2), 0xABABAB),
-        Transform::from_translation(
-            UVec2::new(fb.centered_str_x(s, 2.0), fb.centered_str_y(2.0)).as_vec2(),
-        )
-        .with_scale(Vec2::splat(2.0)),
-        StateScoped(MenuState::GameOver),
-    ));
-
-    let s = "PRESS SPACE TO RESTART";
-
-    commands.spawn((
-        Text::new(s).with_shadow(UVec2::new(2, 2), 0xABABAB),
-        Transform::from_translation(
-            UVec2::new(
-                fb.centered_str_x(s, 2.0),
-                fb.centered_str_y(2.0) + fb.font_height * 3,
-            )
-            .as_vec2(),
-        )
-        .with_scale(Vec2::splat(2.0)),
-        StateScoped(MenuState::GameOver),
-    ));
+    // the demo doesn't get to put itself in the table
+    let new_high_score = config.mode != BootMode::Attract && save.0.rank(score.current).is_some();
+
+    commands.spawn((AudioSource::new(DIE), StateScoped(MenuState::GameOver)));
+
+    commands
+        .spawn((
+            panel(
+                UiNode::new(Anchor::Center)
+                    .with_padding(Vec2::splat(16.0))
+                    .with_layout(Layout::Vertical(24.0)),
+                0x202030,
+            ),
+            StateScoped(MenuState::GameOver),
+        ))
+        .with_children(|screen| {
+            screen.spawn(label(
+                UiNode::default(),
+                Text::new("GAME OVER").with_shadow(UVec2::new(2, 2), 0xABABAB),
+                2.0,
+            ));
+            screen.spawn(label(
+                UiNode::default(),
+                Text::new(s).with_shadow(UVec2::new(2, 2), 0xABABAB),
+                2.0,
+            ));
+
+            if new_high_score {
+                screen.spawn(label(
+                    UiNode::default(),
+                    Text::new("NEW HIGH SCORE!\nTYPE YOUR INITIALS AND PRESS ENTER")
+                        .with_shadow(UVec2::new(1, 1), 0xABABAB),
+                    1.0,
+                ));
+                screen.spawn((
+                    label(
+                        UiNode::default(),
+                        Text::new("___").with_shadow(UVec2::new(2, 2), 0xABABAB),
+                        3.0,
+                    ),
+                    InitialsEntry::new(score.current, score.seed),
+                ));
+            } else {
+                screen.spawn((
+                    button(UiNode::default(), Text::new("PRESS SPACE TO RESTART"), 2.0),
+                    MenuAction::Play,
+                ));
+            }
+        });
 
     score.current = 0;
 }
@@ -3,49 +3,174 @@
     Released under EUPL 1.2 License
 */
 
+use alloc::vec::Vec;
 use bevy_ecs::prelude::*;
 
-use crate::utils::fb::Framebuffer;
+use crate::{
+    arch::smp,
+    utils::{
+        console::{self, Cell},
+        fb::Framebuffer,
+        font, recorder,
+    },
+};
 
-use super::ecs::*;
+use super::{
+    ecs::*,
+    ui::{ComputedNode, UiNode},
+};
+
+pub enum DrawCmd<'a> {
+    Rect(&'a Rect, &'a Transform),
+    Sprite(&'a Sprite, &'a Transform),
+    NineSlice(&'a NineSlice, &'a Transform),
+    Tiled(&'a TiledSprite, &'a Transform),
+    Text(&'a Text, &'a Transform),
+    Panel(&'a ComputedNode, u32),
+    Console(&'a [Vec<Cell>], usize),
+}
+
+impl DrawCmd<'_> {
+    pub fn draw(&self, fb: &mut Framebuffer) {
+        match self {
+            DrawCmd::Rect(rect, transform) => fb.draw_rect(
+                transform.position,
+                (rect.size * transform.scale).as_uvec2(),
+                rect.color,
+            ),
+            DrawCmd::Sprite(sprite, transform) => fb.draw_sprite_rotated(
+                transform.position,
+                sprite.size.as_uvec2(),
+                transform.scale,
+                sprite.data,
+                Some(0),
+                transform.rotation,
+                sprite.sampling,
+            ),
+            DrawCmd::NineSlice(slice, transform) => fb.draw_nine_slice(
+                transform.position.floor().as_ivec2(),
+                (slice.size * transform.scale).as_uvec2(),
+                transform.scale,
+                slice.data,
+                slice.texture_size.as_uvec2(),
+                slice.insets,
+                slice.fill,
+                Some(0),
+            ),
+            DrawCmd::Tiled(tiled, transform) => fb.draw_sprite_tiled(
+                transform.position.floor().as_ivec2(),
+                (tiled.size * transform.scale).as_uvec2(),
+                transform.scale,
+                tiled.data,
+                tiled.texture_size.as_uvec2(),
+                Some(0),
+            ),
+            DrawCmd::Text(text, transform) => match &text.font {
+                Some((font, style)) => font::draw_text(
+                    fb,
+                    transform.position.as_ivec2(),
+                    font,
+                    &text.text,
+                    style,
+                    text.fg,
+                    text.shadow
+                        .map(|(offset, color)| (offset.as_ivec2(), color)),
+                ),
+                None => fb.draw_str_with_shadow(
+                    transform.position.as_uvec2(),
+                    &text.text,
+                    text.fg,
+                    text.bg,
+                    transform.scale,
+                    text.shadow,
+                ),
+            },
+            DrawCmd::Panel(node, color) => fb.draw_rect(node.pos, node.size.as_uvec2(), *color),
+            DrawCmd::Console(lines, rows) => console::draw(fb, lines, *rows),
+        }
+    }
+}
+
+// every core gets one horizontal band of the screen and runs the whole draw list clipped to it
+pub fn rasterize_tiled(fb: &mut Framebuffer, cmds: &[DrawCmd], clear_color: u32) {
+    let band_height = fb.size.y.div_ceil(smp::threads() as u32).max(1);
+
+    smp::scope(|s| {
+        for mut band in fb.bands(band_height) {
+            s.spawn(move || {
+                band.clear(clear_color);
+                for cmd in cmds {
+                    cmd.draw(&mut band);
+                }
+            });
+        }
+    });
+    fb.present();
+}
 
 pub fn render_fixed_update(
     mut fb: ResMut<Framebuffer>,
     sprites: Query<(&Sprite, &Transform)>,
-    rects: Query<(&Rect, &Transform)>,
-    texts: Query<(&Text, &Transform)>,
+    rects: Query<(&Rect, &Transform), Without<UiNode>>,
+    slices: Query<(&NineSlice, &Transform)>,
+    tiled: Query<(&TiledSprite, &Transform)>,
+    texts: Query<(&Text, &Transform), Without<UiNode>>,
+    ui: Query<(
+        &ComputedNode,
+        Option<&Rect>,
+        Option<&Text>,
+        Option<&Transform>,
+    )>,
 ) {
-    fb.clear(0x000000);
-
-    for (rect, transform) in &rects {
-        fb.draw_rect(
-            transform.position,
-            (rect.size * transform.scale).as_uvec2(),
-            rect.color,
-        );
-    }
+    let mut cmds = Vec::new();
+    cmds.extend(
+        rects
+            .iter()
+            .map(|(rect, transform)| DrawCmd::Rect(rect, transform)),
+    );
+    cmds.extend(
+        tiled
+            .iter()
+            .map(|(tiled, transform)| DrawCmd::Tiled(tiled, transform)),
+    );
+    cmds.extend(
+        slices
+            .iter()
+            .map(|(slice, transform)| DrawCmd::NineSlice(slice, transform)),
+    );
+    cmds.extend(
+        sprites
+            .iter()
+            .map(|(sprite, transform)| DrawCmd::Sprite(sprite, transform)),
+    );
+    cmds.extend(
+        texts
+            .iter()
+            .map(|(text, transform)| DrawCmd::Text(text, transform)),
+    );
 
-    for (sprite, transform) in &sprites {
-        fb.draw_sprite_rotated(
-            transform.position,
-            sprite.size.as_uvec2(),
-            transform.scale,
-            sprite.data,
-            Some(0),
-            transform.rotation,
-        );
+    // the ui goes over the world, parents before their children
+    let mut nodes = ui
+        .iter()
+        .filter(|(node, ..)| node.order != 0)
+        .collect::<Vec<_>>();
+    nodes.sort_by_key(|(node, ..)| node.order);
+    for (node, rect, text, transform) in nodes {
+        if let Some(rect) = rect {
+            cmds.push(DrawCmd::Panel(node, rect.color));
+        }
+        if let (Some(text), Some(transform)) = (text, transform) {
+            cmds.push(DrawCmd::Text(text, transform));
+        }
     }
 
-    for (text, transform) in &texts {
-        fb.draw_str_with_shadow(
-            transform.position.as_uvec2(),
-            &text.text,
-            text.fg,
-            text.bg,
-            transform.scale,
-            text.shadow,
-        );
+    // goes last so it's on top of everything
+    let console_rows = console::rows_for(&fb);
+    let console_lines = console::snapshot(console_rows);
+    if let Some(lines) = &console_lines {
+        cmds.push(DrawCmd::Console(lines, console_rows));
     }
 
-    fb.present();
+    rasterize_tiled(&mut fb, &cmds, 0x000000);
+    recorder::on_frame(&fb);
 }
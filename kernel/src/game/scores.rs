@@ -0,0 +1,137 @@
+/*
+    Copyright (C) 2025 bugo07
+    Released under EUPL 1.2 License
+*/
+
+// the high score table from the save, and typing your initials in when you make it
+
+use alloc::{format, string::String, vec::Vec};
+use bevy_ecs::prelude::*;
+use pc_keyboard::KeyCode;
+
+use crate::{
+    arch::keyboard::KeyboardState,
+    game::{
+        MenuAction,
+        ecs::Text,
+        ui::{UiNode, button},
+    },
+    utils::save::{self, HighScore, SaveRecord},
+};
+
+const LETTER_KEYS: [KeyCode; 26] = [
+    KeyCode::A,
+    KeyCode::B,
+    KeyCode::C,
+    KeyCode::D,
+    KeyCode::E,
+    KeyCode::F,
+    KeyCode::G,
+    KeyCode::H,
+    KeyCode::I,
+    KeyCode::J,
+    KeyCode::K,
+    KeyCode::L,
+    KeyCode::M,
+    KeyCode::N,
+    KeyCode::O,
+    KeyCode::P,
+    KeyCode::Q,
+    KeyCode::R,
+    KeyCode::S,
+    KeyCode::T,
+    KeyCode::U,
+    KeyCode::V,
+    KeyCode::W,
+    KeyCode::X,
+    KeyCode::Y,
+    KeyCode::Z,
+];
+
+// what's on disk, changes get written back with save::store
+#[derive(Resource, Default)]
+pub struct SaveData(pub SaveRecord);
+
+impl SaveData {
+    pub fn store(&self) {
+        save::store(self.0.clone());
+    }
+}
+
+// a label taking the initials for a new high score, enter files it into the table
+#[derive(Component)]
+pub struct InitialsEntry {
+    pub letters: Vec<u8>,
+    pub score: u32,
+    pub seed: u64,
+}
+
+impl InitialsEntry {
+    pub fn new(score: u32, seed: u64) -> Self {
+        Self {
+            letters: Vec::new(),
+            score,
+            seed,
+        }
+    }
+
+    fn text(&self) -> String {
+        (0..3)
+            .map(|i| self.letters.get(i).map_or('_', |&x| x as char))
+            .collect()
+    }
+}
+
+pub fn high_score_table(record: &SaveRecord) -> String {
+    let mut table = String::from("HIGH SCORES");
+    for (i, entry) in record.high_scores.iter().enumerate() {
+        let initials = entry.initials.map(|x| x as char);
+        table += &format!(
+            "\n{}. {}{}{} {:>4}",
+            i + 1,
+            initials[0],
+            initials[1],
+            initials[2],
+            entry.score
+        );
+    }
+    table
+}
+
+pub fn initials_input(
+    mut commands: Commands,
+    keyboard: Res<KeyboardState>,
+    entry: Single<(Entity, &mut InitialsEntry, &mut Text, &ChildOf)>,
+    mut save: ResMut<SaveData>,
+) {
+    let (entity, mut entry, mut text, parent) = entry.into_inner();
+
+    if let Some(letter) = LETTER_KEYS
+        .iter()
+        .position(|&key| keyboard.just_pressed(key))
+        && entry.letters.len() < 3
+    {
+        entry.letters.push(b'A' + letter as u8);
+    }
+    if keyboard.just_pressed(KeyCode::Backspace) {
+        entry.letters.pop();
+    }
+    text.text = entry.text();
+
+    if keyboard.just_pressed(KeyCode::Return) && entry.letters.len() == 3 {
+        save.0.insert(HighScore {
+            initials: [entry.letters[0], entry.letters[1], entry.letters[2]],
+            score: entry.score,
+            seed: entry.seed,
+        });
+        save.store();
+
+        // the restart button only shows up now so enter can't hit it early
+        commands.entity(entity).remove::<InitialsEntry>();
+        commands.spawn((
+            button(UiNode::default(), Text::new("PRESS SPACE TO RESTART"), 2.0),
+            MenuAction::Play,
+            ChildOf(parent.parent()),
+        ));
+    }
+}
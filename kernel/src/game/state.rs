@@ -0,0 +1,203 @@
+/*
+    Copyright (C) 2025 bugo07
+    Released under EUPL 1.2 License
+*/
+
+// states are resources only changed through NextState. game_loop calls
+// run_state_transitions once a frame, which runs OnExit, OnTransition and OnEnter
+// for every state that actually changed and despawns what was scoped to the old one
+
+use core::{fmt::Debug, hash::Hash, marker::PhantomData, ops::Deref};
+
+use alloc::vec::Vec;
+use bevy_ecs::{prelude::*, schedule::ScheduleLabel};
+
+pub trait States: Clone + PartialEq + Eq + Hash + Debug + Send + Sync + 'static {}
+
+// a state that only exists while its source state is in a certain value, it starts
+// out as its default every time it comes back
+pub trait SubStates: States + Default {
+    type Source: States;
+
+    fn exists_in(source: &Self::Source) -> bool;
+}
+
+#[derive(Resource, Debug)]
+pub struct State<S: States>(S);
+
+impl<S: States> State<S> {
+    pub fn get(&self) -> &S {
+        &self.0
+    }
+}
+
+impl<S: States> Deref for State<S> {
+    type Target = S;
+
+    fn deref(&self) -> &S {
+        &self.0
+    }
+}
+
+// setting the state it's already in does nothing, reenter goes through the exit and
+// enter schedules anyway (restarting a round)
+#[derive(Resource, Debug)]
+pub struct NextState<S: States> {
+    next: Option<S>,
+    reenter: bool,
+}
+
+impl<S: States> NextState<S> {
+    fn new(next: Option<S>) -> Self {
+        Self {
+            next,
+            reenter: false,
+        }
+    }
+
+    pub fn set(&mut self, state: S) {
+        self.next = Some(state);
+        self.reenter = false;
+    }
+
+    pub fn reenter(&mut self, state: S) {
+        self.next = Some(state);
+        self.reenter = true;
+    }
+}
+
+#[derive(ScheduleLabel, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct OnEnter<S: States>(pub S);
+
+#[derive(ScheduleLabel, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct OnExit<S: States>(pub S);
+
+#[derive(ScheduleLabel, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct OnTransition<S: States> {
+    pub from: S,
+    pub to: S,
+}
+
+// despawned (with its children) when its state is exited
+#[derive(Component)]
+pub struct StateScoped<S: States>(pub S);
+
+// in registration order, so sub states see their source's change in the same frame
+#[derive(Resource, Default)]
+struct StateTransitions(Vec<fn(&mut World)>);
+
+// the sub states of S get exited before S is
+#[derive(Resource)]
+struct SubStateExits<S: States>(Vec<fn(&mut World)>, PhantomData<S>);
+
+impl<S: States> Default for SubStateExits<S> {
+    fn default() -> Self {
+        Self(Vec::new(), PhantomData)
+    }
+}
+
+// OnEnter(initial) runs on the first run_state_transitions
+pub fn init_state<S: States>(world: &mut World, initial: S) {
+    world.insert_resource(NextState::new(Some(initial)));
+    world
+        .get_resource_or_init::<StateTransitions>()
+        .0
+        .push(apply_transition::<S>);
+}
+
+pub fn init_sub_state<S: SubStates>(world: &mut World) {
+    world.insert_resource(NextState::<S>::new(None));
+    world
+        .get_resource_or_init::<SubStateExits<S::Source>>()
+        .0
+        .push(exit_sub_state::<S>);
+    world
+        .get_resource_or_init::<StateTransitions>()
+        .0
+        .push(sync_sub_state::<S>);
+}
+
+pub fn run_state_transitions(world: &mut World) {
+    let Some(transitions) = world.get_resource::<StateTransitions>() else {
+        return;
+    };
+    for transition in transitions.0.clone() {
+        transition(world);
+    }
+}
+
+fn apply_transition<S: States>(world: &mut World) {
+    let mut next_state = world.resource_mut::<NextState<S>>();
+    let reenter = core::mem::take(&mut next_state.reenter);
+    let Some(next) = next_state.next.take() else {
+        return;
+    };
+    let current = world.get_resource::<State<S>>().map(|x| x.0.clone());
+    if current.as_ref() == Some(&next) && !reenter {
+        return;
+    }
+
+    if let Some(current) = current {
+        exit_state(world, &current);
+        world.insert_resource(State(next.clone()));
+        let _ = world.try_run_schedule(OnTransition {
+            from: current,
+            to: next.clone(),
+        });
+    } else {
+        world.insert_resource(State(next.clone()));
+    }
+    let _ = world.try_run_schedule(OnEnter(next));
+}
+
+fn sync_sub_state<S: SubStates>(world: &mut World) {
+    let exists = world
+        .get_resource::<State<S::Source>>()
+        .is_some_and(|source| S::exists_in(source));
+
+    if !exists {
+        // nothing to move to while it doesn't exist
+        world.resource_mut::<NextState<S>>().next = None;
+        return;
+    }
+
+    if !world.contains_resource::<State<S>>() {
+        world.resource_mut::<NextState<S>>().set(S::default());
+    }
+    apply_transition::<S>(world);
+}
+
+fn exit_sub_state<S: SubStates>(world: &mut World) {
+    let Some(current) = world.remove_resource::<State<S>>() else {
+        return;
+    };
+    exit_state(world, &current.0);
+}
+
+fn exit_state<S: States>(world: &mut World, state: &S) {
+    if let Some(exits) = world.get_resource::<SubStateExits<S>>() {
+        for exit in exits.0.clone() {
+            exit(world);
+        }
+    }
+
+    let _ = world.try_run_schedule(OnExit(state.clone()));
+
+    let scoped = world
+        .query::<(Entity, &StateScoped<S>)>()
+        .iter(world)
+        .filter(|(_, scope)| scope.0 == *state)
+        .map(|(entity, _)| entity)
+        .collect::<Vec<_>>();
+    for entity in scoped {
+        // might be gone already with its parent
+        let _ = world.try_despawn(entity);
+    }
+}
+
+pub fn in_state<S: States>(state: S) -> impl FnMut(Option<Res<State<S>>>) -> bool {
+    move |current_state: Option<Res<State<S>>>| match current_state {
+        Some(current_state) => *current_state.get() == state,
+        None => false,
+    }
+}
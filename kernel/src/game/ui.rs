@@ -0,0 +1,425 @@
+/*
+    Copyright (C) 2025 bugo07
+    Released under EUPL 1.2 License
+*/
+
+// retained ui on top of the ecs. nodes are a tree through ChildOf/Children, ui_layout
+// sizes it bottom up and places it top down from the screen every fixed frame so
+// menus don't care about the resolution
+
+use alloc::{collections::btree_map::BTreeMap, string::String, vec::Vec};
+use bevy_ecs::prelude::*;
+use bevy_math::Vec2;
+use pc_keyboard::KeyCode;
+
+use crate::{arch::keyboard::KeyboardState, utils::fb::Framebuffer};
+
+use super::ecs::{Rect, Text, Transform};
+
+pub const BUTTON_COLOR: u32 = 0x303040;
+pub const BUTTON_FOCUS_COLOR: u32 = 0x5A8F29;
+
+// where a node sits in the space its parent gives it
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub enum Anchor {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    #[default]
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+impl Anchor {
+    fn fraction(self) -> Vec2 {
+        match self {
+            Anchor::TopLeft => Vec2::new(0.0, 0.0),
+            Anchor::Top => Vec2::new(0.5, 0.0),
+            Anchor::TopRight => Vec2::new(1.0, 0.0),
+            Anchor::Left => Vec2::new(0.0, 0.5),
+            Anchor::Center => Vec2::new(0.5, 0.5),
+            Anchor::Right => Vec2::new(1.0, 0.5),
+            Anchor::BottomLeft => Vec2::new(0.0, 1.0),
+            Anchor::Bottom => Vec2::new(0.5, 1.0),
+            Anchor::BottomRight => Vec2::new(1.0, 1.0),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+pub enum Val {
+    // as big as the content
+    #[default]
+    Auto,
+    Px(f32),
+    // of the parent's content box
+    Percent(f32),
+}
+
+impl Val {
+    fn measure(self, auto: f32) -> f32 {
+        match self {
+            Val::Px(px) => px,
+            _ => auto,
+        }
+    }
+
+    fn resolve(self, auto: f32, parent: f32) -> f32 {
+        match self {
+            Val::Auto => auto,
+            Val::Px(px) => px,
+            Val::Percent(percent) => parent * percent / 100.0,
+        }
+    }
+}
+
+// how children are placed, the stacks take the spacing between them
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+pub enum Layout {
+    // all children get the whole content box and anchor themselves in it
+    #[default]
+    Overlay,
+    Vertical(f32),
+    Horizontal(f32),
+}
+
+#[derive(Component, Clone, Default, Debug)]
+#[require(ComputedNode)]
+pub struct UiNode {
+    pub anchor: Anchor,
+    pub offset: Vec2,
+    pub width: Val,
+    pub height: Val,
+    pub padding: Vec2,
+    pub layout: Layout,
+}
+
+impl UiNode {
+    pub fn new(anchor: Anchor) -> Self {
+        Self {
+            anchor,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_offset(mut self, offset: Vec2) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    pub fn with_size(mut self, width: Val, height: Val) -> Self {
+        self.width = width;
+        self.height = height;
+        self
+    }
+
+    pub fn with_width(mut self, width: Val) -> Self {
+        self.width = width;
+        self
+    }
+
+    pub fn with_padding(mut self, padding: Vec2) -> Self {
+        self.padding = padding;
+        self
+    }
+
+    pub fn with_layout(mut self, layout: Layout) -> Self {
+        self.layout = layout;
+        self
+    }
+}
+
+// filled in by ui_layout. order is the draw order (parents before children), 0 means
+// the node hasn't been laid out yet
+#[derive(Component, Clone, Copy, Default, Debug)]
+pub struct ComputedNode {
+    pub pos: Vec2,
+    pub size: Vec2,
+    pub content: Vec2,
+    pub order: u32,
+}
+
+// a node that can take focus, its Rect gets colored by the focus state
+#[derive(Component, Clone, Copy)]
+pub struct Button {
+    pub color: u32,
+    pub focus_color: u32,
+}
+
+impl Default for Button {
+    fn default() -> Self {
+        Self {
+            color: BUTTON_COLOR,
+            focus_color: BUTTON_FOCUS_COLOR,
+        }
+    }
+}
+
+// `activated` only holds the button for the frame enter/space hit it
+#[derive(Resource, Default, Debug)]
+pub struct UiFocus {
+    pub focused: Option<Entity>,
+    pub activated: Option<Entity>,
+}
+
+// keeps the node's text in sync with a resource, see bind_text
+#[derive(Component)]
+pub struct BindText<R: Resource>(pub fn(&R) -> String);
+
+pub fn label(node: UiNode, text: Text, scale: f32) -> impl Bundle {
+    (
+        node,
+        text,
+        Transform::from_translation(Vec2::ZERO).with_scale(Vec2::splat(scale)),
+    )
+}
+
+pub fn panel(node: UiNode, color: u32) -> impl Bundle {
+    (node, Rect::new(Vec2::ZERO, color))
+}
+
+pub fn button(node: UiNode, text: Text, scale: f32) -> impl Bundle {
+    let button = Button::default();
+    (
+        node.with_padding(Vec2::new(8.0, 4.0) * scale),
+        button,
+        Rect::new(Vec2::ZERO, button.color),
+        text,
+        Transform::from_translation(Vec2::ZERO).with_scale(Vec2::splat(scale)),
+    )
+}
+
+type NodeQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        &'static UiNode,
+        Option<&'static Children>,
+        Option<&'static Text>,
+        Option<&'static Transform>,
+    ),
+>;
+
+pub fn ui_layout(
+    fb: Res<Framebuffer>,
+    roots: Query<Entity, (With<UiNode>, Without<ChildOf>)>,
+    mut queries: ParamSet<(
+        NodeQuery,
+        Query<(&mut ComputedNode, Option<&mut Transform>, Option<&mut Rect>)>,
+    )>,
+) {
+    let mut placed = Vec::new();
+    {
+        let nodes = queries.p0();
+        let mut sizes = BTreeMap::new();
+        let mut order = 0;
+        for root in &roots {
+            measure(&fb, &nodes, root, &mut sizes);
+            place(
+                &nodes,
+                &sizes,
+                root,
+                (Vec2::ZERO, fb.size.as_vec2()),
+                &mut order,
+                &mut placed,
+            );
+        }
+    }
+
+    let mut computed = queries.p1();
+    for (entity, node) in placed {
+        let Ok((mut computed, transform, rect)) = computed.get_mut(entity) else {
+            continue;
+        };
+        *computed = node;
+        if let Some(mut transform) = transform {
+            transform.position = node.content;
+        }
+        if let Some(mut rect) = rect {
+            rect.size = node.size;
+        }
+    }
+}
+
+fn ui_children(nodes: &NodeQuery, children: Option<&Children>) -> Vec<Entity> {
+    children.map_or(Vec::new(), |children| {
+        let mut children = children.to_vec();
+        children.retain(|&child| nodes.contains(child));
+        children
+    })
+}
+
+fn text_size(fb: &Framebuffer, text: &Text, transform: Option<&Transform>) -> Vec2 {
+    match &text.font {
+        Some((font, style)) => font.measure(&text.text, style),
+        None => fb
+            .measure_str(&text.text, transform.map_or(Vec2::ONE, |x| x.scale))
+            .as_vec2(),
+    }
+}
+
+// outer size of every node if it got to pick, percentages count as their content
+fn measure(
+    fb: &Framebuffer,
+    nodes: &NodeQuery,
+    entity: Entity,
+    sizes: &mut BTreeMap<Entity, Vec2>,
+) -> Vec2 {
+    let Ok((node, children, text, transform)) = nodes.get(entity) else {
+        return Vec2::ZERO;
+    };
+
+    let children = ui_children(nodes, children)
+        .into_iter()
+        .map(|child| measure(fb, nodes, child, sizes))
+        .collect::<Vec<_>>();
+    let gaps = children.len().saturating_sub(1) as f32;
+    let content = match node.layout {
+        Layout::Overlay => children.iter().fold(Vec2::ZERO, |acc, &x| acc.max(x)),
+        Layout::Vertical(spacing) => Vec2::new(
+            children.iter().fold(0.0, |acc, x| x.x.max(acc)),
+            children.iter().map(|x| x.y).sum::<f32>() + spacing * gaps,
+        ),
+        Layout::Horizontal(spacing) => Vec2::new(
+            children.iter().map(|x| x.x).sum::<f32>() + spacing * gaps,
+            children.iter().fold(0.0, |acc, x| x.y.max(acc)),
+        ),
+    };
+    let content = text.map_or(content, |text| content.max(text_size(fb, text, transform)));
+
+    let auto = content + node.padding * 2.0;
+    let size = Vec2::new(node.width.measure(auto.x), node.height.measure(auto.y));
+    sizes.insert(entity, size);
+    size
+}
+
+// `slot` is the part of the parent this node gets to anchor itself in
+fn place(
+    nodes: &NodeQuery,
+    sizes: &BTreeMap<Entity, Vec2>,
+    entity: Entity,
+    slot: (Vec2, Vec2),
+    order: &mut u32,
+    placed: &mut Vec<(Entity, ComputedNode)>,
+) {
+    let Ok((node, children, _, _)) = nodes.get(entity) else {
+        return;
+    };
+    let measured = sizes.get(&entity).copied().unwrap_or_default();
+    let (slot_pos, slot_size) = slot;
+
+    let size = Vec2::new(
+        node.width.resolve(measured.x, slot_size.x),
+        node.height.resolve(measured.y, slot_size.y),
+    );
+    let pos = slot_pos + (slot_size - size) * node.anchor.fraction() + node.offset;
+    let content = pos + node.padding;
+    let inner = (size - node.padding * 2.0).max(Vec2::ZERO);
+
+    *order += 1;
+    placed.push((
+        entity,
+        ComputedNode {
+            pos,
+            size,
+            content,
+            order: *order,
+        },
+    ));
+
+    let mut cursor = content;
+    for child in ui_children(nodes, children) {
+        let Ok((child_node, ..)) = nodes.get(child) else {
+            continue;
+        };
+        let child_size = sizes.get(&child).copied().unwrap_or_default();
+
+        let slot = match node.layout {
+            Layout::Overlay => (content, inner),
+            Layout::Vertical(spacing) => {
+                let height = child_node.height.resolve(child_size.y, inner.y);
+                let slot = (cursor, Vec2::new(inner.x, height));
+                cursor.y += height + spacing;
+                slot
+            }
+            Layout::Horizontal(spacing) => {
+                let width = child_node.width.resolve(child_size.x, inner.x);
+                let slot = (cursor, Vec2::new(width, inner.y));
+                cursor.x += width + spacing;
+                slot
+            }
+        };
+        place(nodes, sizes, child, slot, order, placed);
+    }
+}
+
+// up/down (or tab) move the focus through the buttons in layout order, enter or space
+// activates the focused one
+pub fn ui_navigation(
+    keyboard: Res<KeyboardState>,
+    mut focus: ResMut<UiFocus>,
+    buttons: Query<(Entity, &ComputedNode), With<Button>>,
+) {
+    let mut buttons = buttons
+        .iter()
+        .filter(|(_, node)| node.order != 0)
+        .collect::<Vec<_>>();
+    buttons.sort_by_key(|(_, node)| node.order);
+
+    focus.activated = None;
+    if buttons.is_empty() {
+        focus.focused = None;
+        return;
+    }
+
+    let current = focus
+        .focused
+        .and_then(|focused| buttons.iter().position(|(entity, _)| *entity == focused));
+    let step =
+        if keyboard.just_pressed(KeyCode::ArrowUp) || keyboard.just_pressed(KeyCode::ArrowLeft) {
+            -1
+        } else if keyboard.just_pressed(KeyCode::ArrowDown)
+            || keyboard.just_pressed(KeyCode::ArrowRight)
+            || keyboard.just_pressed(KeyCode::Tab)
+        {
+            1
+        } else {
+            0
+        };
+
+    let index = match current {
+        Some(index) => (index as isize + step).rem_euclid(buttons.len() as isize) as usize,
+        // the old one went away with its screen
+        None => 0,
+    };
+    focus.focused = Some(buttons[index].0);
+
+    if current.is_some()
+        && (keyboard.just_pressed(KeyCode::Return) || keyboard.just_pressed(KeyCode::Spacebar))
+    {
+        focus.activated = focus.focused;
+    }
+}
+
+pub fn ui_button_style(focus: Res<UiFocus>, mut buttons: Query<(Entity, &Button, &mut Rect)>) {
+    for (entity, button, mut rect) in &mut buttons {
+        rect.color = if focus.focused == Some(entity) {
+            button.focus_color
+        } else {
+            button.color
+        };
+    }
+}
+
+// registered once per bound resource type, e.g. bind_text::<Score>
+pub fn bind_text<R: Resource>(resource: Res<R>, mut labels: Query<(Ref<BindText<R>>, &mut Text)>) {
+    for (bind, mut text) in &mut labels {
+        if resource.is_changed() || bind.is_added() {
+            text.text = (bind.0)(&resource);
+        }
+    }
+}
@@ -27,53 +27,105 @@ use alloc::string::ToString;
 
 use bevy_math::{UVec2, Vec2};
 
-use crate::utils::{bootloader::get_framebuffers, fb::Framebuffer};
+use crate::utils::fb::Framebuffer;
 
 pub static CPU_FREQ: AtomicU64 = AtomicU64::new(0);
 
 #[unsafe(no_mangle)]
 extern "C" fn kmain() -> ! {
+    utils::logger::init();
     arch::mem::init();
+    utils::cmdline::init();
+    utils::logger::init_filters();
+    arch::mem::protect_kernel();
     arch::gdt::init();
     arch::ints::init();
     arch::ints::pic::init();
     utils::asm::toggle_ints(true);
     arch::ints::pic::unmask(1);
     arch::time::init();
-    game::game_loop();
+    arch::smp::init();
+    arch::thread::init();
+    arch::syscall::init();
+    utils::video::init();
+    utils::serial::init_rx();
+    utils::screenshot::init();
+    arch::elf::init();
+    utils::font::init();
+    assets::init();
+    arch::audio::init();
+    utils::block::init();
+    utils::save::init();
+    utils::vfs::init();
+    utils::executor::spawn("game", game::game_loop());
+    utils::executor::spawn("log drain", utils::logger::drain_task());
+    utils::executor::spawn("shell", utils::shell::shell_task());
+    utils::executor::spawn("screenshot", utils::screenshot::screenshot_task());
+    utils::executor::spawn("elf loader", arch::elf::loader_task());
+    utils::executor::spawn("save", utils::save::save_task());
+    utils::executor::spawn("audio", arch::audio::audio_task());
+    utils::executor::run();
 }
 
+const PANIC_LOG_LINES: usize = 10;
+
 // * horrible design but works
 #[panic_handler]
 fn rust_panic(info: &core::panic::PanicInfo) -> ! {
     utils::asm::toggle_ints(false);
-    let mut fb = Framebuffer::new_from_limine(&get_framebuffers().next().unwrap());
     let location = info.location().unwrap();
-    let msg = info.message().to_string();
-
-    fb.clear(0x000000);
-    let s = "PANIC";
-    let pos = UVec2::new(
-        fb.centered_str_x(s, 2.0),
-        fb.centered_str_y(2.0) - fb.font_height * 5,
-    );
-    error!("{s}");
-    fb.draw_str(pos, s, 0xFFFFFF, None, Vec2::splat(2.0));
-    let s = &alloc::format!(
+    let location = &alloc::format!(
         "{}:{}:{}",
         location.file(),
         location.line(),
         location.column(),
     );
-    error!("{s}");
-    let pos: UVec2 = UVec2::new(fb.centered_str_x(s, 1.0), fb.centered_str_y(1.0));
-    fb.draw_str(pos, s, 0xFFFFFF, None, Vec2::splat(1.0));
-    let pos = UVec2::new(
-        fb.centered_str_x(&msg, 1.5),
-        fb.centered_str_y(1.5) + fb.font_height * 5,
-    );
+    let msg = info.message().to_string();
+    error!("PANIC");
+    error!("{location}");
     error!("{msg}");
-    fb.draw_str(pos, &msg, 0xFFFFFF, None, Vec2::splat(1.5));
-    fb.present();
+
+    // whatever led up to it
+    utils::logger::flush();
+    let records = utils::logger::recent(PANIC_LOG_LINES)
+        .iter()
+        .map(|record| {
+            alloc::format!(
+                "[{}] {}: {}",
+                record.tag(),
+                utils::logger::short_target(record.target.as_str()),
+                record.message
+            )
+        })
+        .collect::<alloc::vec::Vec<_>>();
+
+    // on every screen, whichever one someone's looking at
+    for screen in utils::video::screens() {
+        let mut fb = Framebuffer::new_from_screen(&screen);
+        fb.clear(0x000000);
+        let s = "PANIC";
+        let pos = UVec2::new(
+            fb.centered_str_x(s, 2.0),
+            fb.centered_str_y(2.0) - fb.font_height * 5,
+        );
+        fb.draw_str(pos, s, 0xFFFFFF, None, Vec2::splat(2.0));
+        let pos = UVec2::new(fb.centered_str_x(location, 1.0), fb.centered_str_y(1.0));
+        fb.draw_str(pos, location, 0xFFFFFF, None, Vec2::splat(1.0));
+        let pos = UVec2::new(
+            fb.centered_str_x(&msg, 1.5),
+            fb.centered_str_y(1.5) + fb.font_height * 5,
+        );
+        fb.draw_str(pos, &msg, 0xFFFFFF, None, Vec2::splat(1.5));
+
+        for (i, s) in records.iter().enumerate() {
+            let line = (PANIC_LOG_LINES - records.len() + i) as u32;
+            let pos = UVec2::new(
+                fb.font_width,
+                fb.size.y - fb.font_height * (PANIC_LOG_LINES as u32 + 1 - line),
+            );
+            fb.draw_str(pos, s, 0xABABAB, None, Vec2::ONE);
+        }
+        fb.present();
+    }
     utils::asm::halt_loop();
 }
@@ -66,6 +66,16 @@ where
     ret
 }
 
+// pulses the reset line through the ps/2 controller, triple faults if that didn't work
+pub fn reboot() -> ! {
+    toggle_ints(false);
+    while inb(0x64) & 0x02 != 0 {}
+    outb(0x64, 0xFE);
+
+    let null_idt = [0u8; 10];
+    unsafe { asm!("lidt [{}]", "int3", in(reg) &null_idt, options(noreturn)) };
+}
+
 pub struct CpuidResult {
     pub eax: u32,
     pub ebx: u32,
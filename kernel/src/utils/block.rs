@@ -0,0 +1,251 @@
+/*
+    Copyright (C) 2025 bugo07
+    Released under EUPL 1.2 License
+*/
+
+// sector level storage. drivers register their disks here at boot and everything
+// else (saves, filesystems) goes through `BlockDevice`. buffers are always whole
+// sectors, and gpt partitions can be opened as devices of their own
+
+use alloc::{string::String, sync::Arc, vec, vec::Vec};
+
+use crate::{
+    arch::{ahci, ata},
+    info,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockError {
+    OutOfRange,
+    // buffer isn't a multiple of the sector size
+    Unaligned,
+    Timeout,
+    Io(&'static str),
+}
+
+pub trait BlockDevice: Send + Sync {
+    fn name(&self) -> &str;
+
+    fn sector_size(&self) -> usize {
+        512
+    }
+
+    fn sector_count(&self) -> u64;
+
+    fn read(&self, lba: u64, buf: &mut [u8]) -> Result<(), BlockError>;
+
+    fn write(&self, lba: u64, buf: &[u8]) -> Result<(), BlockError>;
+
+    fn capacity(&self) -> u64 {
+        self.sector_count() * self.sector_size() as u64
+    }
+}
+
+// the range and size checks every driver does before touching the hardware,
+// gives back how many sectors `len` bytes are
+pub fn check_access(device: &dyn BlockDevice, lba: u64, len: usize) -> Result<u64, BlockError> {
+    if !len.is_multiple_of(device.sector_size()) {
+        return Err(BlockError::Unaligned);
+    }
+    let count = (len / device.sector_size()) as u64;
+    match lba.checked_add(count) {
+        Some(end) if end <= device.sector_count() => Ok(count),
+        _ => Err(BlockError::OutOfRange),
+    }
+}
+
+static DEVICES: spin::Mutex<Vec<Arc<dyn BlockDevice>>> = spin::Mutex::new(Vec::new());
+
+pub fn init() {
+    info!("probing disks...");
+    for drive in ata::probe() {
+        register(Arc::new(drive));
+    }
+    for drive in ahci::probe() {
+        register(Arc::new(drive));
+    }
+    info!("{} disks", DEVICES.lock().len());
+}
+
+pub fn register(device: Arc<dyn BlockDevice>) {
+    info!(
+        "{}: {} sectors of {} bytes ({} MiB)",
+        device.name(),
+        device.sector_count(),
+        device.sector_size(),
+        device.capacity() / (1024 * 1024)
+    );
+    DEVICES.lock().push(device);
+}
+
+pub fn devices() -> Vec<Arc<dyn BlockDevice>> {
+    DEVICES.lock().clone()
+}
+
+// a disk in memory, for tests and anything that wants a scratch device
+pub struct RamDisk {
+    name: String,
+    sector_size: usize,
+    data: spin::Mutex<Vec<u8>>,
+}
+
+impl RamDisk {
+    pub fn new(name: &str, sector_size: usize, sectors: u64) -> Self {
+        Self::from_bytes(name, sector_size, vec![0; sector_size * sectors as usize])
+    }
+
+    // `data` gets cut down to whole sectors
+    pub fn from_bytes(name: &str, sector_size: usize, mut data: Vec<u8>) -> Self {
+        data.truncate(data.len() - data.len() % sector_size);
+        Self {
+            name: String::from(name),
+            sector_size,
+            data: spin::Mutex::new(data),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.data.lock().clone()
+    }
+}
+
+impl BlockDevice for RamDisk {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn sector_size(&self) -> usize {
+        self.sector_size
+    }
+
+    fn sector_count(&self) -> u64 {
+        (self.data.lock().len() / self.sector_size) as u64
+    }
+
+    fn read(&self, lba: u64, buf: &mut [u8]) -> Result<(), BlockError> {
+        check_access(self, lba, buf.len())?;
+        let start = lba as usize * self.sector_size;
+        buf.copy_from_slice(&self.data.lock()[start..start + buf.len()]);
+        Ok(())
+    }
+
+    fn write(&self, lba: u64, buf: &[u8]) -> Result<(), BlockError> {
+        check_access(self, lba, buf.len())?;
+        let start = lba as usize * self.sector_size;
+        self.data.lock()[start..start + buf.len()].copy_from_slice(buf);
+        Ok(())
+    }
+}
+
+// a slice of another device
+pub struct Partition {
+    device: Arc<dyn BlockDevice>,
+    name: String,
+    label: String,
+    start: u64,
+    sectors: u64,
+}
+
+impl Partition {
+    // the gpt name, can be empty
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+}
+
+impl BlockDevice for Partition {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn sector_size(&self) -> usize {
+        self.device.sector_size()
+    }
+
+    fn sector_count(&self) -> u64 {
+        self.sectors
+    }
+
+    fn read(&self, lba: u64, buf: &mut [u8]) -> Result<(), BlockError> {
+        check_access(self, lba, buf.len())?;
+        self.device.read(self.start + lba, buf)
+    }
+
+    fn write(&self, lba: u64, buf: &[u8]) -> Result<(), BlockError> {
+        check_access(self, lba, buf.len())?;
+        self.device.write(self.start + lba, buf)
+    }
+}
+
+const GPT_SIGNATURE: &[u8; 8] = b"EFI PART";
+
+// the partitions in the gpt on `device`, none if it doesn't have one
+pub fn partitions(device: &Arc<dyn BlockDevice>) -> Result<Vec<Partition>, BlockError> {
+    let sector_size = device.sector_size();
+    let mut header = vec![0u8; sector_size];
+    device.read(1, &mut header)?;
+    if &header[0..8] != GPT_SIGNATURE {
+        return Ok(Vec::new());
+    }
+
+    let u32_at =
+        |x: &[u8], offset: usize| u32::from_le_bytes(x[offset..offset + 4].try_into().unwrap());
+    let u64_at =
+        |x: &[u8], offset: usize| u64::from_le_bytes(x[offset..offset + 8].try_into().unwrap());
+    let entries_lba = u64_at(&header, 72);
+    let entry_count = u32_at(&header, 80) as usize;
+    let entry_size = u32_at(&header, 84) as usize;
+    if entry_size < 128 || entry_count > 1024 {
+        return Err(BlockError::Io("bad gpt header"));
+    }
+
+    let table_sectors = (entry_count * entry_size).div_ceil(sector_size);
+    let mut table = vec![0u8; table_sectors * sector_size];
+    device.read(entries_lba, &mut table)?;
+
+    let mut partitions = Vec::new();
+    for (index, entry) in table.chunks_exact(entry_size).take(entry_count).enumerate() {
+        // unused entries have no type
+        if entry[0..16].iter().all(|&x| x == 0) {
+            continue;
+        }
+
+        let label = char::decode_utf16(
+            entry[56..128]
+                .as_chunks::<2>()
+                .0
+                .iter()
+                .map(|&x| u16::from_le_bytes(x))
+                .take_while(|&x| x != 0),
+        )
+        .map(|x| x.unwrap_or(char::REPLACEMENT_CHARACTER))
+        .collect::<String>();
+
+        let first = u64_at(entry, 32);
+        let last = u64_at(entry, 40);
+        if last < first || last >= device.sector_count() {
+            return Err(BlockError::Io("bad gpt entry"));
+        }
+        let name = if label.is_empty() {
+            alloc::format!("{}p{}", device.name(), index + 1)
+        } else {
+            alloc::format!("{}:{label}", device.name())
+        };
+        partitions.push(Partition {
+            device: device.clone(),
+            name,
+            label,
+            start: first,
+            sectors: last - first + 1,
+        });
+    }
+    Ok(partitions)
+}
+
+// looks through the gpt on `device` for a partition labelled `label`
+pub fn find_partition(
+    device: &Arc<dyn BlockDevice>,
+    label: &str,
+) -> Result<Option<Partition>, BlockError> {
+    Ok(partitions(device)?.into_iter().find(|x| x.label == label))
+}
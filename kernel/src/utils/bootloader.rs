@@ -11,8 +11,9 @@ use limine::{
     memory_map::Entry,
     request::{
         BootloaderInfoRequest, DeviceTreeBlobRequest, ExecutableAddressRequest,
-        ExecutableFileRequest, FramebufferRequest, HhdmRequest, MemoryMapRequest, MpRequest,
-        RequestsEndMarker, RequestsStartMarker, RsdpRequest,
+        ExecutableCmdlineRequest, ExecutableFileRequest, FramebufferRequest, HhdmRequest,
+        MemoryMapRequest, ModuleRequest, MpRequest, RequestsEndMarker, RequestsStartMarker,
+        RsdpRequest,
     },
     response::{BootloaderInfoResponse, ExecutableAddressResponse, MpResponse},
 };
@@ -45,6 +46,10 @@ pub static EXECUTABLE_ADDRESS_REQUEST: ExecutableAddressRequest = ExecutableAddr
 #[unsafe(link_section = ".requests")]
 pub static EXECUTABLE_FILE_REQUEST: ExecutableFileRequest = ExecutableFileRequest::new();
 
+#[used]
+#[unsafe(link_section = ".requests")]
+pub static EXECUTABLE_CMDLINE_REQUEST: ExecutableCmdlineRequest = ExecutableCmdlineRequest::new();
+
 #[used]
 #[unsafe(link_section = ".requests")]
 pub static MP_REQUEST: MpRequest = MpRequest::new();
@@ -61,6 +66,10 @@ pub static BOOTLOADER_INFO_REQUEST: BootloaderInfoRequest = BootloaderInfoReques
 #[unsafe(link_section = ".requests")]
 pub static DEVICE_TREE_REQUEST: DeviceTreeBlobRequest = DeviceTreeBlobRequest::new();
 
+#[used]
+#[unsafe(link_section = ".requests")]
+pub static MODULE_REQUEST: ModuleRequest = ModuleRequest::new();
+
 pub fn get_framebuffers() -> impl Iterator<Item = Framebuffer<'static>> {
     FRAMEBUFFER_REQUEST
         .get_response()
@@ -84,6 +93,13 @@ pub fn get_executable_file() -> &'static File {
     EXECUTABLE_FILE_REQUEST.get_response().unwrap().file()
 }
 
+pub fn get_cmdline() -> &'static str {
+    EXECUTABLE_CMDLINE_REQUEST
+        .get_response()
+        .and_then(|x| x.cmdline().to_str().ok())
+        .unwrap_or("")
+}
+
 pub fn get_mp_response() -> &'static MpResponse {
     MP_REQUEST.get_response().unwrap()
 }
@@ -101,3 +117,10 @@ pub fn get_bootloader_info() -> &'static BootloaderInfoResponse {
 pub fn get_device_tree() -> Option<*const ()> {
     DEVICE_TREE_REQUEST.get_response().map(|x| x.dtb_ptr())
 }
+
+pub fn get_modules() -> &'static [&'static File] {
+    MODULE_REQUEST
+        .get_response()
+        .map(|x| x.modules())
+        .unwrap_or(&[])
+}
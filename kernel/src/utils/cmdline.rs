@@ -0,0 +1,240 @@
+/*
+    Copyright (C) 2025 bugo07
+    Released under EUPL 1.2 License
+*/
+
+// options from the limine cmdline, `key=value` separated by spaces. parsed once right
+// after the heap is up, everything later asks `config()`. the game gets a copy as the
+// BootConfig resource
+//
+//   timer=kvm|tsc|pit          timer to prefer over the usual pick
+//   log=info,game=trace        log filters, see logger::set_filters
+//   seed=1234                  pipes of every round come from this seed
+//   mode=normal|attract        attract plays by itself until a key gets pressed
+//   fps=120                    fixed update (physics and drawing) rate
+//   keymap=us|uk|de|fr|...     keyboard layout
+//   video=1024x768             one of the modes limine reported, see `modes` in the shell
+//   scale=integer|fit          how the game's resolution gets blown up to the screen
+//   display=mirror|extend      the same picture on every screen, or one wide one across
+//   volume=0..100              sound effect volume in percent, 0 mutes the card
+//   program=demo               ring 3 program to boot into, the built-in game runs
+//                              once it exits or if it can't be started
+
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+use bevy_ecs::prelude::*;
+use bevy_math::UVec2;
+use pc_keyboard::layouts::{self, AnyLayout};
+
+use crate::{
+    arch::time::TimerKind,
+    info,
+    utils::{
+        bootloader::get_cmdline,
+        fb::{DisplayLayout, ScaleMode},
+    },
+    warn,
+};
+
+pub const MAX_FPS: u32 = 1000;
+pub const MAX_VOLUME: u32 = 100;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BootMode {
+    #[default]
+    Normal,
+    // demo loop, the bird flies itself and nothing gets saved
+    Attract,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Keymap {
+    #[default]
+    Us,
+    Uk,
+    De,
+    Fr,
+    No,
+    Fi,
+    Jp,
+    Dvorak,
+    Colemak,
+}
+
+impl Keymap {
+    pub fn layout(&self) -> AnyLayout {
+        match self {
+            Keymap::Us => AnyLayout::Us104Key(layouts::Us104Key),
+            Keymap::Uk => AnyLayout::Uk105Key(layouts::Uk105Key),
+            Keymap::De => AnyLayout::De105Key(layouts::De105Key),
+            Keymap::Fr => AnyLayout::Azerty(layouts::Azerty),
+            Keymap::No => AnyLayout::No105Key(layouts::No105Key),
+            Keymap::Fi => AnyLayout::FiSe105Key(layouts::FiSe105Key),
+            Keymap::Jp => AnyLayout::Jis109Key(layouts::Jis109Key),
+            Keymap::Dvorak => AnyLayout::Dvorak104Key(layouts::Dvorak104Key),
+            Keymap::Colemak => AnyLayout::Colemak(layouts::Colemak),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CmdlineError {
+    UnknownKey(String),
+    BadValue { key: String, value: String },
+}
+
+#[derive(Resource, Debug, Clone, PartialEq, Eq)]
+pub struct BootConfig {
+    pub timer: Option<TimerKind>,
+    pub log: Option<String>,
+    pub seed: Option<u64>,
+    pub mode: BootMode,
+    pub fps: u32,
+    pub keymap: Keymap,
+    pub video: Option<UVec2>,
+    pub scale: ScaleMode,
+    pub display: DisplayLayout,
+    pub volume: u32,
+    pub program: Option<String>,
+}
+
+impl Default for BootConfig {
+    fn default() -> Self {
+        Self {
+            timer: None,
+            log: None,
+            seed: None,
+            mode: BootMode::Normal,
+            fps: 60,
+            keymap: Keymap::Us,
+            video: None,
+            scale: ScaleMode::Integer,
+            display: DisplayLayout::Mirror,
+            volume: MAX_VOLUME,
+            program: None,
+        }
+    }
+}
+
+impl BootConfig {
+    // bad options are left at their defaults and handed back next to the config
+    pub fn parse(cmdline: &str) -> (Self, Vec<CmdlineError>) {
+        let mut config = Self::default();
+        let mut errors = Vec::new();
+
+        for arg in cmdline.split_whitespace() {
+            let (key, value) = arg.split_once('=').unwrap_or((arg, ""));
+            if let Err(err) = config.set(key, value) {
+                errors.push(err);
+            }
+        }
+        (config, errors)
+    }
+
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), CmdlineError> {
+        let bad_value = || CmdlineError::BadValue {
+            key: key.to_string(),
+            value: value.to_string(),
+        };
+
+        match key {
+            "timer" => {
+                self.timer = Some(match value {
+                    "kvm" => TimerKind::KVM,
+                    "tsc" => TimerKind::TSC,
+                    "hpet" => TimerKind::HPET,
+                    "pit" => TimerKind::PIT,
+                    _ => return Err(bad_value()),
+                })
+            }
+            "log" if !value.is_empty() => self.log = Some(value.to_string()),
+            "seed" => self.seed = Some(value.parse().map_err(|_| bad_value())?),
+            "mode" => {
+                self.mode = match value {
+                    "normal" => BootMode::Normal,
+                    "attract" => BootMode::Attract,
+                    _ => return Err(bad_value()),
+                }
+            }
+            "fps" => match value.parse() {
+                Ok(fps @ 1..=MAX_FPS) => self.fps = fps,
+                _ => return Err(bad_value()),
+            },
+            "keymap" => {
+                self.keymap = match value {
+                    "us" => Keymap::Us,
+                    "uk" => Keymap::Uk,
+                    "de" => Keymap::De,
+                    "fr" => Keymap::Fr,
+                    "no" => Keymap::No,
+                    "fi" | "se" => Keymap::Fi,
+                    "jp" => Keymap::Jp,
+                    "dvorak" => Keymap::Dvorak,
+                    "colemak" => Keymap::Colemak,
+                    _ => return Err(bad_value()),
+                }
+            }
+            "video" => {
+                let size = value
+                    .split_once('x')
+                    .and_then(|(x, y)| Some(UVec2::new(x.parse().ok()?, y.parse().ok()?)))
+                    .filter(|x| x.min_element() > 0)
+                    .ok_or_else(bad_value)?;
+                self.video = Some(size);
+            }
+            "scale" => {
+                self.scale = match value {
+                    "integer" => ScaleMode::Integer,
+                    "fit" => ScaleMode::Fit,
+                    _ => return Err(bad_value()),
+                }
+            }
+            "display" => {
+                self.display = match value {
+                    "mirror" => DisplayLayout::Mirror,
+                    "extend" => DisplayLayout::Extend,
+                    _ => return Err(bad_value()),
+                }
+            }
+            "volume" => match value.parse() {
+                Ok(volume @ 0..=MAX_VOLUME) => self.volume = volume,
+                _ => return Err(bad_value()),
+            },
+            "program" if !value.is_empty() => self.program = Some(value.to_string()),
+            "log" | "program" => return Err(bad_value()),
+            _ => return Err(CmdlineError::UnknownKey(key.to_string())),
+        }
+        Ok(())
+    }
+
+    // length of one fixed update tick
+    pub fn tick_secs(&self) -> f32 {
+        1.0 / self.fps as f32
+    }
+}
+
+static CONFIG: spin::Once<BootConfig> = spin::Once::new();
+
+// needs the heap
+pub fn init() {
+    let cmdline = get_cmdline();
+    let (config, errors) = BootConfig::parse(cmdline);
+    for err in errors {
+        match err {
+            CmdlineError::UnknownKey(key) => warn!("unknown cmdline option {key}, ignoring it"),
+            CmdlineError::BadValue { key, value } => {
+                warn!("bad value {value:?} for cmdline option {key}, using the default")
+            }
+        }
+    }
+    info!("cmdline {cmdline:?}: {config:?}");
+    CONFIG.call_once(|| config);
+}
+
+// the defaults until init ran
+pub fn config() -> &'static BootConfig {
+    static DEFAULT: spin::Lazy<BootConfig> = spin::Lazy::new(BootConfig::default);
+    CONFIG.get().unwrap_or(&DEFAULT)
+}
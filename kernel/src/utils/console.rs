@@ -0,0 +1,237 @@
+/*
+    Copyright (C) 2025 bugo07
+    Released under EUPL 1.2 License
+*/
+
+// framebuffer console that mirrors the log, drawn over the game when toggled.
+// understands the sgr escapes from `serial::color` so it looks like the serial output
+
+use alloc::{collections::vec_deque::VecDeque, vec::Vec};
+use bevy_math::{UVec2, Vec2};
+
+use crate::utils::fb::Framebuffer;
+
+pub const SCROLLBACK_LINES: usize = 500;
+pub const CONSOLE_BG: u32 = 0x101018;
+const DEFAULT_FG: u32 = 0xC0C0C0;
+
+#[derive(Clone, Copy)]
+pub struct Cell {
+    pub ch: u8,
+    pub fg: u32,
+    pub bg: Option<u32>,
+}
+
+enum Escape {
+    None,
+    Esc,
+    Csi(Vec<u8>),
+}
+
+pub struct Console {
+    lines: VecDeque<Vec<Cell>>,
+    fg: u32,
+    bg: Option<u32>,
+    escape: Escape,
+    scroll: usize, // lines scrolled up from the bottom
+    visible: bool,
+}
+
+static CONSOLE: spin::Mutex<Console> = spin::Mutex::new(Console::new());
+
+impl Console {
+    const fn new() -> Self {
+        Self {
+            lines: VecDeque::new(),
+            fg: DEFAULT_FG,
+            bg: None,
+            escape: Escape::None,
+            scroll: 0,
+            visible: false,
+        }
+    }
+
+    fn write_byte(&mut self, byte: u8) {
+        match &mut self.escape {
+            Escape::Esc => {
+                self.escape = if byte == b'[' {
+                    Escape::Csi(Vec::new())
+                } else {
+                    Escape::None
+                };
+                return;
+            }
+            Escape::Csi(params) => {
+                match byte {
+                    b'0'..=b'9' | b';' => params.push(byte),
+                    b'm' => {
+                        let params = core::mem::take(params);
+                        self.escape = Escape::None;
+                        self.apply_sgr(&params);
+                    }
+                    // anything else (cursor movement etc) doesn't mean anything here
+                    _ => self.escape = Escape::None,
+                }
+                return;
+            }
+            Escape::None => {}
+        }
+
+        match byte {
+            0x1B => self.escape = Escape::Esc,
+            b'\n' => self.new_line(),
+            b'\r' => {}
+            byte => {
+                if self.lines.is_empty() {
+                    self.new_line();
+                }
+                let cell = Cell {
+                    ch: byte,
+                    fg: self.fg,
+                    bg: self.bg,
+                };
+                self.lines.back_mut().unwrap().push(cell);
+            }
+        }
+    }
+
+    fn new_line(&mut self) {
+        if self.lines.len() >= SCROLLBACK_LINES {
+            self.lines.pop_front();
+        } else if self.scroll > 0 {
+            // keep the view still while new lines come in
+            self.scroll += 1;
+        }
+        self.lines.push_back(Vec::new());
+    }
+
+    fn apply_sgr(&mut self, params: &[u8]) {
+        let params = core::str::from_utf8(params).unwrap_or("");
+        let mut params = params.split(';').map(|x| x.parse::<u8>().unwrap_or(0));
+
+        while let Some(param) = params.next() {
+            match param {
+                0 => {
+                    self.fg = DEFAULT_FG;
+                    self.bg = None;
+                }
+                30..=37 => self.fg = palette(param - 30),
+                90..=97 => self.fg = palette(param - 90 + 8),
+                40..=47 => self.bg = Some(palette(param - 40)),
+                100..=107 => self.bg = Some(palette(param - 100 + 8)),
+                39 => self.fg = DEFAULT_FG,
+                49 => self.bg = None,
+                38 | 48 => {
+                    let color = match params.next() {
+                        Some(5) => params.next().map(palette),
+                        Some(2) => {
+                            let (r, g, b) = (params.next(), params.next(), params.next());
+                            Some(
+                                (r.unwrap_or(0) as u32) << 16
+                                    | (g.unwrap_or(0) as u32) << 8
+                                    | b.unwrap_or(0) as u32,
+                            )
+                        }
+                        _ => None,
+                    };
+                    if let Some(color) = color {
+                        if param == 38 {
+                            self.fg = color;
+                        } else {
+                            self.bg = Some(color);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+// xterm 256 color palette
+fn palette(index: u8) -> u32 {
+    const BASE: [u32; 16] = [
+        0x000000, 0x800000, 0x008000, 0x808000, 0x000080, 0x800080, 0x008080, 0xC0C0C0, 0x808080,
+        0xFF0000, 0x00FF00, 0xFFFF00, 0x0000FF, 0xFF00FF, 0x00FFFF, 0xFFFFFF,
+    ];
+
+    match index {
+        0..16 => BASE[index as usize],
+        16..232 => {
+            let index = index - 16;
+            let level = |x: u8| if x == 0 { 0 } else { 55 + x as u32 * 40 };
+            level(index / 36) << 16 | level(index / 6 % 6) << 8 | level(index % 6)
+        }
+        _ => {
+            let gray = 8 + (index - 232) as u32 * 10;
+            gray << 16 | gray << 8 | gray
+        }
+    }
+}
+
+pub fn write_str(s: &str) {
+    let mut console = CONSOLE.lock();
+    for byte in s.bytes() {
+        console.write_byte(byte);
+    }
+}
+
+pub fn toggle() -> bool {
+    let mut console = CONSOLE.lock();
+    console.visible = !console.visible;
+    console.scroll = 0;
+    console.visible
+}
+
+pub fn is_visible() -> bool {
+    CONSOLE.lock().visible
+}
+
+// positive scrolls back in time
+pub fn scroll(lines: isize) {
+    let mut console = CONSOLE.lock();
+    let max = console.lines.len().saturating_sub(1);
+    console.scroll = console.scroll.saturating_add_signed(lines).min(max);
+}
+
+// copy of what's on screen, so drawing doesn't hold the lock. none when hidden
+pub fn snapshot(rows: usize) -> Option<Vec<Vec<Cell>>> {
+    let console = CONSOLE.lock();
+    if !console.visible {
+        return None;
+    }
+
+    let end = console.lines.len() - console.scroll.min(console.lines.len());
+    let start = end.saturating_sub(rows);
+    Some(console.lines.range(start..end).cloned().collect())
+}
+
+// how many text rows the overlay gets, the top half of the screen
+pub fn rows_for(fb: &Framebuffer) -> usize {
+    (fb.size.y / 2 / (fb.font_height + fb.font_spacing)) as usize
+}
+
+// `rows` has to come from the whole screen since `fb` might be a band of it
+pub fn draw(fb: &mut Framebuffer, lines: &[Vec<Cell>], rows: usize) {
+    let line_height = fb.font_height + fb.font_spacing;
+    let rows = rows as u32;
+    fb.draw_rect(
+        Vec2::ZERO,
+        UVec2::new(fb.size.x, rows * line_height),
+        CONSOLE_BG,
+    );
+
+    let (_, max) = fb.bounds();
+    let char_width = fb.font_width + fb.font_spacing;
+    let first_row = rows.saturating_sub(lines.len() as u32);
+    for (row, line) in lines.iter().enumerate() {
+        let y = (first_row + row as u32) * line_height;
+        for (col, cell) in line.iter().enumerate() {
+            let x = col as u32 * char_width;
+            if x as i32 >= max.x {
+                break;
+            }
+            fb.draw_char(UVec2::new(x, y), cell.ch, cell.fg, cell.bg, Vec2::ONE, None);
+        }
+    }
+}
@@ -0,0 +1,150 @@
+/*
+    Copyright (C) 2025 bugo07
+    Released under EUPL 1.2 License
+*/
+
+use core::{
+    future::Future,
+    pin::Pin,
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+    task::{Context, Poll, Waker},
+};
+
+use alloc::{boxed::Box, collections::btree_map::BTreeMap, sync::Arc, task::Wake, vec::Vec};
+
+use crate::{arch::thread::WaitQueue, debug, utils::asm::without_ints};
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+static SPAWNED: spin::Mutex<Vec<Task>> = spin::Mutex::new(Vec::new());
+// set by wakers so the executor knows it can't block yet
+static WOKEN: AtomicBool = AtomicBool::new(false);
+static IDLE: WaitQueue = WaitQueue::new();
+
+pub struct Task {
+    id: u64,
+    name: &'static str,
+    future: Pin<Box<dyn Future<Output = ()> + Send>>,
+    waker: Arc<TaskWaker>,
+}
+
+// * waking only flips atomics so it's safe to do from an irq handler
+struct TaskWaker {
+    ready: AtomicBool,
+}
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.ready.store(true, Ordering::Release);
+        WOKEN.store(true, Ordering::Release);
+        IDLE.wake_all();
+    }
+}
+
+pub fn spawn(name: &'static str, future: impl Future<Output = ()> + Send + 'static) {
+    let task = Task {
+        id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+        name,
+        future: Box::pin(future),
+        waker: Arc::new(TaskWaker {
+            ready: AtomicBool::new(true),
+        }),
+    };
+    without_ints(|| SPAWNED.lock().push(task));
+    WOKEN.store(true, Ordering::Release);
+    IDLE.wake_all();
+}
+
+pub fn run() -> ! {
+    let mut tasks: BTreeMap<u64, Task> = BTreeMap::new();
+
+    loop {
+        WOKEN.store(false, Ordering::Release);
+
+        for task in without_ints(|| core::mem::take(&mut *SPAWNED.lock())) {
+            debug!("spawned task {} ({})", task.id, task.name);
+            tasks.insert(task.id, task);
+        }
+
+        tasks.retain(|_, task| {
+            if !task.waker.ready.swap(false, Ordering::AcqRel) {
+                return true;
+            }
+
+            let waker = Waker::from(task.waker.clone());
+            let mut cx = Context::from_waker(&waker);
+            match task.future.as_mut().poll(&mut cx) {
+                Poll::Ready(()) => {
+                    debug!("task {} ({}) finished", task.id, task.name);
+                    false
+                }
+                Poll::Pending => true,
+            }
+        });
+
+        // block the thread until an irq wakes something up
+        IDLE.wait_while(|| !WOKEN.load(Ordering::Acquire));
+    }
+}
+
+pub fn yield_now() -> YieldNow {
+    YieldNow { yielded: false }
+}
+
+pub struct YieldNow {
+    yielded: bool,
+}
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.yielded {
+            return Poll::Ready(());
+        }
+
+        self.yielded = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+// single slot waker that can be registered from a task and woken from an irq
+pub struct AtomicWaker {
+    waker: spin::Mutex<Option<Waker>>,
+}
+
+impl Default for AtomicWaker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AtomicWaker {
+    pub const fn new() -> Self {
+        Self {
+            waker: spin::Mutex::new(None),
+        }
+    }
+
+    pub fn register(&self, waker: &Waker) {
+        without_ints(|| {
+            let mut slot = self.waker.lock();
+            if !slot.as_ref().is_some_and(|x| x.will_wake(waker)) {
+                *slot = Some(waker.clone());
+            }
+        });
+    }
+
+    // doesn't touch the allocator, so irq handlers can call it
+    pub fn wake(&self) {
+        if let Some(lock) = self.waker.try_lock()
+            && let Some(waker) = lock.as_ref()
+        {
+            waker.wake_by_ref();
+        }
+    }
+}
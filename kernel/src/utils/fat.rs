@@ -0,0 +1,1198 @@
+/*
+    Copyright (C) 2025 bugo07
+    Released under EUPL 1.2 License
+*/
+
+// fat12/16/32 with long file names. the whole allocation table is kept in memory
+// (a few hundred KiB for a boot partition) and changed sectors get written back to
+// every copy after each operation. directories are read whole, they're small.
+// a file's node number is where its short entry sits on the disk, in bytes, and 0 is
+// the root directory. there's no clock so timestamps stay zeroed, and the fat32
+// free cluster hint in the fsinfo sector isn't kept up to date
+
+use alloc::{collections::BTreeSet, format, string::String, sync::Arc, vec, vec::Vec};
+
+use crate::utils::{
+    block::{BlockDevice, BlockError},
+    vfs::{DirEntry, FileSystem, FileType, FsError, Metadata},
+};
+
+const ATTR_READ_ONLY: u8 = 0x01;
+const ATTR_HIDDEN: u8 = 0x02;
+const ATTR_SYSTEM: u8 = 0x04;
+const ATTR_VOLUME_ID: u8 = 0x08;
+const ATTR_DIRECTORY: u8 = 0x10;
+const ATTR_ARCHIVE: u8 = 0x20;
+const ATTR_LONG_NAME: u8 = ATTR_READ_ONLY | ATTR_HIDDEN | ATTR_SYSTEM | ATTR_VOLUME_ID;
+
+const ENTRY_SIZE: usize = 32;
+const ENTRY_END: u8 = 0x00;
+const ENTRY_DELETED: u8 = 0xE5;
+const LFN_LAST: u8 = 0x40;
+const LFN_CHARS: usize = 13;
+// where the 13 utf-16 characters of a long name entry are
+const LFN_OFFSETS: [usize; LFN_CHARS] = [1, 3, 5, 7, 9, 14, 16, 18, 20, 22, 24, 28, 30];
+const MAX_NAME: usize = 255;
+
+// the short name case bits windows uses for all lowercase parts
+const CASE_LOWER_BASE: u8 = 0x08;
+const CASE_LOWER_EXT: u8 = 0x10;
+
+const SHORT_NAME_SPECIAL: &[u8] = b"!#$%&'()-@^_`{}~";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FatKind {
+    Fat12,
+    Fat16,
+    Fat32,
+}
+
+impl FatKind {
+    // the type only depends on the cluster count
+    fn from_clusters(clusters: u32) -> Self {
+        if clusters < 4085 {
+            FatKind::Fat12
+        } else if clusters < 65525 {
+            FatKind::Fat16
+        } else {
+            FatKind::Fat32
+        }
+    }
+
+    fn min_clusters(&self) -> u32 {
+        match self {
+            FatKind::Fat12 => 1,
+            FatKind::Fat16 => 4085,
+            FatKind::Fat32 => 65525,
+        }
+    }
+
+    fn max_clusters(&self) -> u32 {
+        match self {
+            FatKind::Fat12 => 4084,
+            FatKind::Fat16 => 65524,
+            FatKind::Fat32 => 0x0FFF_FFF4,
+        }
+    }
+
+    // anything from here up ends a chain
+    fn end_of_chain(&self) -> u32 {
+        match self {
+            FatKind::Fat12 => 0xFF8,
+            FatKind::Fat16 => 0xFFF8,
+            FatKind::Fat32 => 0x0FFF_FFF8,
+        }
+    }
+
+    fn table_bytes(&self, entries: u32) -> usize {
+        match self {
+            FatKind::Fat12 => (entries as usize * 3).div_ceil(2),
+            FatKind::Fat16 => entries as usize * 2,
+            FatKind::Fat32 => entries as usize * 4,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Dir {
+    // the fixed root directory area of fat12/16
+    FixedRoot,
+    Chain(u32),
+}
+
+// a directory entry with its long name put back together
+#[derive(Debug, Clone)]
+struct Node {
+    name: String,
+    short: [u8; 11],
+    attr: u8,
+    cluster: u32,
+    size: u32,
+    // where the long name slots and the short entry are on the disk, short entry last
+    slots: Vec<u64>,
+}
+
+impl Node {
+    fn position(&self) -> u64 {
+        *self.slots.last().unwrap()
+    }
+
+    fn is_dir(&self) -> bool {
+        self.attr & ATTR_DIRECTORY != 0
+    }
+
+    fn metadata(&self) -> Metadata {
+        Metadata {
+            kind: if self.is_dir() {
+                FileType::Directory
+            } else {
+                FileType::File
+            },
+            size: self.size as u64,
+        }
+    }
+}
+
+struct Volume {
+    device: Arc<dyn BlockDevice>,
+    kind: FatKind,
+    sector_size: usize,
+    cluster_sectors: u64,
+    fat_start: u64,
+    fat_sectors: u64,
+    fats: u64,
+    root_start: u64,
+    root_sectors: u64,
+    data_start: u64,
+    clusters: u32,
+    root_cluster: u32,
+    table: Vec<u8>,
+    // sectors of the table that changed since the last flush
+    dirty: BTreeSet<u64>,
+    free_hint: u32,
+}
+
+pub struct FatFs {
+    name: String,
+    volume: spin::Mutex<Volume>,
+}
+
+impl FatFs {
+    pub fn mount(device: Arc<dyn BlockDevice>) -> Result<Self, FsError> {
+        let sector_size = device.sector_size();
+        let mut boot = vec![0u8; sector_size];
+        device.read(0, &mut boot)?;
+
+        let u16_at = |offset: usize| u16::from_le_bytes([boot[offset], boot[offset + 1]]) as u64;
+        let u32_at =
+            |offset: usize| u32::from_le_bytes(boot[offset..offset + 4].try_into().unwrap()) as u64;
+
+        let bytes_per_sector = u16_at(11) as usize;
+        let cluster_sectors = boot[13] as u64;
+        let reserved = u16_at(14);
+        let fats = boot[16] as u64;
+        let root_entries = u16_at(17);
+        let total = if u16_at(19) != 0 {
+            u16_at(19)
+        } else {
+            u32_at(32)
+        };
+        let fat_sectors = if u16_at(22) != 0 {
+            u16_at(22)
+        } else {
+            u32_at(36)
+        };
+
+        if boot[510..512] != [0x55, 0xAA]
+            || bytes_per_sector != sector_size
+            || !cluster_sectors.is_power_of_two()
+            || reserved == 0
+            || fats == 0
+            || fat_sectors == 0
+        {
+            return Err(FsError::UnknownFilesystem);
+        }
+
+        let root_sectors = (root_entries * ENTRY_SIZE as u64).div_ceil(sector_size as u64);
+        let root_start = reserved + fats * fat_sectors;
+        let data_start = root_start + root_sectors;
+        if total > device.sector_count() || total <= data_start {
+            return Err(FsError::Corrupt("bad sector counts"));
+        }
+        let clusters = ((total - data_start) / cluster_sectors) as u32;
+        if clusters == 0 {
+            return Err(FsError::Corrupt("no data clusters"));
+        }
+        let kind = FatKind::from_clusters(clusters);
+        if (kind == FatKind::Fat32) != (root_entries == 0) {
+            return Err(FsError::Corrupt(
+                "root directory doesn't match the fat type",
+            ));
+        }
+        if kind.table_bytes(clusters + 2) > (fat_sectors as usize * sector_size) {
+            return Err(FsError::Corrupt("fat too small"));
+        }
+
+        let mut table = vec![0u8; fat_sectors as usize * sector_size];
+        device.read(reserved, &mut table)?;
+
+        let volume = Volume {
+            kind,
+            sector_size,
+            cluster_sectors,
+            fat_start: reserved,
+            fat_sectors,
+            fats,
+            root_start,
+            root_sectors,
+            data_start,
+            clusters,
+            root_cluster: if kind == FatKind::Fat32 {
+                u32_at(44) as u32
+            } else {
+                0
+            },
+            table,
+            dirty: BTreeSet::new(),
+            free_hint: 2,
+            device: device.clone(),
+        };
+        if kind == FatKind::Fat32 && !volume.valid_cluster(volume.root_cluster) {
+            return Err(FsError::Corrupt("bad root cluster"));
+        }
+
+        Ok(Self {
+            name: format!("{} ({kind:?})", device.name()),
+            volume: spin::Mutex::new(volume),
+        })
+    }
+}
+
+impl Volume {
+    fn cluster_size(&self) -> usize {
+        self.cluster_sectors as usize * self.sector_size
+    }
+
+    fn valid_cluster(&self, cluster: u32) -> bool {
+        cluster >= 2 && cluster < self.clusters + 2
+    }
+
+    // byte position of a cluster on the device
+    fn cluster_position(&self, cluster: u32) -> u64 {
+        (self.data_start + (cluster as u64 - 2) * self.cluster_sectors) * self.sector_size as u64
+    }
+
+    // byte addressed access, partial sectors get read and written back whole
+    fn read_bytes(&self, position: u64, buf: &mut [u8]) -> Result<(), BlockError> {
+        let sector_size = self.sector_size as u64;
+        let first = position / sector_size;
+        if position.is_multiple_of(sector_size) && buf.len().is_multiple_of(self.sector_size) {
+            return self.device.read(first, buf);
+        }
+
+        let end = (position + buf.len() as u64).div_ceil(sector_size);
+        let mut sectors = vec![0u8; ((end - first) * sector_size) as usize];
+        self.device.read(first, &mut sectors)?;
+        let offset = (position % sector_size) as usize;
+        buf.copy_from_slice(&sectors[offset..offset + buf.len()]);
+        Ok(())
+    }
+
+    fn write_bytes(&self, position: u64, data: &[u8]) -> Result<(), BlockError> {
+        let sector_size = self.sector_size as u64;
+        let first = position / sector_size;
+        if position.is_multiple_of(sector_size) && data.len().is_multiple_of(self.sector_size) {
+            return self.device.write(first, data);
+        }
+
+        let end = (position + data.len() as u64).div_ceil(sector_size);
+        let mut sectors = vec![0u8; ((end - first) * sector_size) as usize];
+        self.device.read(first, &mut sectors)?;
+        let offset = (position % sector_size) as usize;
+        sectors[offset..offset + data.len()].copy_from_slice(data);
+        self.device.write(first, &sectors)
+    }
+
+    fn fat_get(&self, cluster: u32) -> u32 {
+        let table = &self.table;
+        let index = cluster as usize;
+        match self.kind {
+            FatKind::Fat12 => {
+                let offset = index + index / 2;
+                let pair = u16::from_le_bytes([table[offset], table[offset + 1]]);
+                if index.is_multiple_of(2) {
+                    (pair & 0xFFF) as u32
+                } else {
+                    (pair >> 4) as u32
+                }
+            }
+            FatKind::Fat16 => u16::from_le_bytes([table[index * 2], table[index * 2 + 1]]) as u32,
+            FatKind::Fat32 => {
+                u32::from_le_bytes(table[index * 4..index * 4 + 4].try_into().unwrap())
+                    & 0x0FFF_FFFF
+            }
+        }
+    }
+
+    fn fat_set(&mut self, cluster: u32, value: u32) {
+        let index = cluster as usize;
+        let (offset, len) = match self.kind {
+            FatKind::Fat12 => {
+                let offset = index + index / 2;
+                let mut pair = u16::from_le_bytes([self.table[offset], self.table[offset + 1]]);
+                pair = if index.is_multiple_of(2) {
+                    (pair & 0xF000) | (value as u16 & 0xFFF)
+                } else {
+                    (pair & 0x000F) | ((value as u16) << 4)
+                };
+                self.table[offset..offset + 2].copy_from_slice(&pair.to_le_bytes());
+                (offset, 2)
+            }
+            FatKind::Fat16 => {
+                self.table[index * 2..index * 2 + 2].copy_from_slice(&(value as u16).to_le_bytes());
+                (index * 2, 2)
+            }
+            FatKind::Fat32 => {
+                // the top 4 bits are reserved and stay as they are
+                let old =
+                    u32::from_le_bytes(self.table[index * 4..index * 4 + 4].try_into().unwrap());
+                let value = (old & 0xF000_0000) | (value & 0x0FFF_FFFF);
+                self.table[index * 4..index * 4 + 4].copy_from_slice(&value.to_le_bytes());
+                (index * 4, 4)
+            }
+        };
+        self.dirty.insert((offset / self.sector_size) as u64);
+        self.dirty
+            .insert(((offset + len - 1) / self.sector_size) as u64);
+    }
+
+    fn flush(&mut self) -> Result<(), BlockError> {
+        for sector in core::mem::take(&mut self.dirty) {
+            let data = &self.table
+                [sector as usize * self.sector_size..(sector as usize + 1) * self.sector_size];
+            for copy in 0..self.fats {
+                self.device
+                    .write(self.fat_start + copy * self.fat_sectors + sector, data)?;
+            }
+        }
+        Ok(())
+    }
+
+    // every cluster of the chain starting at `first`, none for 0
+    fn chain(&self, first: u32) -> Result<Vec<u32>, FsError> {
+        let mut chain = Vec::new();
+        let mut cluster = first;
+        while cluster != 0 {
+            if !self.valid_cluster(cluster) || chain.len() > self.clusters as usize {
+                return Err(FsError::Corrupt("bad cluster chain"));
+            }
+            chain.push(cluster);
+            let next = self.fat_get(cluster);
+            cluster = if next >= self.kind.end_of_chain() {
+                0
+            } else {
+                next
+            };
+        }
+        Ok(chain)
+    }
+
+    // a zeroed cluster put at the end of the chain ending in `last`
+    fn alloc_cluster(&mut self, last: Option<u32>) -> Result<u32, FsError> {
+        let count = self.clusters;
+        let cluster = (0..count)
+            .map(|i| (self.free_hint - 2 + i) % count + 2)
+            .find(|&x| self.fat_get(x) == 0)
+            .ok_or(FsError::NoSpace)?;
+
+        self.write_bytes(
+            self.cluster_position(cluster),
+            &vec![0u8; self.cluster_size()],
+        )?;
+        self.fat_set(cluster, self.kind.end_of_chain() | 0x7);
+        if let Some(last) = last {
+            self.fat_set(last, cluster);
+        }
+        self.free_hint = cluster;
+        Ok(cluster)
+    }
+
+    // `count` more clusters after `last`, all or nothing
+    fn grow_chain(&mut self, last: Option<u32>, count: usize) -> Result<Vec<u32>, FsError> {
+        let mut added = Vec::new();
+        for _ in 0..count {
+            match self.alloc_cluster(added.last().copied().or(last)) {
+                Ok(cluster) => added.push(cluster),
+                Err(err) => {
+                    if let Some(last) = last {
+                        self.fat_set(last, self.kind.end_of_chain() | 0x7);
+                    }
+                    for &cluster in &added {
+                        self.fat_set(cluster, 0);
+                    }
+                    return Err(err);
+                }
+            }
+        }
+        Ok(added)
+    }
+
+    fn free_chain(&mut self, first: u32) -> Result<(), FsError> {
+        for cluster in self.chain(first)? {
+            self.fat_set(cluster, 0);
+        }
+        Ok(())
+    }
+
+    fn root_dir(&self) -> Dir {
+        match self.kind {
+            FatKind::Fat32 => Dir::Chain(self.root_cluster),
+            _ => Dir::FixedRoot,
+        }
+    }
+
+    // `..` entries point at cluster 0 for the root, even on fat32
+    fn dir_at(&self, cluster: u32) -> Dir {
+        if cluster == 0 {
+            self.root_dir()
+        } else {
+            Dir::Chain(cluster)
+        }
+    }
+
+    // every 32 byte slot of a directory along with where it is
+    fn slots(&self, dir: Dir) -> Result<Vec<(u64, [u8; ENTRY_SIZE])>, FsError> {
+        let mut data = match dir {
+            Dir::FixedRoot => {
+                let mut data = vec![0u8; self.root_sectors as usize * self.sector_size];
+                self.device.read(self.root_start, &mut data)?;
+                vec![(self.root_start * self.sector_size as u64, data)]
+            }
+            Dir::Chain(first) => {
+                let mut data = Vec::new();
+                for cluster in self.chain(first)? {
+                    let mut cluster_data = vec![0u8; self.cluster_size()];
+                    self.read_bytes(self.cluster_position(cluster), &mut cluster_data)?;
+                    data.push((self.cluster_position(cluster), cluster_data));
+                }
+                data
+            }
+        };
+
+        Ok(data
+            .iter_mut()
+            .flat_map(|(start, data)| {
+                let start = *start;
+                data.as_chunks::<ENTRY_SIZE>()
+                    .0
+                    .iter()
+                    .enumerate()
+                    .map(move |(i, slot)| (start + (i * ENTRY_SIZE) as u64, *slot))
+            })
+            .collect())
+    }
+
+    fn entries(&self, dir: Dir) -> Result<Vec<Node>, FsError> {
+        Ok(parse_entries(&self.slots(dir)?, self.kind))
+    }
+
+    fn find(&self, dir: Dir, name: &str) -> Result<Option<Node>, FsError> {
+        Ok(self.entries(dir)?.into_iter().find(|x| {
+            names_match(&x.name, name) || names_match(&short_name_str(&x.short, 0), name)
+        }))
+    }
+
+    // the node at `path`, None for the root
+    fn resolve(&self, path: &str) -> Result<Option<Node>, FsError> {
+        let mut node: Option<Node> = None;
+        for part in components(path) {
+            let dir = match &node {
+                None => self.root_dir(),
+                Some(x) if x.is_dir() => self.dir_at(x.cluster),
+                Some(_) => return Err(FsError::NotADirectory),
+            };
+            node = Some(self.find(dir, part)?.ok_or(FsError::NotFound)?);
+        }
+        // `..` from a top level directory leads back to the root
+        Ok(node.filter(|x| !(x.is_dir() && x.cluster == 0)))
+    }
+
+    // the directory `path` would be in, its first cluster (0 for the root) and the name
+    fn resolve_parent<'a>(&self, path: &'a str) -> Result<(Dir, u32, &'a str), FsError> {
+        let parts = components(path).collect::<Vec<_>>();
+        let Some((&name, parents)) = parts.split_last() else {
+            return Err(FsError::InvalidName);
+        };
+        match self.resolve(&parents.join("/"))? {
+            None => Ok((self.root_dir(), 0, name)),
+            Some(x) if x.is_dir() => Ok((self.dir_at(x.cluster), x.cluster, name)),
+            Some(_) => Err(FsError::NotADirectory),
+        }
+    }
+
+    fn read_entry(&self, position: u64) -> Result<[u8; ENTRY_SIZE], FsError> {
+        let mut entry = [0u8; ENTRY_SIZE];
+        self.read_bytes(position, &mut entry)?;
+        if entry[0] == ENTRY_END || entry[0] == ENTRY_DELETED {
+            return Err(FsError::NotFound);
+        }
+        Ok(entry)
+    }
+
+    fn entry_cluster(&self, entry: &[u8; ENTRY_SIZE]) -> u32 {
+        let low = u16::from_le_bytes([entry[26], entry[27]]) as u32;
+        // the high half is something else on fat12/16
+        let high = match self.kind {
+            FatKind::Fat32 => u16::from_le_bytes([entry[20], entry[21]]) as u32,
+            _ => 0,
+        };
+        high << 16 | low
+    }
+
+    fn set_entry_cluster(&self, entry: &mut [u8; ENTRY_SIZE], cluster: u32) {
+        entry[26..28].copy_from_slice(&(cluster as u16).to_le_bytes());
+        if self.kind == FatKind::Fat32 {
+            entry[20..22].copy_from_slice(&((cluster >> 16) as u16).to_le_bytes());
+        }
+    }
+
+    // a new entry in the parent of `path`, pointing at nothing yet
+    fn create(&mut self, path: &str, attr: u8) -> Result<(Node, u32), FsError> {
+        let (dir, parent, name) = self.resolve_parent(path)?;
+        if !valid_name(name) {
+            return Err(FsError::InvalidName);
+        }
+        let existing = self.entries(dir)?;
+        if existing
+            .iter()
+            .any(|x| names_match(&x.name, name) || names_match(&short_name_str(&x.short, 0), name))
+        {
+            return Err(FsError::AlreadyExists);
+        }
+
+        // plain 8.3 names don't need a long name
+        let (short, long) = match exact_short_name(name) {
+            Some(short) => (short, false),
+            None => {
+                let short = generate_short_name(name, |x| existing.iter().any(|e| e.short == *x))
+                    .ok_or(FsError::AlreadyExists)?;
+                (short, true)
+            }
+        };
+
+        let mut entries = Vec::new();
+        if long {
+            let units = name.encode_utf16().collect::<Vec<_>>();
+            let count = units.len().div_ceil(LFN_CHARS);
+            let checksum = short_checksum(&short);
+            for seq in (1..=count).rev() {
+                let mut slot = [0u8; ENTRY_SIZE];
+                slot[0] = seq as u8 | if seq == count { LFN_LAST } else { 0 };
+                slot[11] = ATTR_LONG_NAME;
+                slot[13] = checksum;
+                // the name ends in a 0 and is padded with 0xFFFF after that
+                for (i, &offset) in LFN_OFFSETS.iter().enumerate() {
+                    let index = (seq - 1) * LFN_CHARS + i;
+                    let unit = match index.cmp(&units.len()) {
+                        core::cmp::Ordering::Less => units[index],
+                        core::cmp::Ordering::Equal => 0,
+                        core::cmp::Ordering::Greater => 0xFFFF,
+                    };
+                    slot[offset..offset + 2].copy_from_slice(&unit.to_le_bytes());
+                }
+                entries.push(slot);
+            }
+        }
+        let mut entry = [0u8; ENTRY_SIZE];
+        entry[0..11].copy_from_slice(&short);
+        entry[11] = attr;
+        entries.push(entry);
+
+        let positions = self.free_slots(dir, entries.len())?;
+        for (position, slot) in positions.iter().zip(&entries) {
+            self.write_bytes(*position, slot)?;
+        }
+        self.flush()?;
+
+        Ok((
+            Node {
+                name: String::from(name),
+                short,
+                attr,
+                cluster: 0,
+                size: 0,
+                slots: positions,
+            },
+            parent,
+        ))
+    }
+
+    // `count` free slots in a row, directories other than the fat12/16 root grow
+    fn free_slots(&mut self, dir: Dir, count: usize) -> Result<Vec<u64>, FsError> {
+        loop {
+            let slots = self.slots(dir)?;
+            let mut run = Vec::new();
+            for (position, slot) in &slots {
+                if slot[0] == ENTRY_END || slot[0] == ENTRY_DELETED {
+                    run.push(*position);
+                    if run.len() == count {
+                        return Ok(run);
+                    }
+                } else {
+                    run.clear();
+                }
+            }
+
+            match dir {
+                Dir::FixedRoot => return Err(FsError::NoSpace),
+                Dir::Chain(first) => {
+                    let last = self.chain(first)?.last().copied();
+                    self.alloc_cluster(last)?;
+                }
+            }
+        }
+    }
+
+    fn node_metadata(&self, node: u64) -> Result<Metadata, FsError> {
+        if node == 0 {
+            return Ok(Metadata {
+                kind: FileType::Directory,
+                size: 0,
+            });
+        }
+        let entry = self.read_entry(node)?;
+        Ok(Metadata {
+            kind: if entry[11] & ATTR_DIRECTORY != 0 {
+                FileType::Directory
+            } else {
+                FileType::File
+            },
+            size: u32::from_le_bytes(entry[28..32].try_into().unwrap()) as u64,
+        })
+    }
+
+    // a file's entry, refusing directories
+    fn file_entry(&self, node: u64) -> Result<[u8; ENTRY_SIZE], FsError> {
+        if node == 0 {
+            return Err(FsError::IsADirectory);
+        }
+        let entry = self.read_entry(node)?;
+        if entry[11] & ATTR_DIRECTORY != 0 {
+            return Err(FsError::IsADirectory);
+        }
+        Ok(entry)
+    }
+
+    fn read_at(&self, node: u64, offset: u64, buf: &mut [u8]) -> Result<usize, FsError> {
+        let entry = self.file_entry(node)?;
+        let size = u32::from_le_bytes(entry[28..32].try_into().unwrap()) as u64;
+        if offset >= size {
+            return Ok(0);
+        }
+        let len = buf.len().min((size - offset) as usize);
+        let chain = self.chain(self.entry_cluster(&entry))?;
+        self.transfer(&chain, offset, len, |volume, position, range| {
+            volume.read_bytes(position, &mut buf[range])
+        })?;
+        Ok(len)
+    }
+
+    fn write_at(&mut self, node: u64, offset: u64, data: &[u8]) -> Result<usize, FsError> {
+        let mut entry = self.file_entry(node)?;
+        let size = u32::from_le_bytes(entry[28..32].try_into().unwrap()) as u64;
+        if offset > size {
+            self.write_at(node, size, &vec![0u8; (offset - size) as usize])?;
+            entry = self.file_entry(node)?;
+        }
+
+        let end = offset + data.len() as u64;
+        if end > u32::MAX as u64 {
+            return Err(FsError::NoSpace);
+        }
+
+        let mut chain = self.chain(self.entry_cluster(&entry))?;
+        let needed = (end as usize).div_ceil(self.cluster_size());
+        if needed > chain.len() {
+            let added = self.grow_chain(chain.last().copied(), needed - chain.len())?;
+            chain.extend(added);
+        }
+
+        self.transfer(&chain, offset, data.len(), |volume, position, range| {
+            volume.write_bytes(position, &data[range])
+        })?;
+
+        if let Some(&first) = chain.first() {
+            self.set_entry_cluster(&mut entry, first);
+        }
+        entry[11] |= ATTR_ARCHIVE;
+        entry[28..32].copy_from_slice(&(end.max(size) as u32).to_le_bytes());
+        self.write_bytes(node, &entry)?;
+        self.flush()?;
+        Ok(data.len())
+    }
+
+    // walks `len` bytes from `offset` through the clusters of a chain, `f` gets the
+    // device position and the part of the caller's buffer for each piece
+    fn transfer(
+        &self,
+        chain: &[u32],
+        offset: u64,
+        len: usize,
+        mut f: impl FnMut(&Self, u64, core::ops::Range<usize>) -> Result<(), BlockError>,
+    ) -> Result<(), FsError> {
+        let cluster_size = self.cluster_size() as u64;
+        let mut done = 0;
+        while done < len {
+            let at = offset + done as u64;
+            let cluster = *chain
+                .get((at / cluster_size) as usize)
+                .ok_or(FsError::Corrupt("file is longer than its clusters"))?;
+            let within = at % cluster_size;
+            let count = ((cluster_size - within) as usize).min(len - done);
+            f(
+                self,
+                self.cluster_position(cluster) + within,
+                done..done + count,
+            )?;
+            done += count;
+        }
+        Ok(())
+    }
+
+    fn truncate(&mut self, node: u64) -> Result<(), FsError> {
+        let mut entry = self.file_entry(node)?;
+        self.free_chain(self.entry_cluster(&entry))?;
+        self.set_entry_cluster(&mut entry, 0);
+        entry[28..32].fill(0);
+        self.write_bytes(node, &entry)?;
+        self.flush()?;
+        Ok(())
+    }
+
+    fn create_dir(&mut self, path: &str) -> Result<(), FsError> {
+        let (node, parent) = self.create(path, ATTR_DIRECTORY)?;
+        let cluster = match self.alloc_cluster(None) {
+            Ok(cluster) => cluster,
+            Err(err) => {
+                self.remove_node(&node)?;
+                return Err(err);
+            }
+        };
+
+        let mut dot = [0u8; ENTRY_SIZE];
+        dot[0..11].copy_from_slice(b".          ");
+        dot[11] = ATTR_DIRECTORY;
+        self.set_entry_cluster(&mut dot, cluster);
+        let mut dotdot = [0u8; ENTRY_SIZE];
+        dotdot[0..11].copy_from_slice(b"..         ");
+        dotdot[11] = ATTR_DIRECTORY;
+        self.set_entry_cluster(&mut dotdot, parent);
+        let position = self.cluster_position(cluster);
+        self.write_bytes(position, &dot)?;
+        self.write_bytes(position + ENTRY_SIZE as u64, &dotdot)?;
+
+        let mut entry = self.read_entry(node.position())?;
+        self.set_entry_cluster(&mut entry, cluster);
+        self.write_bytes(node.position(), &entry)?;
+        self.flush()?;
+        Ok(())
+    }
+
+    fn remove(&mut self, path: &str) -> Result<(), FsError> {
+        let node = self.resolve(path)?.ok_or(FsError::InvalidName)?;
+        if node.is_dir()
+            && self
+                .entries(Dir::Chain(node.cluster))?
+                .iter()
+                .any(|x| x.name != "." && x.name != "..")
+        {
+            return Err(FsError::DirectoryNotEmpty);
+        }
+        self.remove_node(&node)
+    }
+
+    fn remove_node(&mut self, node: &Node) -> Result<(), FsError> {
+        for &position in &node.slots {
+            self.write_bytes(position, &[ENTRY_DELETED])?;
+        }
+        self.free_chain(node.cluster)?;
+        self.flush()?;
+        Ok(())
+    }
+}
+
+fn components(path: &str) -> impl Iterator<Item = &str> {
+    path.split('/').filter(|x| !x.is_empty() && *x != ".")
+}
+
+fn names_match(a: &str, b: &str) -> bool {
+    a.chars()
+        .flat_map(char::to_lowercase)
+        .eq(b.chars().flat_map(char::to_lowercase))
+}
+
+fn short_checksum(short: &[u8; 11]) -> u8 {
+    short
+        .iter()
+        .fold(0u8, |sum, &x| sum.rotate_right(1).wrapping_add(x))
+}
+
+// "NAME    EXT" to "NAME.EXT", in lowercase where the case bits say so
+fn short_name_str(short: &[u8; 11], case: u8) -> String {
+    let part = |bytes: &[u8], lower: bool| {
+        let mut part = bytes
+            .iter()
+            .map(|&x| if lower { x.to_ascii_lowercase() } else { x } as char)
+            .collect::<String>();
+        part.truncate(part.trim_end_matches(' ').len());
+        part
+    };
+
+    let mut base = part(&short[0..8], case & CASE_LOWER_BASE != 0);
+    // a first byte of 0xE5 is stored as 0x05
+    if short[0] == 0x05 {
+        base.replace_range(0..1, "\u{E5}");
+    }
+    let ext = part(&short[8..11], case & CASE_LOWER_EXT != 0);
+    if ext.is_empty() {
+        base
+    } else {
+        format!("{base}.{ext}")
+    }
+}
+
+fn valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.encode_utf16().count() <= MAX_NAME
+        && name != "."
+        && name != ".."
+        && !name.ends_with('.')
+        && !name.ends_with(' ')
+        && !name.chars().any(|x| x < ' ' || "\"*/:<>?\\|".contains(x))
+}
+
+fn short_char(x: u8) -> bool {
+    x.is_ascii_uppercase() || x.is_ascii_digit() || SHORT_NAME_SPECIAL.contains(&x)
+}
+
+// the name as a short entry when it already is a valid uppercase 8.3 name
+fn exact_short_name(name: &str) -> Option<[u8; 11]> {
+    let (base, ext) = name.rsplit_once('.').unwrap_or((name, ""));
+    if base.is_empty()
+        || base.len() > 8
+        || ext.len() > 3
+        || !base.bytes().chain(ext.bytes()).all(short_char)
+    {
+        return None;
+    }
+    let mut short = [b' '; 11];
+    short[..base.len()].copy_from_slice(base.as_bytes());
+    short[8..8 + ext.len()].copy_from_slice(ext.as_bytes());
+    Some(short)
+}
+
+// a NAME~N.EXT alias for a long name that isn't taken yet
+fn generate_short_name(name: &str, taken: impl Fn(&[u8; 11]) -> bool) -> Option<[u8; 11]> {
+    let clean = |part: &str| {
+        part.chars()
+            .filter(|&x| x != ' ' && x != '.')
+            .map(|x| {
+                let x = x.to_ascii_uppercase();
+                if x.is_ascii() && short_char(x as u8) {
+                    x as u8
+                } else {
+                    b'_'
+                }
+            })
+            .collect::<Vec<_>>()
+    };
+
+    let trimmed = name.trim_start_matches('.');
+    let (base, ext) = match trimmed.rsplit_once('.') {
+        Some((base, ext)) if !base.is_empty() => (clean(base), clean(ext)),
+        _ => (clean(trimmed), Vec::new()),
+    };
+    let base = if base.is_empty() { b"_".to_vec() } else { base };
+
+    for n in 1..1_000_000u32 {
+        let tail = format!("~{n}");
+        let keep = base.len().min(8 - tail.len());
+        let mut short = [b' '; 11];
+        short[..keep].copy_from_slice(&base[..keep]);
+        short[keep..keep + tail.len()].copy_from_slice(tail.as_bytes());
+        let ext_len = ext.len().min(3);
+        short[8..8 + ext_len].copy_from_slice(&ext[..ext_len]);
+        if !taken(&short) {
+            return Some(short);
+        }
+    }
+    None
+}
+
+struct LongName {
+    units: Vec<[u16; LFN_CHARS]>,
+    checksum: u8,
+    // the sequence number the next slot should have
+    next: u8,
+    slots: Vec<u64>,
+}
+
+// long name slots come before their short entry, last part first
+fn parse_entries(slots: &[(u64, [u8; ENTRY_SIZE])], kind: FatKind) -> Vec<Node> {
+    let mut nodes = Vec::new();
+    let mut long: Option<LongName> = None;
+
+    for &(position, slot) in slots {
+        match slot[0] {
+            ENTRY_END => break,
+            ENTRY_DELETED => {
+                long = None;
+                continue;
+            }
+            _ => {}
+        }
+
+        let attr = slot[11];
+        if attr & 0x3F == ATTR_LONG_NAME {
+            let seq = slot[0] & 0x1F;
+            if slot[0] & LFN_LAST != 0 && seq > 0 {
+                long = Some(LongName {
+                    units: vec![[0; LFN_CHARS]; seq as usize],
+                    checksum: slot[13],
+                    next: seq,
+                    slots: Vec::new(),
+                });
+            }
+            // orphaned or out of order parts are ignored
+            match long.as_mut() {
+                Some(x) if x.next == seq && x.checksum == slot[13] && seq > 0 => {
+                    for (i, &offset) in LFN_OFFSETS.iter().enumerate() {
+                        x.units[seq as usize - 1][i] =
+                            u16::from_le_bytes([slot[offset], slot[offset + 1]]);
+                    }
+                    x.next = seq - 1;
+                    x.slots.push(position);
+                }
+                _ => long = None,
+            }
+            continue;
+        }
+
+        if attr & ATTR_VOLUME_ID != 0 {
+            long = None;
+            continue;
+        }
+
+        let short: [u8; 11] = slot[0..11].try_into().unwrap();
+        let (name, mut slots) = match long.take() {
+            Some(x) if x.next == 0 && x.checksum == short_checksum(&short) => {
+                let units = x
+                    .units
+                    .iter()
+                    .flatten()
+                    .copied()
+                    .take_while(|&x| x != 0)
+                    .collect::<Vec<_>>();
+                (String::from_utf16_lossy(&units), x.slots)
+            }
+            _ => (short_name_str(&short, slot[12]), Vec::new()),
+        };
+        slots.push(position);
+
+        let low = u16::from_le_bytes([slot[26], slot[27]]) as u32;
+        let high = match kind {
+            FatKind::Fat32 => u16::from_le_bytes([slot[20], slot[21]]) as u32,
+            _ => 0,
+        };
+        nodes.push(Node {
+            name,
+            short,
+            attr,
+            cluster: high << 16 | low,
+            size: u32::from_le_bytes(slot[28..32].try_into().unwrap()),
+            slots,
+        });
+    }
+    nodes
+}
+
+impl FileSystem for FatFs {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn open(&self, path: &str, create: bool) -> Result<u64, FsError> {
+        let mut volume = self.volume.lock();
+        match volume.resolve(path) {
+            Ok(Some(node)) if !node.is_dir() => Ok(node.position()),
+            Ok(_) => Err(FsError::IsADirectory),
+            Err(FsError::NotFound) if create => Ok(volume.create(path, ATTR_ARCHIVE)?.0.position()),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn stat(&self, path: &str) -> Result<Metadata, FsError> {
+        let volume = self.volume.lock();
+        Ok(match volume.resolve(path)? {
+            Some(node) => node.metadata(),
+            None => volume.node_metadata(0)?,
+        })
+    }
+
+    fn node_stat(&self, node: u64) -> Result<Metadata, FsError> {
+        self.volume.lock().node_metadata(node)
+    }
+
+    fn read_at(&self, node: u64, offset: u64, buf: &mut [u8]) -> Result<usize, FsError> {
+        self.volume.lock().read_at(node, offset, buf)
+    }
+
+    fn write_at(&self, node: u64, offset: u64, data: &[u8]) -> Result<usize, FsError> {
+        self.volume.lock().write_at(node, offset, data)
+    }
+
+    fn truncate(&self, node: u64) -> Result<(), FsError> {
+        self.volume.lock().truncate(node)
+    }
+
+    fn read_dir(&self, path: &str) -> Result<Vec<DirEntry>, FsError> {
+        let volume = self.volume.lock();
+        let dir = match volume.resolve(path)? {
+            None => volume.root_dir(),
+            Some(node) if node.is_dir() => volume.dir_at(node.cluster),
+            Some(_) => return Err(FsError::NotADirectory),
+        };
+        Ok(volume
+            .entries(dir)?
+            .into_iter()
+            .filter(|x| x.name != "." && x.name != "..")
+            .map(|x| DirEntry {
+                metadata: x.metadata(),
+                name: x.name,
+            })
+            .collect())
+    }
+
+    fn create_dir(&self, path: &str) -> Result<(), FsError> {
+        self.volume.lock().create_dir(path)
+    }
+
+    fn remove(&self, path: &str) -> Result<(), FsError> {
+        self.volume.lock().remove(path)
+    }
+}
+
+// writes an empty filesystem over the whole device. the sectors per cluster are the
+// smallest that keep the cluster count inside what `kind` allows
+pub fn format(device: &dyn BlockDevice, kind: FatKind, label: &str) -> Result<(), FsError> {
+    let sector_size = device.sector_size();
+    let total = device.sector_count().min(u32::MAX as u64);
+    let reserved: u64 = if kind == FatKind::Fat32 { 32 } else { 1 };
+    let fats = 2;
+    let root_entries: u64 = if kind == FatKind::Fat32 { 0 } else { 512 };
+    let root_sectors = (root_entries * ENTRY_SIZE as u64).div_ceil(sector_size as u64);
+
+    let (cluster_sectors, fat_sectors, clusters) = (0..8)
+        .map(|shift| 1u64 << shift)
+        .find_map(|cluster_sectors| {
+            // the fat's size and the cluster count depend on each other
+            let mut fat_sectors = 1;
+            loop {
+                let data = total.checked_sub(reserved + fats * fat_sectors + root_sectors)?;
+                let clusters = (data / cluster_sectors) as u32;
+                let needed = (kind.table_bytes(clusters + 2) as u64).div_ceil(sector_size as u64);
+                if needed <= fat_sectors {
+                    return (clusters <= kind.max_clusters()).then_some((
+                        cluster_sectors,
+                        fat_sectors,
+                        clusters,
+                    ));
+                }
+                fat_sectors = needed;
+            }
+        })
+        .ok_or(FsError::NoSpace)?;
+    if clusters < kind.min_clusters() || FatKind::from_clusters(clusters) != kind {
+        return Err(FsError::NoSpace);
+    }
+
+    let mut label_bytes = [b' '; 11];
+    for (byte, x) in label_bytes.iter_mut().zip(label.bytes()) {
+        *byte = x.to_ascii_uppercase();
+    }
+
+    let mut boot = vec![0u8; sector_size];
+    boot[0..3].copy_from_slice(if kind == FatKind::Fat32 {
+        &[0xEB, 0x58, 0x90]
+    } else {
+        &[0xEB, 0x3C, 0x90]
+    });
+    boot[3..11].copy_from_slice(b"FLAPPYOS");
+    boot[11..13].copy_from_slice(&(sector_size as u16).to_le_bytes());
+    boot[13] = cluster_sectors as u8;
+    boot[14..16].copy_from_slice(&(reserved as u16).to_le_bytes());
+    boot[16] = fats as u8;
+    boot[17..19].copy_from_slice(&(root_entries as u16).to_le_bytes());
+    if total < 0x10000 && kind != FatKind::Fat32 {
+        boot[19..21].copy_from_slice(&(total as u16).to_le_bytes());
+    } else {
+        boot[32..36].copy_from_slice(&(total as u32).to_le_bytes());
+    }
+    boot[21] = 0xF8; // fixed disk
+    boot[24..26].copy_from_slice(&32u16.to_le_bytes()); // sectors per track
+    boot[26..28].copy_from_slice(&64u16.to_le_bytes()); // heads
+
+    let serial = 0x464C_4150u32.to_le_bytes();
+    let ebpb = if kind == FatKind::Fat32 {
+        boot[36..40].copy_from_slice(&(fat_sectors as u32).to_le_bytes());
+        boot[44..48].copy_from_slice(&2u32.to_le_bytes()); // root cluster
+        boot[48..50].copy_from_slice(&1u16.to_le_bytes()); // fsinfo sector
+        boot[50..52].copy_from_slice(&6u16.to_le_bytes()); // backup boot sector
+        64
+    } else {
+        boot[22..24].copy_from_slice(&(fat_sectors as u16).to_le_bytes());
+        36
+    };
+    boot[ebpb] = 0x80; // drive number
+    boot[ebpb + 2] = 0x29; // extended boot signature
+    boot[ebpb + 3..ebpb + 7].copy_from_slice(&serial);
+    boot[ebpb + 7..ebpb + 18].copy_from_slice(&label_bytes);
+    boot[ebpb + 18..ebpb + 26].copy_from_slice(match kind {
+        FatKind::Fat12 => b"FAT12   ",
+        FatKind::Fat16 => b"FAT16   ",
+        FatKind::Fat32 => b"FAT32   ",
+    });
+    boot[510] = 0x55;
+    boot[511] = 0xAA;
+
+    // reserved sectors, the fats and the root directory start out zeroed
+    let zero_sectors = reserved + fats * fat_sectors + root_sectors.max(cluster_sectors);
+    let zeros = vec![0u8; 64 * sector_size];
+    let mut lba = 0;
+    while lba < zero_sectors {
+        let count = (zero_sectors - lba).min(64);
+        device.write(lba, &zeros[..count as usize * sector_size])?;
+        lba += count;
+    }
+    // fat32's root cluster comes after the fats, the loop above covered it
+    device.write(0, &boot)?;
+
+    if kind == FatKind::Fat32 {
+        let mut info = vec![0u8; sector_size];
+        info[0..4].copy_from_slice(&0x4161_5252u32.to_le_bytes());
+        info[484..488].copy_from_slice(&0x6141_7272u32.to_le_bytes());
+        info[488..492].copy_from_slice(&u32::MAX.to_le_bytes()); // free count unknown
+        info[492..496].copy_from_slice(&u32::MAX.to_le_bytes());
+        info[508..512].copy_from_slice(&0xAA55_0000u32.to_le_bytes());
+        device.write(1, &info)?;
+        device.write(6, &boot)?;
+        device.write(7, &info)?;
+    }
+
+    // media byte and end of chain markers in the first two entries, fat32 also has
+    // the root directory's cluster
+    let head: &[u8] = match kind {
+        FatKind::Fat12 => &[0xF8, 0xFF, 0xFF],
+        FatKind::Fat16 => &[0xF8, 0xFF, 0xFF, 0xFF],
+        FatKind::Fat32 => &[
+            0xF8, 0xFF, 0xFF, 0x0F, 0xFF, 0xFF, 0xFF, 0x0F, 0xFF, 0xFF, 0xFF, 0x0F,
+        ],
+    };
+    let mut first = vec![0u8; sector_size];
+    first[..head.len()].copy_from_slice(head);
+    for copy in 0..fats {
+        device.write(reserved + copy * fat_sectors, &first)?;
+    }
+
+    if label.is_empty() {
+        return Ok(());
+    }
+    let mut entry = [0u8; ENTRY_SIZE];
+    entry[0..11].copy_from_slice(&label_bytes);
+    entry[11] = ATTR_VOLUME_ID;
+    let root = reserved + fats * fat_sectors;
+    let mut sector = vec![0u8; sector_size];
+    sector[..ENTRY_SIZE].copy_from_slice(&entry);
+    device.write(root, &sector)?;
+    Ok(())
+}
@@ -3,46 +3,346 @@
     Released under EUPL 1.2 License
 */
 
-use core::ffi::c_void;
+use core::{
+    ffi::c_void,
+    marker::PhantomData,
+    ops::{Deref, DerefMut},
+};
 
 use alloc::vec::Vec;
 use bevy_ecs::prelude::*;
-use bevy_math::{UVec2, Vec2, ops::*};
+use bevy_math::{IVec2, UVec2, Vec2, ops::*};
 
 use crate::utils::asm::memcpy;
 
-#[derive(Debug, Resource)]
-pub struct Framebuffer {
-    pub backbuffer: Vec<u32>,
+// how sprites get sampled when they're scaled or rotated
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Sampling {
+    #[default]
+    Nearest,
+    // smooth, good for big upscales like the pipes
+    Bilinear,
+    // nearest inside, 2x2 samples on the edges so rotations aren't as jagged
+    Supersampled,
+}
+
+// border of a nine-slice sprite in texels
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Insets {
+    pub left: u32,
+    pub top: u32,
+    pub right: u32,
+    pub bottom: u32,
+}
+
+impl Insets {
+    pub const fn new(left: u32, top: u32, right: u32, bottom: u32) -> Self {
+        Self {
+            left,
+            top,
+            right,
+            bottom,
+        }
+    }
+}
+
+// what happens to the middle of a nine-slice
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SliceFill {
+    #[default]
+    Stretch,
+    Tile,
+}
+
+// one physical screen, in whatever mode it ended up in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Screen {
+    // which of the displays, programs pick one by it when blitting
+    pub index: usize,
     pub addr: *mut u8,
     pub size: UVec2,
     pub pitch: u32,
     pub bpp: u32,
+}
+
+unsafe impl Send for Screen {}
+unsafe impl Sync for Screen {}
+
+// how a virtual resolution gets onto the screen
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ScaleMode {
+    // the biggest whole multiple that fits, so every pixel stays the same size. falls
+    // back to Fit on screens smaller than the virtual resolution
+    #[default]
+    Integer,
+    // as big as the aspect ratio allows
+    Fit,
+}
+
+// what a virtual resolution does with more than one screen
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DisplayLayout {
+    // every screen shows the whole thing
+    #[default]
+    Mirror,
+    // side by side, the virtual resolution gets one screen's worth wider per screen
+    Extend,
+}
+
+// which part of a virtual resolution framebuffer lands where on a screen, the rest of
+// the screen is black bars
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Viewport {
+    pub source_offset: UVec2,
+    pub source_size: UVec2,
+    pub offset: UVec2,
+    pub size: UVec2,
+}
+
+impl Viewport {
+    pub fn new(source_size: UVec2, screen: UVec2, mode: ScaleMode) -> Self {
+        let factor = (screen / source_size).min_element();
+        let size = if mode == ScaleMode::Integer && factor >= 1 {
+            source_size * factor
+        } else if screen.x as u64 * source_size.y as u64 <= screen.y as u64 * source_size.x as u64 {
+            // as wide as the screen, bars above and below
+            let height = source_size.y as u64 * screen.x as u64 / source_size.x as u64;
+            UVec2::new(screen.x, height as u32)
+        } else {
+            let width = source_size.x as u64 * screen.y as u64 / source_size.y as u64;
+            UVec2::new(width as u32, screen.y)
+        };
+
+        Self {
+            source_offset: UVec2::ZERO,
+            source_size,
+            offset: (screen - size) / 2,
+            size,
+        }
+    }
+
+    pub fn with_source_offset(mut self, offset: UVec2) -> Self {
+        self.source_offset = offset;
+        self
+    }
+}
+
+// a screen the backbuffer gets presented to, scaled if there's a viewport
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Output {
+    pub screen: Screen,
+    pub viewport: Option<Viewport>,
+}
+
+// what a framebuffer draws into, its own pixels or some rows of another one's
+#[derive(Debug)]
+pub enum Backbuffer {
+    Owned(Vec<u32>),
+    Borrowed(*mut u32, usize),
+}
+
+impl Deref for Backbuffer {
+    type Target = [u32];
+
+    fn deref(&self) -> &[u32] {
+        match self {
+            Backbuffer::Owned(pixels) => pixels,
+            Backbuffer::Borrowed(ptr, len) => unsafe { core::slice::from_raw_parts(*ptr, *len) },
+        }
+    }
+}
+
+impl DerefMut for Backbuffer {
+    fn deref_mut(&mut self) -> &mut [u32] {
+        match self {
+            Backbuffer::Owned(pixels) => pixels,
+            Backbuffer::Borrowed(ptr, len) => unsafe {
+                core::slice::from_raw_parts_mut(*ptr, *len)
+            },
+        }
+    }
+}
+
+// a horizontal strip of a framebuffer drawn to in place, it can't outlive the rows it
+// borrows
+pub struct Band<'a> {
+    fb: Framebuffer,
+    _rows: PhantomData<&'a mut [u32]>,
+}
+
+impl Deref for Band<'_> {
+    type Target = Framebuffer;
+
+    fn deref(&self) -> &Framebuffer {
+        &self.fb
+    }
+}
+
+impl DerefMut for Band<'_> {
+    fn deref_mut(&mut self) -> &mut Framebuffer {
+        &mut self.fb
+    }
+}
+
+#[derive(Debug, Resource)]
+pub struct Framebuffer {
+    pub backbuffer: Backbuffer,
+    pub size: UVec2,
+    pub origin: UVec2, // where the backbuffer sits on screen, non-zero for bands
+    // where `present` puts it, none for off screen ones
+    pub outputs: Vec<Output>,
     pub font: &'static [u8],
     pub font_width: u32,
     pub font_height: u32,
     pub font_spacing: u32,
+    scratch: Scratch,
+}
+
+// what `present_scaled` builds each frame, kept around so presenting doesn't allocate
+#[derive(Debug, Default)]
+struct Scratch {
+    columns: Vec<usize>,
+    pixels: Vec<u32>,
 }
 
 unsafe impl Send for Framebuffer {}
 unsafe impl Sync for Framebuffer {}
 
 impl Framebuffer {
-    pub fn new_from_limine(fb: &limine::framebuffer::Framebuffer) -> Self {
+    pub fn new_from_screen(screen: &Screen) -> Self {
         Framebuffer {
-            backbuffer: alloc::vec![0; fb.width() as usize * fb.height() as usize],
-            addr: fb.addr(),
-            size: UVec2::new(fb.width() as u32, fb.height() as u32),
-            pitch: fb.pitch() as u32,
-            bpp: fb.bpp() as u32,
-            font: include_bytes!("../../res/font.bin"),
+            backbuffer: Backbuffer::Owned(alloc::vec![
+                0;
+                screen.size.x as usize * screen.size.y as usize
+            ]),
+            size: screen.size,
+            origin: UVec2::ZERO,
+            outputs: alloc::vec![Output {
+                screen: *screen,
+                viewport: None,
+            }],
+            font: crate::assets::console_font(),
             font_width: 8,
             font_height: 16,
             font_spacing: 1,
+            scratch: Scratch::default(),
+        }
+    }
+
+    // draws at `size` whatever the screens are, `present` scales it into the middle of
+    // each. the bars around it get blacked out here and after a program had the screen
+    pub fn new_virtual(
+        size: UVec2,
+        mode: ScaleMode,
+        layout: DisplayLayout,
+        screens: &[Screen],
+    ) -> Self {
+        let count = screens.len().max(1) as u32;
+        let canvas = match layout {
+            DisplayLayout::Mirror => size,
+            DisplayLayout::Extend => UVec2::new(size.x * count, size.y),
+        };
+
+        let mut fb = Self::from_backbuffer(
+            alloc::vec![0; canvas.x as usize * canvas.y as usize],
+            canvas,
+        );
+        fb.font = crate::assets::console_font();
+        fb.outputs = screens
+            .iter()
+            .enumerate()
+            .map(|(i, screen)| {
+                let viewport = Viewport::new(size, screen.size, mode);
+                Output {
+                    screen: *screen,
+                    viewport: Some(match layout {
+                        DisplayLayout::Mirror => viewport,
+                        DisplayLayout::Extend => {
+                            viewport.with_source_offset(UVec2::new(size.x * i as u32, 0))
+                        }
+                    }),
+                }
+            })
+            .collect();
+        fb.clear_screens();
+        fb
+    }
+
+    // blacks out every screen this presents to, bars included
+    pub fn clear_screens(&self) {
+        for output in &self.outputs {
+            let row = alloc::vec![0; output.screen.size.x as usize];
+            for y in 0..output.screen.size.y {
+                blit(
+                    &output.screen,
+                    &row,
+                    UVec2::new(0, y),
+                    UVec2::new(output.screen.size.x, 1),
+                );
+            }
         }
     }
 
+    // only a backbuffer, `present` does nothing. for rendering off screen and for tests
+    pub fn from_backbuffer(backbuffer: Vec<u32>, size: UVec2) -> Self {
+        assert_eq!(backbuffer.len(), size.x as usize * size.y as usize);
+        Framebuffer {
+            backbuffer: Backbuffer::Owned(backbuffer),
+            size,
+            origin: UVec2::ZERO,
+            outputs: Vec::new(),
+            font: BUILTIN_FONT,
+            font_width: 8,
+            font_height: 16,
+            font_spacing: 1,
+            scratch: Scratch::default(),
+        }
+    }
+
+    // splits the backbuffer into strips `height` rows high (the last one can be less)
+    // that get drawn to in screen space, one per core. none of them present, that's
+    // left to the whole thing once they're done
+    pub fn bands(&mut self, height: u32) -> Vec<Band<'_>> {
+        let (size, origin) = (self.size, self.origin);
+        let (font, font_width, font_height, font_spacing) = (
+            self.font,
+            self.font_width,
+            self.font_height,
+            self.font_spacing,
+        );
+        let height = height.max(1);
+        self.backbuffer
+            .chunks_mut(size.x.max(1) as usize * height as usize)
+            .enumerate()
+            .map(|(i, rows)| Band {
+                fb: Framebuffer {
+                    size: UVec2::new(size.x, rows.len() as u32 / size.x.max(1)),
+                    origin: UVec2::new(origin.x, origin.y + i as u32 * height),
+                    backbuffer: Backbuffer::Borrowed(rows.as_mut_ptr(), rows.len()),
+                    outputs: Vec::new(),
+                    font,
+                    font_width,
+                    font_height,
+                    font_spacing,
+                    scratch: Scratch::default(),
+                },
+                _rows: PhantomData,
+            })
+            .collect()
+    }
+
+    // drawable screen space area as (min, max), max is exclusive
+    pub fn bounds(&self) -> (IVec2, IVec2) {
+        let min = self.origin.as_ivec2();
+        (min, min + self.size.as_ivec2())
+    }
+
     pub fn draw_pixel(&mut self, pos: UVec2, color: u32) {
+        if pos.x < self.origin.x || pos.y < self.origin.y {
+            return;
+        }
+
+        let pos = pos - self.origin;
         if pos.x >= self.size.x || pos.y >= self.size.y {
             return;
         }
@@ -56,11 +356,10 @@ impl Framebuffer {
         let end_x = start_x + size.x as i32;
         let end_y = start_y + size.y as i32;
 
-        let screen_w = self.size.x as i32;
-        let screen_h = self.size.y as i32;
+        let (min, max) = self.bounds();
 
-        for y in start_y.max(0)..end_y.min(screen_h) {
-            for x in start_x.max(0)..end_x.min(screen_w) {
+        for y in start_y.max(min.y)..end_y.min(max.y) {
+            for x in start_x.max(min.x)..end_x.min(max.x) {
                 self.draw_pixel(UVec2::new(x as u32, y as u32), color);
             }
         }
@@ -109,6 +408,12 @@ impl Framebuffer {
         let scaled_width = ceil(self.font_width as f32 * scale.x) as u32;
         let scaled_height = ceil(self.font_height as f32 * scale.y) as u32;
 
+        let (min, max) = self.bounds();
+        let shadow_y = shadow.map(|(offset, _)| offset.y).unwrap_or(0);
+        if pos.y as i32 >= max.y || ((pos.y + scaled_height + shadow_y) as i32) < min.y {
+            return;
+        }
+
         for sy in 0..scaled_height {
             for sx in 0..scaled_width {
                 let font_x = floor(sx as f32 / scale.x) as u32;
@@ -153,13 +458,13 @@ impl Framebuffer {
 
         let start_x = pos.x;
 
-        for ch in s.bytes() {
-            if ch == b'\n' {
+        for ch in s.chars() {
+            if ch == '\n' {
                 pos.x = start_x;
                 pos.y += scaled_height + self.font_spacing;
                 continue;
             }
-            self.draw_char(pos, ch, fg, bg, scale, shadow);
+            self.draw_char(pos, cp437_from_char(ch), fg, bg, scale, shadow);
             pos.x += scaled_width + self.font_spacing;
         }
     }
@@ -168,8 +473,28 @@ impl Framebuffer {
         self.draw_str_with_shadow(pos, s, fg, bg, scale, None);
     }
 
+    // size of the box draw_str fills, without the shadow
+    pub fn measure_str(&self, s: &str, scale: Vec2) -> UVec2 {
+        let scaled_width = ceil(self.font_width as f32 * scale.x) as u32;
+        let scaled_height = ceil(self.font_height as f32 * scale.y) as u32;
+        let columns = s.lines().map(|line| line.chars().count()).max();
+        let Some(columns) = columns.filter(|&x| x > 0) else {
+            return UVec2::ZERO;
+        };
+        let rows = s.lines().count() as u32;
+
+        UVec2::new(
+            columns as u32 * (scaled_width + self.font_spacing) - self.font_spacing,
+            rows * (scaled_height + self.font_spacing) - self.font_spacing,
+        )
+    }
+
     pub fn centered_str_x(&self, s: &str, scale_x: f32) -> u32 {
-        let longest_line = s.lines().map(|line| line.len()).max().unwrap_or(0);
+        let longest_line = s
+            .lines()
+            .map(|line| line.chars().count())
+            .max()
+            .unwrap_or(0);
         (self.size.x / 2)
             .saturating_sub((longest_line as f32 * self.font_width as f32 * scale_x / 2.0) as u32)
     }
@@ -178,6 +503,9 @@ impl Framebuffer {
         self.size.y / 2 - (self.font_height as f32 * scale_y / 2.0) as u32
     }
 
+    // sprite data is argb like png_to_rust writes it. texels with no alpha or equal to
+    // `transparent` are skipped, partly transparent ones get blended
+    #[allow(clippy::too_many_arguments)]
     pub fn draw_sprite_rotated(
         &mut self,
         pos: Vec2,
@@ -186,7 +514,44 @@ impl Framebuffer {
         data: &[u32],
         transparent: Option<u32>,
         angle_rad: f32,
+        sampling: Sampling,
     ) {
+        let scaled_size = (size.as_vec2() * scale).as_uvec2();
+
+        // only walk the pixels that land inside this framebuffer
+        let (min, max) = self.bounds();
+        let first_row = (min.y - pos.y as i32).clamp(0, scaled_size.y as i32) as u32;
+        let last_row = (max.y - pos.y as i32).clamp(0, scaled_size.y as i32) as u32;
+        let first_col = (min.x - pos.x as i32).clamp(0, scaled_size.x as i32) as u32;
+        let last_col = (max.x - pos.x as i32).clamp(0, scaled_size.x as i32) as u32;
+
+        let texel = |x: i32, y: i32| {
+            if x < 0 || y < 0 || x >= size.x as i32 || y >= size.y as i32 {
+                return 0;
+            }
+            let color = data[(y as u32 * size.x + x as u32) as usize];
+            if Some(color) == transparent { 0 } else { color }
+        };
+
+        let integer_scale = scale.x >= 1.0
+            && scale.y >= 1.0
+            && floor(scale.x) == scale.x
+            && floor(scale.y) == scale.y;
+        if angle_rad == 0.0 && integer_scale && sampling != Sampling::Bilinear {
+            // every screen pixel lands inside a single texel here, so no trig, no float
+            // math and supersampling couldn't change anything either
+            let (scale_x, scale_y) = (scale.x as u32, scale.y as u32);
+            for sy in first_row..last_row {
+                let screen_y = (pos.y as i32 + sy as i32) as u32;
+                for sx in first_col..last_col {
+                    let screen_x = (pos.x as i32 + sx as i32) as u32;
+                    let color = texel((sx / scale_x) as i32, (sy / scale_y) as i32);
+                    self.blend_pixel(UVec2::new(screen_x, screen_y), color);
+                }
+            }
+            return;
+        }
+
         let pivot = size.as_vec2() / 2.0;
         let sin = sin(angle_rad);
         let cos = cos(angle_rad);
@@ -194,54 +559,234 @@ impl Framebuffer {
         let inv_scale_x = 1.0 / scale.x;
         let inv_scale_y = 1.0 / scale.y;
 
-        let scaled_size = (size.as_vec2() * scale).as_uvec2();
-        let screen_w = scaled_size.x;
-        let screen_h = scaled_size.y;
-
-        for sy in 0..screen_h {
-            let dy = sy as f32 * inv_scale_y - pivot.y;
-            for sx in 0..screen_w {
-                let dx = sx as f32 * inv_scale_x - pivot.x;
-
-                let src_x = cos * dx + sin * dy + pivot.x;
-                let src_y = -sin * dx + cos * dy + pivot.y;
-
-                let src_ix = floor(src_x) as i32;
-                let src_iy = floor(src_y) as i32;
-
-                if src_ix < 0 || src_iy < 0 || src_ix >= size.x as i32 || src_iy >= size.y as i32 {
-                    continue;
-                }
-
-                let color = data[(src_iy as u32 * size.x + src_ix as u32) as usize];
+        // sprite space position of a point in the sprite's screen rect
+        let to_src = |x: f32, y: f32| {
+            let dx = x * inv_scale_x - pivot.x;
+            let dy = y * inv_scale_y - pivot.y;
+            Vec2::new(
+                cos * dx + sin * dy + pivot.x,
+                -sin * dx + cos * dy + pivot.y,
+            )
+        };
+        let nearest = |src: Vec2| texel(floor(src.x) as i32, floor(src.y) as i32);
+
+        for sy in first_row..last_row {
+            let screen_y = (pos.y as i32 + sy as i32) as u32;
+            for sx in first_col..last_col {
+                let screen_x = (pos.x as i32 + sx as i32) as u32;
+                let (x, y) = (sx as f32, sy as f32);
+
+                let color = match sampling {
+                    Sampling::Nearest => nearest(to_src(x, y)),
+                    Sampling::Bilinear => {
+                        // centre of the screen pixel against the texel centres
+                        let src = to_src(x + 0.5, y + 0.5) - 0.5;
+                        let (x0, y0) = (floor(src.x), floor(src.y));
+                        let (fx, fy) = (src.x - x0, src.y - y0);
+                        let (x0, y0) = (x0 as i32, y0 as i32);
+                        mix_argb(&[
+                            (texel(x0, y0), (1.0 - fx) * (1.0 - fy)),
+                            (texel(x0 + 1, y0), fx * (1.0 - fy)),
+                            (texel(x0, y0 + 1), (1.0 - fx) * fy),
+                            (texel(x0 + 1, y0 + 1), fx * fy),
+                        ])
+                    }
+                    Sampling::Supersampled => {
+                        let samples = [
+                            nearest(to_src(x + 0.25, y + 0.25)),
+                            nearest(to_src(x + 0.75, y + 0.25)),
+                            nearest(to_src(x + 0.25, y + 0.75)),
+                            nearest(to_src(x + 0.75, y + 0.75)),
+                        ];
+                        // only edges need mixing, inside a texel all four agree
+                        if samples.iter().all(|x| *x == samples[0]) {
+                            samples[0]
+                        } else {
+                            mix_argb(&samples.map(|x| (x, 0.25)))
+                        }
+                    }
+                };
+                self.blend_pixel(UVec2::new(screen_x, screen_y), color);
+            }
+        }
+    }
 
-                if Some(color) == transparent {
-                    continue;
-                }
+    pub fn draw_sprite(
+        &mut self,
+        pos: Vec2,
+        size: UVec2,
+        scale: Vec2,
+        data: &[u32],
+        transparent: Option<u32>,
+    ) {
+        self.draw_sprite_rotated(pos, size, scale, data, transparent, 0.0, Sampling::Nearest);
+    }
 
-                let screen_x = pos.x as i32 + sx as i32;
-                let screen_y = pos.y as i32 + sy as i32;
+    // draws the `src_pos`/`src_size` texels of a sprite over the `pos`/`size` rect on
+    // screen. the region gets stretched to `tile` and repeated from `pos` to fill the
+    // rect, so `tile == size` is a plain stretch. integer only, clipped to the framebuffer
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw_sprite_region(
+        &mut self,
+        pos: IVec2,
+        size: UVec2,
+        tile: UVec2,
+        data: &[u32],
+        data_size: UVec2,
+        src_pos: UVec2,
+        src_size: UVec2,
+        transparent: Option<u32>,
+    ) {
+        let src_size = src_size.min(data_size.saturating_sub(src_pos));
+        if size.cmpeq(UVec2::ZERO).any()
+            || tile.cmpeq(UVec2::ZERO).any()
+            || src_size.cmpeq(UVec2::ZERO).any()
+        {
+            return;
+        }
 
-                if screen_x >= 0
-                    && screen_y >= 0
-                    && screen_x < self.size.x as i32
-                    && screen_y < self.size.y as i32
-                {
-                    self.draw_pixel(UVec2::new(screen_x as u32, screen_y as u32), color);
+        let (min, max) = self.bounds();
+        let start = pos.max(min);
+        let end = (pos + size.as_ivec2()).min(max);
+        // u64 so huge stretches can't overflow
+        let texel =
+            |offset: u32, tile: u32, src: u32| (offset % tile) as u64 * src as u64 / tile as u64;
+
+        for y in start.y..end.y {
+            let src_y = src_pos.y + texel((y - pos.y) as u32, tile.y, src_size.y) as u32;
+            let row = &data[(src_y * data_size.x) as usize..][..data_size.x as usize];
+            for x in start.x..end.x {
+                let src_x = src_pos.x + texel((x - pos.x) as u32, tile.x, src_size.x) as u32;
+                let color = row[src_x as usize];
+                if Some(color) != transparent {
+                    self.blend_pixel(UVec2::new(x as u32, y as u32), color);
                 }
             }
         }
     }
 
-    pub fn draw_sprite(
+    // repeats a whole sprite, each copy `scale` times its size
+    pub fn draw_sprite_tiled(
         &mut self,
-        pos: Vec2,
+        pos: IVec2,
+        size: UVec2,
+        scale: Vec2,
+        data: &[u32],
+        data_size: UVec2,
+        transparent: Option<u32>,
+    ) {
+        let tile = (data_size.as_vec2() * scale).round().as_uvec2();
+        self.draw_sprite_region(
+            pos,
+            size,
+            tile,
+            data,
+            data_size,
+            UVec2::ZERO,
+            data_size,
+            transparent,
+        );
+    }
+
+    // keeps the `insets` border of a sprite undistorted (only scaled by `scale`) and fills
+    // the rest of `size` by stretching or tiling the edges and the middle
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw_nine_slice(
+        &mut self,
+        pos: IVec2,
         size: UVec2,
         scale: Vec2,
         data: &[u32],
+        data_size: UVec2,
+        insets: Insets,
+        fill: SliceFill,
         transparent: Option<u32>,
     ) {
-        self.draw_sprite_rotated(pos, size, scale, data, transparent, 0.0);
+        // texel edges of the three columns and rows, then the same on screen
+        let src_x = slice_edges(data_size.x, insets.left, insets.right, 1.0);
+        let src_y = slice_edges(data_size.y, insets.top, insets.bottom, 1.0);
+        let dst_x = slice_edges(size.x, insets.left, insets.right, scale.x);
+        let dst_y = slice_edges(size.y, insets.top, insets.bottom, scale.y);
+
+        for row in 0..3 {
+            for col in 0..3 {
+                let src_pos = UVec2::new(src_x[col], src_y[row]);
+                let src_size = UVec2::new(src_x[col + 1], src_y[row + 1]) - src_pos;
+                let dst_pos = UVec2::new(dst_x[col], dst_y[row]);
+                let dst_size = UVec2::new(dst_x[col + 1], dst_y[row + 1]) - dst_pos;
+
+                // corners always stretch, edges only repeat along their length
+                let tile = match fill {
+                    SliceFill::Stretch => dst_size,
+                    SliceFill::Tile => {
+                        let scaled = (src_size.as_vec2() * scale)
+                            .round()
+                            .as_uvec2()
+                            .max(UVec2::ONE);
+                        UVec2::new(
+                            if col == 1 { scaled.x } else { dst_size.x },
+                            if row == 1 { scaled.y } else { dst_size.y },
+                        )
+                    }
+                };
+
+                self.draw_sprite_region(
+                    pos + dst_pos.as_ivec2(),
+                    dst_size,
+                    tile,
+                    data,
+                    data_size,
+                    src_pos,
+                    src_size,
+                    transparent,
+                );
+            }
+        }
+    }
+
+    // blends `color` through an 8 bit coverage mask, what text rendering ends up as
+    pub fn draw_coverage(&mut self, pos: IVec2, size: UVec2, coverage: &[u8], color: u32) {
+        let (min, max) = self.bounds();
+        let start = pos.max(min);
+        let end = (pos + size.as_ivec2()).min(max);
+
+        for y in start.y..end.y {
+            let row = ((y - pos.y) as u32 * size.x) as usize;
+            for x in start.x..end.x {
+                let alpha = coverage[row + (x - pos.x) as usize] as u32;
+                self.blend_pixel(
+                    UVec2::new(x as u32, y as u32),
+                    alpha << 24 | (color & 0xFFFFFF),
+                );
+            }
+        }
+    }
+
+    // draws an argb color over what's there by its alpha
+    pub fn blend_pixel(&mut self, pos: UVec2, color: u32) {
+        let alpha = color >> 24;
+        if alpha == 0 {
+            return;
+        }
+        if alpha == 255 {
+            self.draw_pixel(pos, color);
+            return;
+        }
+
+        let (min, max) = self.bounds();
+        let screen = pos.as_ivec2();
+        if screen.x < min.x || screen.y < min.y || screen.x >= max.x || screen.y >= max.y {
+            return;
+        }
+        let pos = pos - self.origin;
+        let dst = &mut self.backbuffer[(pos.y * self.size.x + pos.x) as usize];
+
+        let channel = |shift: u32| {
+            let src = (color >> shift) & 0xFF;
+            let old = (*dst >> shift) & 0xFF;
+            ((src * alpha + old * (255 - alpha) + 127) / 255) << shift
+        };
+        *dst = 0xFF000000 | channel(16) | channel(8) | channel(0);
     }
 
     pub fn clear(&mut self, color: u32) {
@@ -249,15 +794,127 @@ impl Framebuffer {
     }
 
     pub fn present(&mut self) {
-        for y in 0..self.size.y {
-            let src = &self.backbuffer
-                [y as usize * (self.size.x as usize)..(y as usize + 1) * self.size.x as usize];
-            let dst = unsafe { self.addr.add((y * self.pitch) as usize) as *mut u32 };
-            memcpy(
-                dst as *mut c_void,
-                src.as_ptr() as *const c_void,
-                self.size.x as usize * 4,
-            );
+        let mut scratch = core::mem::take(&mut self.scratch);
+        for output in &self.outputs {
+            match output.viewport {
+                Some(viewport) => self.present_scaled(&output.screen, viewport, &mut scratch),
+                None => blit(&output.screen, &self.backbuffer, self.origin, self.size),
+            }
         }
+        self.scratch = scratch;
     }
+
+    // nearest neighbour into the viewport, only the screen rows that sample from the
+    // backbuffer's own rows when it doesn't start at the top
+    fn present_scaled(&self, screen: &Screen, viewport: Viewport, scratch: &mut Scratch) {
+        let source = viewport.source_offset;
+        let source_height = viewport.source_size.y as u64;
+        let screen_row = |y: u32| {
+            let y = y.clamp(source.y, source.y + viewport.source_size.y) - source.y;
+            (y as u64 * viewport.size.y as u64).div_ceil(source_height) as u32
+        };
+        let start = screen_row(self.origin.y);
+        let end = screen_row(self.origin.y + self.size.y);
+        if start == end {
+            return;
+        }
+
+        let width = viewport.size.x;
+        let Scratch { columns, pixels } = scratch;
+        columns.clear();
+        columns.extend((0..width).map(|x| {
+            let x = x as u64 * viewport.source_size.x as u64 / width as u64;
+            (source.x + x as u32 - self.origin.x) as usize
+        }));
+        pixels.clear();
+        for y in start..end {
+            let src_y = source.y + (y as u64 * source_height / viewport.size.y as u64) as u32
+                - self.origin.y;
+            let row = &self.backbuffer[(src_y * self.size.x) as usize..][..self.size.x as usize];
+            pixels.extend(columns.iter().map(|&x| row[x]));
+        }
+
+        blit(
+            screen,
+            pixels,
+            viewport.offset + UVec2::new(0, start),
+            UVec2::new(width, end - start),
+        );
+    }
+}
+
+// tightly packed pixels to the screen at `pos`
+fn blit(screen: &Screen, data: &[u32], pos: UVec2, size: UVec2) {
+    for y in 0..size.y {
+        let src = &data[y as usize * size.x as usize..(y as usize + 1) * size.x as usize];
+        let dst = unsafe {
+            screen
+                .addr
+                .add(((pos.y + y) * screen.pitch + pos.x * 4) as usize) as *mut u32
+        };
+        memcpy(
+            dst as *mut c_void,
+            src.as_ptr() as *const c_void,
+            size.x as usize * 4,
+        );
+    }
+}
+
+// 8x16 code page 437, 256 glyphs of 16 rows
+pub const BUILTIN_FONT: &[u8] = include_bytes!("../../res/font.bin");
+
+// the built-in font is code page 437, the upper half of it in unicode
+pub const CP437_HIGH: [char; 128] = [
+    'Ç', 'ü', 'é', 'â', 'ä', 'à', 'å', 'ç', 'ê', 'ë', 'è', 'ï', 'î', 'ì', 'Ä', 'Å', //
+    'É', 'æ', 'Æ', 'ô', 'ö', 'ò', 'û', 'ù', 'ÿ', 'Ö', 'Ü', '¢', '£', '¥', '₧', 'ƒ', //
+    'á', 'í', 'ó', 'ú', 'ñ', 'Ñ', 'ª', 'º', '¿', '⌐', '¬', '½', '¼', '¡', '«', '»', //
+    '░', '▒', '▓', '│', '┤', '╡', '╢', '╖', '╕', '╣', '║', '╗', '╝', '╜', '╛', '┐', //
+    '└', '┴', '┬', '├', '─', '┼', '╞', '╟', '╚', '╔', '╩', '╦', '╠', '═', '╬', '╧', //
+    '╨', '╤', '╥', '╙', '╘', '╒', '╓', '╫', '╪', '┘', '┌', '█', '▄', '▌', '▐', '▀', //
+    'α', 'ß', 'Γ', 'π', 'Σ', 'σ', 'µ', 'τ', 'Φ', 'Θ', 'Ω', 'δ', '∞', 'φ', 'ε', '∩', //
+    '≡', '±', '≥', '≤', '⌠', '⌡', '÷', '≈', '°', '∙', '·', '√', 'ⁿ', '²', '■', '\u{A0}',
+];
+
+// anything the built-in font doesn't have comes out as '?'
+pub fn cp437_from_char(ch: char) -> u8 {
+    if ch.is_ascii() {
+        return ch as u8;
+    }
+    CP437_HIGH
+        .iter()
+        .position(|x| *x == ch)
+        .map_or(b'?', |x| 0x80 + x as u8)
+}
+
+// [0, start of middle, end of middle, length]. the borders give way (keeping their
+// ratio) when they don't both fit
+fn slice_edges(length: u32, start: u32, end: u32, scale: f32) -> [u32; 4] {
+    let mut start = round(start as f32 * scale) as u32;
+    let mut end = round(end as f32 * scale) as u32;
+    if start + end > length {
+        let total = start + end;
+        start = (start as u64 * length as u64 / total as u64) as u32;
+        end = length - start;
+    }
+    [0, start, length - end, length]
+}
+
+// weighted average of argb colors, weighted by alpha too so transparent texels
+// don't drag the color towards black
+fn mix_argb(samples: &[(u32, f32)]) -> u32 {
+    let (mut alpha, mut r, mut g, mut b) = (0.0, 0.0, 0.0, 0.0);
+    for &(color, weight) in samples {
+        let weight = weight * (color >> 24) as f32;
+        alpha += weight;
+        r += weight * ((color >> 16) & 0xFF) as f32;
+        g += weight * ((color >> 8) & 0xFF) as f32;
+        b += weight * (color & 0xFF) as f32;
+    }
+    if alpha <= 0.0 {
+        return 0;
+    }
+
+    let channel = |x: f32| (x / alpha + 0.5) as u32;
+    let alpha = (alpha + 0.5) as u32;
+    alpha.min(255) << 24 | channel(r) << 16 | channel(g) << 8 | channel(b)
 }
@@ -0,0 +1,634 @@
+/*
+    Copyright (C) 2025 bugo07
+    Released under EUPL 1.2 License
+*/
+
+// fonts for proportional text. bitmap fonts (psf2, bdf and the built-in 8x16) and
+// truetype through ab_glyph, glyphs get rasterized once per size and cached.
+// * sizes are the line height in pixels, bitmap fonts get scaled nearest neighbour
+// * the console and `Framebuffer::draw_str` still use the built-in font directly
+
+use ab_glyph::{Font as _, FontArc, PxScale, ScaleFont};
+use alloc::{
+    collections::btree_map::BTreeMap,
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+use bevy_math::{IVec2, UVec2, Vec2, ops::*};
+
+use crate::utils::fb::{BUILTIN_FONT, CP437_HIGH, Framebuffer};
+
+const PSF2_MAGIC: [u8; 4] = [0x72, 0xB5, 0x4A, 0x86];
+const PSF2_HAS_UNICODE_TABLE: u32 = 1;
+const GLYPH_CACHE_SIZE: usize = 1024;
+const FALLBACK_CHAR: char = '?';
+
+#[derive(Debug)]
+pub enum FontError {
+    UnknownFormat,
+    Truncated,
+    Invalid(&'static str),
+}
+
+// a glyph at one size, `offset` is where its top left goes from the pen on the baseline
+#[derive(Debug, Clone, Default)]
+pub struct Glyph {
+    pub offset: IVec2,
+    pub size: UVec2,
+    pub advance: f32,
+    pub coverage: Vec<u8>,
+}
+
+struct BitmapFont {
+    glyphs: BTreeMap<char, Glyph>,
+    ascent: u32,
+    descent: u32,
+}
+
+enum Source {
+    Bitmap(BitmapFont),
+    Ttf(FontArc),
+}
+
+pub struct Font {
+    source: Source,
+    cache: spin::Mutex<BTreeMap<(char, u32), Arc<Glyph>>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Align {
+    #[default]
+    Left,
+    Center,
+    Right,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct TextStyle {
+    pub size: f32,
+    pub align: Align,
+    pub max_width: Option<f32>, // wraps on spaces, or anywhere if a word doesn't fit
+}
+
+impl TextStyle {
+    pub fn new(size: f32) -> Self {
+        Self {
+            size,
+            align: Align::Left,
+            max_width: None,
+        }
+    }
+
+    pub fn with_align(mut self, align: Align) -> Self {
+        self.align = align;
+        self
+    }
+
+    pub fn with_max_width(mut self, max_width: f32) -> Self {
+        self.max_width = Some(max_width);
+        self
+    }
+}
+
+pub struct PlacedGlyph {
+    pub pos: IVec2, // top left, relative to the layout
+    pub glyph: Arc<Glyph>,
+}
+
+pub struct TextLayout {
+    pub glyphs: Vec<PlacedGlyph>,
+    pub size: Vec2,
+}
+
+static FONTS: spin::RwLock<BTreeMap<String, Arc<Font>>> = spin::RwLock::new(BTreeMap::new());
+
+lazy_static::lazy_static! {
+    static ref BUILTIN: Arc<Font> = Arc::new(Font::builtin());
+}
+
+pub fn init() {
+    register("builtin", BUILTIN.clone());
+}
+
+pub fn register(name: &str, font: Arc<Font>) {
+    FONTS.write().insert(name.to_string(), font);
+}
+
+pub fn get(name: &str) -> Option<Arc<Font>> {
+    FONTS.read().get(name).cloned()
+}
+
+pub fn builtin() -> Arc<Font> {
+    BUILTIN.clone()
+}
+
+pub fn names() -> Vec<String> {
+    FONTS.read().keys().cloned().collect()
+}
+
+impl Font {
+    fn new(source: Source) -> Self {
+        Self {
+            source,
+            cache: spin::Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    // the same 8x16 code page 437 font the framebuffer uses, with its 1px spacing
+    pub fn builtin() -> Self {
+        let data = BUILTIN_FONT;
+        let mut glyphs = BTreeMap::new();
+        let chars = (0x20..0x7F)
+            .map(|x| (x as u8 as char, x))
+            .chain(CP437_HIGH.iter().enumerate().map(|(i, x)| (*x, 0x80 + i)));
+        for (ch, index) in chars {
+            let rows = &data[index * 16..(index + 1) * 16];
+            let mut glyph = bitmap_glyph(rows, UVec2::new(8, 16), 1);
+            glyph.offset = IVec2::new(0, -12);
+            glyph.advance = 9.0;
+            glyphs.insert(ch, glyph);
+        }
+
+        Self::new(Source::Bitmap(BitmapFont {
+            glyphs,
+            ascent: 12,
+            descent: 4,
+        }))
+    }
+
+    // guesses the format from the first few bytes
+    pub fn load(data: &'static [u8]) -> Result<Self, FontError> {
+        if data.starts_with(&PSF2_MAGIC) {
+            Self::from_psf2(data)
+        } else if data.starts_with(b"STARTFONT") {
+            Self::from_bdf(data)
+        } else if data.starts_with(&[0, 1, 0, 0])
+            || data.starts_with(b"true")
+            || data.starts_with(b"OTTO")
+        {
+            Self::from_ttf(data)
+        } else {
+            Err(FontError::UnknownFormat)
+        }
+    }
+
+    pub fn from_ttf(data: &'static [u8]) -> Result<Self, FontError> {
+        let font =
+            FontArc::try_from_slice(data).map_err(|_| FontError::Invalid("bad truetype font"))?;
+        Ok(Self::new(Source::Ttf(font)))
+    }
+
+    // https://www.win.tue.nl/~aeb/linux/kbd/font-formats-1.html
+    pub fn from_psf2(data: &[u8]) -> Result<Self, FontError> {
+        let field = |i: usize| {
+            data.get(i * 4..i * 4 + 4)
+                .map(|x| u32::from_le_bytes(x.try_into().unwrap()))
+                .ok_or(FontError::Truncated)
+        };
+        if !data.starts_with(&PSF2_MAGIC) {
+            return Err(FontError::UnknownFormat);
+        }
+        let header_size = field(2)? as usize;
+        let flags = field(3)?;
+        let count = field(4)? as usize;
+        let glyph_size = field(5)? as usize;
+        let size = UVec2::new(field(7)?, field(6)?);
+        if size.x == 0 || size.y == 0 || glyph_size < size.x.div_ceil(8) as usize * size.y as usize
+        {
+            return Err(FontError::Invalid("glyph size doesn't fit the dimensions"));
+        }
+
+        let glyph_data = data
+            .get(header_size..header_size + count * glyph_size)
+            .ok_or(FontError::Truncated)?;
+        // psf has no baseline, put a quarter of it below
+        let descent = size.y / 4;
+        let ascent = size.y - descent;
+        let glyph = |index: usize| {
+            let mut glyph = bitmap_glyph(&glyph_data[index * glyph_size..][..glyph_size], size, 0);
+            glyph.offset = IVec2::new(0, -(ascent as i32));
+            glyph
+        };
+
+        let mut glyphs = BTreeMap::new();
+        if flags & PSF2_HAS_UNICODE_TABLE != 0 {
+            let mut table = &data[header_size + count * glyph_size..];
+            for index in 0..count {
+                // utf-8 chars, then 0xFE before multi char sequences we don't care about, 0xFF ends it
+                let end = table
+                    .iter()
+                    .position(|x| *x == 0xFF)
+                    .ok_or(FontError::Truncated)?;
+                let singles = table[..end].split(|x| *x == 0xFE).next().unwrap_or(&[]);
+                for ch in core::str::from_utf8(singles).unwrap_or("").chars() {
+                    glyphs.insert(ch, glyph(index));
+                }
+                table = &table[end + 1..];
+            }
+        } else {
+            for index in 0..count {
+                if let Some(ch) = char::from_u32(index as u32) {
+                    glyphs.insert(ch, glyph(index));
+                }
+            }
+        }
+
+        Ok(Self::new(Source::Bitmap(BitmapFont {
+            glyphs,
+            ascent,
+            descent,
+        })))
+    }
+
+    // https://adobe-type-tools.github.io/font-tech-notes/pdfs/5005.BDF_Spec.pdf, only
+    // what's needed for drawing
+    pub fn from_bdf(data: &[u8]) -> Result<Self, FontError> {
+        let text = core::str::from_utf8(data).map_err(|_| FontError::Invalid("bdf isn't utf-8"))?;
+        let mut lines = text.lines().map(str::trim);
+        let number = |x: Option<&str>| {
+            x.and_then(|x| x.parse::<i32>().ok())
+                .ok_or(FontError::Invalid("bad number"))
+        };
+
+        let mut bounding_box = (0, 0, 0, 0);
+        let (mut ascent, mut descent) = (None, None);
+        let mut glyphs = BTreeMap::new();
+
+        while let Some(line) = lines.next() {
+            let mut words = line.split_whitespace();
+            match words.next() {
+                Some("FONTBOUNDINGBOX") => {
+                    bounding_box = (
+                        number(words.next())?,
+                        number(words.next())?,
+                        number(words.next())?,
+                        number(words.next())?,
+                    );
+                }
+                Some("FONT_ASCENT") => ascent = Some(number(words.next())?),
+                Some("FONT_DESCENT") => descent = Some(number(words.next())?),
+                Some("STARTCHAR") => {
+                    let mut encoding = None;
+                    let mut advance = bounding_box.0;
+                    let mut bbx = bounding_box;
+                    let mut rows = Vec::new();
+
+                    for line in lines.by_ref() {
+                        let mut words = line.split_whitespace();
+                        match words.next() {
+                            Some("ENCODING") => encoding = Some(number(words.next())?),
+                            Some("DWIDTH") => advance = number(words.next())?,
+                            Some("BBX") => {
+                                bbx = (
+                                    number(words.next())?,
+                                    number(words.next())?,
+                                    number(words.next())?,
+                                    number(words.next())?,
+                                );
+                            }
+                            Some("BITMAP") => {}
+                            Some("ENDCHAR") => break,
+                            Some(hex) if bbx.1 > rows.len() as i32 => {
+                                let bytes = (0..hex.len() / 2).map(|i| {
+                                    u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).unwrap_or(0)
+                                });
+                                let mut row = bytes.collect::<Vec<_>>();
+                                row.resize((bbx.0.max(0) as usize).div_ceil(8), 0);
+                                rows.extend(row);
+                            }
+                            _ => {}
+                        }
+                    }
+
+                    // -1 means the glyph isn't in the standard encoding
+                    let Some(ch) = encoding.and_then(|x| char::from_u32(x as u32)) else {
+                        continue;
+                    };
+                    let size = UVec2::new(bbx.0.max(0) as u32, bbx.1.max(0) as u32);
+                    rows.resize(size.x.div_ceil(8) as usize * size.y as usize, 0);
+                    let mut glyph = bitmap_glyph(&rows, size, 0);
+                    // bdf offsets are from the baseline to the bottom left, y up
+                    glyph.offset = IVec2::new(bbx.2, -(bbx.3 + bbx.1));
+                    glyph.advance = advance as f32;
+                    glyphs.insert(ch, glyph);
+                }
+                _ => {}
+            }
+        }
+
+        let ascent = ascent.unwrap_or(bounding_box.1 + bounding_box.3).max(0) as u32;
+        let descent = descent.unwrap_or(-bounding_box.3).max(0) as u32;
+        if ascent + descent == 0 {
+            return Err(FontError::Invalid("no ascent or bounding box"));
+        }
+        Ok(Self::new(Source::Bitmap(BitmapFont {
+            glyphs,
+            ascent,
+            descent,
+        })))
+    }
+
+    pub fn line_height(&self, size: f32) -> f32 {
+        match &self.source {
+            Source::Bitmap(_) => size,
+            Source::Ttf(font) => {
+                let font = font.as_scaled(PxScale::from(size));
+                font.height() + font.line_gap()
+            }
+        }
+    }
+
+    pub fn ascent(&self, size: f32) -> f32 {
+        match &self.source {
+            Source::Bitmap(font) => font.ascent as f32 * size / (font.ascent + font.descent) as f32,
+            Source::Ttf(font) => font.as_scaled(PxScale::from(size)).ascent(),
+        }
+    }
+
+    pub fn has_glyph(&self, ch: char) -> bool {
+        match &self.source {
+            Source::Bitmap(font) => font.glyphs.contains_key(&ch),
+            Source::Ttf(font) => font.glyph_id(ch).0 != 0,
+        }
+    }
+
+    // what gets drawn for `ch`, which is '?' if the font doesn't have it
+    fn resolve(&self, ch: char) -> char {
+        if self.has_glyph(ch) {
+            ch
+        } else {
+            FALLBACK_CHAR
+        }
+    }
+
+    // doesn't rasterize anything
+    pub fn advance(&self, ch: char, size: f32) -> f32 {
+        let ch = self.resolve(ch);
+        match &self.source {
+            Source::Bitmap(font) => {
+                let scale = size / (font.ascent + font.descent) as f32;
+                font.glyphs.get(&ch).map_or(0.0, |x| x.advance * scale)
+            }
+            Source::Ttf(font) => font
+                .as_scaled(PxScale::from(size))
+                .h_advance(font.glyph_id(ch)),
+        }
+    }
+
+    pub fn kern(&self, first: char, second: char, size: f32) -> f32 {
+        match &self.source {
+            Source::Bitmap(_) => 0.0,
+            Source::Ttf(font) => {
+                let (first, second) = (
+                    font.glyph_id(self.resolve(first)),
+                    font.glyph_id(self.resolve(second)),
+                );
+                font.as_scaled(PxScale::from(size)).kern(first, second)
+            }
+        }
+    }
+
+    pub fn glyph(&self, ch: char, size: f32) -> Arc<Glyph> {
+        let ch = self.resolve(ch);
+        let key = (ch, size.to_bits());
+        if let Some(glyph) = self.cache.lock().get(&key) {
+            return glyph.clone();
+        }
+
+        let glyph = Arc::new(self.rasterize(ch, size));
+        let mut cache = self.cache.lock();
+        if cache.len() >= GLYPH_CACHE_SIZE {
+            cache.clear();
+        }
+        cache.insert(key, glyph.clone());
+        glyph
+    }
+
+    fn rasterize(&self, ch: char, size: f32) -> Glyph {
+        match &self.source {
+            Source::Bitmap(font) => {
+                let Some(glyph) = font.glyphs.get(&ch) else {
+                    return Glyph::default();
+                };
+                let scale = size / (font.ascent + font.descent) as f32;
+                if scale == 1.0 {
+                    return glyph.clone();
+                }
+
+                let scaled = (glyph.size.as_vec2() * scale).ceil().as_uvec2();
+                let mut coverage = Vec::with_capacity((scaled.x * scaled.y) as usize);
+                for y in 0..scaled.y {
+                    let src_y = ((y as f32 / scale) as u32).min(glyph.size.y - 1);
+                    for x in 0..scaled.x {
+                        let src_x = ((x as f32 / scale) as u32).min(glyph.size.x - 1);
+                        coverage.push(glyph.coverage[(src_y * glyph.size.x + src_x) as usize]);
+                    }
+                }
+                Glyph {
+                    offset: (glyph.offset.as_vec2() * scale).round().as_ivec2(),
+                    size: scaled,
+                    advance: glyph.advance * scale,
+                    coverage,
+                }
+            }
+            Source::Ttf(font) => {
+                let scale = PxScale::from(size);
+                let id = font.glyph_id(ch);
+                let advance = font.as_scaled(scale).h_advance(id);
+                // spaces and such have no outline
+                let Some(outline) = font.outline_glyph(id.with_scale(scale)) else {
+                    return Glyph {
+                        advance,
+                        ..Glyph::default()
+                    };
+                };
+
+                let bounds = outline.px_bounds();
+                let size = UVec2::new(bounds.width() as u32, bounds.height() as u32);
+                let mut coverage = alloc::vec![0; (size.x * size.y) as usize];
+                outline.draw(|x, y, c| {
+                    if x < size.x && y < size.y {
+                        coverage[(y * size.x + x) as usize] = (c * 255.0 + 0.5).min(255.0) as u8;
+                    }
+                });
+                Glyph {
+                    offset: IVec2::new(bounds.min.x as i32, bounds.min.y as i32),
+                    size,
+                    advance,
+                    coverage,
+                }
+            }
+        }
+    }
+
+    // pen advance over a line including kerning
+    pub fn line_width(&self, line: &str, size: f32) -> f32 {
+        let mut width = 0.0;
+        let mut prev = None;
+        for ch in line.chars() {
+            if let Some(prev) = prev {
+                width += self.kern(prev, ch, size);
+            }
+            width += self.advance(ch, size);
+            prev = Some(ch);
+        }
+        width
+    }
+
+    // splits into lines on '\n' and where `max_width` runs out
+    pub fn wrap<'a>(&self, text: &'a str, style: &TextStyle) -> Vec<&'a str> {
+        let mut lines = Vec::new();
+        for paragraph in text.split('\n') {
+            let Some(max_width) = style.max_width else {
+                lines.push(paragraph);
+                continue;
+            };
+
+            let mut start = 0;
+            while start < paragraph.len() {
+                let rest = &paragraph[start..];
+                let mut end = rest.len();
+                if self.line_width(rest, style.size) > max_width {
+                    end = self.fit(rest, max_width, style.size);
+                }
+
+                lines.push(rest[..end].trim_end_matches(' '));
+                start += end;
+                // the space that got wrapped on doesn't start the next line
+                start +=
+                    paragraph[start..].len() - paragraph[start..].trim_start_matches(' ').len();
+            }
+            if paragraph.is_empty() {
+                lines.push(paragraph);
+            }
+        }
+        lines
+    }
+
+    // byte length of the longest start of `line` that fits, broken after a word if possible
+    fn fit(&self, line: &str, max_width: f32, size: f32) -> usize {
+        let mut width = 0.0;
+        let mut prev = None;
+        let mut last_space = None;
+        for (i, ch) in line.char_indices() {
+            if let Some(prev) = prev {
+                width += self.kern(prev, ch, size);
+            }
+            width += self.advance(ch, size);
+            if width > max_width && ch != ' ' {
+                // always take at least one char so this can't get stuck
+                let first_end = line.chars().next().map_or(0, char::len_utf8);
+                return last_space.unwrap_or(i).max(first_end);
+            }
+            if ch == ' ' {
+                last_space = Some(i + 1);
+            }
+            prev = Some(ch);
+        }
+        line.len()
+    }
+
+    pub fn measure(&self, text: &str, style: &TextStyle) -> Vec2 {
+        let lines = self.wrap(text, style);
+        let width = lines
+            .iter()
+            .map(|x| self.line_width(x, style.size))
+            .fold(0.0, f32::max);
+        Vec2::new(
+            style.max_width.unwrap_or(width).max(width),
+            lines.len() as f32 * self.line_height(style.size),
+        )
+    }
+
+    pub fn layout(&self, text: &str, style: &TextStyle) -> TextLayout {
+        let lines = self.wrap(text, style);
+        let widths = lines
+            .iter()
+            .map(|x| self.line_width(x, style.size))
+            .collect::<Vec<_>>();
+        let block_width = style
+            .max_width
+            .unwrap_or(0.0)
+            .max(widths.iter().copied().fold(0.0, f32::max));
+        let line_height = self.line_height(style.size);
+        let ascent = self.ascent(style.size);
+
+        let mut glyphs = Vec::new();
+        for (row, (line, width)) in lines.iter().zip(&widths).enumerate() {
+            let mut pen_x = match style.align {
+                Align::Left => 0.0,
+                Align::Center => (block_width - width) / 2.0,
+                Align::Right => block_width - width,
+            };
+            let baseline = round(ascent + row as f32 * line_height) as i32;
+
+            let mut prev = None;
+            for ch in line.chars() {
+                if let Some(prev) = prev {
+                    pen_x += self.kern(prev, ch, style.size);
+                }
+                let glyph = self.glyph(ch, style.size);
+                if !glyph.coverage.is_empty() {
+                    glyphs.push(PlacedGlyph {
+                        pos: IVec2::new(round(pen_x) as i32, baseline) + glyph.offset,
+                        glyph: glyph.clone(),
+                    });
+                }
+                pen_x += glyph.advance;
+                prev = Some(ch);
+            }
+        }
+
+        TextLayout {
+            glyphs,
+            size: Vec2::new(block_width, lines.len() as f32 * line_height),
+        }
+    }
+}
+
+// msb first rows of bits to 0/255 coverage
+fn bitmap_glyph(rows: &[u8], size: UVec2, spacing: u32) -> Glyph {
+    let bytes_per_row = size.x.div_ceil(8) as usize;
+    let mut coverage = Vec::with_capacity((size.x * size.y) as usize);
+    for y in 0..size.y as usize {
+        for x in 0..size.x as usize {
+            let byte = rows.get(y * bytes_per_row + x / 8).copied().unwrap_or(0);
+            coverage.push(if byte >> (7 - x % 8) & 1 != 0 { 255 } else { 0 });
+        }
+    }
+    Glyph {
+        offset: IVec2::ZERO,
+        size,
+        advance: (size.x + spacing) as f32,
+        coverage,
+    }
+}
+
+pub fn draw_layout(fb: &mut Framebuffer, pos: IVec2, layout: &TextLayout, color: u32) {
+    for placed in &layout.glyphs {
+        fb.draw_coverage(
+            pos + placed.pos,
+            placed.glyph.size,
+            &placed.glyph.coverage,
+            color,
+        );
+    }
+}
+
+// `pos` is the top left of the text block, the shadow goes under the text
+pub fn draw_text(
+    fb: &mut Framebuffer,
+    pos: IVec2,
+    font: &Font,
+    text: &str,
+    style: &TextStyle,
+    color: u32,
+    shadow: Option<(IVec2, u32)>,
+) {
+    let layout = font.layout(text, style);
+    if let Some((offset, shadow_color)) = shadow {
+        draw_layout(fb, pos + offset, &layout, shadow_color);
+    }
+    draw_layout(fb, pos, &layout, color);
+}
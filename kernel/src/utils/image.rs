@@ -0,0 +1,178 @@
+/*
+    Copyright (C) 2025 bugo07
+    Released under EUPL 1.2 License
+*/
+
+// encoders for getting pixels out of the machine. both take 0xRRGGBB pixels, the
+// same layout as `Framebuffer::backbuffer`, and drop the top byte
+
+use alloc::vec::Vec;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+    Png,
+    Qoi,
+}
+
+impl ImageFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ImageFormat::Png => "png",
+            ImageFormat::Qoi => "qoi",
+        }
+    }
+
+    pub fn encode(&self, pixels: &[u32], width: u32, height: u32) -> Vec<u8> {
+        match self {
+            ImageFormat::Png => encode_png(pixels, width, height),
+            ImageFormat::Qoi => encode_qoi(pixels, width, height),
+        }
+    }
+}
+
+pub fn crc32(data: &[u8]) -> u32 {
+    crc32_update(0xFFFFFFFF, data) ^ 0xFFFFFFFF
+}
+
+// for checksums over data that isn't in one piece, start with !0 and invert at the end
+pub fn crc32_update(mut crc: u32, data: &[u8]) -> u32 {
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xEDB88320 & (crc & 1).wrapping_neg());
+        }
+    }
+    crc
+}
+
+pub fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    // 5552 is the most bytes that can be summed before b could overflow
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    (b << 16) | a
+}
+
+fn png_chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = out.len();
+    out.extend_from_slice(kind);
+    out.extend_from_slice(data);
+    let crc = crc32(&out[start..]);
+    out.extend_from_slice(&crc.to_be_bytes());
+}
+
+// 8 bit rgb, no filtering and stored (uncompressed) deflate blocks. big, but cheap
+// enough to make on a kernel without a compressor
+pub fn encode_png(pixels: &[u32], width: u32, height: u32) -> Vec<u8> {
+    let mut raw = Vec::with_capacity((width as usize * 3 + 1) * height as usize);
+    for row in pixels.chunks(width as usize).take(height as usize) {
+        raw.push(0); // filter type none
+        for pixel in row {
+            let [b, g, r, _] = pixel.to_le_bytes();
+            raw.extend_from_slice(&[r, g, b]);
+        }
+    }
+
+    let mut zlib = Vec::with_capacity(raw.len() + raw.len() / 65535 * 5 + 16);
+    zlib.extend_from_slice(&[0x78, 0x01]);
+    let blocks = raw.chunks(65535).collect::<Vec<_>>();
+    for (i, block) in blocks.iter().enumerate() {
+        zlib.push((i == blocks.len() - 1) as u8); // BFINAL, BTYPE 00
+        let len = block.len() as u16;
+        zlib.extend_from_slice(&len.to_le_bytes());
+        zlib.extend_from_slice(&(!len).to_le_bytes());
+        zlib.extend_from_slice(block);
+    }
+    if blocks.is_empty() {
+        zlib.extend_from_slice(&[1, 0, 0, 0xFF, 0xFF]);
+    }
+    zlib.extend_from_slice(&adler32(&raw).to_be_bytes());
+
+    let mut ihdr = Vec::with_capacity(13);
+    ihdr.extend_from_slice(&width.to_be_bytes());
+    ihdr.extend_from_slice(&height.to_be_bytes());
+    ihdr.extend_from_slice(&[8, 2, 0, 0, 0]); // depth, rgb, deflate, no filter, no interlace
+
+    let mut out = Vec::with_capacity(zlib.len() + 64);
+    out.extend_from_slice(b"\x89PNG\r\n\x1a\n");
+    png_chunk(&mut out, b"IHDR", &ihdr);
+    png_chunk(&mut out, b"IDAT", &zlib);
+    png_chunk(&mut out, b"IEND", &[]);
+    out
+}
+
+// https://qoiformat.org/qoi-specification.pdf, rgb only
+pub fn encode_qoi(pixels: &[u32], width: u32, height: u32) -> Vec<u8> {
+    const QOI_OP_INDEX: u8 = 0x00;
+    const QOI_OP_DIFF: u8 = 0x40;
+    const QOI_OP_LUMA: u8 = 0x80;
+    const QOI_OP_RUN: u8 = 0xC0;
+    const QOI_OP_RGB: u8 = 0xFE;
+
+    let count = width as usize * height as usize;
+    let mut out = Vec::with_capacity(14 + count * 2 + 8);
+    out.extend_from_slice(b"qoif");
+    out.extend_from_slice(&width.to_be_bytes());
+    out.extend_from_slice(&height.to_be_bytes());
+    out.extend_from_slice(&[3, 0]); // rgb, srgb
+
+    let mut index = [[0u8; 4]; 64];
+    let mut prev = [0u8, 0, 0, 255];
+    let mut run = 0u8;
+
+    for (i, pixel) in pixels.iter().take(count).enumerate() {
+        let [b, g, r, _] = pixel.to_le_bytes();
+        let px = [r, g, b, 255];
+
+        if px == prev {
+            run += 1;
+            if run == 62 || i == count - 1 {
+                out.push(QOI_OP_RUN | (run - 1));
+                run = 0;
+            }
+            continue;
+        }
+        if run > 0 {
+            out.push(QOI_OP_RUN | (run - 1));
+            run = 0;
+        }
+
+        let hash = (r as usize * 3 + g as usize * 5 + b as usize * 7 + 255 * 11) % 64;
+        if index[hash] == px {
+            out.push(QOI_OP_INDEX | hash as u8);
+        } else {
+            index[hash] = px;
+
+            let dr = r.wrapping_sub(prev[0]) as i8;
+            let dg = g.wrapping_sub(prev[1]) as i8;
+            let db = b.wrapping_sub(prev[2]) as i8;
+            let dr_dg = dr.wrapping_sub(dg);
+            let db_dg = db.wrapping_sub(dg);
+
+            if (-2..=1).contains(&dr) && (-2..=1).contains(&dg) && (-2..=1).contains(&db) {
+                out.push(
+                    QOI_OP_DIFF | ((dr + 2) as u8) << 4 | ((dg + 2) as u8) << 2 | (db + 2) as u8,
+                );
+            } else if (-32..=31).contains(&dg)
+                && (-8..=7).contains(&dr_dg)
+                && (-8..=7).contains(&db_dg)
+            {
+                out.push(QOI_OP_LUMA | (dg + 32) as u8);
+                out.push(((dr_dg + 8) as u8) << 4 | (db_dg + 8) as u8);
+            } else {
+                out.extend_from_slice(&[QOI_OP_RGB, r, g, b]);
+            }
+        }
+        prev = px;
+    }
+
+    out.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 1]);
+    out
+}
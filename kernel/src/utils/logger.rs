@@ -0,0 +1,414 @@
+/*
+    Copyright (C) 2025 bugo07
+    Released under EUPL 1.2 License
+*/
+
+// `log` backend. records go into a ring of recent entries and get written to serial
+// by `drain_task`, so logging never waits on the uart. before the executor runs
+// (and on panic) the ring is flushed right away instead.
+// * nothing in here allocates on the logging path, so irqs and syscalls can log too.
+// * the on-screen console is fed from the same place as serial
+
+use core::{
+    fmt::Write,
+    future::poll_fn,
+    sync::atomic::{AtomicBool, AtomicU8, AtomicU64, Ordering},
+    task::Poll,
+};
+
+use alloc::{string::String, vec::Vec};
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+use crate::{
+    arch::time::preferred_timer_ns,
+    utils::{
+        asm::int_status,
+        cmdline, console,
+        executor::{AtomicWaker, yield_now},
+        heapless::HeaplessString,
+        serial::{SerialWriter, color},
+    },
+};
+
+pub const LOG_RING_SIZE: usize = 256;
+// `ok!` tags its target with this, so it still goes through the module's filter
+const OK_SUFFIX: &str = "::OK";
+
+#[derive(Clone, Copy)]
+pub struct LogRecord {
+    pub seq: u64,
+    pub time_ns: u64,
+    pub level: Level,
+    pub ok: bool, // from `ok!`, logged as info
+    pub target: HeaplessString<48>,
+    pub message: HeaplessString<208>,
+}
+
+impl LogRecord {
+    // what goes between the brackets
+    pub fn tag(&self) -> &'static str {
+        if self.ok {
+            " OK "
+        } else {
+            level_name(self.level)
+        }
+    }
+}
+
+struct LogRing {
+    records: [Option<LogRecord>; LOG_RING_SIZE],
+    next_seq: u64,
+}
+
+impl LogRing {
+    fn get(&self, seq: u64) -> Option<&LogRecord> {
+        self.records[seq as usize % LOG_RING_SIZE]
+            .as_ref()
+            .filter(|x| x.seq == seq)
+    }
+}
+
+struct Filter {
+    module: String,
+    level: LevelFilter,
+}
+
+static LOGGER: Logger = Logger;
+static RING: spin::Mutex<LogRing> = spin::Mutex::new(LogRing {
+    records: [const { None }; LOG_RING_SIZE],
+    next_seq: 0,
+});
+static FILTERS: spin::RwLock<Vec<Filter>> = spin::RwLock::new(Vec::new());
+static DEFAULT_LEVEL: AtomicU8 = AtomicU8::new(if cfg!(debug_assertions) {
+    LevelFilter::Debug as u8
+} else {
+    LevelFilter::Info as u8
+});
+
+static PUSHED: AtomicU64 = AtomicU64::new(0); // next_seq, readable without the lock
+static DRAINED: AtomicU64 = AtomicU64::new(0); // next seq to go out over serial
+static DRAINING: AtomicBool = AtomicBool::new(false);
+static DRAIN_WAKER: AtomicWaker = AtomicWaker::new();
+
+struct Logger;
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= level_for(metadata.target())
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let ok_target = record.target().strip_suffix(OK_SUFFIX);
+        let mut entry = LogRecord {
+            seq: 0,
+            time_ns: preferred_timer_ns(),
+            level: record.level(),
+            ok: ok_target.is_some(),
+            target: HeaplessString::new(),
+            message: HeaplessString::new(),
+        };
+        entry
+            .target
+            .write_str(ok_target.unwrap_or(record.target()))
+            .ok();
+        write!(entry.message, "{}", record.args()).ok();
+
+        let pushed = with_ring(|ring| {
+            entry.seq = ring.next_seq;
+            ring.records[entry.seq as usize % LOG_RING_SIZE] = Some(entry);
+            ring.next_seq += 1;
+            PUSHED.store(ring.next_seq, Ordering::Release);
+        });
+
+        if pushed.is_none() {
+            // ring is busy and we can't wait, better out of order than lost
+            write_record(&entry);
+        } else if !DRAINING.load(Ordering::Acquire) {
+            flush();
+        }
+    }
+
+    fn flush(&self) {
+        flush();
+    }
+}
+
+pub fn init() {
+    log::set_logger(&LOGGER).ok();
+    log::set_max_level(LevelFilter::Trace);
+    update_max_level();
+}
+
+// after cmdline::init, filters look like `log=info,arch::elf=debug,bevy_ecs=warn`
+pub fn init_filters() {
+    if let Some(spec) = &cmdline::config().log
+        && let Err(err) = set_filters(spec)
+    {
+        log::warn!("bad log filter in cmdline: {err}");
+    }
+}
+
+// takes `level` or `module=level` pairs separated by commas
+pub fn set_filters(spec: &str) -> Result<(), String> {
+    let mut default = None;
+    let mut filters = Vec::new();
+
+    for part in spec.split(',').filter(|x| !x.is_empty()) {
+        let (module, level) = match part.split_once('=') {
+            Some((module, level)) => (Some(module), level),
+            None => (None, part),
+        };
+        let level = parse_level(level).ok_or_else(|| alloc::format!("unknown level {level}"))?;
+
+        match module {
+            Some(module) => filters.push(Filter {
+                module: String::from(module),
+                level,
+            }),
+            None => default = Some(level),
+        }
+    }
+
+    // readers only ever try_read, so this is fine to hold with interrupts on
+    let mut lock = FILTERS.write();
+    for filter in filters {
+        lock.retain(|x| x.module != filter.module);
+        lock.push(filter);
+    }
+    drop(lock);
+    if let Some(level) = default {
+        DEFAULT_LEVEL.store(level as u8, Ordering::Relaxed);
+    }
+    update_max_level();
+    Ok(())
+}
+
+pub fn clear_filters() {
+    FILTERS.write().clear();
+    update_max_level();
+}
+
+// for the shell
+pub fn describe_filters() -> String {
+    let mut out = alloc::format!("{}", level_from_u8(DEFAULT_LEVEL.load(Ordering::Relaxed)));
+    for filter in FILTERS.read().iter() {
+        write!(out, ",{}={}", filter.module, filter.level).ok();
+    }
+    out.to_lowercase()
+}
+
+fn parse_level(level: &str) -> Option<LevelFilter> {
+    match level {
+        "dbug" => Some(LevelFilter::Debug),
+        "trce" => Some(LevelFilter::Trace),
+        level => level.parse().ok(),
+    }
+}
+
+fn level_from_u8(level: u8) -> LevelFilter {
+    LevelFilter::iter()
+        .find(|x| *x as u8 == level)
+        .unwrap_or(LevelFilter::Info)
+}
+
+// `log` skips disabled records before formatting anything, keep it as tight as we can
+fn update_max_level() {
+    let mut max = level_from_u8(DEFAULT_LEVEL.load(Ordering::Relaxed));
+    if let Some(filters) = FILTERS.try_read() {
+        for filter in filters.iter() {
+            max = max.max(filter.level);
+        }
+    }
+    log::set_max_level(max);
+}
+
+fn module_matches(target: &str, module: &str) -> bool {
+    let target = target.strip_prefix("flappy_os::").unwrap_or(target);
+    target
+        .strip_prefix(module)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+}
+
+fn level_for(target: &str) -> LevelFilter {
+    let default = level_from_u8(DEFAULT_LEVEL.load(Ordering::Relaxed));
+    // filters are only ever written by the shell, so this only fails in a tiny window
+    let Some(filters) = FILTERS.try_read() else {
+        return default;
+    };
+
+    filters
+        .iter()
+        .filter(|x| module_matches(target, &x.module))
+        .max_by_key(|x| x.module.len())
+        .map_or(default, |x| x.level)
+}
+
+// interrupt handlers and syscalls can't wait since the holder might be what they
+// interrupted, everyone else spins with interrupts on so the holder can finish
+fn with_ring<R>(f: impl FnOnce(&mut LogRing) -> R) -> Option<R> {
+    if int_status() {
+        Some(f(&mut RING.lock()))
+    } else {
+        RING.try_lock().map(|mut ring| f(&mut ring))
+    }
+}
+
+fn next_undrained() -> Option<LogRecord> {
+    with_ring(|ring| {
+        let mut seq = DRAINED.load(Ordering::Acquire);
+        if seq >= ring.next_seq {
+            return None;
+        }
+
+        let oldest = ring.next_seq.saturating_sub(LOG_RING_SIZE as u64);
+        if seq < oldest {
+            crate::println!("... {} log records dropped", oldest - seq);
+            seq = oldest;
+        }
+        DRAINED.store(seq + 1, Ordering::Release);
+        ring.get(seq).copied()
+    })
+    .flatten()
+}
+
+fn write_record(record: &LogRecord) {
+    let level_color = match record.level {
+        _ if record.ok => color::DARK_GREEN,
+        Level::Error => color::RED,
+        Level::Warn => color::YELLOW,
+        Level::Info => color::GREEN,
+        Level::Debug => color::CYAN,
+        Level::Trace => color::GRAY,
+    };
+
+    let digits = 5;
+
+    let subsecond = (record.time_ns % 1_000_000_000) / 10u64.pow(9 - digits);
+    let seconds_total = record.time_ns / 1_000_000_000;
+    let seconds = seconds_total % 60;
+    let minutes = seconds_total / 60 % 60;
+    let hours = seconds_total / 3600;
+
+    let mut line = HeaplessString::<512>::new();
+    writeln!(
+        line,
+        "[{:02}:{:02}:{:02}.{:0width$}] [ {}{}{} ] {}{}:{} {}",
+        hours,
+        minutes,
+        seconds,
+        subsecond,
+        level_color,
+        record.tag(),
+        color::RESET,
+        color::GRAY,
+        short_target(record.target.as_str()),
+        color::RESET,
+        record.message,
+        width = digits as usize
+    )
+    .ok();
+
+    SerialWriter.write_str(line.as_str()).ok();
+    // the console allocates, so it misses what gets logged from interrupt context
+    if int_status() {
+        console::write_str(line.as_str());
+    }
+}
+
+pub fn level_name(level: Level) -> &'static str {
+    match level {
+        Level::Error => "error",
+        Level::Warn => "warn",
+        Level::Info => "info",
+        Level::Debug => "dbug",
+        Level::Trace => "trce",
+    }
+}
+
+pub fn short_target(target: &str) -> &str {
+    match target.rsplit("::").next().unwrap() {
+        "x86_64" | "aarch64" => "chronos",
+        module => module,
+    }
+}
+
+// writes out everything that's still in the ring, used before the executor runs and on panic
+pub fn flush() {
+    while let Some(record) = next_undrained() {
+        write_record(&record);
+    }
+}
+
+// called from the timer irq so logging itself never has to wake anything
+pub fn kick_drain() {
+    if DRAINED.load(Ordering::Acquire) < PUSHED.load(Ordering::Acquire) {
+        DRAIN_WAKER.wake();
+    }
+}
+
+pub async fn drain_task() {
+    DRAINING.store(true, Ordering::Release);
+    loop {
+        poll_fn(|cx| {
+            DRAIN_WAKER.register(cx.waker());
+            if DRAINED.load(Ordering::Acquire) < PUSHED.load(Ordering::Acquire) {
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        })
+        .await;
+
+        // a few at a time so a log storm doesn't starve the other tasks
+        for _ in 0..16 {
+            let Some(record) = next_undrained() else {
+                break;
+            };
+            write_record(&record);
+        }
+        yield_now().await;
+    }
+}
+
+// newest last
+pub fn recent(count: usize) -> Vec<LogRecord> {
+    // allocated up front, the ring lock is taken with interrupts off
+    let mut out = Vec::with_capacity(count.min(LOG_RING_SIZE));
+    with_ring(|ring| {
+        let start = ring.next_seq.saturating_sub(out.capacity() as u64);
+        out.extend((start..ring.next_seq).filter_map(|seq| ring.get(seq).copied()));
+    });
+    out
+}
+
+#[macro_export]
+macro_rules! ok {
+    ($($arg:tt)*) => (::log::info!(
+        target: concat!(module_path!(), "::OK"), // OK_SUFFIX
+        $($arg)*
+    ));
+}
+
+#[macro_export]
+macro_rules! info {
+    ($($arg:tt)*) => (::log::info!($($arg)*));
+}
+
+#[macro_export]
+macro_rules! debug {
+    ($($arg:tt)*) => (::log::debug!($($arg)*));
+}
+
+#[macro_export]
+macro_rules! warn {
+    ($($arg:tt)*) => (::log::warn!($($arg)*));
+}
+
+#[macro_export]
+macro_rules! error {
+    ($($arg:tt)*) => (::log::error!($($arg)*));
+}
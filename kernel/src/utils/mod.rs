@@ -4,7 +4,22 @@
 */
 
 pub mod asm;
+pub mod block;
 pub mod bootloader;
+pub mod cmdline;
+pub mod console;
+pub mod executor;
+pub mod fat;
 pub mod fb;
+pub mod font;
 pub mod heapless;
+pub mod image;
+pub mod logger;
+pub mod recorder;
+pub mod save;
+pub mod screenshot;
 pub mod serial;
+pub mod shell;
+pub mod vfs;
+pub mod video;
+pub mod wav;
@@ -0,0 +1,142 @@
+/*
+    Copyright (C) 2025 bugo07
+    Released under EUPL 1.2 License
+*/
+
+// records the backbuffer for a while and streams it out through `screenshot::submit`.
+// each frame only carries what changed since the last frame that was sent:
+//   recording:u16 index:u32 time_ms:u32 flags:u8 scale:u8
+//   then runs of skip:u32 count:u32 rgb[count]
+// frames are dropped while the port is still busy, the timestamps keep the pacing
+// right. `png_to_rust record` turns the capture into an apng or a gif
+
+use core::sync::atomic::{AtomicU16, Ordering};
+
+use alloc::vec::Vec;
+
+use crate::{
+    arch::time::preferred_timer_ns,
+    info,
+    utils::{
+        fb::Framebuffer,
+        screenshot::{self, FORMAT_RECORDING},
+    },
+};
+
+pub const FLAG_LAST: u8 = 1;
+// unchanged pixels shorter than this get sent anyway instead of starting a new run
+const MIN_SKIP: usize = 8;
+
+struct Recording {
+    id: u16,
+    scale: u32,
+    started_ns: u64,
+    until_ns: u64,
+    index: u32,
+    prev: Vec<u32>,
+}
+
+static RECORDING: spin::Mutex<Option<Recording>> = spin::Mutex::new(None);
+static NEXT_ID: AtomicU16 = AtomicU16::new(0);
+
+// `scale` keeps every nth pixel in both directions, serial isn't fast
+pub fn start(seconds: u64, scale: u32) -> u16 {
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    let now = preferred_timer_ns();
+    *RECORDING.lock() = Some(Recording {
+        id,
+        scale: scale.clamp(1, 8),
+        started_ns: now,
+        until_ns: now + seconds * 1_000_000_000,
+        index: 0,
+        prev: Vec::new(),
+    });
+    info!("recording {id} for {seconds}s");
+    id
+}
+
+pub fn stop() {
+    if let Some(recording) = RECORDING.lock().as_mut() {
+        recording.until_ns = 0;
+    }
+}
+
+// called after every rendered frame
+pub fn on_frame(fb: &Framebuffer) {
+    let mut lock = RECORDING.lock();
+    let Some(recording) = lock.as_mut() else {
+        return;
+    };
+
+    let now = preferred_timer_ns();
+    let last = now >= recording.until_ns;
+    if !last && screenshot::queued() > 0 {
+        return;
+    }
+
+    let scale = recording.scale as usize;
+    let width = fb.size.x as usize / scale;
+    let height = fb.size.y as usize / scale;
+    let pixels = (0..height)
+        .flat_map(|y| (0..width).map(move |x| (x, y)))
+        .map(|(x, y)| fb.backbuffer[y * scale * fb.size.x as usize + x * scale] & 0xFFFFFF)
+        .collect::<Vec<_>>();
+
+    let mut data = Vec::new();
+    data.extend_from_slice(&recording.id.to_le_bytes());
+    data.extend_from_slice(&recording.index.to_le_bytes());
+    data.extend_from_slice(&(((now - recording.started_ns) / 1_000_000) as u32).to_le_bytes());
+    data.push(if last { FLAG_LAST } else { 0 });
+    data.push(recording.scale as u8);
+    encode_delta(&mut data, &recording.prev, &pixels);
+
+    screenshot::submit(FORMAT_RECORDING, width as u32, height as u32, data);
+    recording.prev = pixels;
+    recording.index += 1;
+
+    if last {
+        info!(
+            "recording {} done, {} frames",
+            recording.id, recording.index
+        );
+        *lock = None;
+    }
+}
+
+// no previous frame means everything changed
+fn encode_delta(out: &mut Vec<u8>, prev: &[u32], pixels: &[u32]) {
+    let changed = |i: usize| prev.get(i) != Some(&pixels[i]);
+
+    let mut i = 0;
+    let mut run_start = 0; // end of the last run, skips count from here
+    while i < pixels.len() {
+        if !changed(i) {
+            i += 1;
+            continue;
+        }
+
+        // extend the run until there's a long enough stretch of unchanged pixels
+        let start = i;
+        let mut end = i + 1;
+        let mut unchanged = 0;
+        while end < pixels.len() && unchanged < MIN_SKIP {
+            if changed(end) {
+                unchanged = 0;
+            } else {
+                unchanged += 1;
+            }
+            end += 1;
+        }
+        let end = end - unchanged;
+
+        out.extend_from_slice(&((start - run_start) as u32).to_le_bytes());
+        out.extend_from_slice(&((end - start) as u32).to_le_bytes());
+        for pixel in &pixels[start..end] {
+            let [b, g, r, _] = pixel.to_le_bytes();
+            out.extend_from_slice(&[r, g, b]);
+        }
+
+        run_start = end;
+        i = end;
+    }
+}
@@ -0,0 +1,305 @@
+/*
+    Copyright (C) 2025 bugo07
+    Released under EUPL 1.2 License
+*/
+
+// high scores and settings, kept in the first two sectors of the gpt partition
+// labelled "flappysave" (`make all-hdd` makes one). a record is
+//   "FLAPSAVE" version:u16 reserved:u16 sequence:u32 len:u32 crc32:u32 payload[len]
+// with the crc over the payload, numbers little endian. writes alternate between the
+// two sectors and loading takes the newest good one, so a write cut off halfway
+// only loses that save.
+// the game thread can't do port io, it hands records to `save_task` through `store`
+
+use core::{
+    future::poll_fn,
+    sync::atomic::{AtomicBool, Ordering},
+    task::Poll,
+};
+
+use alloc::vec::Vec;
+
+use crate::{
+    info,
+    utils::{
+        block::{self, BlockDevice, BlockError, Partition},
+        executor::AtomicWaker,
+        image::crc32,
+    },
+    warn,
+};
+
+pub const SAVE_PARTITION: &str = "flappysave";
+pub const MAX_HIGH_SCORES: usize = 8;
+
+const MAGIC: &[u8; 8] = b"FLAPSAVE";
+const VERSION: u16 = 1;
+const HEADER_SIZE: usize = 24;
+const SLOT_SIZE: usize = 512;
+const SLOTS: u32 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SaveError {
+    NoPartition,
+    Block(BlockError),
+    BadMagic,
+    BadChecksum,
+    UnsupportedVersion(u16),
+    Truncated,
+}
+
+impl From<BlockError> for SaveError {
+    fn from(err: BlockError) -> Self {
+        SaveError::Block(err)
+    }
+}
+
+// a stored 0 would freeze the game on every boot
+pub const SPEED_PERCENT: core::ops::RangeInclusive<u16> = 10..=1600;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Settings {
+    // of normal game speed
+    pub speed_percent: u16,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self { speed_percent: 100 }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HighScore {
+    pub initials: [u8; 3],
+    pub score: u32,
+    // what the pipes were generated from, replays the exact run
+    pub seed: u64,
+}
+
+// high scores are kept best first
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SaveRecord {
+    pub settings: Settings,
+    pub high_scores: Vec<HighScore>,
+}
+
+impl SaveRecord {
+    // where `score` would land in the table, if it makes it in at all
+    pub fn rank(&self, score: u32) -> Option<usize> {
+        let rank = self
+            .high_scores
+            .iter()
+            .take_while(|x| x.score >= score)
+            .count();
+        (score > 0 && rank < MAX_HIGH_SCORES).then_some(rank)
+    }
+
+    pub fn insert(&mut self, entry: HighScore) {
+        if let Some(rank) = self.rank(entry.score) {
+            self.high_scores.insert(rank, entry);
+            self.high_scores.truncate(MAX_HIGH_SCORES);
+        }
+    }
+
+    fn payload(&self) -> Vec<u8> {
+        let mut payload = Vec::new();
+        payload.extend_from_slice(&self.settings.speed_percent.to_le_bytes());
+        payload.push(self.high_scores.len().min(MAX_HIGH_SCORES) as u8);
+        for entry in self.high_scores.iter().take(MAX_HIGH_SCORES) {
+            payload.extend_from_slice(&entry.initials);
+            payload.extend_from_slice(&entry.score.to_le_bytes());
+            payload.extend_from_slice(&entry.seed.to_le_bytes());
+        }
+        payload
+    }
+
+    pub fn encode(&self, sequence: u32) -> [u8; SLOT_SIZE] {
+        let payload = self.payload();
+        let mut slot = [0u8; SLOT_SIZE];
+        slot[0..8].copy_from_slice(MAGIC);
+        slot[8..10].copy_from_slice(&VERSION.to_le_bytes());
+        slot[12..16].copy_from_slice(&sequence.to_le_bytes());
+        slot[16..20].copy_from_slice(&(payload.len() as u32).to_le_bytes());
+        slot[20..24].copy_from_slice(&crc32(&payload).to_le_bytes());
+        slot[HEADER_SIZE..HEADER_SIZE + payload.len()].copy_from_slice(&payload);
+        slot
+    }
+
+    // gives back the record and its sequence number
+    pub fn decode(slot: &[u8]) -> Result<(Self, u32), SaveError> {
+        if slot.len() < HEADER_SIZE {
+            return Err(SaveError::Truncated);
+        }
+        if &slot[0..8] != MAGIC {
+            return Err(SaveError::BadMagic);
+        }
+        let version = u16::from_le_bytes([slot[8], slot[9]]);
+        if version != VERSION {
+            return Err(SaveError::UnsupportedVersion(version));
+        }
+        let sequence = u32::from_le_bytes(slot[12..16].try_into().unwrap());
+        let len = u32::from_le_bytes(slot[16..20].try_into().unwrap()) as usize;
+        let crc = u32::from_le_bytes(slot[20..24].try_into().unwrap());
+        let payload = slot
+            .get(HEADER_SIZE..HEADER_SIZE + len)
+            .ok_or(SaveError::Truncated)?;
+        if crc32(payload) != crc {
+            return Err(SaveError::BadChecksum);
+        }
+
+        let mut reader = payload.iter().copied();
+        let mut take = |n: usize| -> Result<Vec<u8>, SaveError> {
+            let bytes = reader.by_ref().take(n).collect::<Vec<_>>();
+            if bytes.len() == n {
+                Ok(bytes)
+            } else {
+                Err(SaveError::Truncated)
+            }
+        };
+
+        let speed_percent = u16::from_le_bytes(take(2)?.try_into().unwrap())
+            .clamp(*SPEED_PERCENT.start(), *SPEED_PERCENT.end());
+        let count = take(1)?[0] as usize;
+        let mut high_scores = Vec::new();
+        for _ in 0..count.min(MAX_HIGH_SCORES) {
+            let entry = take(15)?;
+            high_scores.push(HighScore {
+                initials: entry[0..3].try_into().unwrap(),
+                score: u32::from_le_bytes(entry[3..7].try_into().unwrap()),
+                seed: u64::from_le_bytes(entry[7..15].try_into().unwrap()),
+            });
+        }
+
+        Ok((
+            SaveRecord {
+                settings: Settings { speed_percent },
+                high_scores,
+            },
+            sequence,
+        ))
+    }
+}
+
+struct SaveStore {
+    partition: Partition,
+    sequence: u32,
+}
+
+static STORE: spin::Mutex<Option<SaveStore>> = spin::Mutex::new(None);
+static AVAILABLE: AtomicBool = AtomicBool::new(false);
+static LOADED: spin::Mutex<Option<SaveRecord>> = spin::Mutex::new(None);
+static PENDING: spin::Mutex<Option<SaveRecord>> = spin::Mutex::new(None);
+static WAKER: AtomicWaker = AtomicWaker::new();
+
+// finds the save partition and reads what's there, after block::init
+pub fn init() {
+    let partition = block::devices().iter().find_map(|device| {
+        block::find_partition(device, SAVE_PARTITION)
+            .inspect_err(|err| warn!("{}: couldn't read the gpt: {err:?}", device.name()))
+            .ok()
+            .flatten()
+    });
+    let Some(partition) = partition else {
+        info!("no {SAVE_PARTITION} partition, scores won't be kept");
+        return;
+    };
+
+    let (record, sequence) = match load(&partition) {
+        Ok(Some((record, sequence))) => {
+            info!(
+                "loaded save {sequence} from {} ({} high scores)",
+                partition.name(),
+                record.high_scores.len()
+            );
+            (record, sequence)
+        }
+        Ok(None) => {
+            info!("{} is empty, starting a new save", partition.name());
+            (SaveRecord::default(), 0)
+        }
+        Err(err) => {
+            warn!("couldn't load the save from {}: {err:?}", partition.name());
+            return;
+        }
+    };
+
+    *LOADED.lock() = Some(record);
+    *STORE.lock() = Some(SaveStore {
+        partition,
+        sequence,
+    });
+    AVAILABLE.store(true, Ordering::Release);
+}
+
+fn load(partition: &Partition) -> Result<Option<(SaveRecord, u32)>, SaveError> {
+    if partition.sector_count() < SLOTS as u64 || partition.sector_size() != SLOT_SIZE {
+        return Err(SaveError::NoPartition);
+    }
+
+    let mut newest: Option<(SaveRecord, u32)> = None;
+    let mut slot = [0u8; SLOT_SIZE];
+    for lba in 0..SLOTS as u64 {
+        partition.read(lba, &mut slot)?;
+        match SaveRecord::decode(&slot) {
+            Ok((record, sequence)) => {
+                // the counter wraps, so newer means less than half the range ahead
+                if newest
+                    .as_ref()
+                    .is_none_or(|(_, x)| sequence.wrapping_sub(*x) as i32 > 0)
+                {
+                    newest = Some((record, sequence));
+                }
+            }
+            Err(SaveError::BadMagic) => {}
+            Err(err) => warn!("save slot {lba} is bad: {err:?}"),
+        }
+    }
+    Ok(newest)
+}
+
+pub fn available() -> bool {
+    AVAILABLE.load(Ordering::Acquire)
+}
+
+// what was on disk at boot, defaults if there's no save
+pub fn loaded() -> SaveRecord {
+    LOADED.lock().clone().unwrap_or_default()
+}
+
+// queues a write, only the newest record waiting is kept
+pub fn store(record: SaveRecord) {
+    if !available() {
+        return;
+    }
+    *PENDING.lock() = Some(record);
+    WAKER.wake();
+}
+
+pub async fn save_task() {
+    loop {
+        let record = poll_fn(|cx| {
+            WAKER.register(cx.waker());
+            match PENDING.lock().take() {
+                Some(record) => Poll::Ready(record),
+                None => Poll::Pending,
+            }
+        })
+        .await;
+
+        let mut store = STORE.lock();
+        let Some(store) = store.as_mut() else {
+            continue;
+        };
+
+        let sequence = store.sequence.wrapping_add(1);
+        let slot = record.encode(sequence);
+        match store.partition.write((sequence % SLOTS) as u64, &slot) {
+            Ok(()) => {
+                store.sequence = sequence;
+                info!("saved ({} high scores)", record.high_scores.len());
+            }
+            Err(err) => warn!("couldn't save: {err:?}"),
+        }
+    }
+}
@@ -0,0 +1,147 @@
+/*
+    Copyright (C) 2025 bugo07
+    Released under EUPL 1.2 License
+*/
+
+// encoded images go out over com2 (com1 if there's no com2) as frames:
+//   "FSHT" kind:u8 id:u16 seq:u16 len:u16 payload[len] crc32:u32
+// crc covers everything from kind to the end of the payload, numbers are little endian.
+// START's payload is format:u8 width:u32 height:u32 size:u32, DATA frames carry the
+// image in order and END's payload is the crc32 of the whole image.
+// `png_to_rust extract` finds the frames in a capture and writes the images back out.
+// the recorder sends its frames the same way with its own format, see `recorder.rs`
+// * with no com2 the frames end up mixed into the log, the extractor skips over that
+
+use core::{
+    future::poll_fn,
+    sync::atomic::{AtomicU16, Ordering},
+    task::Poll,
+};
+
+use alloc::{collections::vec_deque::VecDeque, vec::Vec};
+
+use crate::{
+    info,
+    utils::{
+        executor::{AtomicWaker, yield_now},
+        fb::Framebuffer,
+        image::{ImageFormat, crc32, crc32_update},
+        serial::{COM1_BASE, COM2_BASE, SerialPort},
+    },
+};
+
+pub const FRAME_MAGIC: [u8; 4] = *b"FSHT";
+pub const FRAME_START: u8 = 0;
+pub const FRAME_DATA: u8 = 1;
+pub const FRAME_END: u8 = 2;
+const CHUNK_SIZE: usize = 1024;
+
+pub const FORMAT_PNG: u8 = 0;
+pub const FORMAT_QOI: u8 = 1;
+pub const FORMAT_RECORDING: u8 = 2;
+
+struct Capture {
+    id: u16,
+    format: u8,
+    width: u32,
+    height: u32,
+    data: Vec<u8>,
+}
+
+static CAPTURES: spin::Mutex<VecDeque<Capture>> = spin::Mutex::new(VecDeque::new());
+static NEXT_ID: AtomicU16 = AtomicU16::new(0);
+static PORT: AtomicU16 = AtomicU16::new(COM1_BASE);
+static WAKER: AtomicWaker = AtomicWaker::new();
+
+pub fn init() {
+    if SerialPort::new(COM2_BASE).init() {
+        PORT.store(COM2_BASE, Ordering::Relaxed);
+        info!("streaming screenshots over com2");
+    } else {
+        info!("no com2, screenshots go out over com1");
+    }
+}
+
+// encodes on the calling thread and queues the result
+pub fn capture(fb: &Framebuffer, format: ImageFormat) -> u16 {
+    let data = format.encode(&fb.backbuffer, fb.size.x, fb.size.y);
+    let format_id = match format {
+        ImageFormat::Png => FORMAT_PNG,
+        ImageFormat::Qoi => FORMAT_QOI,
+    };
+    let id = submit(format_id, fb.size.x, fb.size.y, data);
+    info!(
+        "screenshot {id}: {}x{} {}",
+        fb.size.x,
+        fb.size.y,
+        format.extension(),
+    );
+    id
+}
+
+// queues already encoded data to go out over the port
+pub fn submit(format: u8, width: u32, height: u32, data: Vec<u8>) -> u16 {
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    CAPTURES.lock().push_back(Capture {
+        id,
+        format,
+        width,
+        height,
+        data,
+    });
+    WAKER.wake();
+    id
+}
+
+// how many captures are waiting behind the one being sent
+pub fn queued() -> usize {
+    CAPTURES.lock().len()
+}
+
+fn write_frame(port: SerialPort, kind: u8, id: u16, seq: u16, payload: &[u8]) {
+    let mut header = [0u8; 7];
+    header[0] = kind;
+    header[1..3].copy_from_slice(&id.to_le_bytes());
+    header[3..5].copy_from_slice(&seq.to_le_bytes());
+    header[5..7].copy_from_slice(&(payload.len() as u16).to_le_bytes());
+    let crc = crc32_update(crc32_update(0xFFFFFFFF, &header), payload) ^ 0xFFFFFFFF;
+
+    port.write_all(&FRAME_MAGIC);
+    port.write_all(&header);
+    port.write_all(payload);
+    port.write_all(&crc.to_le_bytes());
+}
+
+pub async fn screenshot_task() {
+    loop {
+        let capture = poll_fn(|cx| {
+            WAKER.register(cx.waker());
+            match CAPTURES.lock().pop_front() {
+                Some(capture) => Poll::Ready(capture),
+                None => Poll::Pending,
+            }
+        })
+        .await;
+
+        let port = SerialPort::new(PORT.load(Ordering::Relaxed));
+        let mut start = Vec::with_capacity(13);
+        start.push(capture.format);
+        start.extend_from_slice(&capture.width.to_le_bytes());
+        start.extend_from_slice(&capture.height.to_le_bytes());
+        start.extend_from_slice(&(capture.data.len() as u32).to_le_bytes());
+        write_frame(port, FRAME_START, capture.id, 0, &start);
+
+        for (seq, chunk) in capture.data.chunks(CHUNK_SIZE).enumerate() {
+            write_frame(port, FRAME_DATA, capture.id, seq as u16, chunk);
+            // don't hog the executor for the whole image
+            yield_now().await;
+        }
+
+        let crc = crc32(&capture.data);
+        let chunks = capture.data.len().div_ceil(CHUNK_SIZE) as u16;
+        write_frame(port, FRAME_END, capture.id, chunks, &crc.to_le_bytes());
+        if capture.format != FORMAT_RECORDING {
+            info!("screenshot {} sent", capture.id);
+        }
+    }
+}
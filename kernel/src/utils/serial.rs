@@ -8,11 +8,21 @@
     Released under EUPL 1.2 License
 */
 
-use core::fmt::Write;
+use core::{
+    fmt::Write,
+    future::poll_fn,
+    sync::atomic::{AtomicU8, AtomicUsize, Ordering},
+    task::Poll,
+};
 
-use super::asm::{inb, outb};
+use alloc::string::String;
 
-const COM1_BASE: u16 = 0x3F8;
+use super::{
+    asm::{inb, outb},
+    executor::AtomicWaker,
+};
+
+pub const COM1_BASE: u16 = 0x3F8;
 
 const COM1_DATA: u16 = COM1_BASE;
 
@@ -36,6 +46,49 @@ pub fn init() {
     outb(COM1_MODEM_CONTROL, 0x0B);
 }
 
+pub const COM2_BASE: u16 = 0x2F8;
+
+// any 16550 port, com1 keeps its own functions above since logging goes through it
+#[derive(Clone, Copy)]
+pub struct SerialPort {
+    base: u16,
+}
+
+impl SerialPort {
+    pub const fn new(base: u16) -> Self {
+        Self { base }
+    }
+
+    // 115200 8n1, returns false if nothing echoes back in loopback mode
+    pub fn init(&self) -> bool {
+        outb(self.base + 1, 0x00);
+        outb(self.base + 3, 0x80);
+        outb(self.base, 0x01);
+        outb(self.base + 1, 0x00);
+        outb(self.base + 3, 0x03);
+        outb(self.base + 2, 0xC7);
+
+        outb(self.base + 4, 0x1E); // loopback
+        outb(self.base, 0xAE);
+        if inb(self.base) != 0xAE {
+            return false;
+        }
+        outb(self.base + 4, 0x0F);
+        true
+    }
+
+    pub fn write(&self, byte: u8) {
+        while (inb(self.base + 5) & 0x20) == 0 {}
+        outb(self.base, byte);
+    }
+
+    pub fn write_all(&self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.write(byte);
+        }
+    }
+}
+
 pub fn serial_write(byte: u8) {
     while (inb(COM1_LINE_STATUS) & 0x20) == 0 {}
     outb(COM1_DATA, byte);
@@ -46,6 +99,71 @@ pub fn serial_read() -> u8 {
     inb(COM1_DATA)
 }
 
+const RX_SIZE: usize = 256;
+static RX_BUF: [AtomicU8; RX_SIZE] = [const { AtomicU8::new(0) }; RX_SIZE];
+static RX_HEAD: AtomicUsize = AtomicUsize::new(0);
+static RX_TAIL: AtomicUsize = AtomicUsize::new(0);
+static RX_WAKER: AtomicWaker = AtomicWaker::new();
+
+pub fn init_rx() {
+    crate::arch::ints::install_interrupt(0x24, serial_interrupt_handler);
+    outb(COM1_MODEM_CONTROL, 0x0B);
+    outb(COM1_INTERRUPT_ENABLE, 0x01); // data available
+    crate::arch::ints::pic::unmask(4);
+}
+
+pub fn serial_interrupt_handler(_stack_frame: *mut crate::arch::ints::StackFrame) {
+    while (inb(COM1_LINE_STATUS) & 1) != 0 {
+        let byte = inb(COM1_DATA);
+        let head = RX_HEAD.load(Ordering::Relaxed);
+        let next = (head + 1) % RX_SIZE;
+        // drop the byte if the reader fell behind
+        if next != RX_TAIL.load(Ordering::Acquire) {
+            RX_BUF[head].store(byte, Ordering::Relaxed);
+            RX_HEAD.store(next, Ordering::Release);
+        }
+    }
+    RX_WAKER.wake();
+    crate::arch::ints::pic::send_eoi(4);
+}
+
+pub fn serial_try_read() -> Option<u8> {
+    let tail = RX_TAIL.load(Ordering::Relaxed);
+    if tail == RX_HEAD.load(Ordering::Acquire) {
+        return None;
+    }
+
+    let byte = RX_BUF[tail].load(Ordering::Relaxed);
+    RX_TAIL.store((tail + 1) % RX_SIZE, Ordering::Release);
+    Some(byte)
+}
+
+pub async fn serial_read_async() -> u8 {
+    poll_fn(|cx| {
+        if let Some(byte) = serial_try_read() {
+            return Poll::Ready(byte);
+        }
+
+        RX_WAKER.register(cx.waker());
+        // the irq could have fired between the check and registering
+        match serial_try_read() {
+            Some(byte) => Poll::Ready(byte),
+            None => Poll::Pending,
+        }
+    })
+    .await
+}
+
+pub async fn serial_read_line() -> String {
+    let mut line = String::new();
+    loop {
+        match serial_read_async().await {
+            b'\r' | b'\n' => return line,
+            byte => line.push(byte as char),
+        }
+    }
+}
+
 pub struct SerialWriter;
 
 impl Write for SerialWriter {
@@ -74,9 +192,7 @@ macro_rules! print {
 #[macro_export]
 macro_rules! println {
     () => ($crate::print!("\n"));
-    ($fmt:expr) => ($crate::print!(concat!($fmt, "\n")));
-    ($fmt:expr, $($arg:tt)*) => ($crate::print!(
-        concat!($fmt, "\n"), $($arg)*));
+    ($($arg:tt)*) => ($crate::print!("{}\n", format_args!($($arg)*)));
 }
 
 pub mod color {
@@ -121,79 +237,3 @@ pub mod color {
         format!("\x1b[{first};2;{r};{g};{b}m") // super dim
     }
 }
-
-pub fn log_message(level: &str, color: &str, mut module_path: &str, args: core::fmt::Arguments) {
-    // #[cfg(not(any(feature = "tests", feature = "uacpi_test")))]
-    {
-        if level == "dbug" && !cfg!(debug_assertions) {
-            return;
-        }
-        module_path = module_path.split("::").last().unwrap();
-        if module_path == "x86_64" || module_path == "aarch64" {
-            module_path = "chronos";
-        }
-
-        let digits = 5;
-
-        let elapsed_ns = crate::arch::time::preferred_timer_ns();
-        let subsecond_ns = elapsed_ns % 1_000_000_000;
-
-        let divisor = 10u64.pow(9 - digits);
-        let subsecond = subsecond_ns / divisor;
-
-        let elapsed_ms = elapsed_ns / 1_000_000;
-        let seconds_total = elapsed_ms / 1000;
-        let seconds = seconds_total % 60;
-        let minutes_total = seconds_total / 60;
-        let minutes = minutes_total % 60;
-        let hours = minutes_total / 60;
-
-        println!(
-            "[{:02}:{:02}:{:02}.{:0width$}] [ {}{}{} ] {}{}:{} {}",
-            hours,
-            minutes,
-            seconds,
-            subsecond,
-            color,
-            level,
-            color::RESET,
-            color::GRAY,
-            module_path,
-            color::RESET,
-            args,
-            width = digits as usize
-        );
-    }
-}
-
-#[macro_export]
-macro_rules! ok {
-    ($($arg:tt)*) => {
-        $crate::utils::serial::log_message(
-            " OK ",
-            $crate::utils::serial::color::DARK_GREEN,
-            module_path!(),
-            format_args!($($arg)*),
-        )
-    };
-}
-
-#[macro_export]
-macro_rules! info {
-    ($($arg:tt)*) => ($crate::utils::serial::log_message("info", $crate::utils::serial::color::GREEN, module_path!(), format_args!($($arg)*)));
-}
-
-#[macro_export]
-macro_rules! debug {
-    ($($arg:tt)*) => ($crate::utils::serial::log_message("dbug", $crate::utils::serial::color::CYAN, module_path!(), format_args!($($arg)*)));
-}
-
-#[macro_export]
-macro_rules! warn {
-    ($($arg:tt)*) => ($crate::utils::serial::log_message("warn", $crate::utils::serial::color::YELLOW, module_path!(), format_args!($($arg)*)));
-}
-
-#[macro_export]
-macro_rules! error {
-    ($($arg:tt)*) => ($crate::utils::serial::log_message("error", $crate::utils::serial::color::RED, module_path!(), format_args!($($arg)*)));
-}
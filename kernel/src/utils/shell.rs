@@ -0,0 +1,335 @@
+/*
+    Copyright (C) 2025 bugo07
+    Released under EUPL 1.2 License
+*/
+
+// debug shell on com1, input comes in through the irq4 receive ring
+
+use alloc::{collections::vec_deque::VecDeque, format, string::String, vec::Vec};
+
+use crate::{
+    arch::{
+        audio::{self, Tone},
+        mem::heap_stats,
+        thread,
+        time::{elapsed_time_pretty, get_timers},
+    },
+    assets,
+    game::{
+        MenuState,
+        debug::{self, DebugRequest},
+    },
+    print, println,
+    utils::{
+        asm::reboot, image::ImageFormat, logger, recorder, serial::serial_read_async, vfs, video,
+    },
+};
+
+const PROMPT: &str = "flappyos> ";
+const HISTORY_SIZE: usize = 16;
+
+const COMMANDS: &[(&str, &str)] = &[
+    ("help", "show this"),
+    ("threads", "list kernel threads"),
+    ("timers", "dump registered timers"),
+    ("heap", "show heap stats"),
+    ("entities", "list ecs entities and their components"),
+    ("state <main|playing|gameover>", "set the menu state"),
+    (
+        "loglevel [<level>|<module>=<level>,..]",
+        "show or set log filters",
+    ),
+    ("loglevel clear", "drop the per-module log filters"),
+    ("dmesg [count]", "show recent log records"),
+    ("screenshot [png|qoi]", "send the screen over com2"),
+    ("record <seconds> [scale]", "record the screen over com2"),
+    ("record stop", "end the current recording early"),
+    ("pause", "pause or resume the round being played"),
+    (
+        "speed <multiplier>",
+        "set how fast the game clock runs, 0 to 16",
+    ),
+    ("ls [path]", "list a directory"),
+    ("cat <path>", "print a file"),
+    ("assets", "list sprites and fonts and where they came from"),
+    ("modes", "list the video modes the display reported"),
+    ("sounds", "list the sounds and how many are playing"),
+    ("play <sound>", "play a sound on the sound card"),
+    ("volume [percent]", "show or set the sound volume"),
+    ("beep [hz] [ms]", "beep the pc speaker"),
+    ("reboot", "reboot the machine"),
+];
+
+pub async fn shell_task() {
+    let mut history: VecDeque<String> = VecDeque::new();
+
+    loop {
+        print!("{PROMPT}");
+        let line = read_line(&history).await;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        if history.front().is_none_or(|x| x != line) {
+            history.push_front(String::from(line));
+            history.truncate(HISTORY_SIZE);
+        }
+        run_command(line);
+    }
+}
+
+// reads a line with echo, backspace, ^C, ^U and arrow key history
+async fn read_line(history: &VecDeque<String>) -> String {
+    let mut line = String::new();
+    let mut browsing: Option<usize> = None;
+
+    loop {
+        match serial_read_async().await {
+            b'\r' | b'\n' => {
+                println!();
+                return line;
+            }
+            0x03 => {
+                println!("^C");
+                return String::new();
+            }
+            0x08 | 0x7F => {
+                if line.pop().is_some() {
+                    print!("\x08 \x08");
+                }
+            }
+            0x15 => replace_line(&mut line, ""),
+            0x1B => {
+                if serial_read_async().await != b'[' {
+                    continue;
+                }
+
+                let index = match (serial_read_async().await, browsing) {
+                    (b'A', None) => Some(0),
+                    (b'A', Some(i)) => Some((i + 1).min(history.len().saturating_sub(1))),
+                    (b'B', Some(0) | None) => None,
+                    (b'B', Some(i)) => Some(i - 1),
+                    _ => continue,
+                };
+                if history.is_empty() {
+                    continue;
+                }
+
+                browsing = index;
+                let entry = index.map_or("", |i| history[i].as_str());
+                replace_line(&mut line, entry);
+            }
+            byte @ 0x20..0x7F => {
+                line.push(byte as char);
+                print!("{}", byte as char);
+            }
+            _ => {}
+        }
+    }
+}
+
+fn replace_line(line: &mut String, new: &str) {
+    for _ in 0..line.len() {
+        print!("\x08 \x08");
+    }
+    line.clear();
+    line.push_str(new);
+    print!("{line}");
+}
+
+fn run_command(line: &str) {
+    let args = line.split_whitespace().collect::<Vec<_>>();
+
+    match args.as_slice() {
+        ["help"] => {
+            for (usage, description) in COMMANDS {
+                println!("  {usage:<40} {description}");
+            }
+        }
+        ["threads"] => print_threads(),
+        ["timers"] => print_timers(),
+        ["heap"] => print_heap(),
+        ["entities"] => debug::request(DebugRequest::ListEntities),
+        ["state", state] => match *state {
+            "main" => debug::request(DebugRequest::SetState(MenuState::Main)),
+            "playing" => debug::request(DebugRequest::SetState(MenuState::Playing)),
+            "gameover" => debug::request(DebugRequest::SetState(MenuState::GameOver)),
+            _ => println!("unknown state {state}"),
+        },
+        ["loglevel"] => println!("log filters: {}", logger::describe_filters()),
+        ["loglevel", "clear"] => logger::clear_filters(),
+        ["loglevel", spec] => {
+            if let Err(err) = logger::set_filters(spec) {
+                println!("{err}");
+            }
+        }
+        ["dmesg"] => print_recent_logs(32),
+        ["dmesg", count] => match count.parse() {
+            Ok(count) => print_recent_logs(count),
+            Err(_) => println!("bad count {count}"),
+        },
+        ["screenshot"] => debug::request(DebugRequest::Screenshot(ImageFormat::Png)),
+        ["screenshot", "png"] => debug::request(DebugRequest::Screenshot(ImageFormat::Png)),
+        ["screenshot", "qoi"] => debug::request(DebugRequest::Screenshot(ImageFormat::Qoi)),
+        ["record", "stop"] => recorder::stop(),
+        ["record", seconds, rest @ ..] if rest.len() <= 1 => {
+            let scale = rest.first().map_or(Ok(2), |x| x.parse());
+            match (seconds.parse(), scale) {
+                (Ok(seconds), Ok(scale)) => {
+                    recorder::start(seconds, scale);
+                }
+                _ => println!("usage: record <seconds> [scale]"),
+            }
+        }
+        ["pause"] => debug::request(DebugRequest::TogglePause),
+        ["speed", speed] => match speed.parse::<f32>() {
+            Ok(speed) if speed.is_finite() => debug::request(DebugRequest::SetSpeed(speed)),
+            _ => println!("bad speed {speed}"),
+        },
+        ["ls"] => list_dir("/"),
+        ["ls", path] => list_dir(path),
+        ["cat", path] => match vfs::read(path) {
+            Ok(data) => println!("{}", String::from_utf8_lossy(&data)),
+            Err(err) => println!("{path}: {err:?}"),
+        },
+        ["assets"] => {
+            for (name, size, source) in assets::list() {
+                println!("{size:>10} {name} ({source:?})");
+            }
+        }
+        ["modes"] => list_modes(),
+        ["sounds"] => list_sounds(),
+        ["play", sound] => {
+            if audio::play(sound, 1.0, false).is_none() {
+                println!("can't play {sound}, see sounds");
+            }
+        }
+        ["volume"] => println!("volume {}%", (audio::volume() * 100.0) as u32),
+        ["volume", percent] => match percent.parse::<u32>() {
+            Ok(percent @ 0..=100) => audio::set_volume(percent as f32 / 100.0),
+            _ => println!("bad volume {percent}"),
+        },
+        ["beep", rest @ ..] if rest.len() <= 2 => {
+            let frequency = rest.first().map_or(Ok(880), |x| x.parse());
+            let ms = rest.get(1).map_or(Ok(200), |x| x.parse());
+            match (frequency, ms) {
+                (Ok(frequency), Ok(ms)) => audio::beep(Tone::new(frequency, ms)),
+                _ => println!("usage: beep [hz] [ms]"),
+            }
+        }
+        ["reboot"] => reboot(),
+        [command, ..] => println!("unknown command {command}, try help"),
+        [] => {}
+    }
+}
+
+fn list_sounds() {
+    for (name, ms) in audio::sounds() {
+        println!("{ms:>6}ms {name}");
+    }
+    if !audio::has_card() {
+        println!("no sound card, only beep works");
+    }
+    println!("{} playing", audio::voices());
+}
+
+// * is the one in use, pick one with video=WxH on the cmdline
+fn list_modes() {
+    let current = video::screen().map(|x| x.size).ok();
+    let modes = video::modes();
+    if modes.is_empty() {
+        println!("the display didn't report any modes");
+    }
+    for mode in modes {
+        let mark = if Some(mode) == current { '*' } else { ' ' };
+        println!("{mark} {}x{}", mode.x, mode.y);
+    }
+}
+
+fn list_dir(path: &str) {
+    match vfs::read_dir(path) {
+        Ok(entries) => {
+            for entry in entries {
+                if entry.metadata.is_dir() {
+                    println!("{:>10} {}/", "-", entry.name);
+                } else {
+                    println!("{:>10} {}", entry.metadata.size, entry.name);
+                }
+            }
+        }
+        Err(err) => println!("{path}: {err:?}"),
+    }
+}
+
+fn print_recent_logs(count: usize) {
+    for record in logger::recent(count) {
+        println!(
+            "{:>6} {:>16} {:<5} {}: {}",
+            record.seq,
+            elapsed_time_pretty(record.time_ns, 3),
+            record.tag(),
+            logger::short_target(record.target.as_str()),
+            record.message
+        );
+    }
+}
+
+pub fn print_threads() {
+    println!(
+        "{:>4} {:<12} {:>4} {:<16} {:>16}",
+        "id", "name", "prio", "state", "cpu time"
+    );
+    for thread in thread::list() {
+        println!(
+            "{:>4} {:<12} {:>4} {:<16} {:>16}",
+            thread.id,
+            thread.name,
+            thread.priority,
+            format!("{:?}", thread.state),
+            elapsed_time_pretty(thread.cpu_ns, 3)
+        );
+    }
+}
+
+fn print_timers() {
+    println!(
+        "{:<6} {:>9} {:>4} {:>14} {:>16}",
+        "name", "supported", "prio", "frequency", "elapsed"
+    );
+    for timer in get_timers().iter() {
+        println!(
+            "{:<6} {:>9} {:>4} {:>14} {:>16}",
+            timer.name(),
+            timer.is_supported(),
+            timer.priority(),
+            timer.frequency,
+            if timer.is_supported() {
+                timer.elapsed_pretty(3)
+            } else {
+                String::from("-")
+            }
+        );
+    }
+}
+
+fn print_heap() {
+    let stats = heap_stats();
+    println!(
+        "allocated {} KiB in {} allocations",
+        stats.allocated_bytes / 1024,
+        stats.allocation_count
+    );
+    println!(
+        "available {} KiB, claimed {} KiB over {} heaps, {} fragments",
+        stats.available_bytes / 1024,
+        stats.claimed_bytes / 1024,
+        stats.heap_count,
+        stats.fragment_count
+    );
+    println!(
+        "{} allocations, {} KiB total since boot",
+        stats.total_allocation_count,
+        stats.total_allocated_bytes / 1024
+    );
+}
@@ -0,0 +1,370 @@
+/*
+    Copyright (C) 2025 bugo07
+    Released under EUPL 1.2 License
+*/
+
+// one tree of absolute paths over whatever filesystems got mounted. the first fat
+// partition found (the one limine booted from) ends up at /boot, others at
+// /<partition name>. filesystems hand out a node number per file that `File` keeps
+// using, so a file removed while it's open turns into NotFound on the next access.
+// all of this is port io underneath, so it's kernel side only
+
+use alloc::{
+    string::{String, ToString},
+    sync::Arc,
+    vec,
+    vec::Vec,
+};
+
+use crate::{
+    debug, info,
+    utils::{
+        block::{self, BlockDevice, BlockError},
+        fat::FatFs,
+    },
+    warn,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FsError {
+    NotFound,
+    NotADirectory,
+    IsADirectory,
+    AlreadyExists,
+    DirectoryNotEmpty,
+    InvalidName,
+    NoSpace,
+    // opened for reading only
+    NotWritable,
+    NotMounted,
+    UnknownFilesystem,
+    Corrupt(&'static str),
+    Block(BlockError),
+}
+
+impl From<BlockError> for FsError {
+    fn from(err: BlockError) -> Self {
+        FsError::Block(err)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileType {
+    File,
+    Directory,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Metadata {
+    pub kind: FileType,
+    pub size: u64,
+}
+
+impl Metadata {
+    pub fn is_dir(&self) -> bool {
+        self.kind == FileType::Directory
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirEntry {
+    pub name: String,
+    pub metadata: Metadata,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpenMode {
+    Read,
+    // creates the file if it's missing and empties it
+    Write,
+    // creates the file if it's missing, writes always go to the end
+    Append,
+    // existing files only
+    ReadWrite,
+}
+
+impl OpenMode {
+    fn writable(&self) -> bool {
+        *self != OpenMode::Read
+    }
+
+    fn creates(&self) -> bool {
+        matches!(self, OpenMode::Write | OpenMode::Append)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SeekFrom {
+    Start(u64),
+    End(i64),
+    Current(i64),
+}
+
+// paths handed in are relative to the mount point, with no leading slash
+pub trait FileSystem: Send + Sync {
+    fn name(&self) -> &str;
+
+    // node of the file at `path`, made empty first if it's missing and `create` is set
+    fn open(&self, path: &str, create: bool) -> Result<u64, FsError>;
+
+    fn stat(&self, path: &str) -> Result<Metadata, FsError>;
+
+    fn node_stat(&self, node: u64) -> Result<Metadata, FsError>;
+
+    // reads up to the end of the file, gives back how much it read
+    fn read_at(&self, node: u64, offset: u64, buf: &mut [u8]) -> Result<usize, FsError>;
+
+    // grows the file as needed, a gap past the end gets zeroed
+    fn write_at(&self, node: u64, offset: u64, data: &[u8]) -> Result<usize, FsError>;
+
+    // empties the file
+    fn truncate(&self, node: u64) -> Result<(), FsError>;
+
+    fn read_dir(&self, path: &str) -> Result<Vec<DirEntry>, FsError>;
+
+    fn create_dir(&self, path: &str) -> Result<(), FsError>;
+
+    // files and empty directories
+    fn remove(&self, path: &str) -> Result<(), FsError>;
+}
+
+struct Mount {
+    // normalized, like "/boot"
+    point: String,
+    fs: Arc<dyn FileSystem>,
+}
+
+static MOUNTS: spin::Mutex<Vec<Mount>> = spin::Mutex::new(Vec::new());
+
+// mounts every fat filesystem on the registered disks, after block::init
+pub fn init() {
+    info!("mounting filesystems...");
+    for device in block::devices() {
+        let partitions = match block::partitions(&device) {
+            Ok(partitions) => partitions,
+            Err(err) => {
+                warn!("{}: couldn't read the gpt: {err:?}", device.name());
+                continue;
+            }
+        };
+
+        // a disk without a gpt can still be one big filesystem
+        let volumes = if partitions.is_empty() {
+            vec![device]
+        } else {
+            partitions
+                .into_iter()
+                .map(|x| Arc::new(x) as Arc<dyn BlockDevice>)
+                .collect()
+        };
+
+        for volume in volumes {
+            match FatFs::mount(volume.clone()) {
+                Ok(fs) => {
+                    let point = if MOUNTS.lock().is_empty() {
+                        String::from("/boot")
+                    } else {
+                        alloc::format!("/{}", volume.name().replace(':', "-"))
+                    };
+                    mount(&point, Arc::new(fs));
+                }
+                Err(FsError::UnknownFilesystem) => {
+                    debug!("{}: no filesystem", volume.name())
+                }
+                Err(err) => warn!("{}: couldn't mount: {err:?}", volume.name()),
+            }
+        }
+    }
+}
+
+pub fn mount(point: &str, fs: Arc<dyn FileSystem>) {
+    let point = normalize(point);
+    info!("mounted {} at {point}", fs.name());
+    let mut mounts = MOUNTS.lock();
+    mounts.retain(|x| x.point != point);
+    mounts.push(Mount { point, fs });
+}
+
+pub fn unmount(point: &str) -> Result<(), FsError> {
+    let point = normalize(point);
+    let mut mounts = MOUNTS.lock();
+    let count = mounts.len();
+    mounts.retain(|x| x.point != point);
+    if mounts.len() == count {
+        return Err(FsError::NotMounted);
+    }
+    Ok(())
+}
+
+// absolute, without `.`, `..` or doubled slashes
+fn normalize(path: &str) -> String {
+    let mut parts = Vec::new();
+    for part in path.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            part => parts.push(part),
+        }
+    }
+    alloc::format!("/{}", parts.join("/"))
+}
+
+// the filesystem `path` is on and the path inside it, the longest mount point wins
+fn resolve(path: &str) -> Result<(Arc<dyn FileSystem>, String), FsError> {
+    let path = normalize(path);
+    let mounts = MOUNTS.lock();
+    mounts
+        .iter()
+        .filter_map(|mount| {
+            let rest = path.strip_prefix(mount.point.trim_end_matches('/'))?;
+            (rest.is_empty() || rest.starts_with('/'))
+                .then(|| (mount, rest.trim_start_matches('/')))
+        })
+        .max_by_key(|(mount, _)| mount.point.len())
+        .map(|(mount, rest)| (mount.fs.clone(), rest.to_string()))
+        .ok_or(FsError::NotMounted)
+}
+
+// the directories mount points make up above the mounted filesystems, so / can be
+// listed without anything mounted there
+fn mount_dirs(path: &str) -> Vec<DirEntry> {
+    let path = normalize(path);
+    let prefix = path.trim_end_matches('/');
+    let mut names = Vec::<String>::new();
+    for mount in MOUNTS.lock().iter() {
+        let Some(rest) = mount.point.strip_prefix(prefix) else {
+            continue;
+        };
+        let Some(name) = rest.strip_prefix('/').and_then(|x| x.split('/').next()) else {
+            continue;
+        };
+        if !name.is_empty() && !names.iter().any(|x| x == name) {
+            names.push(name.to_string());
+        }
+    }
+    names
+        .into_iter()
+        .map(|name| DirEntry {
+            name,
+            metadata: Metadata {
+                kind: FileType::Directory,
+                size: 0,
+            },
+        })
+        .collect()
+}
+
+pub fn open(path: &str, mode: OpenMode) -> Result<File, FsError> {
+    let (fs, path) = resolve(path)?;
+    let node = fs.open(&path, mode.creates())?;
+    if mode == OpenMode::Write {
+        fs.truncate(node)?;
+    }
+    Ok(File {
+        fs,
+        node,
+        position: 0,
+        mode,
+    })
+}
+
+pub fn stat(path: &str) -> Result<Metadata, FsError> {
+    match resolve(path) {
+        Ok((fs, path)) => fs.stat(&path),
+        Err(FsError::NotMounted) if !mount_dirs(path).is_empty() => Ok(Metadata {
+            kind: FileType::Directory,
+            size: 0,
+        }),
+        Err(err) => Err(err),
+    }
+}
+
+pub fn read_dir(path: &str) -> Result<Vec<DirEntry>, FsError> {
+    let dirs = mount_dirs(path);
+    let mut entries = match resolve(path) {
+        Ok((fs, path)) => fs.read_dir(&path)?,
+        Err(FsError::NotMounted) if !dirs.is_empty() => Vec::new(),
+        Err(err) => return Err(err),
+    };
+    for dir in dirs {
+        if !entries.iter().any(|x| x.name == dir.name) {
+            entries.push(dir);
+        }
+    }
+    Ok(entries)
+}
+
+pub fn create_dir(path: &str) -> Result<(), FsError> {
+    let (fs, path) = resolve(path)?;
+    fs.create_dir(&path)
+}
+
+pub fn remove(path: &str) -> Result<(), FsError> {
+    let (fs, path) = resolve(path)?;
+    fs.remove(&path)
+}
+
+// the whole file
+pub fn read(path: &str) -> Result<Vec<u8>, FsError> {
+    open(path, OpenMode::Read)?.read_to_end()
+}
+
+// replaces the file with `data`
+pub fn write(path: &str, data: &[u8]) -> Result<(), FsError> {
+    open(path, OpenMode::Write)?.write(data).map(|_| ())
+}
+
+pub struct File {
+    fs: Arc<dyn FileSystem>,
+    node: u64,
+    position: u64,
+    mode: OpenMode,
+}
+
+impl File {
+    pub fn read(&mut self, buf: &mut [u8]) -> Result<usize, FsError> {
+        let read = self.fs.read_at(self.node, self.position, buf)?;
+        self.position += read as u64;
+        Ok(read)
+    }
+
+    // from the current position on
+    pub fn read_to_end(&mut self) -> Result<Vec<u8>, FsError> {
+        let size = self.stat()?.size;
+        let mut data = vec![0u8; size.saturating_sub(self.position) as usize];
+        let read = self.read(&mut data)?;
+        data.truncate(read);
+        Ok(data)
+    }
+
+    pub fn write(&mut self, data: &[u8]) -> Result<usize, FsError> {
+        if !self.mode.writable() {
+            return Err(FsError::NotWritable);
+        }
+        if self.mode == OpenMode::Append {
+            self.position = self.stat()?.size;
+        }
+        let written = self.fs.write_at(self.node, self.position, data)?;
+        self.position += written as u64;
+        Ok(written)
+    }
+
+    pub fn seek(&mut self, from: SeekFrom) -> Result<u64, FsError> {
+        self.position = match from {
+            SeekFrom::Start(x) => x,
+            SeekFrom::End(x) => self.stat()?.size.saturating_add_signed(x),
+            SeekFrom::Current(x) => self.position.saturating_add_signed(x),
+        };
+        Ok(self.position)
+    }
+
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    pub fn stat(&self) -> Result<Metadata, FsError> {
+        self.fs.node_stat(self.node)
+    }
+}
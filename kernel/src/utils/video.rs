@@ -0,0 +1,146 @@
+/*
+    Copyright (C) 2025 bugo07
+    Released under EUPL 1.2 License
+*/
+
+// the screens and the modes they run in. every framebuffer limine hands over is a
+// screen, the first one is where the shell's `modes` and the cmdline's `video=` apply.
+// limine picks the mode before we get control and reports the others the display can
+// do, `video=WxH` asks for one of those instead. the firmware is gone by then, so
+// switching only works on the bochs adapter, anywhere else limine's mode stays (the
+// `resolution:` option in limine.conf works everywhere). whatever draws to a screen goes
+// through `screens()` rather than limine's response
+
+use alloc::vec::Vec;
+use bevy_math::UVec2;
+use limine::framebuffer::Framebuffer as LimineFramebuffer;
+
+use crate::{
+    arch::{bga, mem::map_mmio},
+    info,
+    utils::{
+        bootloader::{get_framebuffers, get_hhdm_offset},
+        cmdline,
+        fb::Screen,
+    },
+    warn,
+};
+
+// the framebuffer code only does 32 bit pixels
+const BPP: u16 = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VideoError {
+    NoFramebuffer,
+    // not in limine's list of modes
+    UnknownMode,
+    // no bochs adapter to switch with
+    CantSwitch,
+    // the adapter didn't take the mode
+    Rejected,
+}
+
+static SCREENS: spin::Once<Vec<Screen>> = spin::Once::new();
+
+// before anything draws, needs the heap and pci
+pub fn init() {
+    let mut screens = limine_screens();
+    let Some(first) = get_framebuffers().next() else {
+        warn!("no framebuffer, nothing will show up");
+        SCREENS.call_once(|| screens);
+        return;
+    };
+
+    screens[0] = match cmdline::config().video {
+        Some(size) if size != screens[0].size => switch(&first, size).unwrap_or_else(|err| {
+            warn!(
+                "can't switch to {}x{} ({err:?}), staying at {}x{}",
+                size.x, size.y, screens[0].size.x, screens[0].size.y
+            );
+            screens[0]
+        }),
+        _ => screens[0],
+    };
+
+    for screen in &screens {
+        info!(
+            "screen {} is {}x{}",
+            screen.index, screen.size.x, screen.size.y
+        );
+    }
+    SCREENS.call_once(|| screens);
+}
+
+fn switch(fb: &LimineFramebuffer, size: UVec2) -> Result<Screen, VideoError> {
+    let modes = modes();
+    if !modes.is_empty() && !modes.contains(&size) {
+        return Err(VideoError::UnknownMode);
+    }
+    if !bga::available() {
+        return Err(VideoError::CantSwitch);
+    }
+
+    if let Err(err) = bga::set_mode(size.x as u16, size.y as u16, BPP) {
+        // back to what limine left, the framebuffer info still describes that
+        bga::set_mode(fb.width() as u16, fb.height() as u16, fb.bpp()).ok();
+        return Err(err);
+    }
+
+    // a bigger mode can run past what the hhdm covers
+    let phys = fb.addr() as u64 - get_hhdm_offset();
+    let pitch = size.x * BPP as u32 / 8;
+    Ok(Screen {
+        index: 0,
+        addr: map_mmio(phys, pitch as u64 * size.y as u64) as *mut u8,
+        size,
+        pitch,
+        bpp: BPP as u32,
+    })
+}
+
+// the 32 bit modes limine says the first display can do, empty if it didn't say
+pub fn modes() -> Vec<UVec2> {
+    let Some(fb) = get_framebuffers().next() else {
+        return Vec::new();
+    };
+    let mut modes = fb
+        .modes()
+        .unwrap_or(&[])
+        .iter()
+        .filter(|x| x.bpp == BPP)
+        .map(|x| UVec2::new(x.width as u32, x.height as u32))
+        .collect::<Vec<_>>();
+    modes.sort_by_key(|x| (x.x, x.y));
+    modes.dedup();
+    modes
+}
+
+fn limine_screens() -> Vec<Screen> {
+    get_framebuffers()
+        .enumerate()
+        .map(|(index, fb)| limine_screen(index, &fb))
+        .collect()
+}
+
+// every screen, in limine's order and modes until init ran
+pub fn screens() -> Vec<Screen> {
+    match SCREENS.get() {
+        Some(screens) => screens.clone(),
+        None => limine_screens(),
+    }
+}
+
+// the first screen
+pub fn screen() -> Result<Screen, VideoError> {
+    screens().first().copied().ok_or(VideoError::NoFramebuffer)
+}
+
+fn limine_screen(index: usize, fb: &LimineFramebuffer) -> Screen {
+    Screen {
+        index,
+        addr: fb.addr(),
+        size: UVec2::new(fb.width() as u32, fb.height() as u32),
+        pitch: fb.pitch() as u32,
+        bpp: fb.bpp() as u32,
+    }
+}
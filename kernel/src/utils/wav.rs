@@ -0,0 +1,95 @@
+/*
+    Copyright (C) 2025 bugo07
+    Released under EUPL 1.2 License
+*/
+
+// pcm wav files, 8 or 16 bit at any rate. everything comes out as 16 bit mono, the
+// mixer resamples it to whatever the card runs at
+
+use alloc::vec::Vec;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WavError {
+    NotWav,
+    Truncated,
+    // compressed, or a sample size other than 8 or 16
+    Unsupported,
+    NoData,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Wav {
+    pub rate: u32,
+    pub samples: Vec<i16>,
+}
+
+struct Format {
+    channels: u16,
+    rate: u32,
+    bits: u16,
+}
+
+pub fn decode(data: &[u8]) -> Result<Wav, WavError> {
+    if data.len() < 12 || &data[0..4] != b"RIFF" || &data[8..12] != b"WAVE" {
+        return Err(WavError::NotWav);
+    }
+
+    let mut format = None;
+    let mut rest = &data[12..];
+    while rest.len() >= 8 {
+        let id = &rest[0..4];
+        let size = u32::from_le_bytes(rest[4..8].try_into().unwrap()) as usize;
+        let body = rest.get(8..8 + size).ok_or(WavError::Truncated)?;
+
+        match id {
+            b"fmt " => format = Some(parse_format(body)?),
+            b"data" => {
+                let format = format.ok_or(WavError::Truncated)?;
+                return Ok(Wav {
+                    rate: format.rate,
+                    samples: to_mono(&format, body),
+                });
+            }
+            _ => {}
+        }
+        // chunks are padded to an even size
+        rest = rest.get(8 + size + size % 2..).unwrap_or(&[]);
+    }
+    Err(WavError::NoData)
+}
+
+fn parse_format(body: &[u8]) -> Result<Format, WavError> {
+    if body.len() < 16 {
+        return Err(WavError::Truncated);
+    }
+    let tag = u16::from_le_bytes([body[0], body[1]]);
+    let format = Format {
+        channels: u16::from_le_bytes([body[2], body[3]]),
+        rate: u32::from_le_bytes(body[4..8].try_into().unwrap()),
+        bits: u16::from_le_bytes([body[14], body[15]]),
+    };
+    if tag != 1 || !matches!(format.bits, 8 | 16) || format.channels == 0 || format.rate == 0 {
+        return Err(WavError::Unsupported);
+    }
+    Ok(format)
+}
+
+// averages the channels of every frame
+fn to_mono(format: &Format, data: &[u8]) -> Vec<i16> {
+    let sample_size = format.bits as usize / 8;
+    let frame_size = sample_size * format.channels as usize;
+    data.chunks_exact(frame_size)
+        .map(|frame| {
+            let sum = frame
+                .chunks_exact(sample_size)
+                .map(|x| match x {
+                    // 8 bit samples are unsigned
+                    [x] => (*x as i32 - 128) << 8,
+                    [lo, hi] => i16::from_le_bytes([*lo, *hi]) as i32,
+                    _ => unreachable!(),
+                })
+                .sum::<i32>();
+            (sum / format.channels as i32) as i16
+        })
+        .collect()
+}
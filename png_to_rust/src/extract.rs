@@ -0,0 +1,188 @@
+/*
+    Copyright (C) 2025 bugo07
+    Released under EUPL 1.2 License
+*/
+
+// pulls screenshots back out of a serial capture, see kernel/src/utils/screenshot.rs
+// for the frame layout. anything that isn't a valid frame is skipped over.
+
+use std::{collections::HashMap, path::Path};
+
+const FRAME_MAGIC: &[u8; 4] = b"FSHT";
+const FRAME_START: u8 = 0;
+const FRAME_DATA: u8 = 1;
+const FRAME_END: u8 = 2;
+const HEADER_SIZE: usize = 4 + 7;
+
+pub struct Frame<'a> {
+    pub kind: u8,
+    pub id: u16,
+    pub seq: u16,
+    pub payload: &'a [u8],
+}
+
+pub const FORMAT_PNG: u8 = 0;
+pub const FORMAT_QOI: u8 = 1;
+pub const FORMAT_RECORDING: u8 = 2;
+
+pub struct Capture {
+    pub id: u16,
+    pub format: u8,
+    pub width: u32,
+    pub height: u32,
+    pub data: Vec<u8>,
+}
+
+struct Pending {
+    format: u8,
+    width: u32,
+    height: u32,
+    size: usize,
+    next_seq: u16,
+    data: Vec<u8>,
+}
+
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFFFFFFu32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xEDB88320 & (crc & 1).wrapping_neg());
+        }
+    }
+    crc ^ 0xFFFFFFFF
+}
+
+fn u16_at(data: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes(data[at..at + 2].try_into().unwrap())
+}
+
+fn u32_at(data: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(data[at..at + 4].try_into().unwrap())
+}
+
+// the frame starting at `data[0]`, if it's complete and the checksum matches
+fn parse_frame(data: &[u8]) -> Option<(Frame<'_>, usize)> {
+    if data.len() < HEADER_SIZE || &data[..4] != FRAME_MAGIC {
+        return None;
+    }
+
+    let len = u16_at(data, 9) as usize;
+    let total = HEADER_SIZE + len + 4;
+    if data.len() < total || crc32(&data[4..HEADER_SIZE + len]) != u32_at(data, HEADER_SIZE + len) {
+        return None;
+    }
+
+    let frame = Frame {
+        kind: data[4],
+        id: u16_at(data, 5),
+        seq: u16_at(data, 7),
+        payload: &data[HEADER_SIZE..HEADER_SIZE + len],
+    };
+    Some((frame, total))
+}
+
+pub fn frames(mut data: &[u8]) -> Vec<Frame<'_>> {
+    let mut frames = Vec::new();
+    while let Some(start) = data.windows(4).position(|x| x == FRAME_MAGIC) {
+        data = &data[start..];
+        match parse_frame(data) {
+            Some((frame, len)) => {
+                frames.push(frame);
+                data = &data[len..];
+            }
+            None => data = &data[1..],
+        }
+    }
+    frames
+}
+
+// every complete capture in the stream, whatever its format
+pub fn captures(data: &[u8]) -> Vec<Capture> {
+    let mut pending: HashMap<u16, Pending> = HashMap::new();
+    let mut captures = Vec::new();
+
+    for frame in frames(data) {
+        match frame.kind {
+            FRAME_START if frame.payload.len() == 13 => {
+                pending.insert(
+                    frame.id,
+                    Pending {
+                        format: frame.payload[0],
+                        width: u32_at(frame.payload, 1),
+                        height: u32_at(frame.payload, 5),
+                        size: u32_at(frame.payload, 9) as usize,
+                        next_seq: 0,
+                        data: Vec::new(),
+                    },
+                );
+            }
+            FRAME_DATA => {
+                let Some(image) = pending.get_mut(&frame.id) else {
+                    continue;
+                };
+                if frame.seq != image.next_seq {
+                    eprintln!(
+                        "screenshot {}: chunk {} missing, dropping it",
+                        frame.id, image.next_seq
+                    );
+                    pending.remove(&frame.id);
+                    continue;
+                }
+                image.next_seq += 1;
+                image.data.extend_from_slice(frame.payload);
+            }
+            FRAME_END if frame.payload.len() == 4 => {
+                let Some(image) = pending.remove(&frame.id) else {
+                    continue;
+                };
+                if image.data.len() != image.size || crc32(&image.data) != u32_at(frame.payload, 0)
+                {
+                    eprintln!("screenshot {}: checksum mismatch, dropping it", frame.id);
+                    continue;
+                }
+                captures.push(Capture {
+                    id: frame.id,
+                    format: image.format,
+                    width: image.width,
+                    height: image.height,
+                    data: image.data,
+                });
+            }
+            _ => {}
+        }
+    }
+
+    for id in pending.keys() {
+        eprintln!("screenshot {id}: capture ends before the image does");
+    }
+    captures
+}
+
+pub fn run(capture_path: &str, out_dir: &str) {
+    let data = std::fs::read(capture_path).expect("Failed to read capture");
+    std::fs::create_dir_all(out_dir).expect("Failed to create output directory");
+
+    let images = captures(&data)
+        .into_iter()
+        .filter(|x| x.format == FORMAT_PNG || x.format == FORMAT_QOI)
+        .collect::<Vec<_>>();
+    for image in &images {
+        let extension = if image.format == FORMAT_PNG {
+            "png"
+        } else {
+            "qoi"
+        };
+        let path = Path::new(out_dir).join(format!("screenshot-{}.{extension}", image.id));
+        std::fs::write(&path, &image.data).expect("Failed to write image");
+        println!(
+            "Wrote {}x{} screenshot to {}",
+            image.width,
+            image.height,
+            path.display()
+        );
+    }
+    if images.is_empty() {
+        println!("No screenshots found in {capture_path}");
+    }
+}
@@ -5,10 +5,37 @@
 
 use std::io::{BufWriter, Write};
 
+mod extract;
+mod record;
+mod sfx;
+
 fn main() {
     let args: Vec<String> = std::env::args().collect();
+    if args.len() >= 3 && args[1] == "extract" {
+        extract::run(&args[2], args.get(3).map_or(".", |x| x.as_str()));
+        return;
+    }
+    if args.len() >= 3 && args[1] == "record" {
+        let gif = args.iter().any(|x| x == "--gif");
+        let rest = args[3..]
+            .iter()
+            .filter(|x| *x != "--gif")
+            .collect::<Vec<_>>();
+        record::run(&args[2], rest.first().map_or(".", |x| x.as_str()), gif);
+        return;
+    }
+    if args.len() >= 2 && args[1] == "sfx" {
+        sfx::run(args.get(2).map_or(".", |x| x.as_str()));
+        return;
+    }
     if args.len() != 3 {
         eprintln!("Usage: {} <image.png> <output.bin>", args[0]);
+        eprintln!("       {} extract <serial capture> [output dir]", args[0]);
+        eprintln!(
+            "       {} record <serial capture> [output dir] [--gif]",
+            args[0]
+        );
+        eprintln!("       {} sfx [output dir]", args[0]);
         return;
     }
 
@@ -0,0 +1,177 @@
+/*
+    Copyright (C) 2025 bugo07
+    Released under EUPL 1.2 License
+*/
+
+// rebuilds recordings from a serial capture, see kernel/src/utils/recorder.rs for
+// how the delta frames are laid out
+
+use std::{collections::BTreeMap, fs::File, io::BufWriter, path::Path, time::Duration};
+
+use image::{Delay, Frame, RgbaImage, codecs::gif::GifEncoder};
+
+use crate::extract::{FORMAT_RECORDING, captures};
+
+const FLAG_LAST: u8 = 1;
+const FRAME_HEADER_SIZE: usize = 12;
+
+pub struct Recording {
+    pub width: u32,
+    pub height: u32,
+    pub complete: bool,
+    pub frames: Vec<(u32, Vec<u8>)>, // timestamp in ms, rgb
+}
+
+// applies the runs in `delta` on top of `pixels`
+fn apply_delta(pixels: &mut [u8], mut delta: &[u8]) -> Result<(), &'static str> {
+    let mut at = 0;
+    while !delta.is_empty() {
+        if delta.len() < 8 {
+            return Err("truncated run");
+        }
+        let skip = u32::from_le_bytes(delta[0..4].try_into().unwrap()) as usize;
+        let count = u32::from_le_bytes(delta[4..8].try_into().unwrap()) as usize;
+        delta = &delta[8..];
+
+        let start = (at + skip) * 3;
+        let len = count * 3;
+        if delta.len() < len || start + len > pixels.len() {
+            return Err("run out of bounds");
+        }
+        pixels[start..start + len].copy_from_slice(&delta[..len]);
+        delta = &delta[len..];
+        at += skip + count;
+    }
+    Ok(())
+}
+
+pub fn recordings(data: &[u8]) -> BTreeMap<u16, Recording> {
+    let mut recordings: BTreeMap<u16, Recording> = BTreeMap::new();
+    let mut next_index: BTreeMap<u16, u32> = BTreeMap::new();
+
+    for capture in captures(data) {
+        if capture.format != FORMAT_RECORDING || capture.data.len() < FRAME_HEADER_SIZE {
+            continue;
+        }
+
+        let header = &capture.data[..FRAME_HEADER_SIZE];
+        let id = u16::from_le_bytes(header[0..2].try_into().unwrap());
+        let index = u32::from_le_bytes(header[2..6].try_into().unwrap());
+        let time_ms = u32::from_le_bytes(header[6..10].try_into().unwrap());
+        let flags = header[10];
+
+        let recording = recordings.entry(id).or_insert_with(|| Recording {
+            width: capture.width,
+            height: capture.height,
+            complete: false,
+            frames: Vec::new(),
+        });
+        let expected = next_index.entry(id).or_insert(0);
+        if index != *expected || recording.complete {
+            eprintln!("recording {id}: expected frame {expected}, got {index}, skipping");
+            continue;
+        }
+        *expected += 1;
+
+        // every delta builds on the frame before it
+        let mut pixels = recording.frames.last().map_or_else(
+            || vec![0; capture.width as usize * capture.height as usize * 3],
+            |(_, x)| x.clone(),
+        );
+        if let Err(err) = apply_delta(&mut pixels, &capture.data[FRAME_HEADER_SIZE..]) {
+            eprintln!("recording {id}: frame {index}: {err}");
+            recording.complete = true; // everything after this would be garbage
+            continue;
+        }
+
+        recording.frames.push((time_ms, pixels));
+        if flags & FLAG_LAST != 0 {
+            recording.complete = true;
+        }
+    }
+    recordings
+}
+
+// how long each frame stays up, the last one gets the average
+fn delays_ms(frames: &[(u32, Vec<u8>)]) -> Vec<u32> {
+    let mut delays = frames
+        .windows(2)
+        .map(|x| (x[1].0 - x[0].0).max(10))
+        .collect::<Vec<_>>();
+    let average = delays.iter().sum::<u32>() / delays.len().max(1) as u32;
+    delays.push(average.max(10));
+    delays
+}
+
+fn write_apng(path: &Path, recording: &Recording) -> Result<(), png::EncodingError> {
+    let file = BufWriter::new(File::create(path)?);
+    let mut encoder = png::Encoder::new(file, recording.width, recording.height);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_animated(recording.frames.len() as u32, 0)?;
+
+    let mut writer = encoder.write_header()?;
+    for ((_, pixels), delay) in recording.frames.iter().zip(delays_ms(&recording.frames)) {
+        writer.set_frame_delay(delay.min(u16::MAX as u32) as u16, 1000)?;
+        writer.write_image_data(pixels)?;
+    }
+    writer.finish()
+}
+
+fn write_gif(path: &Path, recording: &Recording) -> image::ImageResult<()> {
+    let file = BufWriter::new(File::create(path)?);
+    let mut encoder = GifEncoder::new_with_speed(file, 10);
+    encoder.set_repeat(image::codecs::gif::Repeat::Infinite)?;
+
+    let frames = recording
+        .frames
+        .iter()
+        .zip(delays_ms(&recording.frames))
+        .map(|((_, pixels), delay)| {
+            let rgba = pixels
+                .chunks(3)
+                .flat_map(|x| [x[0], x[1], x[2], 255])
+                .collect();
+            let image = RgbaImage::from_raw(recording.width, recording.height, rgba).unwrap();
+            Frame::from_parts(
+                image,
+                0,
+                0,
+                Delay::from_saturating_duration(Duration::from_millis(delay as u64)),
+            )
+        });
+    encoder.encode_frames(frames)
+}
+
+pub fn run(capture_path: &str, out_dir: &str, gif: bool) {
+    let data = std::fs::read(capture_path).expect("Failed to read capture");
+    std::fs::create_dir_all(out_dir).expect("Failed to create output directory");
+
+    let recordings = recordings(&data);
+    for (id, recording) in &recordings {
+        if recording.frames.is_empty() {
+            continue;
+        }
+        if !recording.complete {
+            eprintln!("recording {id}: capture ends early, keeping what's there");
+        }
+
+        let extension = if gif { "gif" } else { "png" };
+        let path = Path::new(out_dir).join(format!("recording-{id}.{extension}"));
+        if gif {
+            write_gif(&path, recording).expect("Failed to write gif");
+        } else {
+            write_apng(&path, recording).expect("Failed to write apng");
+        }
+        println!(
+            "Wrote {} frames of {}x{} to {}",
+            recording.frames.len(),
+            recording.width,
+            recording.height,
+            path.display()
+        );
+    }
+    if recordings.is_empty() {
+        println!("No recordings found in {capture_path}");
+    }
+}
@@ -0,0 +1,110 @@
+/*
+    Copyright (C) 2025 bugo07
+    Released under EUPL 1.2 License
+*/
+
+// synthesizes the game's sound effects into 16 bit mono wavs, the ones built into the
+// kernel live in kernel/res/sounds/. anything else that writes a pcm wav works as a
+// replacement in assets/sounds/
+
+use std::{f32::consts::TAU, fs, path::Path};
+
+const RATE: u32 = 22050;
+
+// the sample at a time in seconds
+type Effect = fn(f32) -> f32;
+
+// name, length in seconds
+const EFFECTS: [(&str, f32, Effect); 4] = [
+    ("flap", 0.08, flap),
+    ("score", 0.2, score),
+    ("hit", 0.12, hit),
+    ("die", 0.45, die),
+];
+
+pub fn run(output_dir: &str) {
+    fs::create_dir_all(output_dir).expect("Failed to create output dir");
+    for (name, length, effect) in EFFECTS {
+        let samples = (0..(length * RATE as f32) as u32)
+            .map(|i| effect(i as f32 / RATE as f32))
+            .collect::<Vec<_>>();
+        let path = Path::new(output_dir).join(format!("{name}.wav"));
+        fs::write(&path, wav(&samples)).expect("Failed to write sound");
+        println!("Wrote {} samples to {}", samples.len(), path.display());
+    }
+}
+
+// a quick chirp upwards
+fn flap(t: f32) -> f32 {
+    let phase = 500.0 * t + 0.5 * 6000.0 * t * t;
+    square(phase) * 0.5 * fade(t, 0.08)
+}
+
+// two notes, the second one rings out
+fn score(t: f32) -> f32 {
+    let (frequency, start) = if t < 0.06 {
+        (988.0, 0.0)
+    } else {
+        (1319.0, 0.06)
+    };
+    (TAU * frequency * t).sin() * 0.6 * fade(t - start, 0.2 - start)
+}
+
+// a burst of noise over a low thump
+fn hit(t: f32) -> f32 {
+    let thump = (TAU * 110.0 * t).sin() * 0.5;
+    (noise(t) * 0.5 + thump) * fade(t, 0.12).powi(2)
+}
+
+// sliding down from 700hz to 150hz
+fn die(t: f32) -> f32 {
+    let phase = 700.0 * t - 0.5 * (550.0 / 0.45) * t * t;
+    triangle(phase) * 0.6 * fade(t, 0.45)
+}
+
+fn square(phase: f32) -> f32 {
+    if phase.fract() < 0.5 { 1.0 } else { -1.0 }
+}
+
+fn triangle(phase: f32) -> f32 {
+    4.0 * (phase.fract() - 0.5).abs() - 1.0
+}
+
+// linear from 1 at the start to 0 at `length`
+fn fade(t: f32, length: f32) -> f32 {
+    (1.0 - t / length).clamp(0.0, 1.0)
+}
+
+// the same noise every run, so regenerating doesn't change the files
+fn noise(t: f32) -> f32 {
+    let mut x = (t * RATE as f32) as u32 ^ 0x9E37_79B9;
+    x ^= x << 13;
+    x ^= x >> 17;
+    x ^= x << 5;
+    x as f32 / u32::MAX as f32 * 2.0 - 1.0
+}
+
+fn wav(samples: &[f32]) -> Vec<u8> {
+    let data_size = samples.len() as u32 * 2;
+    let mut out = Vec::with_capacity(44 + data_size as usize);
+    out.extend_from_slice(b"RIFF");
+    out.extend_from_slice(&(36 + data_size).to_le_bytes());
+    out.extend_from_slice(b"WAVE");
+
+    out.extend_from_slice(b"fmt ");
+    out.extend_from_slice(&16u32.to_le_bytes());
+    out.extend_from_slice(&1u16.to_le_bytes()); // pcm
+    out.extend_from_slice(&1u16.to_le_bytes()); // mono
+    out.extend_from_slice(&RATE.to_le_bytes());
+    out.extend_from_slice(&(RATE * 2).to_le_bytes());
+    out.extend_from_slice(&2u16.to_le_bytes());
+    out.extend_from_slice(&16u16.to_le_bytes());
+
+    out.extend_from_slice(b"data");
+    out.extend_from_slice(&data_size.to_le_bytes());
+    for sample in samples {
+        let sample = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+        out.extend_from_slice(&sample.to_le_bytes());
+    }
+    out
+}
@@ -0,0 +1,170 @@
+/*
+    Copyright (C) 2025 bugo07
+    Released under EUPL 1.2 License
+*/
+
+// the kernel's wav decoder and software mixer, neither touches hardware so they build
+// on the host as they are
+
+extern crate alloc;
+
+use std::sync::Arc;
+
+use mixer::{CHANNELS, MAX_VOICES, Mixer, Sound};
+use wav::WavError;
+
+#[path = "../../kernel/src/utils/wav.rs"]
+mod wav;
+
+#[allow(dead_code)]
+#[path = "../../kernel/src/arch/audio/mixer.rs"]
+mod mixer;
+
+fn wav_file(tag: u16, channels: u16, rate: u32, bits: u16, data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(b"RIFF");
+    out.extend_from_slice(&(36 + data.len() as u32).to_le_bytes());
+    out.extend_from_slice(b"WAVE");
+    out.extend_from_slice(b"fmt ");
+    out.extend_from_slice(&16u32.to_le_bytes());
+    out.extend_from_slice(&tag.to_le_bytes());
+    out.extend_from_slice(&channels.to_le_bytes());
+    out.extend_from_slice(&rate.to_le_bytes());
+    let block = channels * bits / 8;
+    out.extend_from_slice(&(rate * block as u32).to_le_bytes());
+    out.extend_from_slice(&block.to_le_bytes());
+    out.extend_from_slice(&bits.to_le_bytes());
+    // something to skip over, odd sized so it needs its padding byte
+    out.extend_from_slice(b"LIST");
+    out.extend_from_slice(&3u32.to_le_bytes());
+    out.extend_from_slice(&[1, 2, 3, 0]);
+    out.extend_from_slice(b"data");
+    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    out.extend_from_slice(data);
+    out
+}
+
+fn samples(values: &[i16]) -> Vec<u8> {
+    values.iter().flat_map(|x| x.to_le_bytes()).collect()
+}
+
+#[test]
+fn wav_decodes_to_mono() {
+    let stereo = wav_file(1, 2, 44100, 16, &samples(&[100, 300, -1000, -2000]));
+    let wav = wav::decode(&stereo).unwrap();
+    assert_eq!(wav.rate, 44100);
+    assert_eq!(wav.samples, [200, -1500]);
+
+    // 8 bit is unsigned around 128
+    let wav = wav::decode(&wav_file(1, 1, 8000, 8, &[128, 255, 0])).unwrap();
+    assert_eq!(wav.samples, [0, 127 << 8, -128 << 8]);
+}
+
+#[test]
+fn wav_errors() {
+    assert_eq!(wav::decode(b"RIFF"), Err(WavError::NotWav));
+    assert_eq!(
+        wav::decode(&wav_file(3, 1, 8000, 32, &[0; 4])),
+        Err(WavError::Unsupported)
+    );
+    assert_eq!(
+        wav::decode(&wav_file(1, 1, 8000, 24, &[0; 3])),
+        Err(WavError::Unsupported)
+    );
+
+    let mut no_data = wav_file(1, 1, 8000, 16, &[]);
+    no_data.truncate(no_data.len() - 8);
+    assert_eq!(wav::decode(&no_data), Err(WavError::NoData));
+
+    let mut truncated = wav_file(1, 1, 8000, 16, &samples(&[1, 2, 3]));
+    truncated.pop();
+    assert_eq!(wav::decode(&truncated), Err(WavError::Truncated));
+}
+
+// what `png_to_rust sfx` wrote and the kernel embeds
+#[test]
+fn builtin_sounds_decode() {
+    let sounds: [&[u8]; 4] = [
+        include_bytes!("../../kernel/res/sounds/flap.wav"),
+        include_bytes!("../../kernel/res/sounds/score.wav"),
+        include_bytes!("../../kernel/res/sounds/hit.wav"),
+        include_bytes!("../../kernel/res/sounds/die.wav"),
+    ];
+    for data in sounds {
+        let wav = wav::decode(data).unwrap();
+        assert_eq!(wav.rate, 22050);
+        assert!(!wav.samples.is_empty());
+        assert!(wav.samples.iter().any(|x| *x != 0));
+    }
+}
+
+fn mix(mixer: &mut Mixer, frames: usize) -> Vec<i16> {
+    let mut out = vec![0x5555; frames * CHANNELS];
+    mixer.mix(&mut out);
+    // both sides always get the same
+    for frame in out.chunks(CHANNELS) {
+        assert!(frame.iter().all(|x| *x == frame[0]));
+    }
+    out.iter().step_by(CHANNELS).copied().collect()
+}
+
+#[test]
+fn mixer_plays_and_finishes() {
+    let mut mixer = Mixer::new(8000);
+    let voice = mixer.play(Arc::new(Sound::new(8000, vec![10, 20, 30])), 1.0, false);
+    assert!(mixer.is_playing(voice));
+
+    assert_eq!(mix(&mut mixer, 5), [10, 20, 30, 0, 0]);
+    assert!(!mixer.is_playing(voice));
+    assert_eq!(mixer.voices(), 0);
+    assert_eq!(mix(&mut mixer, 2), [0, 0]);
+}
+
+#[test]
+fn mixer_sums_clips_and_scales() {
+    let mut mixer = Mixer::new(8000);
+    let loud = Arc::new(Sound::new(8000, vec![30000, -30000, 1000]));
+    mixer.play(loud.clone(), 1.0, false);
+    mixer.play(loud, 1.0, false);
+    assert_eq!(mix(&mut mixer, 3), [i16::MAX, i16::MIN, 2000]);
+
+    mixer.play(Arc::new(Sound::new(8000, vec![1000, 1000])), 0.5, false);
+    mixer.set_volume(0.5);
+    assert_eq!(mixer.volume(), 0.5);
+    assert_eq!(mix(&mut mixer, 2), [250, 250]);
+}
+
+#[test]
+fn mixer_resamples() {
+    // twice the card's rate skips every other sample, half of it fills in between
+    let mut mixer = Mixer::new(8000);
+    mixer.play(
+        Arc::new(Sound::new(16000, vec![0, 1, 2, 3, 4, 5])),
+        1.0,
+        false,
+    );
+    assert_eq!(mix(&mut mixer, 4), [0, 2, 4, 0]);
+
+    mixer.play(Arc::new(Sound::new(4000, vec![0, 100, 200])), 1.0, false);
+    assert_eq!(mix(&mut mixer, 7), [0, 50, 100, 150, 200, 100, 0]);
+}
+
+#[test]
+fn mixer_loops_stops_and_steals() {
+    let mut mixer = Mixer::new(8000);
+    let looping = mixer.play(Arc::new(Sound::new(8000, vec![1, 2])), 1.0, true);
+    assert_eq!(mix(&mut mixer, 5), [1, 2, 1, 2, 1]);
+    assert!(mixer.is_playing(looping));
+    mixer.stop(looping);
+    assert_eq!(mix(&mut mixer, 2), [0, 0]);
+
+    let sound = Arc::new(Sound::new(8000, vec![1; 16]));
+    let first = mixer.play(sound.clone(), 1.0, false);
+    for _ in 1..MAX_VOICES {
+        mixer.play(sound.clone(), 1.0, false);
+    }
+    assert!(mixer.is_playing(first));
+    mixer.play(sound, 1.0, false);
+    assert!(!mixer.is_playing(first));
+    assert_eq!(mixer.voices(), MAX_VOICES);
+}
@@ -0,0 +1,179 @@
+/*
+    Copyright (C) 2025 bugo07
+    Released under EUPL 1.2 License
+*/
+
+// the kernel's cmdline parser, with stand-ins for the few kernel types it names
+
+extern crate alloc;
+
+use bevy_math::UVec2;
+use cmdline::{BootConfig, BootMode, CmdlineError, Keymap, MAX_FPS};
+
+mod arch {
+    pub mod time {
+        #[allow(clippy::upper_case_acronyms)]
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        pub enum TimerKind {
+            KVM,
+            TSC,
+            HPET,
+            PIT,
+        }
+    }
+}
+
+mod utils {
+    pub mod bootloader {
+        pub fn get_cmdline() -> &'static str {
+            ""
+        }
+    }
+
+    pub mod fb {
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+        pub enum ScaleMode {
+            #[default]
+            Integer,
+            Fit,
+        }
+
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+        pub enum DisplayLayout {
+            #[default]
+            Mirror,
+            Extend,
+        }
+    }
+}
+
+#[allow(dead_code)]
+#[path = "../../kernel/src/utils/cmdline.rs"]
+mod cmdline;
+
+use arch::time::TimerKind;
+use utils::fb::{DisplayLayout, ScaleMode};
+
+fn bad(key: &str, value: &str) -> CmdlineError {
+    CmdlineError::BadValue {
+        key: key.to_string(),
+        value: value.to_string(),
+    }
+}
+
+#[test]
+fn empty_is_default() {
+    for cmdline in ["", "   "] {
+        let (config, errors) = BootConfig::parse(cmdline);
+        assert_eq!(config, BootConfig::default());
+        assert!(errors.is_empty());
+    }
+    assert_eq!(cmdline::config(), &BootConfig::default());
+}
+
+#[test]
+fn every_option() {
+    let (config, errors) = BootConfig::parse(
+        "timer=tsc  log=info,game=trace seed=1234 mode=attract fps=120 keymap=de \
+         video=1024x768 scale=fit display=extend volume=40 program=demo",
+    );
+    assert!(errors.is_empty(), "{errors:?}");
+    assert_eq!(
+        config,
+        BootConfig {
+            timer: Some(TimerKind::TSC),
+            log: Some("info,game=trace".to_string()),
+            seed: Some(1234),
+            mode: BootMode::Attract,
+            fps: 120,
+            keymap: Keymap::De,
+            video: Some(UVec2::new(1024, 768)),
+            scale: ScaleMode::Fit,
+            display: DisplayLayout::Extend,
+            volume: 40,
+            program: Some("demo".to_string()),
+        }
+    );
+    assert_eq!(config.tick_secs(), 1.0 / 120.0);
+
+    // the edges of the ranges still count
+    let (config, errors) = BootConfig::parse(&format!("fps=1 volume=0 keymap=se fps={MAX_FPS}"));
+    assert!(errors.is_empty(), "{errors:?}");
+    assert_eq!((config.fps, config.volume), (MAX_FPS, 0));
+    assert_eq!(config.keymap, Keymap::Fi);
+}
+
+#[test]
+fn bad_values_keep_the_default() {
+    let cases = [
+        ("timer", "rtc"),
+        ("log", ""),
+        ("program", ""),
+        ("seed", "-1"),
+        ("seed", "lots"),
+        ("mode", "Attract"),
+        ("fps", "0"),
+        ("fps", "1001"),
+        ("keymap", "qwerty"),
+        ("video", "1024"),
+        ("video", "0x768"),
+        ("video", "axb"),
+        ("scale", "stretch"),
+        ("display", "clone"),
+        ("volume", "101"),
+        ("volume", "-5"),
+    ];
+    for (key, value) in cases {
+        let (config, errors) = BootConfig::parse(&format!("{key}={value}"));
+        assert_eq!(config, BootConfig::default(), "{key}={value}");
+        assert_eq!(errors, [bad(key, value)]);
+    }
+
+    // a key without `=` gets an empty value
+    let (_, errors) = BootConfig::parse("seed");
+    assert_eq!(errors, [bad("seed", "")]);
+}
+
+#[test]
+fn unknown_keys_are_skipped() {
+    let (config, errors) = BootConfig::parse("quiet fps=30 Timer=pit splash=yes");
+    assert_eq!(config.fps, 30);
+    assert_eq!(config.timer, None);
+    assert_eq!(
+        errors,
+        [
+            CmdlineError::UnknownKey("quiet".to_string()),
+            CmdlineError::UnknownKey("Timer".to_string()),
+            CmdlineError::UnknownKey("splash".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn repeated_keys_last_one_wins() {
+    let (config, errors) = BootConfig::parse("fps=30 volume=10 fps=90 volume=20");
+    assert!(errors.is_empty());
+    assert_eq!((config.fps, config.volume), (90, 20));
+
+    // a bad repeat leaves the earlier good one alone
+    let (config, errors) = BootConfig::parse("fps=30 fps=fast");
+    assert_eq!(config.fps, 30);
+    assert_eq!(errors, [bad("fps", "fast")]);
+
+    let mut config = BootConfig::default();
+    config.set("mode", "attract").unwrap();
+    config.set("mode", "normal").unwrap();
+    assert_eq!(config.mode, BootMode::Normal);
+}
+
+// down here so the kernel code picks them up through `use crate::info` like it does
+// with the real logger
+#[macro_export]
+macro_rules! info {
+    ($($arg:tt)*) => (eprintln!($($arg)*));
+}
+
+#[macro_export]
+macro_rules! warn {
+    ($($arg:tt)*) => (eprintln!($($arg)*));
+}
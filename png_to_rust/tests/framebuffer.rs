@@ -0,0 +1,916 @@
+/*
+    Copyright (C) 2025 bugo07
+    Released under EUPL 1.2 License
+*/
+
+// golden image tests for the kernel's framebuffer primitives and fonts. the kernel's
+// fb.rs and font.rs are built on the host with stand-ins for the bits of the kernel it calls into, each
+// scene gets rendered and compared against tests/golden/<name>.png.
+// * run with UPDATE_GOLDENS=1 to (re)write the goldens after an intended change
+// * on a mismatch the actual render and a diff end up in target/golden-diffs
+
+extern crate alloc;
+
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use bevy_math::{IVec2, UVec2, Vec2};
+use image::{Rgb, RgbImage};
+
+use fb::{
+    CP437_HIGH, DisplayLayout, Framebuffer, Insets, Sampling, ScaleMode, Screen, SliceFill,
+    Viewport,
+};
+use font::{Align, Font, TextStyle};
+
+mod assets {
+    pub fn console_font() -> &'static [u8] {
+        crate::fb::BUILTIN_FONT
+    }
+}
+
+mod utils {
+    pub mod asm {
+        use core::ffi::c_void;
+
+        pub fn memcpy(dest: *mut c_void, src: *const c_void, n: usize) -> *mut c_void {
+            unsafe { core::ptr::copy_nonoverlapping(src as *const u8, dest as *mut u8, n) };
+            dest
+        }
+    }
+
+    pub(crate) use crate::fb;
+}
+
+// only what the scenes use gets called, and it's linted as kernel code already
+#[allow(dead_code, clippy::collapsible_if)]
+#[path = "../../kernel/src/utils/fb.rs"]
+mod fb;
+
+#[allow(dead_code)]
+#[path = "../../kernel/src/utils/font.rs"]
+mod font;
+
+const BIRD_SIZE: UVec2 = UVec2::new(57, 36);
+const BACKGROUND: u32 = 0x203040;
+
+fn golden_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden")
+}
+
+fn diff_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("../target/golden-diffs")
+}
+
+fn headless(width: u32, height: u32) -> Framebuffer {
+    let mut fb = Framebuffer::from_backbuffer(
+        vec![0; (width * height) as usize],
+        UVec2::new(width, height),
+    );
+    fb.clear(BACKGROUND);
+    fb
+}
+
+fn bird() -> Vec<u32> {
+    let data = include_bytes!("../../kernel/res/flappy_bird.bin");
+    data.as_chunks::<4>()
+        .0
+        .iter()
+        .map(|x| u32::from_le_bytes(*x))
+        .collect()
+}
+
+fn to_image(fb: &Framebuffer) -> RgbImage {
+    RgbImage::from_fn(fb.size.x, fb.size.y, |x, y| {
+        let [b, g, r, _] = fb.backbuffer[(y * fb.size.x + x) as usize].to_le_bytes();
+        Rgb([r, g, b])
+    })
+}
+
+fn check_golden(name: &str, fb: &Framebuffer) {
+    let actual = to_image(fb);
+    let path = golden_dir().join(format!("{name}.png"));
+
+    if std::env::var_os("UPDATE_GOLDENS").is_some() {
+        std::fs::create_dir_all(golden_dir()).unwrap();
+        actual.save(&path).unwrap();
+        return;
+    }
+
+    let expected = image::open(&path)
+        .unwrap_or_else(|err| panic!("can't open golden {}: {err}", path.display()))
+        .to_rgb8();
+
+    let mismatched = if expected.dimensions() != actual.dimensions() {
+        None
+    } else {
+        Some(
+            expected
+                .pixels()
+                .zip(actual.pixels())
+                .filter(|(a, b)| a != b)
+                .count(),
+        )
+    };
+    if mismatched == Some(0) {
+        return;
+    }
+
+    std::fs::create_dir_all(diff_dir()).unwrap();
+    let actual_path = diff_dir().join(format!("{name}-actual.png"));
+    actual.save(&actual_path).unwrap();
+
+    let Some(mismatched) = mismatched else {
+        panic!(
+            "{name}: golden is {:?} but render is {:?}, render written to {}",
+            expected.dimensions(),
+            actual.dimensions(),
+            actual_path.display()
+        );
+    };
+
+    // differing pixels in red over a dimmed copy of the golden
+    let diff = RgbImage::from_fn(actual.width(), actual.height(), |x, y| {
+        let (a, b) = (expected.get_pixel(x, y), actual.get_pixel(x, y));
+        if a == b {
+            Rgb(a.0.map(|c| c / 4))
+        } else {
+            Rgb([255, 0, 0])
+        }
+    });
+    let diff_path = diff_dir().join(format!("{name}-diff.png"));
+    diff.save(&diff_path).unwrap();
+
+    panic!(
+        "{name}: {mismatched} pixels differ from {}, see {} and {}",
+        path.display(),
+        actual_path.display(),
+        diff_path.display()
+    );
+}
+
+fn draw_rects(fb: &mut Framebuffer) {
+    fb.draw_rect(Vec2::new(8.0, 8.0), UVec2::new(32, 16), 0xFF0000);
+    fb.draw_rect(Vec2::new(24.0, 16.0), UVec2::new(32, 32), 0x00FF00);
+    // fractional positions floor
+    fb.draw_rect(Vec2::new(60.7, 10.2), UVec2::new(5, 5), 0x0000FF);
+    // partly off every edge
+    fb.draw_rect(Vec2::new(-8.0, 40.0), UVec2::new(16, 8), 0xFFFF00);
+    fb.draw_rect(Vec2::new(120.0, 50.0), UVec2::new(16, 8), 0xFF00FF);
+    fb.draw_rect(Vec2::new(70.0, -4.0), UVec2::new(8, 8), 0x00FFFF);
+    fb.draw_rect(Vec2::new(90.0, 92.0), UVec2::new(8, 8), 0xFFFFFF);
+    // zero sized draws nothing
+    fb.draw_rect(Vec2::new(100.0, 20.0), UVec2::new(0, 10), 0xFFFFFF);
+}
+
+fn draw_lines(fb: &mut Framebuffer) {
+    let (cx, cy) = (64, 48);
+    // one line into each octant, plus the axes
+    let ends = [
+        (120, 60),
+        (80, 92),
+        (50, 92),
+        (4, 70),
+        (4, 30),
+        (40, 2),
+        (90, 2),
+        (124, 20),
+        (124, 48),
+        (64, 94),
+    ];
+    for (i, (x, y)) in ends.into_iter().enumerate() {
+        let color = 0x404040 + (i as u32 * 0x101918);
+        fb.draw_line(cx, cy, x, y, color);
+    }
+    // clipped against the edges
+    fb.draw_line(-20, 90, 20, 70, 0xFFFFFF);
+    fb.draw_line(110, 80, 150, 100, 0xFFFFFF);
+    fb.draw_pixel(UVec2::new(200, 200), 0xFFFFFF);
+}
+
+fn draw_text(fb: &mut Framebuffer) {
+    fb.draw_char(UVec2::new(2, 2), b'A', 0xFFFFFF, None, Vec2::ONE, None);
+    fb.draw_char(
+        UVec2::new(12, 2),
+        b'g',
+        0x000000,
+        Some(0xFFCC00),
+        Vec2::ONE,
+        None,
+    );
+    fb.draw_str(UVec2::new(24, 2), "Hi!", 0x80FF80, None, Vec2::ONE);
+    fb.draw_str(UVec2::new(2, 22), "x2", 0xFF8080, None, Vec2::splat(2.0));
+    fb.draw_str(UVec2::new(44, 22), "1.5", 0x8080FF, None, Vec2::splat(1.5));
+    fb.draw_str(
+        UVec2::new(80, 20),
+        "wide",
+        0xFFFFFF,
+        None,
+        Vec2::new(1.0, 2.0),
+    );
+    // the shadow is drawn right after each glyph pixel, so with a bg the next
+    // pixel's bg paints over the shadow. this is what the game relies on looking right
+    fb.draw_str_with_shadow(
+        UVec2::new(2, 60),
+        "SCORE\n42",
+        0xFFFFFF,
+        None,
+        Vec2::splat(1.5),
+        Some((UVec2::new(2, 2), 0x000000)),
+    );
+    fb.draw_str_with_shadow(
+        UVec2::new(96, 60),
+        "BG",
+        0xFFFFFF,
+        Some(0x806020),
+        Vec2::ONE,
+        Some((UVec2::new(1, 1), 0xFF0000)),
+    );
+    // off the right and bottom edge
+    fb.draw_str(UVec2::new(140, 120), "clip", 0xFFFF00, None, Vec2::ONE);
+}
+
+fn draw_sprites(fb: &mut Framebuffer) {
+    let bird = bird();
+    let transparent = Some(0);
+    fb.draw_sprite(
+        Vec2::new(2.0, 2.0),
+        BIRD_SIZE,
+        Vec2::ONE,
+        &bird,
+        transparent,
+    );
+    fb.draw_sprite_rotated(
+        Vec2::new(70.0, 2.0),
+        BIRD_SIZE,
+        Vec2::ONE,
+        &bird,
+        transparent,
+        core::f32::consts::FRAC_PI_4,
+        Sampling::Nearest,
+    );
+    fb.draw_sprite_rotated(
+        Vec2::new(140.0, 2.0),
+        BIRD_SIZE,
+        Vec2::ONE,
+        &bird,
+        transparent,
+        core::f32::consts::FRAC_PI_2,
+        Sampling::Nearest,
+    );
+    fb.draw_sprite_rotated(
+        Vec2::new(2.0, 44.0),
+        BIRD_SIZE,
+        Vec2::splat(2.0),
+        &bird,
+        transparent,
+        -0.5,
+        Sampling::Nearest,
+    );
+    // no transparent color, the alpha byte still hides the background texels
+    fb.draw_sprite(
+        Vec2::new(120.0, 60.0),
+        BIRD_SIZE,
+        Vec2::new(1.5, 1.0),
+        &bird,
+        None,
+    );
+    // hanging off the bottom
+    fb.draw_sprite(
+        Vec2::new(180.0, 100.0),
+        BIRD_SIZE,
+        Vec2::ONE,
+        &bird,
+        transparent,
+    );
+}
+
+fn draw_sampling(fb: &mut Framebuffer) {
+    let bird = bird();
+    let modes = [
+        Sampling::Nearest,
+        Sampling::Bilinear,
+        Sampling::Supersampled,
+    ];
+    for (i, sampling) in modes.into_iter().enumerate() {
+        let x = 4.0 + i as f32 * 80.0;
+        fb.draw_sprite_rotated(
+            Vec2::new(x, 4.0),
+            BIRD_SIZE,
+            Vec2::splat(1.25),
+            &bird,
+            Some(0),
+            0.5,
+            sampling,
+        );
+        // the pipes get stretched a lot more one way than the other
+        fb.draw_sprite_rotated(
+            Vec2::new(x, 60.0),
+            UVec2::new(12, 8),
+            Vec2::new(1.5, 6.0),
+            &bird[20 * BIRD_SIZE.x as usize..],
+            Some(0),
+            0.0,
+            sampling,
+        );
+    }
+
+    // 50% alpha over a rect, and a fully transparent texel that isn't 0
+    let checker = [0x80FFFFFF, 0xFF000000, 0x00FF0000, 0x80FF0000];
+    fb.draw_rect(Vec2::new(200.0, 100.0), UVec2::new(16, 16), 0x0000FF);
+    for (i, sampling) in modes.into_iter().enumerate() {
+        let pos = Vec2::new(200.0 + i as f32 * 8.0, 104.0);
+        fb.draw_sprite_rotated(
+            pos,
+            UVec2::new(2, 2),
+            Vec2::splat(4.0),
+            &checker,
+            None,
+            0.0,
+            sampling,
+        );
+    }
+}
+
+// 6x6, a 2 texel border with different colored corners and a checkered middle
+fn frame_texture() -> Vec<u32> {
+    let mut data = vec![0xFF808080; 36];
+    for y in 0..6 {
+        for x in 0..6 {
+            let color = match (x, y) {
+                (0..2, 0..2) => 0xFFFF0000,
+                (4.., 0..2) => 0xFF00FF00,
+                (0..2, 4..) => 0xFF0000FF,
+                (4.., 4..) => 0xFFFFFF00,
+                (2..4, 2..4) if (x + y) % 2 == 0 => 0xFFFFFFFF,
+                (2..4, 2..4) => 0xFF000000,
+                _ => continue,
+            };
+            data[y * 6 + x] = color;
+        }
+    }
+    data
+}
+
+fn draw_slices(fb: &mut Framebuffer) {
+    let frame = frame_texture();
+    let size = UVec2::splat(6);
+    let insets = Insets::new(2, 2, 2, 2);
+
+    fb.draw_nine_slice(
+        IVec2::new(4, 4),
+        UVec2::new(40, 24),
+        Vec2::ONE,
+        &frame,
+        size,
+        insets,
+        SliceFill::Stretch,
+        None,
+    );
+    fb.draw_nine_slice(
+        IVec2::new(50, 4),
+        UVec2::new(40, 24),
+        Vec2::ONE,
+        &frame,
+        size,
+        insets,
+        SliceFill::Tile,
+        None,
+    );
+    fb.draw_nine_slice(
+        IVec2::new(96, 4),
+        UVec2::new(40, 30),
+        Vec2::splat(2.0),
+        &frame,
+        size,
+        insets,
+        SliceFill::Tile,
+        None,
+    );
+    // smaller than the borders, they shrink instead of overlapping
+    fb.draw_nine_slice(
+        IVec2::new(140, 4),
+        UVec2::new(3, 5),
+        Vec2::ONE,
+        &frame,
+        size,
+        insets,
+        SliceFill::Stretch,
+        None,
+    );
+    // clipped on every side
+    fb.draw_nine_slice(
+        IVec2::new(-10, 40),
+        UVec2::new(30, 20),
+        Vec2::ONE,
+        &frame,
+        size,
+        insets,
+        SliceFill::Tile,
+        None,
+    );
+    fb.draw_nine_slice(
+        IVec2::new(220, 100),
+        UVec2::new(30, 30),
+        Vec2::ONE,
+        &frame,
+        size,
+        insets,
+        SliceFill::Tile,
+        None,
+    );
+    fb.draw_nine_slice(
+        IVec2::new(160, -8),
+        UVec2::new(30, 20),
+        Vec2::ONE,
+        &frame,
+        size,
+        insets,
+        SliceFill::Stretch,
+        None,
+    );
+
+    fb.draw_sprite_tiled(
+        IVec2::new(30, 40),
+        UVec2::new(45, 20),
+        Vec2::ONE,
+        &frame,
+        size,
+        None,
+    );
+    fb.draw_sprite_tiled(
+        IVec2::new(-3, 70),
+        UVec2::new(60, 50),
+        Vec2::new(2.0, 1.5),
+        &frame,
+        size,
+        None,
+    );
+
+    // a pipe from the game, cap at native size and the body repeated
+    let pipe = include_bytes!("../../kernel/res/pipe.bin")
+        .as_chunks::<4>()
+        .0
+        .iter()
+        .map(|x| u32::from_le_bytes(*x))
+        .collect::<Vec<_>>();
+    fb.draw_nine_slice(
+        IVec2::new(90, 50),
+        UVec2::new(33, 200),
+        Vec2::splat(1.5),
+        &pipe,
+        UVec2::new(22, 160),
+        Insets::new(0, 13, 0, 0),
+        SliceFill::Tile,
+        Some(0),
+    );
+}
+
+// the built-in font as psf2 with a unicode table, so both loaders see the same glyphs
+fn builtin_as_psf2() -> Vec<u8> {
+    let mut data = Vec::new();
+    for field in [0x864AB572u32, 0, 32, 1, 256, 16, 16, 8] {
+        data.extend_from_slice(&field.to_le_bytes());
+    }
+    data.extend_from_slice(include_bytes!("../../kernel/res/font.bin"));
+    for index in 0..256 {
+        let ch = match index {
+            0x20..=0x7E => Some(index as u8 as char),
+            0x80.. => Some(CP437_HIGH[index - 0x80]),
+            _ => None,
+        };
+        if let Some(ch) = ch {
+            data.extend_from_slice(ch.encode_utf8(&mut [0; 4]).as_bytes());
+        }
+        data.push(0xFF);
+    }
+    data
+}
+
+// A, a descending g and the '?' everything else falls back to
+const TEST_BDF: &str = "STARTFONT 2.1
+FONT -test-
+SIZE 8 75 75
+FONTBOUNDINGBOX 6 8 0 -2
+STARTPROPERTIES 2
+FONT_ASCENT 6
+FONT_DESCENT 2
+ENDPROPERTIES
+CHARS 3
+STARTCHAR A
+ENCODING 65
+DWIDTH 6 0
+BBX 5 6 0 0
+BITMAP
+70
+88
+88
+F8
+88
+88
+ENDCHAR
+STARTCHAR g
+ENCODING 103
+DWIDTH 5 0
+BBX 4 6 0 -2
+BITMAP
+70
+90
+90
+70
+10
+60
+ENDCHAR
+STARTCHAR question
+ENCODING 63
+DWIDTH 5 0
+BBX 4 6 0 0
+BITMAP
+60
+90
+10
+20
+00
+20
+ENDCHAR
+ENDFONT
+";
+
+fn draw_fonts(fb: &mut Framebuffer) {
+    let builtin = Font::builtin();
+    let psf = Font::from_psf2(&builtin_as_psf2()).unwrap();
+    let bdf = Font::from_bdf(TEST_BDF.as_bytes()).unwrap();
+
+    // utf-8 through the old fixed path, same glyphs as the font module's
+    fb.draw_str(UVec2::new(2, 2), "Größe ½ café", 0xFFFFFF, None, Vec2::ONE);
+
+    let wrapped = TextStyle::new(16.0).with_max_width(110.0);
+    let text = "the quick brown fox jumps over the lazy dog";
+    for (i, align) in [Align::Left, Align::Center, Align::Right]
+        .into_iter()
+        .enumerate()
+    {
+        fb.draw_rect(
+            Vec2::new(2.0, 22.0 + i as f32 * 70.0),
+            UVec2::new(110, 1),
+            0x406080,
+        );
+        font::draw_text(
+            fb,
+            IVec2::new(2, 24 + i as i32 * 70),
+            &builtin,
+            text,
+            &wrapped.with_align(align),
+            0xFFFFFF,
+            Some((IVec2::ONE, 0x000000)),
+        );
+    }
+
+    font::draw_text(
+        fb,
+        IVec2::new(130, 24),
+        &psf,
+        "PSF2 ✓ Äö",
+        &TextStyle::new(24.0),
+        0x80FF80,
+        None,
+    );
+    font::draw_text(
+        fb,
+        IVec2::new(130, 60),
+        &bdf,
+        "AgAx",
+        &TextStyle::new(16.0),
+        0xFFCC00,
+        None,
+    );
+    font::draw_text(
+        fb,
+        IVec2::new(200, 60),
+        &bdf,
+        "gA",
+        &TextStyle::new(8.0),
+        0xFFCC00,
+        None,
+    );
+    // a word longer than the line gets broken anywhere
+    font::draw_text(
+        fb,
+        IVec2::new(130, 90),
+        &builtin,
+        "supercalifragilistic",
+        &TextStyle::new(12.0).with_max_width(60.0),
+        0xFF8080,
+        None,
+    );
+}
+
+#[test]
+fn rects() {
+    let mut fb = headless(128, 96);
+    draw_rects(&mut fb);
+    check_golden("rects", &fb);
+}
+
+#[test]
+fn lines() {
+    let mut fb = headless(128, 96);
+    draw_lines(&mut fb);
+    check_golden("lines", &fb);
+}
+
+#[test]
+fn text() {
+    let mut fb = headless(160, 128);
+    draw_text(&mut fb);
+    check_golden("text", &fb);
+}
+
+#[test]
+fn sprites() {
+    let mut fb = headless(240, 120);
+    draw_sprites(&mut fb);
+    check_golden("sprites", &fb);
+}
+
+#[test]
+fn fonts() {
+    let mut fb = headless(240, 240);
+    draw_fonts(&mut fb);
+    check_golden("fonts", &fb);
+}
+
+// dejavu sans cut down to ascii and the accents below, see tests/fonts/LICENSE-DejaVu
+const DEJAVU_SANS: &[u8] = include_bytes!("fonts/dejavu-sans-subset.ttf");
+
+#[test]
+fn truetype() {
+    let font = Font::load(DEJAVU_SANS).unwrap();
+
+    let mut fb = headless(240, 80);
+    let style = TextStyle::new(20.0).with_max_width(236.0);
+    font::draw_text(
+        &mut fb,
+        IVec2::new(2, 2),
+        &font,
+        "AVATAR Wave — kerning, ünïcödé",
+        &style,
+        0xFFFFFF,
+        None,
+    );
+    font::draw_text(
+        &mut fb,
+        IVec2::new(2, 50),
+        &font,
+        "small 11px text",
+        &TextStyle::new(11.0),
+        0xC0C0FF,
+        None,
+    );
+    check_golden("truetype", &fb);
+
+    // kerning pulls the pair together
+    let kerned = font.line_width("AV", 20.0);
+    let apart = font.advance('A', 20.0) + font.advance('V', 20.0);
+    assert!(kerned < apart, "{kerned} >= {apart}");
+}
+
+#[test]
+fn text_measure_and_wrap() {
+    let font = Font::builtin();
+    // 9px advance including the built-in spacing
+    assert_eq!(font.line_width("hello", 16.0), 45.0);
+    assert_eq!(font.line_width("½é", 16.0), 18.0);
+    assert_eq!(font.line_width("hello", 32.0), 90.0);
+
+    let style = TextStyle::new(16.0).with_max_width(60.0);
+    assert_eq!(
+        font.wrap("hello big world", &style),
+        ["hello", "big", "world"]
+    );
+    assert_eq!(
+        font.wrap("ab cd ef\n\ngh", &style),
+        ["ab cd", "ef", "", "gh"]
+    );
+    assert_eq!(font.wrap("abcdefghijk", &style), ["abcdef", "ghijk"]);
+    assert_eq!(
+        font.measure("hello big world", &style),
+        Vec2::new(60.0, 48.0)
+    );
+    assert_eq!(
+        font.measure("a\nlonger", &TextStyle::new(16.0)),
+        Vec2::new(54.0, 32.0)
+    );
+
+    // unknown chars fall back to '?' instead of vanishing
+    assert_eq!(font.line_width("日本", 16.0), 18.0);
+    assert!(Arc::ptr_eq(&font.glyph('日', 16.0), &font.glyph('?', 16.0)));
+
+    let fb = headless(200, 20);
+    assert_eq!(fb.centered_str_x("ééé", 1.0), fb.centered_str_x("eee", 1.0));
+}
+
+#[test]
+fn bad_fonts() {
+    assert!(matches!(
+        Font::load(b"nope"),
+        Err(font::FontError::UnknownFormat)
+    ));
+    let psf = builtin_as_psf2();
+    assert!(Font::from_psf2(&psf[..100]).is_err());
+    assert!(Font::from_bdf(b"STARTFONT 2.1\nENDFONT\n").is_err());
+}
+
+#[test]
+fn slices() {
+    let mut fb = headless(240, 120);
+    draw_slices(&mut fb);
+    check_golden("slices", &fb);
+}
+
+#[test]
+fn sampling() {
+    let mut fb = headless(240, 120);
+    draw_sampling(&mut fb);
+    check_golden("sampling", &fb);
+}
+
+// integer scales skip the rotation math, it has to land the same as going through it
+#[test]
+fn integer_scale_fast_path() {
+    let bird = bird();
+    for scale in [Vec2::ONE, Vec2::splat(2.0), Vec2::new(3.0, 1.0)] {
+        let mut fast = headless(200, 120);
+        let mut slow = headless(200, 120);
+        fast.draw_sprite_rotated(
+            Vec2::new(-5.0, 3.0),
+            BIRD_SIZE,
+            scale,
+            &bird,
+            Some(0),
+            0.0,
+            Sampling::Nearest,
+        );
+        // tiny enough that nothing moves, but not zero so it can't take the fast path
+        slow.draw_sprite_rotated(
+            Vec2::new(-5.0, 3.0),
+            BIRD_SIZE,
+            scale,
+            &bird,
+            Some(0),
+            1e-9,
+            Sampling::Nearest,
+        );
+        assert!(
+            *fast.backbuffer == *slow.backbuffer,
+            "fast path differs at {scale}"
+        );
+    }
+}
+
+// the renderer draws the screen as bands on different threads, each one in place in the
+// backbuffer, that has to give the same picture as drawing it in one go
+#[test]
+fn bands_match_full() {
+    type Scene = fn(&mut Framebuffer);
+    let scenes: [(Scene, u32, u32); 7] = [
+        (draw_rects, 128, 96),
+        (draw_lines, 128, 96),
+        (draw_text, 160, 128),
+        (draw_sprites, 240, 120),
+        (draw_sampling, 240, 120),
+        (draw_slices, 240, 120),
+        (draw_fonts, 240, 240),
+    ];
+
+    for (i, (scene, width, height)) in scenes.into_iter().enumerate() {
+        let mut full = headless(width, height);
+        scene(&mut full);
+
+        let mut stitched = headless(width, height);
+        for mut band in stitched.bands(25) {
+            band.clear(BACKGROUND);
+            scene(&mut band);
+        }
+        assert!(
+            *stitched.backbuffer == *full.backbuffer,
+            "scene {i} differs when drawn in bands"
+        );
+    }
+}
+
+#[test]
+fn present_without_screen() {
+    let mut fb = headless(4, 4);
+    fb.draw_pixel(UVec2::new(1, 1), 0xFFFFFF);
+    fb.present();
+    assert_eq!(fb.backbuffer[5], 0xFFFFFF);
+}
+
+#[test]
+fn viewports() {
+    let portrait = UVec2::new(480, 640);
+    let cases = [
+        // 1080p only fits it once, bars all around
+        (
+            UVec2::new(1920, 1080),
+            ScaleMode::Integer,
+            (720, 220),
+            (480, 640),
+        ),
+        (
+            UVec2::new(1920, 1080),
+            ScaleMode::Fit,
+            (555, 0),
+            (810, 1080),
+        ),
+        (
+            UVec2::new(1920, 1440),
+            ScaleMode::Integer,
+            (480, 80),
+            (960, 1280),
+        ),
+        // too small for a whole multiple, gets shrunk instead
+        (
+            UVec2::new(800, 600),
+            ScaleMode::Integer,
+            (175, 0),
+            (450, 600),
+        ),
+        // narrower than the aspect ratio, bars above and below
+        (UVec2::new(400, 1000), ScaleMode::Fit, (0, 233), (400, 533)),
+    ];
+    for (screen, mode, offset, size) in cases {
+        let viewport = Viewport::new(portrait, screen, mode);
+        assert_eq!(
+            (viewport.offset, viewport.size),
+            (UVec2::from(offset), UVec2::from(size)),
+            "{screen} {mode:?}"
+        );
+    }
+}
+
+fn fake_screen(index: usize, pixels: &mut [u32], width: u32) -> Screen {
+    Screen {
+        index,
+        addr: pixels.as_mut_ptr() as *mut u8,
+        size: UVec2::new(width, pixels.len() as u32 / width),
+        pitch: width * 4,
+        bpp: 32,
+    }
+}
+
+// a 2x3 picture onto a 7x8 screen through bands, the way the game presents
+#[test]
+fn present_scaled_with_bars() {
+    let mut screen = vec![0xFF00FFu32; 7 * 8];
+    let mut fb = Framebuffer::new_virtual(
+        UVec2::new(2, 3),
+        ScaleMode::Integer,
+        DisplayLayout::Mirror,
+        &[fake_screen(0, &mut screen, 7)],
+    );
+    assert_eq!(fb.size, UVec2::new(2, 3));
+    fb.backbuffer.copy_from_slice(&[1, 2, 3, 4, 5, 6]);
+    fb.present();
+
+    // 2x doubles it into 4x6, centered and rounded towards the top left
+    #[rustfmt::skip]
+    let expected = [
+        0, 0, 0, 0, 0, 0, 0,
+        0, 1, 1, 2, 2, 0, 0,
+        0, 1, 1, 2, 2, 0, 0,
+        0, 3, 3, 4, 4, 0, 0,
+        0, 3, 3, 4, 4, 0, 0,
+        0, 5, 5, 6, 6, 0, 0,
+        0, 5, 5, 6, 6, 0, 0,
+        0, 0, 0, 0, 0, 0, 0,
+    ];
+    assert_eq!(screen, expected);
+}
+
+// extended over two screens of different sizes, each gets its own half scaled to fit it
+#[test]
+fn present_extended() {
+    let mut left = vec![0xFF00FFu32; 4 * 3];
+    let mut right = vec![0xFF00FFu32; 2];
+    let mut fb = Framebuffer::new_virtual(
+        UVec2::new(2, 1),
+        ScaleMode::Integer,
+        DisplayLayout::Extend,
+        &[fake_screen(0, &mut left, 4), fake_screen(1, &mut right, 2)],
+    );
+    assert_eq!(fb.size, UVec2::new(4, 1));
+    fb.backbuffer.copy_from_slice(&[1, 2, 3, 4]);
+    fb.present();
+
+    #[rustfmt::skip]
+    assert_eq!(left, [
+        1, 1, 2, 2,
+        1, 1, 2, 2,
+        0, 0, 0, 0,
+    ]);
+    assert_eq!(right, [3, 4]);
+}
@@ -0,0 +1,6 @@
+fn main() {
+    let dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+    // rustc runs from the workspace root, so the script needs the full path
+    println!("cargo:rustc-link-arg=-T{dir}/linker.ld");
+    println!("cargo:rerun-if-changed=linker.ld");
+}
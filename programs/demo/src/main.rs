@@ -0,0 +1,149 @@
+/*
+    Copyright (C) 2025 bugo07
+    Released under EUPL 1.2 License
+*/
+
+// a small ring 3 program for the elf loader. it bounces a square around the first
+// screen through the `ProgramApi` the kernel hands over in rdi, until esc gets pressed.
+// * it only ever talks to the kernel through syscalls, once through `int 0x80` too
+
+#![no_std]
+#![no_main]
+
+const SIZE: u32 = 64;
+const PIXELS: usize = (SIZE * SIZE) as usize;
+const FRAME_NS: u64 = 16_666_666;
+
+const SCANCODE_NONE: u64 = u64::MAX;
+const SCANCODE_ESC: u64 = 0x01;
+
+const SYS_EXIT: u64 = 0;
+const SYS_WRITE: u64 = 1;
+
+// the same layout as arch::elf::ProgramApi, the functions are stubs in a page of our own
+#[repr(C)]
+struct ProgramApi {
+    version: u32,
+    fb_width: u32,
+    fb_height: u32,
+    fb_blit: extern "C" fn(*const u32, u32, u32, u32, u32) -> u64,
+    input_poll: extern "C" fn() -> u64,
+    time_ns: extern "C" fn() -> u64,
+    yield_now: extern "C" fn(),
+    write: extern "C" fn(*const u8, u64) -> u64,
+    exit: extern "C" fn(i64) -> !,
+}
+
+impl ProgramApi {
+    fn print(&self, s: &str) {
+        (self.write)(s.as_ptr(), s.len() as u64);
+    }
+
+    fn blit(&self, pixels: &[u32; PIXELS], pos: (u32, u32)) {
+        (self.fb_blit)(pixels.as_ptr(), pos.0, pos.1, SIZE, SIZE);
+    }
+}
+
+static BLACK: [u32; PIXELS] = [0; PIXELS];
+
+// the kernel starts us with rsp 16 byte aligned, the call leaves it the way rust
+// functions expect
+#[unsafe(naked)]
+#[unsafe(no_mangle)]
+extern "C" fn _start() -> ! {
+    core::arch::naked_asm!("call {main}", "ud2", main = sym main);
+}
+
+extern "C" fn main(api: &'static ProgramApi) -> ! {
+    api.print("demo: hello from ring 3\n");
+    let hello = "demo: and through int 0x80\n";
+    int80(SYS_WRITE, hello.as_ptr() as u64, hello.len() as u64);
+
+    if api.version != 1 || api.fb_width < SIZE || api.fb_height < SIZE {
+        api.print("demo: no room to draw\n");
+        (api.exit)(1);
+    }
+
+    let mut square = [0; PIXELS]; // 16k of the 64k stack
+    let limit = (api.fb_width - SIZE, api.fb_height - SIZE);
+    let (mut pos, mut velocity) = ((limit.0 / 2, limit.1 / 2), (5i32, 3i32));
+    let mut next_frame = (api.time_ns)();
+
+    'frames: loop {
+        loop {
+            match (api.input_poll)() {
+                SCANCODE_NONE => break,
+                SCANCODE_ESC => break 'frames,
+                _ => {}
+            }
+        }
+
+        // cycles through the colour wheel every few seconds
+        let t = next_frame / FRAME_NS;
+        let color = rainbow((t % 360) as u32);
+        for (i, pixel) in square.iter_mut().enumerate() {
+            let (x, y) = (i as u32 % SIZE, i as u32 / SIZE);
+            let edge = x < 2 || y < 2 || x >= SIZE - 2 || y >= SIZE - 2;
+            *pixel = if edge { 0xFFFFFF } else { color };
+        }
+
+        api.blit(&BLACK, pos);
+        pos = (
+            step(pos.0, &mut velocity.0, limit.0),
+            step(pos.1, &mut velocity.1, limit.1),
+        );
+        api.blit(&square, pos);
+
+        next_frame += FRAME_NS;
+        while (api.time_ns)() < next_frame {
+            (api.yield_now)();
+        }
+    }
+
+    api.blit(&BLACK, pos);
+    api.print("demo: bye\n");
+    (api.exit)(0)
+}
+
+// moves one axis, bouncing off both ends
+fn step(pos: u32, velocity: &mut i32, limit: u32) -> u32 {
+    let next = pos as i32 + *velocity;
+    if next < 0 || next > limit as i32 {
+        *velocity = -*velocity;
+    }
+    next.clamp(0, limit as i32) as u32
+}
+
+// fully saturated hue, 0..360
+fn rainbow(hue: u32) -> u32 {
+    let x = (hue % 60 * 255 / 60) as u8;
+    let (r, g, b) = match hue / 60 {
+        0 => (255, x, 0),
+        1 => (255 - x, 255, 0),
+        2 => (0, 255, x),
+        3 => (0, 255 - x, 255),
+        4 => (x, 0, 255),
+        _ => (255, 0, 255 - x),
+    };
+    (r as u32) << 16 | (g as u32) << 8 | b as u32
+}
+
+fn int80(number: u64, a0: u64, a1: u64) -> u64 {
+    let ret;
+    unsafe {
+        core::arch::asm!(
+            "int 0x80",
+            inlateout("rax") number => ret,
+            in("rdi") a0,
+            in("rsi") a1,
+            options(nostack),
+        );
+    }
+    ret
+}
+
+#[panic_handler]
+fn panic(_info: &core::panic::PanicInfo) -> ! {
+    int80(SYS_EXIT, -1i64 as u64, 0);
+    loop {}
+}